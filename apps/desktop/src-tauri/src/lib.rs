@@ -1,7 +1,7 @@
 use fn_config::AppConfig;
 use fn_core::{
-    ExportResponse, ListWorkspaceEntriesResponse, OpenFileResponse, SaveFileResponse,
-    WatchStartedResponse,
+    ExportResponse, ListWorkspaceEntriesResponse, OpenFileResponse, ResolveWikiLinksResponse,
+    SaveFileResponse, WatchStartedResponse,
 };
 
 #[tauri::command]
@@ -59,6 +59,15 @@ fn list_workspace_entries(
     fn_fs::list_workspace_entries(&root, relative_path.as_deref()).map_err(|err| err.to_string())
 }
 
+#[tauri::command]
+fn resolve_wiki_links(targets: Vec<String>) -> Result<ResolveWikiLinksResponse, String> {
+    let config = fn_config::load().map_err(|err| err.to_string())?;
+    let root =
+        config.workspace_root.ok_or_else(|| "workspace root is not configured".to_string())?;
+
+    fn_fs::resolve_wiki_links(&root, &targets).map_err(|err| err.to_string())
+}
+
 #[cfg_attr(mobile, tauri::mobile_entry_point)]
 pub fn run() {
     tauri::Builder::default()
@@ -71,7 +80,8 @@ pub fn run() {
             watch_file,
             load_app_config,
             set_workspace_root,
-            list_workspace_entries
+            list_workspace_entries,
+            resolve_wiki_links
         ])
         .run(tauri::generate_context!())
         .expect("error while running FerrumNote");
@@ -1,4 +1,4 @@
-import { useCallback, useEffect, useMemo, useRef, useState } from "react";
+import { useCallback, useEffect, useMemo, useState } from "react";
 import {
   exportHtml,
   exportPdf,
@@ -6,6 +6,7 @@ import {
   loadAppConfig,
   openFile,
   pickWorkspaceDirectory,
+  resolveWikiLinks,
   saveAsFile,
   saveFile,
   setWorkspaceRoot,
@@ -46,7 +47,7 @@ function App() {
   const [selectedEntryPath, setSelectedEntryPath] = useState("");
   const [saveState, setSaveState] = useState<SaveState>("saved");
   const [editorMode, setEditorMode] = useState<EditorMode>("writer");
-  const previousMarkdown = useRef(INITIAL_DOC);
+  const [changedBlocks, setChangedBlocks] = useState<string[]>([]);
 
   const runtimeMode = useMemo(() => detectRuntimeMode(), []);
   const runtimeCapabilities = useMemo(() => getRuntimeCapabilities(runtimeMode), [runtimeMode]);
@@ -67,20 +68,13 @@ function App() {
       });
   }, []);
 
-  const changedBlocks = useMemo(() => {
-    const prev = previousMarkdown.current.split("\n\n");
-    const next = markdown.split("\n\n");
-    const size = Math.max(prev.length, next.length);
-    const changed: string[] = [];
-
-    for (let i = 0; i < size; i += 1) {
-      if ((prev[i] || "") !== (next[i] || "")) {
-        changed.push(`block-${i + 1}`);
-      }
-    }
-
-    return changed;
-  }, [markdown]);
+  const recordChangedBlocks = useCallback((nodeIds: number[]) => {
+    setChangedBlocks((current) => {
+      const next = new Set(current);
+      nodeIds.forEach((nodeId) => next.add(String(nodeId)));
+      return next.size === current.length ? current : Array.from(next);
+    });
+  }, []);
 
   const matchCount = useMemo(() => countMatches(markdown, query), [markdown, query]);
   const documentStats = useMemo(() => calculateDocumentStats(markdown), [markdown]);
@@ -152,6 +146,19 @@ function App() {
     [messages.app.explorerLoadFailed, runtimeCapabilities.workspaceExplorer, workspaceRootPath]
   );
 
+  const resolveDocumentWikiLinks = useMemo(() => {
+    if (!runtimeCapabilities.workspaceExplorer || !workspaceRootPath) {
+      return undefined;
+    }
+
+    return async (targets: string[]) => {
+      const resolved = await resolveWikiLinks(targets);
+      return resolved.links
+        .filter((link) => link.relative_path !== null)
+        .map((link) => link.target);
+    };
+  }, [runtimeCapabilities.workspaceExplorer, workspaceRootPath]);
+
   useEffect(() => {
     setDirectoryEntries({});
     setExpandedDirectories([]);
@@ -224,7 +231,7 @@ function App() {
     try {
       const file = await openFile(entry.absolute_path);
       setMarkdown(file.content);
-      previousMarkdown.current = file.content;
+      setChangedBlocks([]);
       setActivePath(file.path);
       setVersion(file.version);
       setDirty(false);
@@ -281,7 +288,7 @@ function App() {
 
       setVersion(saved.version);
       setDirty(false);
-      previousMarkdown.current = markdown;
+      setChangedBlocks([]);
       setSaveState("saved");
       setStatus(`Saved: ${saved.path}`);
     } catch (error) {
@@ -307,7 +314,7 @@ function App() {
       setActivePath(saved.path);
       setVersion(saved.version);
       setDirty(false);
-      previousMarkdown.current = markdown;
+      setChangedBlocks([]);
       setSaveState("saved");
       setStatus(`Saved as: ${saved.path}`);
       if (runtimeCapabilities.fileWatch) {
@@ -406,7 +413,7 @@ function App() {
 
         setVersion(saved.version);
         setDirty(false);
-        previousMarkdown.current = markdown;
+        setChangedBlocks([]);
         setSaveState("saved");
         setStatus(`Autosaved at ${new Date().toLocaleTimeString()}`);
       } catch (error) {
@@ -618,6 +625,9 @@ function App() {
                 setEditorMode((current) => (current === "source" ? "writer" : "source"))
               }
               onChange={updateDocument}
+              onBlocksChange={recordChangedBlocks}
+              documentPath={activePath || null}
+              resolveWikiLinks={resolveDocumentWikiLinks}
               labels={messages.editor}
             />
           </section>
@@ -4,6 +4,7 @@ import type {
   ExportResponse,
   ListWorkspaceEntriesResponse,
   OpenFileResponse,
+  ResolveWikiLinksResponse,
   SaveFileResponse,
   WatchStartedResponse
 } from "../types/contracts";
@@ -85,6 +86,11 @@ export async function listWorkspaceEntries(
   });
 }
 
+export async function resolveWikiLinks(targets: string[]): Promise<ResolveWikiLinksResponse> {
+  ensureCapability("workspaceExplorer", "resolve wiki-links");
+  return invoke<ResolveWikiLinksResponse>("resolve_wiki_links", { targets });
+}
+
 export async function pickWorkspaceDirectory(): Promise<string | null> {
   ensureCapability("workspaceExplorer", "open folder");
   const input = window.prompt("Enter workspace directory path", "");
@@ -23,11 +23,25 @@ type MarkdownEditorProps = {
   mode: EditorMode;
   onModeToggle: () => void;
   onChange: (next: string) => void;
+  onBlocksChange?: (nodeIds: number[]) => void;
+  /** Where the document is stored; relative image sources resolve against it. */
+  documentPath?: string | null;
+  /** Returns the wiki-link targets that name a note of the workspace. */
+  resolveWikiLinks?: (targets: string[]) => Promise<string[]>;
   labels: EditorLabels;
 };
 
-export function MarkdownEditor({ value, mode, onModeToggle, onChange, labels }: MarkdownEditorProps) {
-  const engine = useEngine(value, onChange);
+export function MarkdownEditor({
+  value,
+  mode,
+  onModeToggle,
+  onChange,
+  onBlocksChange,
+  documentPath = null,
+  resolveWikiLinks,
+  labels
+}: MarkdownEditorProps) {
+  const engine = useEngine(value, onChange, onBlocksChange, documentPath, resolveWikiLinks);
 
   useEffect(() => {
     const onToggleMode = (event: KeyboardEvent) => {
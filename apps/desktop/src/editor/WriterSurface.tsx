@@ -1,7 +1,7 @@
-import { useEffect, useLayoutEffect, useRef } from "react";
+import { useEffect, useLayoutEffect, useRef, type CSSProperties } from "react";
 import type { EngineCommandString, EngineSnapshot, RenderBlockVM } from "../engine/types";
 import { readDomSelection, serializeWriterMarkdown } from "./domMapping";
-import { resolveDeleteRange, resolveWriterIntent, resolveWriterShortcut } from "./inputPipeline";
+import { resolveDeleteCommand, resolveWriterIntent, resolveWriterShortcut } from "./inputPipeline";
 import { restoreDomSelection } from "./selectionMapping";
 
 const encoder = new TextEncoder();
@@ -182,8 +182,9 @@ export function WriterSurface({
         return;
       }
 
+      const deleteCommand = resolveDeleteCommand(event.inputType);
       const intent = resolveWriterIntent(event.inputType);
-      if (!intent) {
+      if (!deleteCommand && !intent) {
         return;
       }
 
@@ -193,6 +194,16 @@ export function WriterSurface({
       }
 
       rememberSelection(offsets);
+      if (deleteCommand) {
+        event.preventDefault();
+        if (hasSelectionChanged(offsets, snapshot.selection)) {
+          onSetSelection(offsets.anchor_utf8, offsets.head_utf8);
+        }
+        requestSelectionRestore();
+        onApplyCommand(deleteCommand);
+        return;
+      }
+
       switch (intent) {
         case "insert_text": {
           event.preventDefault();
@@ -200,26 +211,6 @@ export function WriterSurface({
           onReplaceText(offsets.start_utf8, offsets.end_utf8, event.data ?? "");
           return;
         }
-        case "delete_backward": {
-          event.preventDefault();
-          const range = resolveDeleteRange(snapshot.markdown, offsets, "backward");
-          if (!range) {
-            return;
-          }
-          requestSelectionRestore();
-          onReplaceText(range.startUtf8, range.endUtf8, "");
-          return;
-        }
-        case "delete_forward": {
-          event.preventDefault();
-          const range = resolveDeleteRange(snapshot.markdown, offsets, "forward");
-          if (!range) {
-            return;
-          }
-          requestSelectionRestore();
-          onReplaceText(range.startUtf8, range.endUtf8, "");
-          return;
-        }
         case "history_undo":
           event.preventDefault();
           requestSelectionRestore();
@@ -286,7 +277,7 @@ export function WriterSurface({
       root.removeEventListener("compositionstart", onCompositionStart);
       root.removeEventListener("compositionend", onCompositionEnd);
     };
-  }, [onRedo, onReplaceText, onSetMarkdown, onSetSelection, onUndo, snapshot]);
+  }, [onApplyCommand, onRedo, onReplaceText, onSetMarkdown, onSetSelection, onUndo, snapshot]);
 
   if (!ready || !snapshot) {
     return <div className="writer-loading">{loadingLabel}</div>;
@@ -341,11 +332,21 @@ function renderBlock(block: RenderBlockVM, index: number) {
     block.kind === "heading" && block.attrs.heading_level
       ? ` writer-block--heading-${block.attrs.heading_level}`
       : "";
+  const calloutKindClass =
+    block.kind === "callout" && block.attrs.callout_kind
+      ? ` writer-block--callout-${block.attrs.callout_kind}`
+      : "";
+  const quoteDepth = block.attrs.quote_depth;
+  const quoted = quoteDepth !== null && quoteDepth !== undefined;
+  const quoteStyle = quoted ? ({ "--quote-depth": quoteDepth + 1 } as CSSProperties) : undefined;
 
   return (
     <div
-      key={`${block.node_id}-${index}`}
-      className={`writer-block writer-block--${block.kind}${headingLevelClass}`}
+      key={block.node_id}
+      className={`writer-block writer-block--${block.kind}${headingLevelClass}${calloutKindClass}${
+        quoted ? " writer-block--quoted" : ""
+      }`}
+      style={quoteStyle}
       data-block="true"
       data-kind={block.kind}
       data-node-id={block.node_id}
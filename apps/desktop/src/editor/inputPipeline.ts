@@ -1,21 +1,10 @@
 import type { EngineCommandString, WriterInputIntent } from "../engine/types";
 
-const encoder = new TextEncoder();
-
-export type WriterSelectionRange = {
-  start_utf8: number;
-  end_utf8: number;
-};
-
 export function resolveWriterIntent(inputType: string): WriterInputIntent | null {
   switch (inputType) {
     case "insertText":
     case "insertCompositionText":
       return "insert_text";
-    case "deleteContentBackward":
-      return "delete_backward";
-    case "deleteContentForward":
-      return "delete_forward";
     case "insertParagraph":
     case "insertLineBreak":
       return "insert_paragraph";
@@ -30,6 +19,28 @@ export function resolveWriterIntent(inputType: string): WriterInputIntent | null
   }
 }
 
+/** Engine command for a deletion input, which the engine resolves by grapheme, word or line. */
+export function resolveDeleteCommand(inputType: string): EngineCommandString | null {
+  switch (inputType) {
+    case "deleteContentBackward":
+      return "delete_backward:grapheme";
+    case "deleteContentForward":
+      return "delete_forward:grapheme";
+    case "deleteWordBackward":
+      return "delete_backward:word";
+    case "deleteWordForward":
+      return "delete_forward:word";
+    case "deleteSoftLineBackward":
+    case "deleteHardLineBackward":
+      return "delete_backward:line";
+    case "deleteSoftLineForward":
+    case "deleteHardLineForward":
+      return "delete_forward:line";
+    default:
+      return null;
+  }
+}
+
 export function resolveWriterShortcut(event: KeyboardEvent): EngineCommandString | null {
   const mod = event.ctrlKey || event.metaKey;
   if (!mod) {
@@ -50,6 +61,14 @@ export function resolveWriterShortcut(event: KeyboardEvent): EngineCommandString
     return "toggle_inline_code";
   }
 
+  if (key === "x" && event.shiftKey) {
+    return "toggle_strikethrough";
+  }
+
+  if (key === "h" && event.shiftKey) {
+    return "toggle_highlight";
+  }
+
   if (key === "z" && event.shiftKey) {
     return "redo";
   }
@@ -80,61 +99,3 @@ export function resolveWriterShortcut(event: KeyboardEvent): EngineCommandString
 
   return null;
 }
-
-export function previousUtf8Boundary(markdown: string, utf8Offset: number): number {
-  let boundary = 0;
-  for (const character of markdown) {
-    const nextBoundary = boundary + encoder.encode(character).length;
-    if (nextBoundary >= utf8Offset) {
-      return boundary;
-    }
-    boundary = nextBoundary;
-  }
-  return boundary;
-}
-
-export function nextUtf8Boundary(markdown: string, utf8Offset: number): number {
-  let boundary = 0;
-  for (const character of markdown) {
-    boundary += encoder.encode(character).length;
-    if (boundary > utf8Offset) {
-      return boundary;
-    }
-  }
-  return boundary;
-}
-
-export function resolveDeleteRange(
-  markdown: string,
-  selection: WriterSelectionRange,
-  direction: "backward" | "forward"
-): { startUtf8: number; endUtf8: number } | null {
-  if (selection.start_utf8 !== selection.end_utf8) {
-    return {
-      startUtf8: selection.start_utf8,
-      endUtf8: selection.end_utf8
-    };
-  }
-
-  if (direction === "backward") {
-    const startUtf8 = previousUtf8Boundary(markdown, selection.start_utf8);
-    if (startUtf8 === selection.end_utf8) {
-      return null;
-    }
-
-    return {
-      startUtf8,
-      endUtf8: selection.end_utf8
-    };
-  }
-
-  const endUtf8 = nextUtf8Boundary(markdown, selection.end_utf8);
-  if (endUtf8 === selection.start_utf8) {
-    return null;
-  }
-
-  return {
-    startUtf8: selection.start_utf8,
-    endUtf8
-  };
-}
@@ -1,15 +1,41 @@
-import type { EngineCommandString, EngineSelection, EngineSnapshot } from "./types";
+import { applySnapshotPatch, patchedBlockIds } from "./patch";
+import type {
+  EngineCommandString,
+  EngineDiagnostic,
+  EngineLineCol,
+  EngineOffsetUnit,
+  EngineSelection,
+  EngineSelectionSet,
+  EngineSnapshot,
+  EngineSnapshotPatch
+} from "./types";
 import wasmUrl from "./pkg/fn_engine_wasm_bg.wasm?url";
 
 type WasmEngineHandle = {
   snapshot(): string;
+  patch(): string;
   markdown(): string;
+  diagnostics(): string;
   set_markdown(markdown: string): string;
+  set_markdown_patch(markdown: string): string;
+  set_document_path_patch(path: string | undefined): string;
+  wiki_link_targets(): string;
+  set_resolved_wiki_links_patch(targets: string | undefined): string;
   replace_text(start_utf8: number, end_utf8: number, insert: string): string;
+  replace_text_patch(start_utf8: number, end_utf8: number, insert: string): string;
+  replace_text_in_patch(start: number, end: number, insert: string, unit: EngineOffsetUnit): string;
   set_selection(anchor_utf8: number, head_utf8: number): string;
+  set_selection_patch(anchor_utf8: number, head_utf8: number): string;
+  set_selection_in_patch(anchor: number, head: number, unit: EngineOffsetUnit): string;
+  set_selections(selections: string): string;
+  set_selections_patch(selections: string): string;
   apply_command(command: string): string;
+  apply_command_patch(command: string): string;
   undo(): string;
   redo(): string;
+  convert_offset(offset: number, from: EngineOffsetUnit, to: EngineOffsetUnit): number;
+  line_col(offset: number, unit: EngineOffsetUnit): string;
+  offset_at(line: number, column: number, unit: EngineOffsetUnit): number;
 };
 
 type WasmModule = {
@@ -20,12 +46,34 @@ type WasmModule = {
 export type EngineApi = {
   snapshot: () => EngineSnapshot;
   markdown: () => string;
+  /** Undefined and unused footnotes, in document order. */
+  diagnostics: () => EngineDiagnostic[];
   setMarkdown: (markdown: string) => EngineSnapshot;
+  /** Sets the path relative image sources resolve against. */
+  setDocumentPath: (path: string | null) => EngineSnapshot;
+  /** The distinct wiki-link targets of the document, in document order. */
+  wikiLinkTargets: () => string[];
+  /** Sets the wiki-link targets that resolve to a note; `null` leaves links unflagged. */
+  setResolvedWikiLinks: (targets: string[] | null) => EngineSnapshot;
   replaceText: (startUtf8: number, endUtf8: number, insert: string) => EngineSnapshot;
+  /** Like `replaceText`, with offsets counted in `unit`. */
+  replaceTextIn: (
+    start: number,
+    end: number,
+    insert: string,
+    unit: EngineOffsetUnit
+  ) => EngineSnapshot;
   setSelection: (anchorUtf8: number, headUtf8: number) => EngineSnapshot;
+  setSelectionIn: (anchor: number, head: number, unit: EngineOffsetUnit) => EngineSnapshot;
+  setSelections: (selections: EngineSelectionSet) => EngineSnapshot;
   applyCommand: (command: EngineCommandString) => EngineSnapshot;
   undo: () => EngineSnapshot;
   redo: () => EngineSnapshot;
+  convertOffset: (offset: number, from: EngineOffsetUnit, to: EngineOffsetUnit) => number;
+  lineCol: (offset: number, unit: EngineOffsetUnit) => EngineLineCol;
+  offsetAt: (position: EngineLineCol, unit: EngineOffsetUnit) => number;
+  /** Block IDs added, changed or removed since the previous call. */
+  takeChangedBlocks: () => number[];
 };
 
 let wasmModulePromise: Promise<WasmModule> | null = null;
@@ -52,18 +100,49 @@ function parseSnapshot(payload: string): EngineSnapshot {
 export async function createEngine(markdown: string): Promise<EngineApi> {
   const module = await loadWasmModule();
   const handle = new module.EngineHandle(markdown);
+  let current = parseSnapshot(handle.snapshot());
+  let changedBlocks: number[] = [];
+
+  // After the first snapshot every update travels as a patch against the previous one.
+  const applyPatch = (payload: string) => {
+    const patch = JSON.parse(payload) as EngineSnapshotPatch;
+    current = applySnapshotPatch(current, patch);
+    changedBlocks.push(...patchedBlockIds(patch));
+    return current;
+  };
 
   return {
-    snapshot: () => parseSnapshot(handle.snapshot()),
+    snapshot: () => current,
     markdown: () => handle.markdown(),
-    setMarkdown: (nextMarkdown) => parseSnapshot(handle.set_markdown(nextMarkdown)),
+    diagnostics: () => JSON.parse(handle.diagnostics()) as EngineDiagnostic[],
+    setMarkdown: (nextMarkdown) => applyPatch(handle.set_markdown_patch(nextMarkdown)),
+    setDocumentPath: (path) => applyPatch(handle.set_document_path_patch(path ?? undefined)),
+    wikiLinkTargets: () => JSON.parse(handle.wiki_link_targets()) as string[],
+    setResolvedWikiLinks: (targets) =>
+      applyPatch(
+        handle.set_resolved_wiki_links_patch(targets ? JSON.stringify(targets) : undefined)
+      ),
     replaceText: (startUtf8, endUtf8, insert) =>
-      parseSnapshot(handle.replace_text(startUtf8, endUtf8, insert)),
+      applyPatch(handle.replace_text_patch(startUtf8, endUtf8, insert)),
+    replaceTextIn: (start, end, insert, unit) =>
+      applyPatch(handle.replace_text_in_patch(start, end, insert, unit)),
     setSelection: (anchorUtf8, headUtf8) =>
-      parseSnapshot(handle.set_selection(anchorUtf8, headUtf8)),
-    applyCommand: (command) => parseSnapshot(handle.apply_command(command)),
-    undo: () => parseSnapshot(handle.undo()),
-    redo: () => parseSnapshot(handle.redo())
+      applyPatch(handle.set_selection_patch(anchorUtf8, headUtf8)),
+    setSelectionIn: (anchor, head, unit) =>
+      applyPatch(handle.set_selection_in_patch(anchor, head, unit)),
+    setSelections: (selections) =>
+      applyPatch(handle.set_selections_patch(JSON.stringify(selections))),
+    applyCommand: (command) => applyPatch(handle.apply_command_patch(command)),
+    undo: () => applyPatch(handle.apply_command_patch("undo")),
+    redo: () => applyPatch(handle.apply_command_patch("redo")),
+    convertOffset: (offset, from, to) => handle.convert_offset(offset, from, to),
+    lineCol: (offset, unit) => JSON.parse(handle.line_col(offset, unit)) as EngineLineCol,
+    offsetAt: (position, unit) => handle.offset_at(position.line, position.column, unit),
+    takeChangedBlocks: () => {
+      const taken = changedBlocks;
+      changedBlocks = [];
+      return taken;
+    }
   };
 }
 
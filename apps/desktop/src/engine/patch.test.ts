@@ -0,0 +1,81 @@
+import { describe, expect, it } from "vitest";
+import { applySnapshotPatch, patchedBlockIds } from "./patch";
+import type { EngineSnapshot, RenderBlockVM } from "./types";
+
+function paragraph(nodeId: number, start: number, text: string): RenderBlockVM {
+  const end = start + new TextEncoder().encode(text).length;
+  return {
+    node_id: nodeId,
+    kind: "paragraph",
+    children: [
+      {
+        node_id: nodeId * 10,
+        kind: "text",
+        text,
+        range: { start_utf8: start, end_utf8: end },
+        children: [],
+        attrs: { start_utf8: start, end_utf8: end }
+      }
+    ],
+    attrs: { start_utf8: start, end_utf8: end, raw_markdown: text }
+  };
+}
+
+const base: EngineSnapshot = {
+  revision: 3,
+  markdown: "ü one\n\ntwo\n\nthree",
+  blocks: [paragraph(1, 0, "ü one"), paragraph(2, 8, "two"), paragraph(3, 13, "three")],
+  selection: { anchor_utf8: 6, head_utf8: 6 },
+  secondary_selections: []
+};
+
+describe("applySnapshotPatch", () => {
+  it("splices text, replaces changed blocks and shifts the rest", () => {
+    const next = applySnapshotPatch(base, {
+      base_revision: 3,
+      revision: 4,
+      changes: { edits: [{ range: { start_utf8: 6, end_utf8: 6 }, insert: "🙂" }] },
+      blocks: {
+        added: [],
+        changed: [paragraph(1, 0, "ü one🙂")],
+        removed: [],
+        order: null,
+        offset_shifts: [{ from_utf8: 8, delta_utf8: 4 }]
+      },
+      selection: { anchor_utf8: 10, head_utf8: 10 },
+      secondary_selections: [{ anchor_utf8: 14, head_utf8: 14 }]
+    });
+
+    expect(next.markdown).toBe("ü one🙂\n\ntwo\n\nthree");
+    expect(next.blocks).toEqual([
+      paragraph(1, 0, "ü one🙂"),
+      paragraph(2, 12, "two"),
+      paragraph(3, 17, "three")
+    ]);
+    expect(next.revision).toBe(4);
+    expect(next.secondary_selections).toEqual([{ anchor_utf8: 14, head_utf8: 14 }]);
+  });
+
+  it("reuses unmoved blocks and follows the new order", () => {
+    const patch = {
+      base_revision: 3,
+      revision: 4,
+      changes: { edits: [{ range: { start_utf8: 8, end_utf8: 13 }, insert: "" }] },
+      blocks: {
+        added: [paragraph(4, 8, "three")],
+        changed: [],
+        removed: [2, 3],
+        order: [1, 4],
+        offset_shifts: []
+      },
+      selection: { anchor_utf8: 8, head_utf8: 8 },
+      secondary_selections: []
+    };
+    const next = applySnapshotPatch(base, patch);
+
+    expect(next.markdown).toBe("ü one\n\nthree");
+    expect(next.blocks.map((block) => block.node_id)).toEqual([1, 4]);
+    expect(next.blocks[0]).toBe(base.blocks[0]);
+    expect(patchedBlockIds(patch)).toEqual([4, 2, 3]);
+  });
+});
@@ -0,0 +1,127 @@
+import type {
+  EngineSnapshot,
+  EngineSnapshotPatch,
+  EngineTextEdit,
+  RenderAttrs,
+  RenderBlockVM,
+  RenderInlineVM
+} from "./types";
+
+/**
+ * Rebuilds the current snapshot from the one the engine last published. Blocks the
+ * patch does not mention are reused as-is, or shallow-copied when they moved.
+ */
+export function applySnapshotPatch(
+  base: EngineSnapshot,
+  patch: EngineSnapshotPatch
+): EngineSnapshot {
+  const replaced = new Map<number, RenderBlockVM>();
+  for (const block of [...patch.blocks.added, ...patch.blocks.changed]) {
+    replaced.set(block.node_id, block);
+  }
+  const removed = new Set(patch.blocks.removed);
+
+  const kept = new Map<number, RenderBlockVM>();
+  for (const block of base.blocks) {
+    if (replaced.has(block.node_id) || removed.has(block.node_id)) {
+      continue;
+    }
+    kept.set(block.node_id, shiftBlock(block, shiftFor(patch, block.attrs.start_utf8 ?? 0)));
+  }
+
+  const order = patch.blocks.order ?? base.blocks.map((block) => block.node_id);
+  const blocks = order.flatMap((nodeId) => {
+    const block = kept.get(nodeId) ?? replaced.get(nodeId);
+    return block ? [block] : [];
+  });
+
+  return {
+    revision: patch.revision,
+    markdown: patch.changes.edits.reduceRight(spliceUtf8, base.markdown),
+    blocks,
+    selection: patch.selection,
+    secondary_selections: patch.secondary_selections
+  };
+}
+
+/** IDs of every block the patch added, changed or removed. */
+export function patchedBlockIds(patch: EngineSnapshotPatch): number[] {
+  return [
+    ...patch.blocks.added.map((block) => block.node_id),
+    ...patch.blocks.changed.map((block) => block.node_id),
+    ...patch.blocks.removed
+  ];
+}
+
+function shiftFor(patch: EngineSnapshotPatch, startUtf8: number): number {
+  let delta = 0;
+  for (const shift of patch.blocks.offset_shifts) {
+    if (shift.from_utf8 > startUtf8) {
+      break;
+    }
+    delta = shift.delta_utf8;
+  }
+  return delta;
+}
+
+function shiftAttrs(attrs: RenderAttrs, delta: number): RenderAttrs {
+  return {
+    ...attrs,
+    start_utf8: attrs.start_utf8 == null ? attrs.start_utf8 : attrs.start_utf8 + delta,
+    end_utf8: attrs.end_utf8 == null ? attrs.end_utf8 : attrs.end_utf8 + delta
+  };
+}
+
+function shiftInline(inline: RenderInlineVM, delta: number): RenderInlineVM {
+  return {
+    ...inline,
+    range: {
+      start_utf8: inline.range.start_utf8 + delta,
+      end_utf8: inline.range.end_utf8 + delta
+    },
+    children: inline.children.map((child) => shiftInline(child, delta)),
+    attrs: shiftAttrs(inline.attrs, delta)
+  };
+}
+
+function shiftBlock(block: RenderBlockVM, delta: number): RenderBlockVM {
+  if (delta === 0) {
+    return block;
+  }
+
+  return {
+    ...block,
+    children: block.children.map((child) => shiftInline(child, delta)),
+    attrs: shiftAttrs(block.attrs, delta)
+  };
+}
+
+function utf8Width(codeUnit: number, next: number): [bytes: number, units: number] {
+  if (codeUnit < 0x80) {
+    return [1, 1];
+  }
+  if (codeUnit < 0x800) {
+    return [2, 1];
+  }
+  if (codeUnit >= 0xd800 && codeUnit <= 0xdbff && next >= 0xdc00 && next <= 0xdfff) {
+    return [4, 2];
+  }
+  return [3, 1];
+}
+
+function codeUnitIndex(text: string, utf8Offset: number, fromIndex = 0, fromUtf8 = 0): number {
+  let index = fromIndex;
+  let consumed = fromUtf8;
+  while (consumed < utf8Offset && index < text.length) {
+    const [bytes, units] = utf8Width(text.charCodeAt(index), text.charCodeAt(index + 1));
+    consumed += bytes;
+    index += units;
+  }
+  return index;
+}
+
+function spliceUtf8(text: string, edit: EngineTextEdit): string {
+  const start = codeUnitIndex(text, edit.range.start_utf8);
+  const end = codeUnitIndex(text, edit.range.end_utf8, start, edit.range.start_utf8);
+  return text.slice(0, start) + edit.insert + text.slice(end);
+}
@@ -3,6 +3,18 @@ export type EngineSelection = {
   head_utf8: number;
 };
 
+export type EngineOffsetUnit = "utf8" | "utf16" | "char";
+
+export type EngineLineCol = {
+  line: number;
+  column: number;
+};
+
+export type EngineSelectionSet = {
+  primary: EngineSelection;
+  secondary: EngineSelection[];
+};
+
 export type RenderAttrs = {
   heading_level?: number | null;
   href?: string | null;
@@ -13,23 +25,65 @@ export type RenderAttrs = {
   end_utf8?: number | null;
   list_index?: number | null;
   raw_markdown?: string | null;
+  table_header?: boolean | null;
+  column_index?: number | null;
+  column_alignment?: TableAlignment | null;
+  checked?: boolean | null;
+  list_depth?: number | null;
+  quote_depth?: number | null;
+  src?: string | null;
+  alt?: string | null;
+  display_math?: boolean | null;
+  footnote_label?: string | null;
+  footnote_number?: number | null;
+  wiki_target?: string | null;
+  wiki_heading?: string | null;
+  unresolved?: boolean | null;
+  callout_kind?: CalloutKind | null;
+  syntax?: string | null;
 };
 
+export type CalloutKind = "note" | "tip" | "important" | "warning" | "caution";
+
+export type TableAlignment = "none" | "left" | "center" | "right";
+
 export type RenderBlockKind =
   | "paragraph"
   | "heading"
   | "blockquote_paragraph"
+  | "callout"
   | "bullet_list_item"
   | "ordered_list_item"
-  | "fenced_code";
+  | "fenced_code"
+  | "indented_code"
+  | "math_block"
+  | "thematic_break"
+  | "html_block"
+  | "front_matter"
+  | "table"
+  | "footnote_definition"
+  | "link_definition"
+  | "custom";
 
 export type RenderInlineKind =
   | "text"
   | "strong"
   | "emphasis"
+  | "strikethrough"
+  | "highlight"
+  | "subscript"
+  | "superscript"
   | "inline_code"
+  | "math"
+  | "footnote_reference"
+  | "wiki_link"
   | "link"
-  | "code_text";
+  | "image"
+  | "hard_break"
+  | "code_text"
+  | "table_row"
+  | "table_cell"
+  | "custom";
 
 export type RenderInlineVM = {
   node_id: number;
@@ -55,12 +109,58 @@ export type EngineSnapshot = {
   markdown: string;
   blocks: RenderBlockVM[];
   selection: EngineSelection;
+  secondary_selections: EngineSelection[];
+};
+
+export type EngineTextEdit = {
+  range: {
+    start_utf8: number;
+    end_utf8: number;
+  };
+  insert: string;
+};
+
+export type EngineChangeSet = {
+  edits: EngineTextEdit[];
 };
 
+export type EngineOffsetShift = {
+  from_utf8: number;
+  delta_utf8: number;
+};
+
+export type EngineBlockDiff = {
+  added: RenderBlockVM[];
+  changed: RenderBlockVM[];
+  removed: number[];
+  order: number[] | null;
+  offset_shifts: EngineOffsetShift[];
+};
+
+export type EngineSnapshotPatch = {
+  base_revision: number;
+  revision: number;
+  changes: EngineChangeSet;
+  blocks: EngineBlockDiff;
+  selection: EngineSelection;
+  secondary_selections: EngineSelection[];
+};
+
+export type EngineDiagnosticKind = "undefined_footnote" | "unused_footnote";
+
+export type EngineDiagnostic = {
+  range: {
+    start_utf8: number;
+    end_utf8: number;
+  };
+  kind: EngineDiagnosticKind;
+  message: string;
+};
+
+export type EngineMotionUnit = "grapheme" | "word" | "line" | "paragraph";
+
 export type WriterInputIntent =
   | "insert_text"
-  | "delete_backward"
-  | "delete_forward"
   | "insert_paragraph"
   | "insert_from_paste"
   | "history_undo"
@@ -70,9 +170,33 @@ export type EngineCommandString =
   | "toggle_strong"
   | "toggle_emphasis"
   | "toggle_inline_code"
+  | "toggle_strikethrough"
+  | "toggle_highlight"
   | "toggle_blockquote"
+  | "cycle_callout"
   | "toggle_bullet_list"
   | "toggle_ordered_list"
+  | "toggle_task"
+  | "indent_list_item"
+  | "outdent_list_item"
+  | "table_insert_row_above"
+  | "table_insert_row_below"
+  | "table_insert_column_left"
+  | "table_insert_column_right"
+  | "table_delete_row"
+  | "table_delete_column"
+  | "table_next_cell"
+  | "table_previous_cell"
+  | "add_next_occurrence"
+  | "select_all_occurrences"
+  | "go_to_footnote_definition"
+  | "go_to_footnote_reference"
+  | "insert_footnote"
+  | `delete_backward:${EngineMotionUnit}`
+  | `delete_forward:${EngineMotionUnit}`
+  | `extend_backward:${EngineMotionUnit}`
+  | `extend_forward:${EngineMotionUnit}`
+  | `table_align:${TableAlignment}`
   | "undo"
   | "redo"
   | `toggle_heading:${number}`
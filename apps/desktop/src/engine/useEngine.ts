@@ -2,6 +2,10 @@ import { useCallback, useEffect, useRef, useState } from "react";
 import { createEngine, type EngineApi } from "./adapter";
 import type { EngineCommandString, EngineSnapshot } from "./types";
 
+// How long the wiki-link targets must stay the same before they are resolved, so
+// typing inside `[[…]]` does not walk the workspace on every keystroke.
+const wikiLinkResolveDelayMs = 300;
+
 type UseEngineResult = {
   ready: boolean;
   error: string | null;
@@ -16,13 +20,25 @@ type UseEngineResult = {
 
 export function useEngine(
   markdown: string,
-  onMarkdownChange: (nextMarkdown: string) => void
+  onMarkdownChange: (nextMarkdown: string) => void,
+  onBlocksChange?: (nodeIds: number[]) => void,
+  documentPath: string | null = null,
+  resolveWikiLinks?: (targets: string[]) => Promise<string[]>
 ): UseEngineResult {
   const initialMarkdown = useRef(markdown);
   const [engine, setEngine] = useState<EngineApi | null>(null);
   const [snapshot, setSnapshot] = useState<EngineSnapshot | null>(null);
   const [error, setError] = useState<string | null>(null);
   const lastKnownMarkdown = useRef(markdown);
+  const onBlocksChangeRef = useRef(onBlocksChange);
+  onBlocksChangeRef.current = onBlocksChange;
+
+  const reportChangedBlocks = useCallback((instance: EngineApi) => {
+    const nodeIds = instance.takeChangedBlocks();
+    if (nodeIds.length > 0) {
+      onBlocksChangeRef.current?.(nodeIds);
+    }
+  }, []);
 
   useEffect(() => {
     let cancelled = false;
@@ -66,10 +82,81 @@ export function useEngine(
       lastKnownMarkdown.current = nextSnapshot.markdown;
       setSnapshot(nextSnapshot);
       setError(null);
+      reportChangedBlocks(engine);
     } catch (reason) {
       setError(String(reason));
     }
-  }, [engine, markdown]);
+  }, [engine, markdown, reportChangedBlocks]);
+
+  useEffect(() => {
+    if (!engine) {
+      return;
+    }
+
+    try {
+      setSnapshot(engine.setDocumentPath(documentPath));
+      reportChangedBlocks(engine);
+    } catch (reason) {
+      setError(String(reason));
+    }
+  }, [documentPath, engine, reportChangedBlocks]);
+
+  // The wiki-link targets last queued for the resolver, so they are only resolved again
+  // once the document links to other targets or the resolver changes.
+  const wikiLinkTargetsKey = useRef<string | null>(null);
+  const wikiLinkTimer = useRef<number | null>(null);
+
+  useEffect(() => {
+    wikiLinkTargetsKey.current = null;
+  }, [resolveWikiLinks]);
+
+  useEffect(
+    () => () => {
+      if (wikiLinkTimer.current !== null) {
+        window.clearTimeout(wikiLinkTimer.current);
+      }
+    },
+    []
+  );
+
+  useEffect(() => {
+    if (!engine || !snapshot) {
+      return;
+    }
+
+    const targets = engine.wikiLinkTargets();
+    const key = resolveWikiLinks ? JSON.stringify(targets) : "";
+    if (key === wikiLinkTargetsKey.current) {
+      return;
+    }
+    wikiLinkTargetsKey.current = key;
+    if (wikiLinkTimer.current !== null) {
+      window.clearTimeout(wikiLinkTimer.current);
+      wikiLinkTimer.current = null;
+    }
+
+    if (!resolveWikiLinks) {
+      setSnapshot(engine.setResolvedWikiLinks(null));
+      reportChangedBlocks(engine);
+      return;
+    }
+
+    wikiLinkTimer.current = window.setTimeout(() => {
+      wikiLinkTimer.current = null;
+      resolveWikiLinks(targets)
+        .then((resolved) => {
+          if (wikiLinkTargetsKey.current !== key) {
+            return;
+          }
+
+          setSnapshot(engine.setResolvedWikiLinks(resolved));
+          reportChangedBlocks(engine);
+        })
+        .catch((reason) => {
+          setError(String(reason));
+        });
+    }, wikiLinkResolveDelayMs);
+  }, [engine, snapshot, resolveWikiLinks, reportChangedBlocks]);
 
   const publish = useCallback(
     (nextSnapshot: EngineSnapshot | null) => {
@@ -95,13 +182,15 @@ export function useEngine(
       }
 
       try {
-        return publish(executor(engine));
+        const nextSnapshot = publish(executor(engine));
+        reportChangedBlocks(engine);
+        return nextSnapshot;
       } catch (reason) {
         setError(String(reason));
         return null;
       }
     },
-    [engine, publish]
+    [engine, publish, reportChangedBlocks]
   );
 
   const setMarkdown = useCallback(
@@ -118,13 +207,14 @@ export function useEngine(
         if (nextSnapshot.markdown !== markdown) {
           onMarkdownChange(nextSnapshot.markdown);
         }
+        reportChangedBlocks(engine);
         return nextSnapshot;
       } catch (reason) {
         setError(String(reason));
         return null;
       }
     },
-    [engine, markdown, onMarkdownChange]
+    [engine, markdown, onMarkdownChange, reportChangedBlocks]
   );
 
   return {
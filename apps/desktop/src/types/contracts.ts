@@ -46,6 +46,17 @@ export type ListWorkspaceEntriesResponse = {
   entries: WorkspaceEntry[];
 };
 
+export type WikiLinkResolution = {
+  target: string;
+  relative_path: string | null;
+  absolute_path: string | null;
+};
+
+export type ResolveWikiLinksResponse = {
+  root_path: string;
+  links: WikiLinkResolution[];
+};
+
 export type AppConfig = {
   autosave_ms: number;
   theme: string;
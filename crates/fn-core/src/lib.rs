@@ -62,3 +62,16 @@ pub struct ListWorkspaceEntriesResponse {
     pub current_relative_path: String,
     pub entries: Vec<WorkspaceEntry>,
 }
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct WikiLinkResolution {
+    pub target: String,
+    pub relative_path: Option<String>,
+    pub absolute_path: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct ResolveWikiLinksResponse {
+    pub root_path: String,
+    pub links: Vec<WikiLinkResolution>,
+}
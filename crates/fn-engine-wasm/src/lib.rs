@@ -1,6 +1,18 @@
-use fn_engine::{transaction::EngineCommand, Engine};
+use fn_engine::{
+    selection::{LineCol, OffsetUnit, SelectionSet},
+    transaction::EngineCommand,
+    Engine,
+};
+use serde::Serialize;
+use std::collections::HashSet;
 use wasm_bindgen::prelude::*;
 
+#[wasm_bindgen]
+extern "C" {
+    #[wasm_bindgen(js_namespace = Date, js_name = now)]
+    fn date_now() -> f64;
+}
+
 #[wasm_bindgen]
 pub struct EngineHandle {
     engine: Engine,
@@ -13,18 +25,65 @@ impl EngineHandle {
         EngineHandle { engine: Engine::new(markdown) }
     }
 
-    pub fn snapshot(&self) -> Result<String, JsValue> {
-        serde_json::to_string(&self.engine.snapshot())
-            .map_err(|error| JsValue::from_str(&error.to_string()))
+    /// Full snapshot JSON. It becomes the base that the `*_patch` methods diff against.
+    pub fn snapshot(&mut self) -> Result<String, JsValue> {
+        to_json(&self.engine.publish_snapshot())
+    }
+
+    /// Patch JSON against the last snapshot or patch this handle returned.
+    pub fn patch(&mut self) -> Result<String, JsValue> {
+        to_json(&self.engine.publish_patch())
     }
 
     pub fn markdown(&self) -> String {
         self.engine.markdown()
     }
 
+    /// JSON array of the document's diagnostics, such as undefined footnotes.
+    pub fn diagnostics(&self) -> Result<String, JsValue> {
+        to_json(&self.engine.diagnostics())
+    }
+
     pub fn set_markdown(&mut self, markdown: String) -> Result<String, JsValue> {
-        let snapshot = self.engine.set_markdown(markdown);
-        serde_json::to_string(&snapshot).map_err(|error| JsValue::from_str(&error.to_string()))
+        self.engine.set_markdown(markdown);
+        self.snapshot()
+    }
+
+    pub fn set_markdown_patch(&mut self, markdown: String) -> Result<String, JsValue> {
+        self.engine.set_markdown(markdown);
+        self.patch()
+    }
+
+    /// Sets the path relative image sources resolve against; `None` leaves them as
+    /// written.
+    pub fn set_document_path(&mut self, path: Option<String>) -> Result<String, JsValue> {
+        self.engine.set_document_path(path);
+        self.snapshot()
+    }
+
+    pub fn set_document_path_patch(&mut self, path: Option<String>) -> Result<String, JsValue> {
+        self.engine.set_document_path(path);
+        self.patch()
+    }
+
+    /// JSON array of the document's distinct wiki-link targets, for the host to resolve.
+    pub fn wiki_link_targets(&self) -> Result<String, JsValue> {
+        to_json(&self.engine.wiki_link_targets())
+    }
+
+    /// Sets the wiki-link targets, a JSON array, that resolve to a note; links to other
+    /// targets are flagged as unresolved. `None` leaves every link unflagged.
+    pub fn set_resolved_wiki_links(&mut self, targets: Option<String>) -> Result<String, JsValue> {
+        self.engine.set_resolved_wiki_links(targets.as_deref().map(parse_targets).transpose()?);
+        self.snapshot()
+    }
+
+    pub fn set_resolved_wiki_links_patch(
+        &mut self,
+        targets: Option<String>,
+    ) -> Result<String, JsValue> {
+        self.engine.set_resolved_wiki_links(targets.as_deref().map(parse_targets).transpose()?);
+        self.patch()
     }
 
     pub fn replace_text(
@@ -34,42 +93,180 @@ impl EngineHandle {
         insert: String,
     ) -> Result<String, JsValue> {
         self.engine
-            .replace_text(start_utf8, end_utf8, insert)
-            .map_err(|error| JsValue::from_str(&error.to_string()))?;
+            .replace_text_at(start_utf8, end_utf8, insert, date_now() as u64)
+            .map_err(to_js_error)?;
         self.snapshot()
     }
 
-    pub fn set_selection(&mut self, anchor_utf8: u32, head_utf8: u32) -> Result<String, JsValue> {
+    pub fn replace_text_patch(
+        &mut self,
+        start_utf8: u32,
+        end_utf8: u32,
+        insert: String,
+    ) -> Result<String, JsValue> {
         self.engine
-            .set_selection(anchor_utf8, head_utf8)
-            .map_err(|error| JsValue::from_str(&error.to_string()))?;
+            .replace_text_at(start_utf8, end_utf8, insert, date_now() as u64)
+            .map_err(to_js_error)?;
+        self.patch()
+    }
+
+    /// Like `replace_text`, with offsets counted in `unit` (`utf8`, `utf16` or `char`).
+    pub fn replace_text_in(
+        &mut self,
+        start: u32,
+        end: u32,
+        insert: String,
+        unit: String,
+    ) -> Result<String, JsValue> {
+        self.replace_in(start, end, insert, &unit)?;
+        self.snapshot()
+    }
+
+    pub fn replace_text_in_patch(
+        &mut self,
+        start: u32,
+        end: u32,
+        insert: String,
+        unit: String,
+    ) -> Result<String, JsValue> {
+        self.replace_in(start, end, insert, &unit)?;
+        self.patch()
+    }
+
+    pub fn set_selection(&mut self, anchor_utf8: u32, head_utf8: u32) -> Result<String, JsValue> {
+        self.engine.set_selection(anchor_utf8, head_utf8).map_err(to_js_error)?;
+        self.snapshot()
+    }
+
+    pub fn set_selection_patch(
+        &mut self,
+        anchor_utf8: u32,
+        head_utf8: u32,
+    ) -> Result<String, JsValue> {
+        self.engine.set_selection(anchor_utf8, head_utf8).map_err(to_js_error)?;
+        self.patch()
+    }
+
+    /// Like `set_selection`, with offsets counted in `unit`.
+    pub fn set_selection_in(
+        &mut self,
+        anchor: u32,
+        head: u32,
+        unit: String,
+    ) -> Result<String, JsValue> {
+        self.select_in(anchor, head, &unit)?;
         self.snapshot()
     }
 
+    pub fn set_selection_in_patch(
+        &mut self,
+        anchor: u32,
+        head: u32,
+        unit: String,
+    ) -> Result<String, JsValue> {
+        self.select_in(anchor, head, &unit)?;
+        self.patch()
+    }
+
+    /// Takes a `SelectionSet` as JSON: `{ "primary": {..}, "secondary": [..] }`.
+    pub fn set_selections(&mut self, selections: String) -> Result<String, JsValue> {
+        self.engine.set_selections(parse_selections(&selections)?).map_err(to_js_error)?;
+        self.snapshot()
+    }
+
+    pub fn set_selections_patch(&mut self, selections: String) -> Result<String, JsValue> {
+        self.engine.set_selections(parse_selections(&selections)?).map_err(to_js_error)?;
+        self.patch()
+    }
+
     pub fn apply_command(&mut self, command: String) -> Result<String, JsValue> {
-        let command = EngineCommand::parse(&command)
-            .ok_or_else(|| JsValue::from_str("unsupported command"))?;
-        self.engine
-            .apply_command(command)
-            .map_err(|error| JsValue::from_str(&error.to_string()))?;
+        self.apply(&command)?;
         self.snapshot()
     }
 
+    pub fn apply_command_patch(&mut self, command: String) -> Result<String, JsValue> {
+        self.apply(&command)?;
+        self.patch()
+    }
+
     pub fn undo(&mut self) -> Result<String, JsValue> {
-        self.engine.undo().map_err(|error| JsValue::from_str(&error.to_string())).and_then(
-            |snapshot| {
-                serde_json::to_string(&snapshot)
-                    .map_err(|error| JsValue::from_str(&error.to_string()))
-            },
-        )
+        self.engine.undo().map_err(to_js_error)?;
+        self.snapshot()
     }
 
     pub fn redo(&mut self) -> Result<String, JsValue> {
-        self.engine.redo().map_err(|error| JsValue::from_str(&error.to_string())).and_then(
-            |snapshot| {
-                serde_json::to_string(&snapshot)
-                    .map_err(|error| JsValue::from_str(&error.to_string()))
-            },
-        )
+        self.engine.redo().map_err(to_js_error)?;
+        self.snapshot()
+    }
+
+    pub fn convert_offset(&self, offset: u32, from: String, to: String) -> Result<u32, JsValue> {
+        self.engine
+            .convert_offset(offset, parse_unit(&from)?, parse_unit(&to)?)
+            .map_err(to_js_error)
+    }
+
+    /// `{ "line": .., "column": .. }` JSON for `offset`, both counted in `unit`.
+    pub fn line_col(&self, offset: u32, unit: String) -> Result<String, JsValue> {
+        to_json(&self.engine.line_col(offset, parse_unit(&unit)?).map_err(to_js_error)?)
+    }
+
+    pub fn offset_at(&self, line: u32, column: u32, unit: String) -> Result<u32, JsValue> {
+        Ok(self.engine.offset_at(LineCol { line, column }, parse_unit(&unit)?))
     }
 }
+
+impl EngineHandle {
+    fn apply(&mut self, command: &str) -> Result<(), JsValue> {
+        let command = parse_command(command)?;
+        self.engine.apply_command_at(command, date_now() as u64).map_err(to_js_error)?;
+        Ok(())
+    }
+
+    fn utf8_offset(&self, offset: u32, unit: &str) -> Result<u32, JsValue> {
+        self.engine.convert_offset(offset, parse_unit(unit)?, OffsetUnit::Utf8).map_err(to_js_error)
+    }
+
+    fn replace_in(
+        &mut self,
+        start: u32,
+        end: u32,
+        insert: String,
+        unit: &str,
+    ) -> Result<(), JsValue> {
+        let start = self.utf8_offset(start, unit)?;
+        let end = self.utf8_offset(end, unit)?;
+        self.engine.replace_text_at(start, end, insert, date_now() as u64).map_err(to_js_error)?;
+        Ok(())
+    }
+
+    fn select_in(&mut self, anchor: u32, head: u32, unit: &str) -> Result<(), JsValue> {
+        let anchor = self.utf8_offset(anchor, unit)?;
+        let head = self.utf8_offset(head, unit)?;
+        self.engine.set_selection(anchor, head).map_err(to_js_error)?;
+        Ok(())
+    }
+}
+
+fn parse_command(command: &str) -> Result<EngineCommand, JsValue> {
+    EngineCommand::parse(command).ok_or_else(|| JsValue::from_str("unsupported command"))
+}
+
+fn parse_unit(unit: &str) -> Result<OffsetUnit, JsValue> {
+    OffsetUnit::parse(unit).ok_or_else(|| JsValue::from_str("unsupported offset unit"))
+}
+
+fn parse_selections(selections: &str) -> Result<SelectionSet, JsValue> {
+    serde_json::from_str(selections).map_err(to_js_error)
+}
+
+fn parse_targets(targets: &str) -> Result<HashSet<String>, JsValue> {
+    serde_json::from_str(targets).map_err(to_js_error)
+}
+
+fn to_json(value: &impl Serialize) -> Result<String, JsValue> {
+    serde_json::to_string(value).map_err(|error| JsValue::from_str(&error.to_string()))
+}
+
+fn to_js_error(error: impl ToString) -> JsValue {
+    JsValue::from_str(&error.to_string())
+}
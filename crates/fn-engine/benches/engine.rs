@@ -0,0 +1,104 @@
+//! Times `Engine` typing and commands on a large document, which is where whole-text
+//! copies in the edit and command paths show up.
+
+use criterion::{black_box, criterion_group, criterion_main, BatchSize, Criterion};
+use fn_engine::{transaction::EngineCommand, Engine};
+
+const DOC_BYTES: usize = 1024 * 1024;
+const EDITS: usize = 200;
+
+fn document() -> String {
+    let section = "## Section heading\n\nSome paragraph text with **strong** and `code` spans \
+                   that wraps onto a fairly long line.\n\n- item one\n- [ ] task two\n\n\
+                   > quoted line\n\n";
+    section.repeat(DOC_BYTES / section.len())
+}
+
+/// Deterministic ASCII-only offsets so every position is a char boundary.
+fn offsets(len: usize) -> Vec<u32> {
+    let mut seed = 0x2545_f491_4f6c_dd1d_u64;
+    (0..EDITS)
+        .map(|_| {
+            seed ^= seed << 13;
+            seed ^= seed >> 7;
+            seed ^= seed << 17;
+            (seed % len as u64) as u32
+        })
+        .collect()
+}
+
+fn bench_typing(c: &mut Criterion) {
+    let markdown = document();
+    let offsets = offsets(markdown.len());
+    let engine = Engine::new(markdown);
+    let mut group = c.benchmark_group("engine_typing_200_chars");
+    group.sample_size(10);
+
+    group.bench_function("replace_text", |b| {
+        b.iter_batched(
+            || engine.clone(),
+            |mut engine| {
+                for offset in &offsets {
+                    engine.replace_text(*offset, *offset, "x").unwrap();
+                }
+                engine
+            },
+            BatchSize::LargeInput,
+        )
+    });
+    group.bench_function("replace_text_and_publish_patch", |b| {
+        b.iter_batched(
+            || {
+                let mut engine = engine.clone();
+                engine.publish_snapshot();
+                engine
+            },
+            |mut engine| {
+                for offset in &offsets {
+                    engine.replace_text(*offset, *offset, "x").unwrap();
+                    black_box(engine.publish_patch());
+                }
+                engine
+            },
+            BatchSize::LargeInput,
+        )
+    });
+    group.finish();
+}
+
+fn bench_commands(c: &mut Criterion) {
+    let markdown = document();
+    let offsets = offsets(markdown.len() - 8);
+    let engine = Engine::new(markdown);
+    let commands = [
+        EngineCommand::ToggleStrong,
+        EngineCommand::ToggleStrikethrough,
+        EngineCommand::ToggleBlockquote,
+        EngineCommand::ToggleTask,
+        EngineCommand::IndentListItem,
+        EngineCommand::AddNextOccurrence,
+    ];
+    let mut group = c.benchmark_group("engine_commands_200");
+    group.sample_size(10);
+
+    for command in commands {
+        group.bench_function(format!("{command:?}"), |b| {
+            b.iter_batched(
+                || engine.clone(),
+                |mut engine| {
+                    for offset in &offsets {
+                        engine.set_selection(*offset, *offset + 4).unwrap();
+                        // Commands that do not apply at a position fail without editing.
+                        black_box(engine.apply_command(command.clone()).ok());
+                    }
+                    engine
+                },
+                BatchSize::LargeInput,
+            )
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, bench_typing, bench_commands);
+criterion_main!(benches);
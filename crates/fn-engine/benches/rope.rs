@@ -0,0 +1,118 @@
+//! Compares `RopeText` with the plain `String` storage it replaced, on a document
+//! the size of the large acceptance-baseline files.
+
+use criterion::{black_box, criterion_group, criterion_main, BatchSize, Criterion};
+use fn_engine::{rope::RopeText, selection::TextRange};
+
+const DOC_BYTES: usize = 8 * 1024 * 1024;
+const EDITS: usize = 200;
+
+/// The previous `RopeText`: every edit shifts the whole tail and every clone copies
+/// the full buffer.
+#[derive(Clone)]
+struct StringText(String);
+
+impl StringText {
+    fn replace_range(&mut self, range: TextRange, insert: &str) {
+        self.0.replace_range(range.start_utf8 as usize..range.end_utf8 as usize, insert);
+    }
+
+    fn line_bounds(&self, offset: usize) -> (usize, usize) {
+        let start = self.0[..offset].rfind('\n').map(|index| index + 1).unwrap_or(0);
+        let end = self.0[offset..].find('\n').map(|index| offset + index).unwrap_or(self.0.len());
+        (start, end)
+    }
+}
+
+fn document() -> String {
+    let section = "## Section heading\n\nSome paragraph text with **strong** and `code` spans \
+                   that wraps onto a fairly long line.\n\n- item one\n- item two\n\n";
+    section.repeat(DOC_BYTES / section.len())
+}
+
+/// Deterministic ASCII-only offsets so every position is a char boundary.
+fn offsets(len: usize) -> Vec<u32> {
+    let mut seed = 0x2545_f491_4f6c_dd1d_u64;
+    (0..EDITS)
+        .map(|_| {
+            seed ^= seed << 13;
+            seed ^= seed >> 7;
+            seed ^= seed << 17;
+            (seed % len as u64) as u32
+        })
+        .collect()
+}
+
+fn bench_typing(c: &mut Criterion) {
+    let markdown = document();
+    let offsets = offsets(markdown.len());
+    let mut group = c.benchmark_group("typing_200_chars");
+    group.sample_size(10);
+
+    group.bench_function("rope", |b| {
+        b.iter_batched(
+            || RopeText::new(markdown.clone()),
+            |mut text| {
+                for offset in &offsets {
+                    text.replace_range(TextRange::new(*offset, *offset), "x").unwrap();
+                }
+                text
+            },
+            BatchSize::LargeInput,
+        )
+    });
+    group.bench_function("string", |b| {
+        b.iter_batched(
+            || StringText(markdown.clone()),
+            |mut text| {
+                for offset in &offsets {
+                    text.replace_range(TextRange::new(*offset, *offset), "x");
+                }
+                text
+            },
+            BatchSize::LargeInput,
+        )
+    });
+    group.finish();
+}
+
+fn bench_history_snapshot(c: &mut Criterion) {
+    let markdown = document();
+    let rope = RopeText::new(markdown.clone());
+    let string = StringText(markdown);
+    let mut group = c.benchmark_group("history_snapshot");
+    group.sample_size(20);
+
+    group.bench_function("rope", |b| b.iter(|| black_box(rope.clone())));
+    group.bench_function("string", |b| b.iter(|| black_box(string.clone())));
+    group.finish();
+}
+
+fn bench_line_slice(c: &mut Criterion) {
+    let markdown = document();
+    let offsets = offsets(markdown.len());
+    let rope = RopeText::new(markdown.clone());
+    let string = StringText(markdown);
+    let mut group = c.benchmark_group("line_slice_200");
+
+    group.bench_function("rope", |b| {
+        b.iter(|| {
+            for offset in &offsets {
+                let (start, end) = rope.line_bounds(*offset as usize);
+                black_box(rope.slice(TextRange::new(start as u32, end as u32)).unwrap());
+            }
+        })
+    });
+    group.bench_function("string", |b| {
+        b.iter(|| {
+            for offset in &offsets {
+                let (start, end) = string.line_bounds(*offset as usize);
+                black_box(&string.0[start..end]);
+            }
+        })
+    });
+    group.finish();
+}
+
+criterion_group!(benches, bench_typing, bench_history_snapshot, bench_line_slice);
+criterion_main!(benches);
@@ -1,3 +1,5 @@
+use std::collections::BTreeMap;
+
 use serde::{Deserialize, Serialize};
 
 use crate::selection::TextRange;
@@ -13,18 +15,178 @@ pub struct BlockNode {
 
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
 pub enum BlockKind {
-    Paragraph { inlines: Vec<InlineNode> },
-    Heading { level: u8, inlines: Vec<InlineNode> },
-    Blockquote { children: Vec<BlockNode> },
-    BulletList { items: Vec<ListItemNode> },
-    OrderedList { start: u32, items: Vec<ListItemNode> },
-    FencedCode { language: Option<String>, text_range: TextRange, code: String },
+    Paragraph {
+        inlines: Vec<InlineNode>,
+    },
+    /// Front matter at the start of the document: the text between its delimiter
+    /// lines and the top-level keys read from it.
+    FrontMatter {
+        format: FrontMatterFormat,
+        raw: String,
+        metadata: BTreeMap<String, MetadataValue>,
+    },
+    Heading {
+        level: u8,
+        inlines: Vec<InlineNode>,
+    },
+    Blockquote {
+        children: Vec<BlockNode>,
+    },
+    /// A blockquote whose first line is a GitHub alert marker such as `[!NOTE]`,
+    /// optionally followed by a title. `children` are the quote's other blocks.
+    Callout {
+        kind: CalloutKind,
+        title: Option<String>,
+        children: Vec<BlockNode>,
+    },
+    BulletList {
+        items: Vec<ListItemNode>,
+    },
+    OrderedList {
+        start: u32,
+        items: Vec<ListItemNode>,
+    },
+    /// A fenced code block. `code` is its content with the fence's indentation
+    /// removed from each line; `text_range` covers the content lines in the source.
+    FencedCode {
+        language: Option<String>,
+        text_range: TextRange,
+        code: String,
+    },
+    /// Lines indented by four or more columns; `code` has that indentation removed.
+    IndentedCode {
+        text_range: TextRange,
+        code: String,
+    },
+    /// TeX display math between `$$` lines. `text_range` covers the TeX in the source.
+    MathBlock {
+        text_range: TextRange,
+        tex: String,
+    },
+    /// `***`, `---` or `___` on a line of its own.
+    ThematicBreak,
+    /// Raw HTML, kept as its source lines.
+    HtmlBlock {
+        html: String,
+    },
+    Table {
+        alignments: Vec<TableAlignment>,
+        header: TableRowNode,
+        rows: Vec<TableRowNode>,
+    },
+    /// `[^label]: text`, whose children are the footnote's content.
+    FootnoteDefinition {
+        label: String,
+        children: Vec<BlockNode>,
+    },
+    /// `[label]: href "title"`, which reference links resolve against.
+    LinkDefinition {
+        label: String,
+        href: String,
+        title: Option<String>,
+    },
+    /// A block from the syntax plugin registered as `name`.
+    Custom {
+        name: String,
+        inlines: Vec<InlineNode>,
+    },
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, Hash)]
+#[serde(rename_all = "snake_case")]
+pub enum FrontMatterFormat {
+    /// Between `---` lines.
+    Yaml,
+    /// Between `+++` lines.
+    Toml,
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, Hash)]
+#[serde(rename_all = "snake_case")]
+pub enum CalloutKind {
+    Note,
+    Tip,
+    Important,
+    Warning,
+    Caution,
+}
+
+impl CalloutKind {
+    /// Every kind, in the order `cycle_callout` steps through them.
+    pub const ALL: [Self; 5] =
+        [Self::Note, Self::Tip, Self::Important, Self::Warning, Self::Caution];
+
+    /// The kind written as `name` in a marker, ignoring case.
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|kind| kind.name().eq_ignore_ascii_case(name))
+    }
+
+    /// The name as written in a marker, such as `NOTE`.
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Note => "NOTE",
+            Self::Tip => "TIP",
+            Self::Important => "IMPORTANT",
+            Self::Warning => "WARNING",
+            Self::Caution => "CAUTION",
+        }
+    }
+
+    /// The title shown when the marker has none, such as `Note`.
+    pub fn label(self) -> &'static str {
+        match self {
+            Self::Note => "Note",
+            Self::Tip => "Tip",
+            Self::Important => "Important",
+            Self::Warning => "Warning",
+            Self::Caution => "Caution",
+        }
+    }
+
+    /// The kind after this one, wrapping from the last kind to the first.
+    pub fn next(self) -> Self {
+        let index = Self::ALL.iter().position(|kind| *kind == self).unwrap_or_default();
+        Self::ALL[(index + 1) % Self::ALL.len()]
+    }
+}
+
+/// A front matter value: a scalar as written, or a list of scalars.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq, Hash)]
+#[serde(untagged)]
+pub enum MetadataValue {
+    Text(String),
+    List(Vec<String>),
+}
+
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum TableAlignment {
+    #[default]
+    None,
+    Left,
+    Center,
+    Right,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct TableRowNode {
+    pub node_id: NodeId,
+    pub range: TextRange,
+    pub cells: Vec<TableCellNode>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct TableCellNode {
+    pub node_id: NodeId,
+    pub range: TextRange,
+    pub inlines: Vec<InlineNode>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
 pub struct ListItemNode {
     pub node_id: NodeId,
     pub range: TextRange,
+    pub checked: Option<bool>,
     pub children: Vec<BlockNode>,
 }
 
@@ -37,9 +199,69 @@ pub struct InlineNode {
 
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
 pub enum InlineKind {
-    Text { value: String },
-    Strong { children: Vec<InlineNode> },
-    Emphasis { children: Vec<InlineNode> },
-    InlineCode { value: String },
-    Link { href: String, title: Option<String>, children: Vec<InlineNode> },
+    Text {
+        value: String,
+    },
+    Strong {
+        children: Vec<InlineNode>,
+    },
+    Emphasis {
+        children: Vec<InlineNode>,
+    },
+    /// `~~text~~`.
+    Strikethrough {
+        children: Vec<InlineNode>,
+    },
+    /// `==text==`.
+    Highlight {
+        children: Vec<InlineNode>,
+    },
+    /// `~text~`.
+    Subscript {
+        children: Vec<InlineNode>,
+    },
+    /// `^text^`.
+    Superscript {
+        children: Vec<InlineNode>,
+    },
+    InlineCode {
+        value: String,
+    },
+    /// TeX math between `$` or, for display math, `$$` delimiters. The node's range
+    /// covers the delimiters.
+    Math {
+        display: bool,
+        tex: String,
+    },
+    /// `[^label]`. `definition` is the node ID of the footnote definition it refers
+    /// to, if the document has one.
+    FootnoteReference {
+        label: String,
+        definition: Option<NodeId>,
+    },
+    /// `[[target#heading|alias]]`, naming another note of the workspace. `target` is
+    /// empty for a heading of the same note.
+    WikiLink {
+        target: String,
+        alias: Option<String>,
+        heading: Option<String>,
+    },
+    /// A line break kept in the output: two spaces or a backslash at the line end.
+    HardBreak,
+    Link {
+        href: String,
+        title: Option<String>,
+        children: Vec<InlineNode>,
+    },
+    /// The children are the alt text.
+    Image {
+        src: String,
+        title: Option<String>,
+        children: Vec<InlineNode>,
+    },
+    /// A span from the syntax plugin registered as `name`.
+    Custom {
+        name: String,
+        value: String,
+    },
 }
@@ -0,0 +1,355 @@
+use serde::{Deserialize, Serialize};
+
+use crate::{rope::RopeText, selection::TextRange, transaction::TextEdit, EngineError, Result};
+
+/// Which side of an insertion a mapped position sticks to.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum Assoc {
+    Before,
+    After,
+}
+
+/// Replacements sorted by position, neither overlapping nor touching, all in the
+/// coordinates of the text before the change.
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq, Eq)]
+pub struct ChangeSet {
+    edits: Vec<TextEdit>,
+}
+
+/// Retain/delete/insert form used while composing.
+#[derive(Debug, Clone)]
+enum Op {
+    Retain(usize),
+    Delete(usize),
+    Insert(String),
+}
+
+impl ChangeSet {
+    /// Builds a change set from replacements in old coordinates. They must be sorted
+    /// and must not overlap; touching ones are merged.
+    pub fn new(edits: impl IntoIterator<Item = TextEdit>) -> Result<Self> {
+        let mut changes = Self::default();
+        for edit in edits {
+            let TextRange { start_utf8: start, end_utf8: end } = edit.range;
+            let overlaps = changes.edits.last().is_some_and(|last| last.range.end_utf8 > start);
+            if start > end || overlaps {
+                return Err(EngineError::InvalidUtf8Range {
+                    start: start as usize,
+                    end: end as usize,
+                });
+            }
+            changes.push(edit);
+        }
+        Ok(changes)
+    }
+
+    pub fn single(range: TextRange, insert: impl Into<String>) -> Self {
+        let mut changes = Self::default();
+        changes.push(TextEdit { range, insert: insert.into() });
+        changes
+    }
+
+    pub fn edits(&self) -> &[TextEdit] {
+        &self.edits
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.edits.is_empty()
+    }
+
+    /// Change in text length, in bytes.
+    pub fn len_delta(&self) -> i64 {
+        self.edits.iter().map(|edit| edit.insert.len() as i64 - i64::from(edit.range.len())).sum()
+    }
+
+    pub fn apply(&self, text: &mut RopeText) -> Result<()> {
+        for edit in self.edits.iter().rev() {
+            text.replace_range(edit.range, &edit.insert)?;
+        }
+        Ok(())
+    }
+
+    /// The change set that turns the changed text back into `original`.
+    pub fn invert(&self, original: &RopeText) -> Result<Self> {
+        let mut delta = 0_i64;
+        let mut inverse = Self::default();
+        for edit in &self.edits {
+            let start = (i64::from(edit.range.start_utf8) + delta) as u32;
+            inverse.push(TextEdit {
+                range: TextRange::new(start, start + edit.insert.len() as u32),
+                insert: original.slice(edit.range)?.into_owned(),
+            });
+            delta += edit.insert.len() as i64 - i64::from(edit.range.len());
+        }
+        Ok(inverse)
+    }
+
+    /// `self` followed by `next`, whose offsets refer to the text after `self`.
+    pub fn compose(&self, next: &Self) -> Self {
+        let mut first = self.ops().into_iter();
+        let mut second = next.ops().into_iter();
+        let mut a = first.next();
+        let mut b = second.next();
+        let mut out = Vec::new();
+
+        loop {
+            match (a.take(), b.take()) {
+                (None, None) => break,
+                (Some(Op::Delete(len)), rest) => {
+                    out.push(Op::Delete(len));
+                    a = first.next();
+                    b = rest;
+                }
+                (rest, Some(Op::Insert(text))) => {
+                    out.push(Op::Insert(text));
+                    a = rest;
+                    b = second.next();
+                }
+                (left, right) => {
+                    // Past the end of either list the text is retained unchanged.
+                    let left = left.unwrap_or(Op::Retain(usize::MAX));
+                    let right = right.unwrap_or(Op::Retain(usize::MAX));
+                    let len = left.len().min(right.len());
+                    if len == usize::MAX {
+                        break;
+                    }
+                    match (&left, &right) {
+                        (Op::Retain(_), Op::Retain(_)) => out.push(Op::Retain(len)),
+                        (Op::Retain(_), Op::Delete(_)) => out.push(Op::Delete(len)),
+                        (Op::Insert(text), Op::Retain(_)) => {
+                            out.push(Op::Insert(text[..len].to_string()))
+                        }
+                        _ => {}
+                    }
+                    a = left.split_off(len).or_else(|| first.next());
+                    b = right.split_off(len).or_else(|| second.next());
+                }
+            }
+        }
+
+        Self::from_ops(out)
+    }
+
+    /// Maps an offset in the old text to the new text. Offsets inside a replaced
+    /// range, or exactly at a pure insertion, go to the side `assoc` names.
+    pub fn map_pos(&self, pos: u32, assoc: Assoc) -> u32 {
+        let mut delta = 0_i64;
+        for edit in &self.edits {
+            let TextRange { start_utf8: start, end_utf8: end } = edit.range;
+            if start > pos || (start == pos && end > pos) {
+                break;
+            }
+            let inserted = edit.insert.len() as i64;
+            if end < pos {
+                delta += inserted - i64::from(end - start);
+                continue;
+            }
+
+            let new_start = i64::from(start) + delta;
+            let after = assoc == Assoc::After || (pos == end && start < end);
+            return (if after { new_start + inserted } else { new_start }) as u32;
+        }
+        (i64::from(pos) + delta) as u32
+    }
+
+    /// One replacement covering every edit, as `(old range, inserted length)`.
+    pub(crate) fn covering_edit(&self) -> Option<(TextRange, usize)> {
+        let first = self.edits.first()?;
+        let last = self.edits.last()?;
+        let range = TextRange::new(first.range.start_utf8, last.range.end_utf8);
+        Some((range, (i64::from(range.len()) + self.len_delta()) as usize))
+    }
+
+    fn push(&mut self, edit: TextEdit) {
+        if edit.range.is_empty() && edit.insert.is_empty() {
+            return;
+        }
+        match self.edits.last_mut() {
+            Some(last) if last.range.end_utf8 == edit.range.start_utf8 => {
+                last.range.end_utf8 = edit.range.end_utf8;
+                last.insert.push_str(&edit.insert);
+            }
+            _ => self.edits.push(edit),
+        }
+    }
+
+    fn ops(&self) -> Vec<Op> {
+        let mut ops = Vec::new();
+        let mut pos = 0;
+        for edit in &self.edits {
+            let start = edit.range.start_utf8 as usize;
+            if start > pos {
+                ops.push(Op::Retain(start - pos));
+            }
+            if !edit.range.is_empty() {
+                ops.push(Op::Delete(edit.range.len() as usize));
+            }
+            if !edit.insert.is_empty() {
+                ops.push(Op::Insert(edit.insert.clone()));
+            }
+            pos = edit.range.end_utf8 as usize;
+        }
+        ops
+    }
+
+    fn from_ops(ops: Vec<Op>) -> Self {
+        let mut changes = Self::default();
+        let mut pos = 0;
+        let mut current: Option<TextEdit> = None;
+        for op in ops {
+            match op {
+                Op::Retain(len) => {
+                    changes.push_pending(&mut current);
+                    pos += len;
+                }
+                Op::Delete(len) => {
+                    let edit = current.get_or_insert_with(|| empty_edit(pos));
+                    edit.range.end_utf8 += len as u32;
+                    pos += len;
+                }
+                Op::Insert(text) => {
+                    current.get_or_insert_with(|| empty_edit(pos)).insert.push_str(&text);
+                }
+            }
+        }
+        changes.push_pending(&mut current);
+        changes
+    }
+
+    fn push_pending(&mut self, current: &mut Option<TextEdit>) {
+        if let Some(edit) = current.take() {
+            self.push(edit);
+        }
+    }
+}
+
+impl Op {
+    fn len(&self) -> usize {
+        match self {
+            Self::Retain(len) | Self::Delete(len) => *len,
+            Self::Insert(text) => text.len(),
+        }
+    }
+
+    /// Drops the first `len` bytes, returning what is left if anything.
+    fn split_off(self, len: usize) -> Option<Self> {
+        let rest = match self {
+            Self::Retain(total) if total == usize::MAX => return Some(self),
+            Self::Retain(total) => Self::Retain(total - len),
+            Self::Delete(total) => Self::Delete(total - len),
+            Self::Insert(mut text) => Self::Insert(text.split_off(len)),
+        };
+        (rest.len() > 0).then_some(rest)
+    }
+}
+
+fn empty_edit(pos: usize) -> TextEdit {
+    TextEdit { range: TextRange::new(pos as u32, pos as u32), insert: String::new() }
+}
+
+#[cfg(test)]
+mod tests {
+    use proptest::prelude::*;
+
+    use super::*;
+
+    fn apply(text: &str, changes: &ChangeSet) -> String {
+        let mut rope = RopeText::new(text);
+        changes.apply(&mut rope).unwrap();
+        rope.to_string()
+    }
+
+    fn edit(start: u32, end: u32, insert: &str) -> TextEdit {
+        TextEdit { range: TextRange::new(start, end), insert: insert.to_string() }
+    }
+
+    const TEXT: &str = "alpha beta\ngamma";
+    const INSERTS: &[&str] = &["", "x", "yz", "\n", "- "];
+
+    /// Raw edits for [`changes`] to place on a text, up to four of them.
+    fn edits() -> impl Strategy<Value = Vec<(usize, usize, &'static str)>> {
+        prop::collection::vec((any::<usize>(), 0..4_usize, prop::sample::select(INSERTS)), 1..5)
+    }
+
+    /// A change set on a text of `len` bytes, each edit some way past the previous one.
+    fn changes(len: usize, edits: &[(usize, usize, &str)]) -> ChangeSet {
+        let mut out = Vec::new();
+        let mut pos = 0;
+        for &(start, span, insert) in edits {
+            if pos > len {
+                break;
+            }
+            let start = pos + start % (len - pos + 1);
+            let end = start + span.min(len - start);
+            out.push(edit(start as u32, end as u32, insert));
+            pos = end + 1;
+        }
+        ChangeSet::new(out).unwrap()
+    }
+
+    #[test]
+    fn applies_and_inverts_multiple_edits() {
+        let changes =
+            ChangeSet::new([edit(0, 0, "**"), edit(5, 5, "**"), edit(6, 11, "")]).unwrap();
+        assert_eq!(apply("hello world", &changes), "**hello** ");
+        assert_eq!(changes.len_delta(), -1);
+
+        let inverse = changes.invert(&RopeText::new("hello world")).unwrap();
+        assert_eq!(apply("**hello** ", &inverse), "hello world");
+        assert!(ChangeSet::new([edit(3, 5, ""), edit(4, 6, "")]).is_err());
+    }
+
+    proptest! {
+        #[test]
+        fn compose_matches_sequential_application(first in edits(), second in edits()) {
+            let first = changes(TEXT.len(), &first);
+            let middle = apply(TEXT, &first);
+            let second = changes(middle.len(), &second);
+            let expected = apply(&middle, &second);
+
+            let composed = first.compose(&second);
+            prop_assert_eq!(&apply(TEXT, &composed), &expected);
+            let inverse = composed.invert(&RopeText::new(TEXT)).unwrap();
+            prop_assert_eq!(apply(&expected, &inverse), TEXT);
+        }
+
+        #[test]
+        fn composed_changes_map_positions_like_sequential_ones(
+            first in edits(),
+            second in edits(),
+            pos in 0..=TEXT.len() as u32,
+        ) {
+            let first = changes(TEXT.len(), &first);
+            let second = changes(apply(TEXT, &first).len(), &second);
+
+            // At or inside a composed edit, an insertion and a deletion next to each
+            // other have merged into one replacement, so only positions clear of the
+            // edits map the same way.
+            let composed = first.compose(&second);
+            prop_assume!(composed
+                .edits()
+                .iter()
+                .all(|edit| pos < edit.range.start_utf8 || pos > edit.range.end_utf8));
+            for assoc in [Assoc::Before, Assoc::After] {
+                prop_assert_eq!(
+                    composed.map_pos(pos, assoc),
+                    second.map_pos(first.map_pos(pos, assoc), assoc)
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn maps_positions_through_changes() {
+        let changes = ChangeSet::new([edit(2, 2, "ab"), edit(4, 6, "xyz")]).unwrap();
+        assert_eq!(changes.map_pos(1, Assoc::Before), 1);
+        assert_eq!(changes.map_pos(2, Assoc::Before), 2);
+        assert_eq!(changes.map_pos(2, Assoc::After), 4);
+        assert_eq!(changes.map_pos(4, Assoc::After), 6);
+        assert_eq!(changes.map_pos(5, Assoc::Before), 6);
+        assert_eq!(changes.map_pos(5, Assoc::After), 9);
+        assert_eq!(changes.map_pos(6, Assoc::Before), 9);
+        assert_eq!(changes.map_pos(8, Assoc::Before), 11);
+    }
+}
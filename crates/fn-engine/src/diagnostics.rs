@@ -0,0 +1,53 @@
+use serde::{Deserialize, Serialize};
+
+use crate::{parser::footnotes::Footnotes, selection::TextRange};
+
+/// A problem in the document that does not stop it from parsing, for the editor to
+/// underline.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct Diagnostic {
+    pub range: TextRange,
+    pub kind: DiagnosticKind,
+    pub message: String,
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum DiagnosticKind {
+    /// A footnote reference without a definition of its label.
+    UndefinedFootnote,
+    /// A footnote definition no reference points at, including every definition of a
+    /// label after the first.
+    UnusedFootnote,
+}
+
+/// References to undefined footnotes and unreferenced definitions, in document order.
+pub fn footnote_diagnostics(footnotes: &Footnotes) -> Vec<Diagnostic> {
+    let undefined = footnotes
+        .references
+        .iter()
+        .filter(|reference| footnotes.definition(&reference.label).is_none())
+        .map(|reference| Diagnostic {
+            range: reference.range,
+            kind: DiagnosticKind::UndefinedFootnote,
+            message: format!("footnote [^{}] is not defined", reference.label),
+        });
+    let unused = footnotes
+        .definitions
+        .iter()
+        .filter(|definition| {
+            !footnotes.references.iter().any(|reference| {
+                footnotes.definition(&reference.label).map(|site| site.node_id)
+                    == Some(definition.node_id)
+            })
+        })
+        .map(|definition| Diagnostic {
+            range: definition.range,
+            kind: DiagnosticKind::UnusedFootnote,
+            message: format!("footnote [^{}] is never referenced", definition.label),
+        });
+
+    let mut diagnostics = undefined.chain(unused).collect::<Vec<_>>();
+    diagnostics.sort_by_key(|diagnostic| diagnostic.range.start_utf8);
+    diagnostics
+}
@@ -0,0 +1,303 @@
+use serde::{Deserialize, Serialize};
+use std::{collections::VecDeque, mem};
+
+use crate::{
+    change_set::{Assoc, ChangeSet},
+    selection::{Selection, SelectionSet, TextRange},
+    transaction::TextEdit,
+};
+
+/// Limits for the undo history. Once either limit is exceeded the oldest undo steps
+/// are dropped, including the newest one if it alone is over the memory budget.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+pub struct HistoryConfig {
+    pub max_depth: usize,
+    /// Approximate bytes held by undo and redo steps together.
+    pub max_bytes: usize,
+    /// Typing after a pause longer than this starts a new undo step.
+    pub group_interval_ms: u64,
+}
+
+impl Default for HistoryConfig {
+    fn default() -> Self {
+        Self { max_depth: 500, max_bytes: 8 * 1024 * 1024, group_interval_ms: 1_000 }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum EditKind {
+    Insert,
+    Delete,
+    Other,
+}
+
+/// An undo step: `forward` redoes it and `inverse` undoes it.
+#[derive(Debug, Clone)]
+pub(crate) struct HistoryEntry {
+    pub forward: ChangeSet,
+    pub inverse: ChangeSet,
+    pub selection_before: SelectionSet,
+    pub selection_after: SelectionSet,
+    kind: EditKind,
+    /// The most recent edit at the primary selection, in the coordinates of the text
+    /// after it, for deciding whether typing continues the step.
+    last_edit: Option<TextEdit>,
+    timestamp_ms: Option<u64>,
+    bytes: usize,
+}
+
+impl HistoryEntry {
+    fn measure(&mut self) {
+        let secondary =
+            self.selection_before.secondary.len() + self.selection_after.secondary.len();
+        self.bytes = mem::size_of::<Self>()
+            + change_bytes(&self.forward)
+            + change_bytes(&self.inverse)
+            + secondary * mem::size_of::<Selection>();
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub(crate) struct History {
+    config: HistoryConfig,
+    undo: VecDeque<HistoryEntry>,
+    redo: Vec<HistoryEntry>,
+    bytes: usize,
+    /// Set after undo/redo so the next edit never extends a replayed step.
+    sealed: bool,
+}
+
+impl History {
+    pub fn config(&self) -> HistoryConfig {
+        self.config
+    }
+
+    pub fn set_config(&mut self, config: HistoryConfig) {
+        self.config = config;
+        self.enforce_limits();
+    }
+
+    pub fn record(
+        &mut self,
+        (forward, inverse): (ChangeSet, ChangeSet),
+        (kind, last_edit): (EditKind, Option<TextEdit>),
+        (selection_before, selection_after): (SelectionSet, SelectionSet),
+        timestamp_ms: Option<u64>,
+    ) {
+        self.bytes -= self.redo.drain(..).map(|entry| entry.bytes).sum::<usize>();
+
+        let moved_edit = last_edit.as_ref().map(|edit| moved_edit(edit, &forward));
+        let sealed = mem::take(&mut self.sealed);
+        let config = self.config;
+        match self.undo.back_mut() {
+            Some(last)
+                if !sealed
+                    && continues(
+                        last,
+                        last_edit.as_ref(),
+                        kind,
+                        &selection_before,
+                        timestamp_ms,
+                        &config,
+                    ) =>
+            {
+                self.bytes -= last.bytes;
+                last.forward = last.forward.compose(&forward);
+                last.inverse = inverse.compose(&last.inverse);
+                last.selection_after = selection_after;
+                last.last_edit = moved_edit;
+                last.timestamp_ms = timestamp_ms.or(last.timestamp_ms);
+                last.measure();
+                self.bytes += last.bytes;
+            }
+            _ => {
+                let mut entry = HistoryEntry {
+                    forward,
+                    inverse,
+                    selection_before,
+                    selection_after,
+                    kind,
+                    last_edit: moved_edit,
+                    timestamp_ms,
+                    bytes: 0,
+                };
+                entry.measure();
+                self.bytes += entry.bytes;
+                self.undo.push_back(entry);
+            }
+        }
+        self.enforce_limits();
+    }
+
+    pub fn pop_undo(&mut self) -> Option<HistoryEntry> {
+        let entry = self.undo.pop_back()?;
+        self.sealed = true;
+        self.bytes -= entry.bytes;
+        Some(entry)
+    }
+
+    pub fn pop_redo(&mut self) -> Option<HistoryEntry> {
+        let entry = self.redo.pop()?;
+        self.sealed = true;
+        self.bytes -= entry.bytes;
+        Some(entry)
+    }
+
+    pub fn push_undo(&mut self, entry: HistoryEntry) {
+        self.bytes += entry.bytes;
+        self.undo.push_back(entry);
+        self.enforce_limits();
+    }
+
+    pub fn push_redo(&mut self, entry: HistoryEntry) {
+        self.bytes += entry.bytes;
+        self.redo.push(entry);
+    }
+
+    fn enforce_limits(&mut self) {
+        while self.undo.len() > self.config.max_depth
+            || (self.bytes > self.config.max_bytes && !self.undo.is_empty())
+        {
+            if let Some(entry) = self.undo.pop_front() {
+                self.bytes -= entry.bytes;
+            }
+        }
+    }
+}
+
+/// Whether `edit` extends the typing or deleting run recorded in `last`.
+fn continues(
+    last: &HistoryEntry,
+    edit: Option<&TextEdit>,
+    kind: EditKind,
+    selection_before: &SelectionSet,
+    timestamp_ms: Option<u64>,
+    config: &HistoryConfig,
+) -> bool {
+    let (Some(edit), Some(previous)) = (edit, last.last_edit.as_ref()) else {
+        return false;
+    };
+    if kind != last.kind || kind == EditKind::Other || *selection_before != last.selection_after {
+        return false;
+    }
+    if let (Some(now), Some(then)) = (timestamp_ms, last.timestamp_ms) {
+        if now.saturating_sub(then) > config.group_interval_ms {
+            return false;
+        }
+    }
+
+    match kind {
+        EditKind::Insert => {
+            let starts_word = !edit.insert.starts_with(char::is_whitespace);
+            let ended_word = !previous.insert.ends_with(char::is_whitespace);
+            edit.range.start_utf8 == previous.range.start_utf8 + previous.insert.len() as u32
+                && (starts_word || !ended_word)
+        }
+        EditKind::Delete => {
+            edit.range.end_utf8 == previous.range.start_utf8
+                || edit.range.start_utf8 == previous.range.start_utf8
+        }
+        EditKind::Other => false,
+    }
+}
+
+/// `edit`, given in the coordinates before `forward`, moved past the edits that
+/// `forward` makes in front of it.
+fn moved_edit(edit: &TextEdit, forward: &ChangeSet) -> TextEdit {
+    let start = forward.map_pos(edit.range.start_utf8, Assoc::Before);
+    TextEdit { range: TextRange::new(start, start + edit.range.len()), insert: edit.insert.clone() }
+}
+
+fn change_bytes(changes: &ChangeSet) -> usize {
+    changes.edits().iter().map(|edit| mem::size_of::<TextEdit>() + edit.insert.len()).sum()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{transaction::EngineCommand, Engine};
+
+    fn type_text(engine: &mut Engine, text: &str, mut timestamp_ms: u64) {
+        for character in text.chars() {
+            let caret = engine.selection().head_utf8;
+            engine.replace_text_at(caret, caret, character, timestamp_ms).unwrap();
+            timestamp_ms += 50;
+        }
+    }
+
+    #[test]
+    fn groups_typing_into_words() {
+        let mut engine = Engine::new("");
+        type_text(&mut engine, "hello world", 0);
+        type_text(&mut engine, " again", 5_000);
+        let caret = engine.selection().head_utf8;
+        engine.replace_text(caret - 1, caret, "").unwrap();
+        engine.replace_text(caret - 2, caret - 1, "").unwrap();
+        assert_eq!(engine.markdown(), "hello world aga");
+
+        engine.undo().unwrap();
+        assert_eq!(engine.markdown(), "hello world again");
+        engine.undo().unwrap();
+        assert_eq!(engine.markdown(), "hello world");
+        engine.undo().unwrap();
+        assert_eq!(engine.markdown(), "hello");
+        assert_eq!(engine.selection(), Selection::collapsed(5));
+        engine.redo().unwrap();
+        assert_eq!(engine.markdown(), "hello world");
+
+        engine.set_selection(0, 0).unwrap();
+        type_text(&mut engine, "Oh", 6_000);
+        assert_eq!(engine.redo().unwrap().markdown, "Ohhello world");
+        engine.undo().unwrap();
+        assert_eq!(engine.markdown(), "hello world");
+        engine.redo().unwrap();
+        assert_eq!(engine.markdown(), "Ohhello world");
+    }
+
+    #[test]
+    fn selection_jumps_and_commands_split_steps() {
+        let mut engine = Engine::new("one two");
+        type_text(&mut engine, "ab", 0);
+        engine.set_selection(9, 9).unwrap();
+        type_text(&mut engine, "cd", 100);
+        engine.set_selection(0, 11).unwrap();
+        engine.apply_command(EngineCommand::ToggleStrong).unwrap();
+        assert_eq!(engine.markdown(), "**abone twocd**");
+
+        engine.undo().unwrap();
+        assert_eq!(engine.markdown(), "abone twocd");
+        assert_eq!(engine.selection(), Selection { anchor_utf8: 0, head_utf8: 11 });
+        engine.undo().unwrap();
+        assert_eq!(engine.markdown(), "abone two");
+        engine.undo().unwrap();
+        assert_eq!(engine.markdown(), "one two");
+    }
+
+    #[test]
+    fn drops_oldest_steps_past_the_limits() {
+        let mut engine = Engine::new("");
+        engine.set_history_config(HistoryConfig { max_depth: 3, ..HistoryConfig::default() });
+        for word in ["a", "b", "c", "d", "e"] {
+            let end = engine.markdown().len() as u32;
+            engine.replace_text(end, end, format!("\n{word}")).unwrap();
+        }
+        (0..5).for_each(|_| {
+            engine.undo().unwrap();
+        });
+        assert_eq!(engine.markdown(), "\na\nb");
+
+        let mut engine = Engine::new("");
+        let step = mem::size_of::<HistoryEntry>() + mem::size_of::<TextEdit>() * 2 + 1_000;
+        let config = HistoryConfig { max_bytes: step * 5 / 2, ..HistoryConfig::default() };
+        engine.set_history_config(config);
+        for word in ["a", "b", "c"] {
+            let end = engine.markdown().len() as u32;
+            engine.replace_text(end, end, format!("\n{}", word.repeat(1_000))).unwrap();
+        }
+        (0..3).for_each(|_| {
+            engine.undo().unwrap();
+        });
+        assert_eq!(engine.markdown(), format!("\n{}", "a".repeat(1_000)));
+        assert!(engine.history.bytes <= config.max_bytes);
+    }
+}
@@ -0,0 +1,164 @@
+//! The input rules [`EnginePluginRegistry::core_markdown`] registers. Both rewrite
+//! Enter typed at the end of a line.
+//!
+//! [`EnginePluginRegistry::core_markdown`]: crate::registry::EnginePluginRegistry::core_markdown
+
+use crate::{
+    parser::{
+        normalize_code_language, parse_blockquote, parse_bullet_marker, parse_ordered_marker,
+        parse_task_marker,
+    },
+    registry::InputRulePlugin,
+    rope::RopeText,
+    selection::{Selection, TextRange},
+    transaction::TextEdit,
+};
+
+/// Enter after an opening fence with no closing fence below inserts the closing
+/// fence and puts the caret on the empty line between.
+pub struct FenceAutoClose;
+
+impl InputRulePlugin for FenceAutoClose {
+    fn name(&self) -> &str {
+        "fence-auto-close"
+    }
+
+    fn apply(
+        &self,
+        text: &RopeText,
+        range: TextRange,
+        insert: &str,
+    ) -> Option<(TextEdit, Selection)> {
+        let (line_start, line_end, line) = enter_at_line_end(text, range, insert)?;
+        if !is_fence_trigger_line(&line) {
+            return None;
+        }
+        let rest = text.slice(TextRange::new(line_end as u32, text.len_utf8() as u32)).ok()?;
+        if has_closing_fence_below(&rest) {
+            return None;
+        }
+
+        let normalized_opening = normalize_fence_opening(&line);
+        let insert = format!("{normalized_opening}\n\n```");
+        let selection =
+            Selection::collapsed(line_start as u32 + normalized_opening.len() as u32 + 1);
+        let edit = TextEdit { range: TextRange::new(line_start as u32, range.end_utf8), insert };
+        Some((edit, selection))
+    }
+}
+
+/// Enter at the end of a non-empty list item or quote line starts the next item or
+/// quote line.
+pub struct ListContinuation;
+
+impl InputRulePlugin for ListContinuation {
+    fn name(&self) -> &str {
+        "list-continuation"
+    }
+
+    fn apply(
+        &self,
+        text: &RopeText,
+        range: TextRange,
+        insert: &str,
+    ) -> Option<(TextEdit, Selection)> {
+        let (_, _, line) = enter_at_line_end(text, range, insert)?;
+        let prefix = list_continuation_prefix(&line)?;
+        let insert = format!("\n{prefix}");
+        let selection = Selection::collapsed(range.start_utf8 + insert.len() as u32);
+        Some((TextEdit { range, insert }, selection))
+    }
+}
+
+/// The bounds and text (without `\r`) of the line when `insert` is a newline typed
+/// at the end of it.
+fn enter_at_line_end(
+    text: &RopeText,
+    range: TextRange,
+    insert: &str,
+) -> Option<(usize, usize, String)> {
+    if insert != "\n" || !range.is_empty() {
+        return None;
+    }
+    let cursor = range.start_utf8 as usize;
+    let (line_start, line_end) = text.line_bounds(cursor);
+    if cursor != line_end {
+        return None;
+    }
+    let line = text.slice(TextRange::new(line_start as u32, line_end as u32)).ok()?;
+    Some((line_start, line_end, line.trim_end_matches('\r').to_string()))
+}
+
+fn is_fence_trigger_line(line: &str) -> bool {
+    let Some(rest) = line.strip_prefix("```") else {
+        return false;
+    };
+    rest.chars().all(|ch| ch.is_ascii_alphanumeric() || matches!(ch, '_' | '-' | '+' | '#'))
+}
+
+fn has_closing_fence_below(rest: &str) -> bool {
+    rest.lines().any(|line| line.trim() == "```")
+}
+
+fn normalize_fence_opening(line: &str) -> String {
+    let suffix = line.strip_prefix("```").unwrap_or_default();
+    let normalized = normalize_code_language(suffix).unwrap_or_default();
+
+    if normalized.is_empty() {
+        "```".to_string()
+    } else {
+        format!("```{normalized}")
+    }
+}
+
+/// The prefix to start the next line with: the line's blockquote markers, followed
+/// by the next list item's marker when the quoted content is a list item.
+fn list_continuation_prefix(line: &str) -> Option<String> {
+    let mut quote_len = 0;
+    while let Some(len) = parse_blockquote(&line[quote_len..]) {
+        quote_len += len;
+    }
+    let (quote, content) = line.split_at(quote_len);
+
+    if let Some(item) = list_item_prefix(content) {
+        return Some(format!("{quote}{item}"));
+    }
+    (quote_len > 0 && content.trim().is_empty().not()).then(|| quote.to_string())
+}
+
+fn list_item_prefix(line: &str) -> Option<String> {
+    let item = line.trim_start_matches(' ');
+    let indent = &line[..line.len() - item.len()];
+
+    if let Some(prefix_len) = parse_bullet_marker(item) {
+        let marker = &item[..prefix_len];
+        return continuation_body(&item[prefix_len..])
+            .map(|task| format!("{indent}{marker}{task}"));
+    }
+
+    if let Some((number, prefix_len)) = parse_ordered_marker(item) {
+        return continuation_body(&item[prefix_len..])
+            .map(|task| format!("{indent}{}. {task}", number + 1));
+    }
+
+    None
+}
+
+/// Decides whether list item content warrants a continuation marker on Enter and
+/// returns the checkbox to carry over (empty for plain items).
+fn continuation_body(content: &str) -> Option<&'static str> {
+    match parse_task_marker(content) {
+        Some((_checked, len)) => content[len..].trim().is_empty().not().then_some("[ ] "),
+        None => content.trim().is_empty().not().then_some(""),
+    }
+}
+
+trait BoolExt {
+    fn not(self) -> bool;
+}
+
+impl BoolExt for bool {
+    fn not(self) -> bool {
+        !self
+    }
+}
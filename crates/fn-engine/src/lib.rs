@@ -1,28 +1,55 @@
 pub mod ast;
+pub mod change_set;
+pub mod diagnostics;
+pub mod history;
+pub mod input_rules;
+pub mod motion;
 pub mod parser;
 pub mod registry;
 pub mod render_snapshot;
 pub mod rope;
 pub mod selection;
+mod table;
 pub mod transaction;
 
-use ast::BlockNode;
-use parser::{normalize_code_language, parse_document};
+use ast::{BlockKind, BlockNode, CalloutKind, ListItemNode, MetadataValue, NodeId};
+use change_set::ChangeSet;
+use diagnostics::{footnote_diagnostics, Diagnostic};
+use history::{EditKind, History, HistoryConfig};
+use motion::{motion_range, move_offset, Direction, MotionUnit};
+use parser::{
+    callouts::parse_callout_marker, footnotes::Footnotes, list_content_column,
+    normalize_code_language, parse_blockquote, parse_bullet_marker, parse_document,
+    parse_ordered_marker, parse_task_marker, reconcile_node_ids, reparse_document,
+    wiki_links::wiki_link_targets, LinkDefinitions, NodeIdGenerator,
+};
 use registry::EnginePluginRegistry;
-use render_snapshot::{build_snapshot, EditorSnapshot};
+use render_snapshot::{
+    build_snapshot, diff_rendered_blocks, flatten_rendered, render_each_block, EditorSnapshot,
+    RenderedBlock, SnapshotPatch,
+};
 use rope::RopeText;
-use selection::{Selection, TextRange};
+use selection::{LineCol, OffsetUnit, Selection, SelectionSet, TextRange};
+use std::{
+    cmp::Reverse,
+    collections::{BTreeMap, HashMap, HashSet},
+};
+use table::{cell_at, table_at, table_text_end, CellPosition, TableGrid};
 use thiserror::Error;
-use transaction::{EngineCommand, EngineTransaction, TransactionKind};
+use transaction::{EngineCommand, EngineTransaction, TextEdit, TransactionKind};
 
 #[derive(Debug, Error, Clone, PartialEq, Eq)]
 pub enum EngineError {
     #[error("invalid utf-8 range {start}..{end}")]
     InvalidUtf8Range { start: usize, end: usize },
+    #[error("offset {offset} is out of bounds")]
+    OffsetOutOfBounds { offset: usize },
     #[error("selection is out of bounds")]
     SelectionOutOfBounds,
     #[error("unsupported command: {0}")]
     UnsupportedCommand(String),
+    #[error("command is not applicable at the current selection: {0}")]
+    CommandNotApplicable(String),
 }
 
 pub type Result<T> = std::result::Result<T, EngineError>;
@@ -32,46 +59,254 @@ pub struct Doc {
     pub text: RopeText,
     pub blocks: Vec<BlockNode>,
     pub revision: u64,
+    node_ids: NodeIdGenerator,
+    registry: EnginePluginRegistry,
+    definitions: LinkDefinitions,
+    footnotes: Footnotes,
+    /// Where the document is stored, which relative image sources resolve against.
+    path: Option<String>,
+    /// The wiki-link targets the host found a note for, once it has resolved them.
+    resolved_wiki_links: Option<HashSet<String>>,
+    /// Top-level blocks reparsed since the last [`take_dirty_blocks`](Self::take_dirty_blocks),
+    /// or `None` when any block may render differently.
+    dirty_blocks: Option<HashSet<NodeId>>,
 }
 
+/// State to roll back to when a command fails halfway.
 #[derive(Debug, Clone)]
-struct HistoryState {
-    markdown: String,
-    selection: Selection,
+struct Checkpoint {
+    text: RopeText,
+    selections: SelectionSet,
+    revision: u64,
+}
+
+/// What the client last received, plus the text changes made since, so the next
+/// patch can be diffed against it.
+#[derive(Debug, Clone)]
+struct PublishedState {
     revision: u64,
+    blocks: Vec<RenderedBlock>,
+    /// The footnote numbers the blocks were rendered with.
+    footnote_numbers: HashMap<NodeId, u32>,
+    changes: ChangeSet,
 }
 
 #[derive(Debug, Clone)]
 pub struct Engine {
     doc: Doc,
+    /// The primary selection, which single-selection commands work on.
     selection: Selection,
-    history: Vec<HistoryState>,
-    future: Vec<HistoryState>,
-    registry: EnginePluginRegistry,
+    /// Further cursors and ranges, kept normalized as in [`SelectionSet::new`].
+    secondary: Vec<Selection>,
+    history: History,
+    published: Option<PublishedState>,
+    /// Edits made so far by the command being applied.
+    pending: ChangeSet,
+    /// Command edits the blocks have not been reparsed for yet.
+    unparsed: ChangeSet,
 }
 
 impl Doc {
     pub fn parse(markdown: impl Into<String>, revision: u64) -> Self {
+        Self::parse_with(markdown, revision, EnginePluginRegistry::core_markdown())
+    }
+
+    /// Parses with the syntax plugins of `registry`, which later reparses reuse.
+    pub fn parse_with(
+        markdown: impl Into<String>,
+        revision: u64,
+        registry: EnginePluginRegistry,
+    ) -> Self {
         let markdown = markdown.into();
-        Self { blocks: parse_document(&markdown), text: RopeText::new(markdown), revision }
+        let mut node_ids = NodeIdGenerator::default();
+        let mut blocks = parse_document(&markdown, &registry);
+        reconcile_node_ids(&[], &mut blocks, &mut node_ids);
+        let definitions = LinkDefinitions::collect(&blocks);
+        let mut doc = Self {
+            blocks,
+            text: RopeText::new(markdown),
+            revision,
+            node_ids,
+            registry,
+            definitions,
+            footnotes: Footnotes::default(),
+            path: None,
+            resolved_wiki_links: None,
+            dirty_blocks: None,
+        };
+        doc.link_footnotes();
+        doc
+    }
+
+    pub fn registry(&self) -> &EnginePluginRegistry {
+        &self.registry
+    }
+
+    pub fn path(&self) -> Option<&str> {
+        self.path.as_deref()
+    }
+
+    pub fn resolved_wiki_links(&self) -> Option<&HashSet<String>> {
+        self.resolved_wiki_links.as_ref()
+    }
+
+    /// The footnote definitions and references, as of the last parse.
+    pub fn footnotes(&self) -> &Footnotes {
+        &self.footnotes
+    }
+
+    /// Reparses the whole text. Nodes whose content survived keep their IDs.
+    fn reparse(&mut self, revision: u64) {
+        let mut blocks = parse_document(&self.text.to_string(), &self.registry);
+        reconcile_node_ids(&self.blocks, &mut blocks, &mut self.node_ids);
+        self.definitions = LinkDefinitions::collect(&blocks);
+        self.blocks = blocks;
+        self.link_footnotes();
+        self.revision = revision;
+        self.dirty_blocks = None;
+    }
+
+    /// The top-level blocks reparsed since the last call, `None` when any block may
+    /// render differently, such as after a full reparse.
+    pub(crate) fn take_dirty_blocks(&mut self) -> Option<HashSet<NodeId>> {
+        self.dirty_blocks.replace(HashSet::new())
+    }
+
+    /// Reparses around one replacement of `range` (old offsets) by `inserted_len` bytes.
+    /// An edit that changes the link definitions can change links anywhere, so it
+    /// falls back to a full reparse.
+    fn reparse_edit(&mut self, range: TextRange, inserted_len: usize, revision: u64) {
+        let previous = std::mem::take(&mut self.blocks);
+        let reparse = reparse_document(
+            &self.text,
+            &self.registry,
+            &self.definitions,
+            previous,
+            range,
+            inserted_len,
+            &mut self.node_ids,
+        );
+        self.blocks = reparse.blocks;
+        self.revision = revision;
+
+        // Only definitions among the reparsed blocks or the ones they replaced can
+        // have changed.
+        let window = &self.blocks[reparse.window.clone()];
+        let touches_definitions = !LinkDefinitions::collect(&reparse.replaced).is_empty()
+            || !LinkDefinitions::collect(window).is_empty();
+        if touches_definitions && LinkDefinitions::collect(&self.blocks) != self.definitions {
+            self.reparse(revision);
+            return;
+        }
+
+        let delta = inserted_len as i64 - range.len() as i64;
+        let old_end = range.end_utf8 as usize;
+        if self.footnotes.splice(&reparse.replaced, window, old_end, delta) {
+            self.footnotes.link(&mut self.blocks);
+            self.dirty_blocks = None;
+            return;
+        }
+        self.footnotes.link(&mut self.blocks[reparse.window.clone()]);
+        let window = &self.blocks[reparse.window];
+        if let Some(dirty) = &mut self.dirty_blocks {
+            dirty.extend(window.iter().map(|block| block.node_id));
+            // Past one ID per block, rendering everything again is as cheap as
+            // looking them up.
+            if dirty.len() > self.blocks.len() {
+                self.dirty_blocks = None;
+            }
+        }
+    }
+
+    /// Footnote references point at definitions anywhere in the document, so they are
+    /// linked over the whole tree once its node IDs are settled.
+    fn link_footnotes(&mut self) {
+        self.footnotes = Footnotes::collect(&self.blocks);
+        self.footnotes.link(&mut self.blocks);
     }
 }
 
 impl Engine {
     pub fn new(markdown: impl Into<String>) -> Self {
-        let doc = Doc::parse(markdown, 0);
+        Self::with_registry(markdown, EnginePluginRegistry::core_markdown())
+    }
+
+    /// An engine that parses, edits and renders with the plugins in `registry`.
+    pub fn with_registry(markdown: impl Into<String>, registry: EnginePluginRegistry) -> Self {
+        let doc = Doc::parse_with(markdown, 0, registry);
         let selection = Selection::collapsed(0);
         Self {
             doc,
             selection,
-            history: Vec::new(),
-            future: Vec::new(),
-            registry: EnginePluginRegistry::core_markdown(),
+            secondary: Vec::new(),
+            history: History::default(),
+            published: None,
+            pending: ChangeSet::default(),
+            unparsed: ChangeSet::default(),
         }
     }
 
     pub fn snapshot(&self) -> EditorSnapshot {
-        build_snapshot(&self.doc, self.selection)
+        build_snapshot(&self.doc, &self.selections())
+    }
+
+    /// Returns the full snapshot and makes it the base for the next
+    /// [`publish_patch`](Self::publish_patch).
+    pub fn publish_snapshot(&mut self) -> EditorSnapshot {
+        self.doc.take_dirty_blocks();
+        let footnote_numbers = self.doc.footnotes.numbers();
+        let blocks = render_each_block(&self.doc, &footnote_numbers);
+        let snapshot = EditorSnapshot {
+            revision: self.doc.revision,
+            markdown: self.doc.text.to_string(),
+            blocks: flatten_rendered(&blocks),
+            selection: self.selection,
+            secondary_selections: self.secondary.clone(),
+        };
+        self.published = Some(PublishedState {
+            revision: snapshot.revision,
+            blocks,
+            footnote_numbers,
+            changes: ChangeSet::default(),
+        });
+        snapshot
+    }
+
+    /// Returns what changed since the last published snapshot or patch and makes the
+    /// current state the new base. Without an earlier publish the base is an empty
+    /// document at revision 0.
+    ///
+    /// Only the top-level blocks reparsed since the last publish are rendered and
+    /// diffed again, unless a change such as new footnote numbering can affect every
+    /// block.
+    pub fn publish_patch(&mut self) -> SnapshotPatch {
+        let base = self.published.take().unwrap_or_else(|| PublishedState {
+            revision: 0,
+            blocks: Vec::new(),
+            footnote_numbers: HashMap::new(),
+            changes: ChangeSet::single(TextRange::default(), self.doc.text.to_string()),
+        });
+        let footnote_numbers = self.doc.footnotes.numbers();
+        let dirty =
+            self.doc.take_dirty_blocks().filter(|_| footnote_numbers == base.footnote_numbers);
+        let (diff, blocks) =
+            diff_rendered_blocks(&self.doc, base.blocks, dirty.as_ref(), &footnote_numbers);
+        let patch = SnapshotPatch {
+            base_revision: base.revision,
+            revision: self.doc.revision,
+            changes: base.changes,
+            blocks: diff,
+            selection: self.selection,
+            secondary_selections: self.secondary.clone(),
+        };
+
+        self.published = Some(PublishedState {
+            revision: self.doc.revision,
+            blocks,
+            footnote_numbers,
+            changes: ChangeSet::default(),
+        });
+        patch
     }
 
     pub fn markdown(&self) -> String {
@@ -79,78 +314,262 @@ impl Engine {
     }
 
     pub fn registry(&self) -> &EnginePluginRegistry {
-        &self.registry
+        self.doc.registry()
+    }
+
+    /// Sets where the document is stored, which relative image sources in the
+    /// snapshot resolve against.
+    pub fn set_document_path(&mut self, path: Option<String>) {
+        if self.doc.path != path {
+            self.doc.path = path;
+            self.doc.dirty_blocks = None;
+        }
+    }
+
+    /// The distinct wiki-link targets of the document, for the host to resolve.
+    pub fn wiki_link_targets(&self) -> Vec<String> {
+        wiki_link_targets(&self.doc.blocks)
+    }
+
+    /// Sets the wiki-link targets that resolve to a note, so the snapshot flags links
+    /// to any other target as unresolved. `None` leaves every link unflagged.
+    pub fn set_resolved_wiki_links(&mut self, targets: Option<HashSet<String>>) {
+        if self.doc.resolved_wiki_links != targets {
+            self.doc.resolved_wiki_links = targets;
+            self.doc.dirty_blocks = None;
+        }
     }
 
     pub fn doc(&self) -> &Doc {
         &self.doc
     }
 
+    /// The top-level keys of the document's front matter, if it starts with one.
+    pub fn metadata(&self) -> Option<&BTreeMap<String, MetadataValue>> {
+        match &self.doc.blocks.first()?.kind {
+            BlockKind::FrontMatter { metadata, .. } => Some(metadata),
+            _ => None,
+        }
+    }
+
+    /// Undefined and unused footnotes, in document order.
+    pub fn diagnostics(&self) -> Vec<Diagnostic> {
+        footnote_diagnostics(self.doc.footnotes())
+    }
+
+    /// The primary selection.
     pub fn selection(&self) -> Selection {
         self.selection
     }
 
+    pub fn selections(&self) -> SelectionSet {
+        SelectionSet { primary: self.selection, secondary: self.secondary.clone() }
+    }
+
+    /// Converts `offset` between units. An offset inside a character rounds down to
+    /// the character's start.
+    pub fn convert_offset(&self, offset: u32, from: OffsetUnit, to: OffsetUnit) -> Result<u32> {
+        let utf8 = self.to_utf8(offset, from)?;
+        Ok(self.doc.text.from_utf8(utf8 as usize, to) as u32)
+    }
+
+    /// Line and column of `offset`, both counted in `unit`.
+    pub fn line_col(&self, offset: u32, unit: OffsetUnit) -> Result<LineCol> {
+        let utf8 = self.to_utf8(offset, unit)?;
+        Ok(self.doc.text.line_col(utf8 as usize, unit))
+    }
+
+    /// Offset in `unit` of a line and column counted in `unit`, clamped to the line.
+    pub fn offset_at(&self, position: LineCol, unit: OffsetUnit) -> u32 {
+        let utf8 = self.doc.text.line_col_to_utf8(position, unit);
+        self.doc.text.from_utf8(utf8, unit) as u32
+    }
+
     pub fn set_markdown(&mut self, markdown: impl Into<String>) -> EditorSnapshot {
-        let next_markdown = markdown.into();
-        self.history.push(self.capture_state());
-        self.future.clear();
-        self.doc = Doc::parse(next_markdown, self.doc.revision + 1);
-        self.selection = self.selection.clamp(self.doc.text.len_utf8() as u32);
+        let next_markdown = RopeText::new(markdown.into());
+        let selection_before = self.selections();
+        let changes = text_diff(&self.doc.text, &next_markdown);
+        let inverse = changes.invert(&self.doc.text).unwrap_or_default();
+        self.doc.text = next_markdown;
+        self.doc.reparse(self.doc.revision + 1);
+        self.restore_selections(selection_before.clone());
+        self.commit(changes, inverse, (EditKind::Other, None), selection_before, None);
         self.snapshot()
     }
 
+    pub fn history_config(&self) -> HistoryConfig {
+        self.history.config()
+    }
+
+    pub fn set_history_config(&mut self, config: HistoryConfig) {
+        self.history.set_config(config);
+    }
+
     pub fn set_selection(&mut self, anchor_utf8: u32, head_utf8: u32) -> Result<Selection> {
         let max = self.doc.text.len_utf8() as u32;
         if anchor_utf8 > max || head_utf8 > max {
             return Err(EngineError::SelectionOutOfBounds);
         }
         self.selection = Selection { anchor_utf8, head_utf8 };
+        self.secondary.clear();
         Ok(self.selection)
     }
 
+    pub fn set_selections(&mut self, selections: SelectionSet) -> Result<SelectionSet> {
+        let max = self.doc.text.len_utf8() as u32;
+        let out_of_bounds = std::iter::once(&selections.primary)
+            .chain(&selections.secondary)
+            .any(|selection| selection.anchor_utf8 > max || selection.head_utf8 > max);
+        if out_of_bounds {
+            return Err(EngineError::SelectionOutOfBounds);
+        }
+        self.restore_selections(selections);
+        Ok(self.selections())
+    }
+
     pub fn replace_text(
         &mut self,
         start_utf8: u32,
         end_utf8: u32,
         insert: impl Into<String>,
+    ) -> Result<EngineTransaction> {
+        self.replace_text_with_time(TextRange::new(start_utf8, end_utf8), insert.into(), None)
+    }
+
+    /// Like [`replace_text`](Self::replace_text), with the time of the keystroke so
+    /// that pauses in typing split undo steps.
+    pub fn replace_text_at(
+        &mut self,
+        start_utf8: u32,
+        end_utf8: u32,
+        insert: impl Into<String>,
+        timestamp_ms: u64,
     ) -> Result<EngineTransaction> {
         let range = TextRange::new(start_utf8, end_utf8);
-        let insert = insert.into();
-        let selection_before = self.selection;
+        self.replace_text_with_time(range, insert.into(), Some(timestamp_ms))
+    }
+
+    fn replace_text_with_time(
+        &mut self,
+        range: TextRange,
+        insert: String,
+        timestamp_ms: Option<u64>,
+    ) -> Result<EngineTransaction> {
+        let selection_before = self.selections();
         self.ensure_range(range)?;
-        self.history.push(self.capture_state());
-        self.future.clear();
 
-        let (effective_range, effective_insert, selection_after) =
-            self.transform_insert(range, &insert)?;
-        self.doc.text.replace_range(effective_range, &effective_insert)?;
-        self.reparse(self.doc.revision + 1);
-        self.selection = selection_after.clamp(self.doc.text.len_utf8() as u32);
+        let kind = match (range.is_empty(), insert.is_empty()) {
+            (true, false) => EditKind::Insert,
+            (false, true) => EditKind::Delete,
+            _ => EditKind::Other,
+        };
+        let targets = self.edit_targets(range);
+        let mut edits = Vec::with_capacity(targets.len());
+        let mut carets = Vec::with_capacity(targets.len());
+        for &(target, is_primary) in &targets {
+            let (edit, selection_after) = self.transform_insert(target, &insert);
+            carets.push((selection_after.head_utf8 - edit.range.start_utf8, is_primary));
+            edits.push(edit);
+        }
+        let changes = ChangeSet::new(edits.iter().cloned())?;
+        let inverse = changes.invert(&self.doc.text)?;
+        let revision_before = self.doc.revision;
+        self.apply_changes(&changes, revision_before + 1)?;
+
+        // Place each caret relative to where its own edit landed in the new text.
+        let mut delta = 0_i64;
+        let mut primary_edit = None;
+        let mut selections = Vec::with_capacity(edits.len());
+        for (edit, (offset, is_primary)) in edits.iter().zip(carets) {
+            let start = (i64::from(edit.range.start_utf8) + delta) as u32;
+            selections.push((Selection::collapsed(start + offset), is_primary));
+            if is_primary {
+                primary_edit = Some(edit.clone());
+            }
+            delta += edit.insert.len() as i64 - i64::from(edit.range.len());
+        }
+        if targets.len() == 1 {
+            selections
+                .extend(self.secondary.iter().map(|selection| (selection.map(&changes), false)));
+        }
+        self.restore_selections(selection_set(selections));
+        let primary_edit = primary_edit.unwrap_or_else(|| edits[0].clone());
+        self.commit(
+            changes.clone(),
+            inverse,
+            (kind, Some(primary_edit.clone())),
+            selection_before.clone(),
+            timestamp_ms,
+        );
 
         Ok(EngineTransaction {
-            kind: TransactionKind::ReplaceText { range: effective_range, insert: effective_insert },
-            selection_before,
+            kind: TransactionKind::ReplaceText {
+                range: primary_edit.range,
+                insert: primary_edit.insert,
+            },
+            changes,
+            selection_before: selection_before.primary,
             selection_after: self.selection,
-            revision_before: self.doc.revision.saturating_sub(1),
+            revision_before,
             revision_after: self.doc.revision,
         })
     }
 
     pub fn apply_command(&mut self, command: EngineCommand) -> Result<EngineTransaction> {
+        self.apply_command_with_time(command, None)
+    }
+
+    /// Like [`apply_command`](Self::apply_command), with the time of the keystroke so
+    /// that pauses between deletions split undo steps.
+    pub fn apply_command_at(
+        &mut self,
+        command: EngineCommand,
+        timestamp_ms: u64,
+    ) -> Result<EngineTransaction> {
+        self.apply_command_with_time(command, Some(timestamp_ms))
+    }
+
+    fn apply_command_with_time(
+        &mut self,
+        command: EngineCommand,
+        timestamp_ms: Option<u64>,
+    ) -> Result<EngineTransaction> {
         match command {
             EngineCommand::Undo => self.undo_transaction(),
             EngineCommand::Redo => self.redo_transaction(),
             other => {
-                let selection_before = self.selection;
                 let revision_before = self.doc.revision;
-                self.history.push(self.capture_state());
-                self.future.clear();
-                self.apply_non_history_command(&other)?;
-                self.reparse(revision_before + 1);
-                self.selection = self.selection.clamp(self.doc.text.len_utf8() as u32);
+                let checkpoint = self.capture_state();
+                self.pending = ChangeSet::default();
+                self.unparsed = ChangeSet::default();
+                if let Err(error) = self.apply_non_history_command(&other) {
+                    self.restore_state(checkpoint);
+                    self.pending = ChangeSet::default();
+                    self.unparsed = ChangeSet::default();
+                    return Err(error);
+                }
+                let changes = std::mem::take(&mut self.pending);
+                let inverse = changes.invert(&checkpoint.text)?;
+                self.sync_blocks(revision_before + 1);
+                self.restore_selections(self.selections());
+                let selection_before = checkpoint.selections;
+                // Deletions group into undo steps like typed ones; the edit that matters
+                // is the one at the primary cursor.
+                let edit = match other {
+                    EngineCommand::DeleteBackward { .. } | EngineCommand::DeleteForward { .. } => {
+                        let cursor = selection_before.primary.head_utf8;
+                        let at_cursor = changes.edits().iter().find(|edit| {
+                            edit.range.start_utf8 <= cursor && cursor <= edit.range.end_utf8
+                        });
+                        (EditKind::Delete, at_cursor.cloned())
+                    }
+                    _ => (EditKind::Other, None),
+                };
+                self.commit(changes.clone(), inverse, edit, selection_before.clone(), timestamp_ms);
                 Ok(EngineTransaction {
                     kind: TransactionKind::ApplyCommand { command: other },
-                    selection_before,
+                    changes,
+                    selection_before: selection_before.primary,
                     selection_after: self.selection,
                     revision_before,
                     revision_after: self.doc.revision,
@@ -169,24 +588,79 @@ impl Engine {
 
     fn apply_non_history_command(&mut self, command: &EngineCommand) -> Result<()> {
         match command {
-            EngineCommand::ToggleStrong => self.toggle_wrapping_marker("**"),
-            EngineCommand::ToggleEmphasis => self.toggle_wrapping_marker("*"),
-            EngineCommand::ToggleInlineCode => self.toggle_wrapping_marker("`"),
+            EngineCommand::ToggleStrong => {
+                self.for_each_selection(false, |engine| engine.toggle_wrapping_marker("**"))
+            }
+            EngineCommand::ToggleEmphasis => {
+                self.for_each_selection(false, |engine| engine.toggle_wrapping_marker("*"))
+            }
+            EngineCommand::ToggleInlineCode => {
+                self.for_each_selection(false, |engine| engine.toggle_wrapping_marker("`"))
+            }
+            EngineCommand::ToggleStrikethrough => {
+                self.for_each_selection(false, |engine| engine.toggle_wrapping_marker("~~"))
+            }
+            EngineCommand::ToggleHighlight => {
+                self.for_each_selection(false, |engine| engine.toggle_wrapping_marker("=="))
+            }
             EngineCommand::ToggleHeading { level } => {
-                self.toggle_line_prefix(&format!("{} ", "#".repeat((*level).clamp(1, 6) as usize)))
+                let prefix = format!("{} ", "#".repeat((*level).clamp(1, 6) as usize));
+                self.for_each_selection(true, |engine| engine.toggle_line_prefix(&prefix))
+            }
+            EngineCommand::ToggleBlockquote => {
+                self.for_each_selection(true, Self::toggle_blockquote)
+            }
+            EngineCommand::ToggleBulletList => {
+                self.for_each_selection(true, |engine| engine.toggle_line_prefix("- "))
+            }
+            EngineCommand::ToggleOrderedList => {
+                self.for_each_selection(true, Self::toggle_ordered_list_prefix)
             }
-            EngineCommand::ToggleBlockquote => self.toggle_line_prefix("> "),
-            EngineCommand::ToggleBulletList => self.toggle_line_prefix("- "),
-            EngineCommand::ToggleOrderedList => self.toggle_ordered_list_prefix(),
+            EngineCommand::ToggleTask => self.for_each_selection(true, Self::toggle_task),
+            EngineCommand::DeleteBackward { unit } => self
+                .for_each_selection(false, |engine| engine.delete_by(*unit, Direction::Backward)),
+            EngineCommand::DeleteForward { unit } => {
+                self.for_each_selection(false, |engine| engine.delete_by(*unit, Direction::Forward))
+            }
+            EngineCommand::ExtendSelection { unit, direction } => {
+                self.for_each_selection(false, |engine| engine.extend_selection(*unit, *direction))
+            }
+            EngineCommand::AddNextOccurrence => self.add_next_occurrence(),
+            EngineCommand::SelectAllOccurrences => self.select_all_occurrences(),
+            _ => {
+                self.secondary.clear();
+                self.apply_primary_command(command)
+            }
+        }
+    }
+
+    fn apply_primary_command(&mut self, command: &EngineCommand) -> Result<()> {
+        match command {
+            EngineCommand::IndentListItem => self.move_list_items(true),
+            EngineCommand::OutdentListItem => self.move_list_items(false),
             EngineCommand::InsertFence { language } => self.insert_fence(language.clone()),
-            EngineCommand::Undo | EngineCommand::Redo => Ok(()),
+            EngineCommand::GoToFootnoteDefinition => self.go_to_footnote_definition(),
+            EngineCommand::GoToFootnoteReference => self.go_to_footnote_reference(),
+            EngineCommand::InsertFootnote => self.insert_footnote(),
+            EngineCommand::CycleCallout => self.cycle_callout(),
+            EngineCommand::InsertTableRowAbove
+            | EngineCommand::InsertTableRowBelow
+            | EngineCommand::InsertTableColumnLeft
+            | EngineCommand::InsertTableColumnRight
+            | EngineCommand::DeleteTableRow
+            | EngineCommand::DeleteTableColumn
+            | EngineCommand::SetTableAlignment { .. }
+            | EngineCommand::NextTableCell
+            | EngineCommand::PreviousTableCell => self.edit_table(command),
+            _ => Ok(()),
         }
     }
 
     fn undo_transaction(&mut self) -> Result<EngineTransaction> {
-        let Some(previous) = self.history.pop() else {
+        let Some(entry) = self.history.pop_undo() else {
             return Ok(EngineTransaction {
                 kind: TransactionKind::ApplyCommand { command: EngineCommand::Undo },
+                changes: ChangeSet::default(),
                 selection_before: self.selection,
                 selection_after: self.selection,
                 revision_before: self.doc.revision,
@@ -194,14 +668,17 @@ impl Engine {
             });
         };
 
-        let current = self.capture_state();
         let selection_before = self.selection;
         let revision_before = self.doc.revision;
-        self.future.push(current);
-        self.restore_state(previous);
+        self.apply_changes(&entry.inverse, revision_before + 1)?;
+        self.restore_selections(entry.selection_before.clone());
+        self.note_published_changes(&entry.inverse);
+        let changes = entry.inverse.clone();
+        self.history.push_redo(entry);
 
         Ok(EngineTransaction {
             kind: TransactionKind::ApplyCommand { command: EngineCommand::Undo },
+            changes,
             selection_before,
             selection_after: self.selection,
             revision_before,
@@ -210,9 +687,10 @@ impl Engine {
     }
 
     fn redo_transaction(&mut self) -> Result<EngineTransaction> {
-        let Some(next) = self.future.pop() else {
+        let Some(entry) = self.history.pop_redo() else {
             return Ok(EngineTransaction {
                 kind: TransactionKind::ApplyCommand { command: EngineCommand::Redo },
+                changes: ChangeSet::default(),
                 selection_before: self.selection,
                 selection_after: self.selection,
                 revision_before: self.doc.revision,
@@ -220,14 +698,17 @@ impl Engine {
             });
         };
 
-        let current = self.capture_state();
         let selection_before = self.selection;
         let revision_before = self.doc.revision;
-        self.history.push(current);
-        self.restore_state(next);
+        self.apply_changes(&entry.forward, revision_before + 1)?;
+        self.restore_selections(entry.selection_after.clone());
+        self.note_published_changes(&entry.forward);
+        let changes = entry.forward.clone();
+        self.history.push_undo(entry);
 
         Ok(EngineTransaction {
             kind: TransactionKind::ApplyCommand { command: EngineCommand::Redo },
+            changes,
             selection_before,
             selection_after: self.selection,
             revision_before,
@@ -235,31 +716,38 @@ impl Engine {
         })
     }
 
+    /// Wraps the selection in `marker`, or unwraps it when the markers already sit
+    /// just outside the selection or at its ends, so toggling twice restores the text
+    /// either way.
     fn toggle_wrapping_marker(&mut self, marker: &str) -> Result<()> {
         let selection = self.selection.range();
-        let markdown = self.doc.text.as_str().to_string();
         let marker_len = marker.len() as u32;
 
         if selection.is_empty() {
             let insert = format!("{marker}{marker}");
-            self.doc.text.replace_range(selection, &insert)?;
+            self.edit_text(selection, &insert)?;
             let cursor = selection.start_utf8 + marker_len;
             self.selection = Selection::collapsed(cursor);
             return Ok(());
         }
 
-        let start = selection.start_utf8 as usize;
-        let end = selection.end_utf8 as usize;
-        if start >= marker.len()
-            && end + marker.len() <= markdown.len()
-            && markdown[start - marker.len()..start] == *marker
-            && markdown[end..end + marker.len()] == *marker
-        {
-            self.doc.text.replace_range(
+        // Marker runs never cross a newline, so only the selection's first and last
+        // lines are read.
+        let text = &self.doc.text;
+        let runs = |start: usize, end: usize| -> Result<(usize, usize)> {
+            Ok(marker_runs(marker, &text.slice(TextRange::new(start as u32, end as u32))?))
+        };
+        let (start, end) = (selection.start_utf8 as usize, selection.end_utf8 as usize);
+        let (first_start, first_end) = text.line_bounds(start);
+        let (last_start, last_end) = text.line_bounds(end);
+        let before = runs(first_start, start)?.1;
+        let after = runs(end, last_end)?.0;
+        if wraps_with_marker(marker, before, after) {
+            self.edit_text(
                 TextRange::new(selection.end_utf8, selection.end_utf8 + marker_len),
                 "",
             )?;
-            self.doc.text.replace_range(
+            self.edit_text(
                 TextRange::new(selection.start_utf8 - marker_len, selection.start_utf8),
                 "",
             )?;
@@ -270,12 +758,26 @@ impl Engine {
             return Ok(());
         }
 
-        self.doc
-            .text
-            .replace_range(TextRange::new(selection.end_utf8, selection.end_utf8), marker)?;
-        self.doc
-            .text
-            .replace_range(TextRange::new(selection.start_utf8, selection.start_utf8), marker)?;
+        let leading = runs(start, first_end.min(end))?.0;
+        let trailing = runs(last_start.max(start), end)?.1;
+        if leading < end - start && wraps_with_marker(marker, leading, trailing) {
+            self.edit_text(
+                TextRange::new(selection.end_utf8 - marker_len, selection.end_utf8),
+                "",
+            )?;
+            self.edit_text(
+                TextRange::new(selection.start_utf8, selection.start_utf8 + marker_len),
+                "",
+            )?;
+            self.selection = Selection {
+                anchor_utf8: selection.start_utf8,
+                head_utf8: selection.end_utf8 - 2 * marker_len,
+            };
+            return Ok(());
+        }
+
+        self.edit_text(TextRange::new(selection.end_utf8, selection.end_utf8), marker)?;
+        self.edit_text(TextRange::new(selection.start_utf8, selection.start_utf8), marker)?;
         self.selection = Selection {
             anchor_utf8: selection.start_utf8 + marker_len,
             head_utf8: selection.end_utf8 + marker_len,
@@ -284,36 +786,106 @@ impl Engine {
     }
 
     fn toggle_line_prefix(&mut self, prefix: &str) -> Result<()> {
-        let markdown = self.doc.text.as_str().to_string();
         let anchor = self.selection.range().start_utf8 as usize;
-        let (line_start, line_end) = line_bounds(&markdown, anchor);
-        let line = &markdown[line_start..line_end];
+        let (line_start, line_end) = self.doc.text.line_bounds(anchor);
+        let line = self.doc.text.slice(TextRange::new(line_start as u32, line_end as u32))?;
         let prefix_len = prefix.len() as u32;
 
         if line.starts_with(prefix) {
-            self.doc.text.replace_range(
-                TextRange::new(line_start as u32, line_start as u32 + prefix_len),
-                "",
-            )?;
+            self.edit_text(TextRange::new(line_start as u32, line_start as u32 + prefix_len), "")?;
             self.selection =
                 shift_selection(self.selection, line_start as u32, -(prefix_len as i32));
         } else {
-            self.doc
-                .text
-                .replace_range(TextRange::new(line_start as u32, line_start as u32), prefix)?;
+            self.edit_text(TextRange::new(line_start as u32, line_start as u32), prefix)?;
             self.selection = shift_selection(self.selection, line_start as u32, prefix_len as i32);
         }
         Ok(())
     }
 
+    /// Quotes every selected line, or takes one level of quoting off them when every
+    /// line that is not blank is quoted already.
+    fn toggle_blockquote(&mut self) -> Result<()> {
+        let lines = selected_lines(&self.doc.text, self.selection.range());
+        let quoted = |(_, line): &(usize, String)| parse_blockquote(line).is_some();
+        let blank = |(_, line): &(usize, String)| line.trim().is_empty();
+        let unquote =
+            lines.iter().any(quoted) && lines.iter().all(|line| quoted(line) || blank(line));
+
+        for (line_start, line) in lines.iter().rev() {
+            let start = *line_start as u32;
+            if unquote {
+                let Some(len) = parse_blockquote(line) else {
+                    continue;
+                };
+                self.edit_text(TextRange::new(start, start + len as u32), "")?;
+                self.selection = shift_selection(self.selection, start, -(len as i32));
+            } else {
+                let prefix = if lines.len() > 1 && line.is_empty() { ">" } else { "> " };
+                self.edit_text(TextRange::new(start, start), prefix)?;
+                self.selection = shift_selection(self.selection, start, prefix.len() as i32);
+            }
+        }
+        Ok(())
+    }
+
+    /// Steps the callout around the cursor to the next kind. Outside of callouts, a
+    /// top-level blockquote at the cursor gets a `[!NOTE]` line, and lines outside of
+    /// quotes are quoted below one.
+    fn cycle_callout(&mut self) -> Result<()> {
+        let text = &self.doc.text;
+        let cursor = self.selection.head_utf8;
+        let covers = |block: &BlockNode| {
+            let (start, end) = (block.range.start_utf8, block.range.end_utf8);
+            start <= cursor && (cursor < end || (cursor == end && !ends_line(text, end)))
+        };
+
+        if let Some(callout) = callout_at(&self.doc.blocks, &covers) {
+            let start = callout.range.start_utf8 as usize;
+            let (line_start, line) = line_at(text, start);
+            let line = &line[start - line_start..];
+            let marker = parse_blockquote(line)
+                .and_then(|len| Some((len, parse_callout_marker(&line[len..])?)))
+                .map(|(len, marker)| (start + len, marker));
+            let Some((text_start, marker)) = marker else {
+                return Err(EngineError::CommandNotApplicable("no callout marker".to_string()));
+            };
+            let name = TextRange::new(
+                (text_start + marker.name.0) as u32,
+                (text_start + marker.name.1) as u32,
+            );
+            let next = marker.kind.next().name();
+            self.edit_text(name, next)?;
+            self.selection = self.selection.map(&ChangeSet::single(name, next));
+            return Ok(());
+        }
+
+        let at = match self
+            .doc
+            .blocks
+            .iter()
+            .find(|block| matches!(block.kind, BlockKind::Blockquote { .. }) && covers(block))
+        {
+            Some(quote) => quote.range.start_utf8,
+            None => {
+                let at = text.line_bounds(self.selection.range().start_utf8 as usize).0;
+                self.toggle_blockquote()?;
+                at as u32
+            }
+        };
+        let marker = format!("> [!{}]\n", CalloutKind::Note.name());
+        let insert_at = TextRange::new(at, at);
+        self.edit_text(insert_at, &marker)?;
+        self.selection = self.selection.map(&ChangeSet::single(insert_at, &marker));
+        Ok(())
+    }
+
     fn toggle_ordered_list_prefix(&mut self) -> Result<()> {
-        let markdown = self.doc.text.as_str().to_string();
         let anchor = self.selection.range().start_utf8 as usize;
-        let (line_start, line_end) = line_bounds(&markdown, anchor);
-        let line = &markdown[line_start..line_end];
+        let (line_start, line_end) = self.doc.text.line_bounds(anchor);
+        let line = self.doc.text.slice(TextRange::new(line_start as u32, line_end as u32))?;
 
-        if let Some((_number, prefix_len)) = parse_ordered_prefix(line) {
-            self.doc.text.replace_range(
+        if let Some((_number, prefix_len)) = parse_ordered_prefix(&line) {
+            self.edit_text(
                 TextRange::new(line_start as u32, (line_start + prefix_len) as u32),
                 "",
             )?;
@@ -321,185 +893,949 @@ impl Engine {
                 shift_selection(self.selection, line_start as u32, -(prefix_len as i32));
         } else {
             let prefix = "1. ";
-            self.doc
-                .text
-                .replace_range(TextRange::new(line_start as u32, line_start as u32), prefix)?;
+            self.edit_text(TextRange::new(line_start as u32, line_start as u32), prefix)?;
             self.selection =
                 shift_selection(self.selection, line_start as u32, prefix.len() as i32);
         }
         Ok(())
     }
 
-    fn insert_fence(&mut self, language: Option<String>) -> Result<()> {
-        let selection = self.selection.range();
-        let language = language.as_deref().and_then(normalize_code_language).unwrap_or_default();
-        let opening =
-            if language.is_empty() { "```".to_string() } else { format!("```{language}") };
-        let insert = format!("{opening}\n\n```");
-        self.doc.text.replace_range(selection, &insert)?;
-        let cursor = selection.start_utf8 + opening.len() as u32 + 1;
-        self.selection = Selection::collapsed(cursor);
-        Ok(())
-    }
-
-    fn transform_insert(
-        &self,
-        range: TextRange,
-        insert: &str,
-    ) -> Result<(TextRange, String, Selection)> {
-        if insert != "\n" {
-            let next_cursor = range.start_utf8 + insert.len() as u32;
-            return Ok((range, insert.to_string(), Selection::collapsed(next_cursor)));
-        }
-
-        let markdown = self.doc.text.as_str();
-        let cursor = range.start_utf8 as usize;
-        let (line_start, line_end) = line_bounds(markdown, cursor);
-        let line = &markdown[line_start..line_end];
-        let trimmed = line.trim_end_matches('\r');
-
-        if range.is_empty()
-            && cursor == line_end
-            && is_fence_trigger_line(trimmed)
-            && !has_closing_fence_below(markdown, line_end)
-        {
-            let normalized_opening = normalize_fence_opening(trimmed);
-            let insert = format!("{normalized_opening}\n\n```");
-            let selection =
-                Selection::collapsed(line_start as u32 + normalized_opening.len() as u32 + 1);
-            return Ok((TextRange::new(line_start as u32, range.end_utf8), insert, selection));
-        }
+    fn toggle_task(&mut self) -> Result<()> {
+        let lines = selected_lines(&self.doc.text, self.selection.range());
+        let multi_line = lines.len() > 1;
 
-        if range.is_empty() && cursor == line_end {
-            if let Some(prefix) = list_continuation_prefix(trimmed) {
-                let insert = format!("\n{prefix}");
-                let selection = Selection::collapsed(range.start_utf8 + insert.len() as u32);
-                return Ok((range, insert, selection));
+        for (line_start, line) in lines.iter().rev() {
+            let indent = container_prefix_len(line);
+            let body = &line[indent..];
+            if multi_line && body.trim().is_empty() {
+                continue;
             }
-        }
 
-        let next_cursor = range.start_utf8 + 1;
-        Ok((range, "\n".to_string(), Selection::collapsed(next_cursor)))
-    }
+            let item_start = (line_start + indent) as u32;
+            let marker_len = parse_bullet_marker(body)
+                .or_else(|| parse_ordered_marker(body).map(|(_number, len)| len));
 
-    fn ensure_range(&self, range: TextRange) -> Result<()> {
-        let start = range.start_utf8 as usize;
-        let end = range.end_utf8 as usize;
-        if start > end || end > self.doc.text.len_utf8() {
-            return Err(EngineError::InvalidUtf8Range { start, end });
+            let Some(marker_len) = marker_len else {
+                self.edit_text(TextRange::new(item_start, item_start), "- [ ] ")?;
+                self.selection = shift_selection(self.selection, item_start, 6);
+                continue;
+            };
+
+            let box_start = item_start + marker_len as u32;
+            match parse_task_marker(&body[marker_len..]) {
+                Some((checked, _len)) => {
+                    let mark = if checked { " " } else { "x" };
+                    self.edit_text(TextRange::new(box_start + 1, box_start + 2), mark)?;
+                }
+                None => {
+                    self.edit_text(TextRange::new(box_start, box_start), "[ ] ")?;
+                    self.selection = shift_selection(self.selection, box_start, 4);
+                }
+            }
         }
         Ok(())
     }
 
-    fn capture_state(&self) -> HistoryState {
-        HistoryState {
-            markdown: self.markdown(),
-            selection: self.selection,
-            revision: self.doc.revision,
+    /// Deletes a non-empty selection, or one `unit` from the cursor in `direction`.
+    fn delete_by(&mut self, unit: MotionUnit, direction: Direction) -> Result<()> {
+        let selected = self.selection.range();
+        let range = if selected.is_empty() {
+            motion_range(&self.doc.text, selected.start_utf8 as usize, unit, direction)
+        } else {
+            selected
+        };
+        if !range.is_empty() {
+            self.edit_text(range, "")?;
         }
+        self.selection = Selection::collapsed(range.start_utf8);
+        Ok(())
     }
 
-    fn restore_state(&mut self, state: HistoryState) {
-        self.doc = Doc::parse(state.markdown, state.revision);
-        self.selection = state.selection.clamp(self.doc.text.len_utf8() as u32);
+    /// Moves the selection head by one `unit`, keeping the anchor.
+    fn extend_selection(&mut self, unit: MotionUnit, direction: Direction) -> Result<()> {
+        let head = move_offset(&self.doc.text, self.selection.head_utf8 as usize, unit, direction);
+        self.selection.head_utf8 = head as u32;
+        Ok(())
     }
 
-    fn reparse(&mut self, revision: u64) {
-        let markdown = self.markdown();
-        self.doc = Doc::parse(markdown, revision);
-    }
-}
+    /// Selects the word at a collapsed primary cursor. Otherwise adds the next
+    /// occurrence of the primary selection's text after the last selection, wrapping
+    /// around the document, and makes it the primary.
+    fn add_next_occurrence(&mut self) -> Result<()> {
+        let primary = self.selection.range();
+        if primary.is_empty() {
+            let word = self.word_at(primary.start_utf8)?;
+            self.selection = Selection { anchor_utf8: word.start_utf8, head_utf8: word.end_utf8 };
+            return Ok(());
+        }
 
-fn line_bounds(markdown: &str, offset: usize) -> (usize, usize) {
-    let clamped = offset.min(markdown.len());
-    let start = markdown[..clamped].rfind('\n').map(|index| index + 1).unwrap_or(0);
-    let end = markdown[clamped..].find('\n').map(|index| clamped + index).unwrap_or(markdown.len());
-    (start, end)
-}
+        let needle = self.doc.text.slice(primary)?.into_owned();
+        let matches = self.doc.text.match_indices(&needle);
+        let selections = self.selections();
+        let last_end = selections.iter().last().map_or(0, |(last, _)| last.range().end_utf8);
+        let taken = |start: u32| {
+            let end = start + needle.len() as u32;
+            selections.iter().any(|(selection, _)| {
+                let range = selection.range();
+                range.start_utf8 < end && start < range.end_utf8
+            })
+        };
+        let starts = || matches.iter().map(|start| *start as u32);
+        let Some(start) = starts()
+            .filter(|start| *start >= last_end)
+            .chain(starts())
+            .find(|start| !taken(*start))
+        else {
+            return Err(EngineError::CommandNotApplicable("no further occurrence".to_string()));
+        };
 
-fn shift_selection(selection: Selection, pivot: u32, delta: i32) -> Selection {
-    Selection {
-        anchor_utf8: shift_offset(selection.anchor_utf8, pivot, delta),
-        head_utf8: shift_offset(selection.head_utf8, pivot, delta),
+        let next = Selection { anchor_utf8: start, head_utf8: start + needle.len() as u32 };
+        let mut secondary = std::mem::take(&mut self.secondary);
+        secondary.push(self.selection);
+        self.restore_selections(SelectionSet::new(next, secondary));
+        Ok(())
     }
-}
 
-fn shift_offset(offset: u32, pivot: u32, delta: i32) -> u32 {
-    if offset < pivot {
-        return offset;
-    }
-    if delta.is_negative() {
-        offset.saturating_sub(delta.unsigned_abs())
-    } else {
-        offset.saturating_add(delta as u32)
+    /// Selects every occurrence of the primary selection's text, or of the word at a
+    /// collapsed primary cursor.
+    fn select_all_occurrences(&mut self) -> Result<()> {
+        let primary = self.selection.range();
+        let target = if primary.is_empty() { self.word_at(primary.start_utf8)? } else { primary };
+        let needle = self.doc.text.slice(target)?.into_owned();
+        let secondary = self
+            .doc
+            .text
+            .match_indices(&needle)
+            .into_iter()
+            .map(|start| start as u32)
+            .filter(|start| *start != target.start_utf8)
+            .map(|start| Selection { anchor_utf8: start, head_utf8: start + needle.len() as u32 })
+            .collect::<Vec<_>>();
+        let primary = Selection { anchor_utf8: target.start_utf8, head_utf8: target.end_utf8 };
+        self.restore_selections(SelectionSet::new(primary, secondary));
+        Ok(())
     }
-}
-
-fn is_fence_trigger_line(line: &str) -> bool {
-    let Some(rest) = line.strip_prefix("```") else {
-        return false;
-    };
-    rest.chars().all(|ch| ch.is_ascii_alphanumeric() || matches!(ch, '_' | '-' | '+' | '#'))
-}
 
-fn has_closing_fence_below(markdown: &str, line_end: usize) -> bool {
-    markdown[line_end..].lines().any(|line| line.trim() == "```")
-}
-
-fn normalize_fence_opening(line: &str) -> String {
-    let suffix = line.strip_prefix("```").unwrap_or_default();
-    let normalized = normalize_code_language(suffix).unwrap_or_default();
-
-    if normalized.is_empty() {
-        "```".to_string()
-    } else {
-        format!("```{normalized}")
+    fn word_at(&self, offset: u32) -> Result<TextRange> {
+        let (line_start, line_end) = self.doc.text.line_bounds(offset as usize);
+        let line = self.doc.text.slice(TextRange::new(line_start as u32, line_end as u32))?;
+        let cursor = offset as usize - line_start;
+        let start = line[..cursor]
+            .char_indices()
+            .rev()
+            .take_while(|(_, ch)| is_word_char(*ch))
+            .last()
+            .map_or(cursor, |(index, _)| index);
+        let end = line[cursor..]
+            .char_indices()
+            .find(|(_, ch)| !is_word_char(*ch))
+            .map_or(line.len(), |(index, _)| cursor + index);
+        if start == end {
+            return Err(EngineError::CommandNotApplicable("no word at cursor".to_string()));
+        }
+        Ok(TextRange::new((line_start + start) as u32, (line_start + end) as u32))
     }
-}
 
-fn list_continuation_prefix(line: &str) -> Option<String> {
-    if line.starts_with("> ") && line[2..].trim().is_empty().not() {
-        return Some("> ".to_string());
-    }
+    /// Moves the list items touched by the selection one nesting level in or out,
+    /// together with their children, then renumbers the ordered lists involved.
+    fn move_list_items(&mut self, indent: bool) -> Result<()> {
+        let text = &self.doc.text;
+        let contexts = list_item_contexts(&self.doc.blocks);
+        let targets = selected_list_items(text, &contexts, self.selection.range());
+
+        let mut line_edits = Vec::new();
+        let mut moved_heads = Vec::new();
+        for context in targets {
+            let (head, head_line) = line_at(text, context.item.range.start_utf8 as usize);
+            let item_column = leading_space_count(&head_line);
+            let target_column = if indent {
+                context.previous.and_then(|previous| {
+                    list_content_column(&line_at(text, previous.range.start_utf8 as usize).1)
+                })
+            } else {
+                context.parent.map(|parent| {
+                    leading_space_count(&line_at(text, parent.range.start_utf8 as usize).1)
+                })
+            };
+            let Some(target_column) = target_column else {
+                continue;
+            };
+            let delta = target_column as i32 - item_column as i32;
+            if delta == 0 || delta.is_positive() != indent {
+                continue;
+            }
 
-    if line.starts_with("- ") && line[2..].trim().is_empty().not() {
-        return Some("- ".to_string());
-    }
+            let item_range = TextRange::new(head as u32, context.item.range.end_utf8);
+            for (line_start, line) in selected_lines(text, item_range) {
+                if !line.trim().is_empty() {
+                    line_edits.push((line_start, delta, leading_space_count(&line)));
+                }
+            }
+            moved_heads.push(head as u32);
+        }
 
-    if let Some((number, prefix_len)) = parse_ordered_prefix(line) {
-        if line[prefix_len..].trim().is_empty().not() {
-            return Some(format!("{}. ", number + 1));
+        if line_edits.is_empty() {
+            return Err(EngineError::CommandNotApplicable("no list item to move".to_string()));
         }
-    }
 
-    None
-}
+        line_edits.sort_by_key(|edit| Reverse(edit.0));
+        for (line_start, delta, spaces) in line_edits {
+            let pivot = line_start as u32;
+            let applied = if delta.is_positive() {
+                self.edit_text(TextRange::new(pivot, pivot), &" ".repeat(delta as usize))?;
+                delta
+            } else {
+                let removable = spaces.min(delta.unsigned_abs() as usize);
+                self.edit_text(TextRange::new(pivot, pivot + removable as u32), "")?;
+                -(removable as i32)
+            };
+            self.selection = shift_selection(self.selection, pivot, applied);
+            for head in moved_heads.iter_mut().filter(|head| **head > pivot) {
+                *head = shift_offset(*head, pivot, applied);
+            }
+        }
 
-fn parse_ordered_prefix(line: &str) -> Option<(u32, usize)> {
-    let digits = line.chars().take_while(|ch| ch.is_ascii_digit()).count();
-    if digits == 0 {
-        return None;
+        self.sync_blocks(self.doc.revision);
+        self.renumber_ordered_lists(&moved_heads)
     }
-    if !line[digits..].starts_with(". ") {
-        return None;
-    }
-    Some((line[..digits].parse::<u32>().ok()?, digits + 2))
-}
 
-trait BoolExt {
-    fn not(self) -> bool;
-}
+    /// Renumbers ordered lists that contain a moved item, hang off a moved item, or
+    /// hold the new parent of a moved item. Lists that start with a moved item or hang
+    /// off one restart at 1; the others keep their first number.
+    fn renumber_ordered_lists(&mut self, moved_heads: &[u32]) -> Result<()> {
+        let text = &self.doc.text;
+        let head_of = |item: &ListItemNode| text.line_bounds(item.range.start_utf8 as usize).0;
+        let is_moved = |item: &ListItemNode| moved_heads.contains(&(head_of(item) as u32));
+        let has_moved_child = |item: &ListItemNode| {
+            item.children.iter().any(|child| match &child.kind {
+                BlockKind::BulletList { items } | BlockKind::OrderedList { items, .. } => {
+                    items.iter().any(is_moved)
+                }
+                _ => false,
+            })
+        };
 
-impl BoolExt for bool {
-    fn not(self) -> bool {
-        !self
-    }
-}
+        let mut edits = Vec::new();
+        for list in ordered_lists(&self.doc.blocks, None) {
+            let parent_moved = list.parent.is_some_and(is_moved);
+            let affected = parent_moved
+                || list.items.iter().any(|item| is_moved(item) || has_moved_child(item));
+            if !affected {
+                continue;
+            }
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use crate::render_snapshot::RenderBlockKind;
+            let start = if parent_moved || list.items.first().is_some_and(is_moved) {
+                1
+            } else {
+                list.start
+            };
+            for (offset, item) in list.items.iter().enumerate() {
+                let (head, line) = line_at(text, item.range.start_utf8 as usize);
+                let indent = leading_space_count(&line);
+                let digits_start = head + indent;
+                let Some((number, marker_len)) = parse_ordered_prefix(&line[indent..]) else {
+                    continue;
+                };
+                let expected = start + offset as u32;
+                if number != expected {
+                    edits.push((digits_start, digits_start + marker_len - 2, expected.to_string()));
+                }
+            }
+        }
+
+        edits.sort_by_key(|edit| Reverse(edit.0));
+        for (start, end, number) in edits {
+            self.edit_text(TextRange::new(start as u32, end as u32), &number)?;
+            let pivot = (start + number.len().min(end - start)) as u32;
+            self.selection =
+                shift_selection(self.selection, pivot, number.len() as i32 - (end - start) as i32);
+        }
+        Ok(())
+    }
+
+    fn insert_fence(&mut self, language: Option<String>) -> Result<()> {
+        let selection = self.selection.range();
+        let language = language.as_deref().and_then(normalize_code_language).unwrap_or_default();
+        let opening =
+            if language.is_empty() { "```".to_string() } else { format!("```{language}") };
+        let insert = format!("{opening}\n\n```");
+        self.edit_text(selection, &insert)?;
+        let cursor = selection.start_utf8 + opening.len() as u32 + 1;
+        self.selection = Selection::collapsed(cursor);
+        Ok(())
+    }
+
+    /// Moves the cursor to the start of the content of the footnote whose reference
+    /// the cursor is in.
+    fn go_to_footnote_definition(&mut self) -> Result<()> {
+        let cursor = self.selection.head_utf8;
+        let footnotes = self.doc.footnotes();
+        let Some(reference) = footnotes.references.iter().find(|reference| {
+            reference.range.start_utf8 <= cursor && cursor <= reference.range.end_utf8
+        }) else {
+            return Err(EngineError::CommandNotApplicable(
+                "no footnote reference at the cursor".to_string(),
+            ));
+        };
+        let Some(definition) = footnotes.definition(&reference.label) else {
+            return Err(EngineError::CommandNotApplicable(format!(
+                "footnote [^{}] is not defined",
+                reference.label
+            )));
+        };
+
+        let start = definition.range.start_utf8 as usize;
+        let (line_start, line) = line_at(&self.doc.text, start);
+        let marker_end = line[start - line_start..]
+            .find("]:")
+            .map_or(start - line_start, |end| start - line_start + end + 2);
+        let content_start = line_start
+            + marker_end
+            + line[marker_end..].bytes().take_while(|byte| matches!(byte, b' ' | b'\t')).count();
+        self.selection = Selection::collapsed(content_start as u32);
+        Ok(())
+    }
+
+    /// Moves the cursor behind the first reference to the footnote definition the
+    /// cursor is in.
+    fn go_to_footnote_reference(&mut self) -> Result<()> {
+        let cursor = self.selection.head_utf8;
+        let footnotes = self.doc.footnotes();
+        // Definitions come in document order with enclosing ones first, so the last
+        // one around the cursor is the innermost.
+        let Some(definition) = footnotes.definitions.iter().rev().find(|definition| {
+            definition.range.start_utf8 <= cursor && cursor <= definition.range.end_utf8
+        }) else {
+            return Err(EngineError::CommandNotApplicable(
+                "no footnote definition at the cursor".to_string(),
+            ));
+        };
+        let Some(reference) = footnotes.references.iter().find(|reference| {
+            footnotes.definition(&reference.label).map(|site| site.node_id)
+                == Some(definition.node_id)
+        }) else {
+            return Err(EngineError::CommandNotApplicable(format!(
+                "footnote [^{}] is not referenced",
+                definition.label
+            )));
+        };
+        self.selection = Selection::collapsed(reference.range.end_utf8);
+        Ok(())
+    }
+
+    /// Inserts `[^n]` after the selection, `n` being the smallest number no footnote
+    /// uses as its label yet, appends an empty definition for it at the end of the
+    /// document and puts the cursor there.
+    fn insert_footnote(&mut self) -> Result<()> {
+        let footnotes = self.doc.footnotes();
+        let taken = |number: &u32| {
+            let label = number.to_string();
+            footnotes
+                .definitions
+                .iter()
+                .chain(&footnotes.references)
+                .any(|site| site.label == label)
+        };
+        let number = (1..).find(|number| !taken(number)).unwrap_or_default();
+
+        let text = &self.doc.text;
+        let len = text.len_utf8();
+        let tail_start = (len.saturating_sub(2)..len)
+            .find(|offset| text.is_char_boundary(*offset))
+            .unwrap_or(len);
+        let tail = text.slice(TextRange::new(tail_start as u32, len as u32))?;
+        let separator = if text.is_empty() || tail == "\n\n" {
+            ""
+        } else if tail.ends_with('\n') {
+            "\n"
+        } else {
+            "\n\n"
+        };
+        let reference = format!("[^{number}]");
+        let definition = format!("{separator}{reference}: ");
+        let end = len as u32;
+        // The definition goes in first so the reference, which is never after it,
+        // keeps its offset.
+        self.edit_text(TextRange::new(end, end), &definition)?;
+        let at = self.selection.range().end_utf8;
+        self.edit_text(TextRange::new(at, at), &reference)?;
+        self.selection =
+            Selection::collapsed(end + reference.len() as u32 + definition.len() as u32);
+        Ok(())
+    }
+
+    fn edit_table(&mut self, command: &EngineCommand) -> Result<()> {
+        let text = &self.doc.text;
+        let cursor = self.selection.head_utf8;
+        let Some(block) = table_at(&self.doc.blocks, text, cursor) else {
+            return Err(EngineError::CommandNotApplicable("no table at cursor".to_string()));
+        };
+        let mut grid = TableGrid::from_block(block, text)
+            .ok_or_else(|| EngineError::CommandNotApplicable("no table at cursor".to_string()))?;
+        // The rendered table starts with the prefix of its first line.
+        let line_start = text.line_bounds(block.range.start_utf8 as usize).0 as u32;
+        let table_start = block.range.start_utf8;
+        let table_end = table_text_end(block, text);
+        let table_range_end = block.range.end_utf8;
+        let current = cell_at(block, text, cursor);
+        let caret_offset = table_cell_caret_offset(block, current, cursor);
+
+        let mut target = current;
+        let mut select_content = false;
+        let mut keep_caret = false;
+        match command {
+            EngineCommand::InsertTableRowAbove => {
+                target.row = current.row.max(1);
+                grid.insert_row(target.row);
+            }
+            EngineCommand::InsertTableRowBelow => {
+                target.row = current.row + 1;
+                grid.insert_row(target.row);
+            }
+            EngineCommand::InsertTableColumnLeft => grid.insert_column(current.column),
+            EngineCommand::InsertTableColumnRight => {
+                target.column = current.column + 1;
+                grid.insert_column(target.column);
+            }
+            EngineCommand::DeleteTableRow | EngineCommand::DeleteTableColumn => {
+                let removes_table = match command {
+                    EngineCommand::DeleteTableRow => grid.rows.len() == 1,
+                    _ => grid.column_count() == 1,
+                };
+                if removes_table {
+                    self.edit_text(TextRange::new(table_start, table_range_end), "")?;
+                    self.selection = Selection::collapsed(table_start);
+                    return Ok(());
+                }
+                if matches!(command, EngineCommand::DeleteTableRow) {
+                    grid.remove_row(current.row);
+                    target.row = current.row.min(grid.rows.len() - 1);
+                } else {
+                    grid.remove_column(current.column);
+                    target.column = current.column.min(grid.column_count() - 1);
+                }
+            }
+            EngineCommand::SetTableAlignment { alignment } => {
+                grid.alignments[current.column] = *alignment;
+                keep_caret = true;
+            }
+            EngineCommand::NextTableCell => {
+                select_content = true;
+                if current.column + 1 < grid.column_count() {
+                    target.column += 1;
+                } else {
+                    target = CellPosition { row: current.row + 1, column: 0 };
+                    if target.row == grid.rows.len() {
+                        grid.insert_row(target.row);
+                    }
+                }
+            }
+            EngineCommand::PreviousTableCell => {
+                select_content = true;
+                if current.column > 0 {
+                    target.column -= 1;
+                } else if current.row > 0 {
+                    target = CellPosition { row: current.row - 1, column: grid.column_count() - 1 };
+                }
+            }
+            _ => return Err(EngineError::UnsupportedCommand(format!("{command:?}"))),
+        }
+
+        let rendered = grid.render();
+        self.edit_text(TextRange::new(line_start, table_end), &rendered.text)?;
+
+        let cell = rendered.cells[target.row][target.column];
+        let cell_start = line_start + cell.start_utf8;
+        let cell_end = line_start + cell.end_utf8;
+        self.selection = if select_content {
+            Selection { anchor_utf8: cell_start, head_utf8: cell_end }
+        } else if keep_caret {
+            Selection::collapsed((cell_start + caret_offset).min(cell_end))
+        } else {
+            Selection::collapsed(cell_start)
+        };
+        Ok(())
+    }
+
+    /// The edit typing `insert` over `range` makes and the caret after it. The first
+    /// input rule that applies decides; otherwise `insert` goes in as typed.
+    fn transform_insert(&self, range: TextRange, insert: &str) -> (TextEdit, Selection) {
+        self.doc
+            .registry
+            .input_rule_plugins
+            .iter()
+            .find_map(|rule| rule.apply(&self.doc.text, range, insert))
+            .unwrap_or_else(|| {
+                let caret = Selection::collapsed(range.start_utf8 + insert.len() as u32);
+                (TextEdit { range, insert: insert.to_string() }, caret)
+            })
+    }
+
+    /// The ranges a replacement of `range` applies to, paired with whether each is the
+    /// primary. When `range` covers the primary selection and there are secondary
+    /// selections, each of them gets the same replacement, widened by as many
+    /// characters as `range` reaches past the primary on either side.
+    fn edit_targets(&self, range: TextRange) -> Vec<(TextRange, bool)> {
+        let primary = self.selection.range();
+        if self.secondary.is_empty()
+            || range.start_utf8 > primary.start_utf8
+            || range.end_utf8 < primary.end_utf8
+        {
+            return vec![(range, true)];
+        }
+
+        let text = &self.doc.text;
+        let chars_in = |start: u32, end: u32| {
+            text.slice(TextRange::new(start, end)).map_or(0, |slice| slice.chars().count())
+        };
+        let before = chars_in(range.start_utf8, primary.start_utf8);
+        let after = chars_in(primary.end_utf8, range.end_utf8);
+
+        let mut targets: Vec<(TextRange, bool)> = Vec::new();
+        for (selection, is_primary) in self.selections().iter() {
+            let target = if is_primary {
+                range
+            } else {
+                let selected = selection.range();
+                TextRange::new(
+                    self.step_chars(selected.start_utf8, before, false),
+                    self.step_chars(selected.end_utf8, after, true),
+                )
+            };
+            // Widened ranges can run into each other; the primary wins over a secondary.
+            if targets.last().is_some_and(|(last, _)| last.end_utf8 > target.start_utf8) {
+                if !is_primary {
+                    continue;
+                }
+                targets.pop();
+            }
+            targets.push((target, is_primary));
+        }
+        targets
+    }
+
+    fn step_chars(&self, mut offset: u32, count: usize, forward: bool) -> u32 {
+        let len = self.doc.text.len_utf8() as u32;
+        for _ in 0..count {
+            loop {
+                if (forward && offset == len) || (!forward && offset == 0) {
+                    return offset;
+                }
+                offset = if forward { offset + 1 } else { offset - 1 };
+                if self.doc.text.is_char_boundary(offset as usize) {
+                    break;
+                }
+            }
+        }
+        offset
+    }
+
+    /// Runs a single-selection command once per selection, last to first so the
+    /// offsets of the selections still to come stay valid, and maps the other
+    /// selections through each command's edits. With `per_line`, a selection starting on
+    /// a line the previous one already covers is skipped, so line toggles apply once.
+    fn for_each_selection(
+        &mut self,
+        per_line: bool,
+        mut command: impl FnMut(&mut Self) -> Result<()>,
+    ) -> Result<()> {
+        if self.secondary.is_empty() {
+            return command(self);
+        }
+
+        let mut targets: Vec<(Selection, bool)> = Vec::new();
+        let mut covered_line = None;
+        for (selection, is_primary) in self.selections().iter() {
+            let range = selection.range();
+            if per_line {
+                let first_line = self.doc.text.byte_to_line(range.start_utf8 as usize);
+                if covered_line.is_some_and(|line| first_line <= line) {
+                    if let Some(previous) = targets.last_mut().filter(|_| is_primary) {
+                        previous.1 = true;
+                    }
+                    continue;
+                }
+                covered_line = Some(self.doc.text.byte_to_line(range.end_utf8 as usize));
+            }
+            targets.push((selection, is_primary));
+        }
+
+        let mut done: Vec<(Selection, bool)> = Vec::with_capacity(targets.len());
+        while let Some((selection, is_primary)) = targets.pop() {
+            self.selection = selection;
+            let before = std::mem::take(&mut self.pending);
+            command(self)?;
+            let step = std::mem::take(&mut self.pending);
+            // Deletions can reach back past the selections still to come.
+            for (other, _) in targets.iter_mut().chain(&mut done) {
+                *other = other.map(&step);
+            }
+            done.push((self.selection, is_primary));
+            self.pending = before.compose(&step);
+        }
+        self.restore_selections(selection_set(done));
+        Ok(())
+    }
+
+    fn to_utf8(&self, offset: u32, unit: OffsetUnit) -> Result<u32> {
+        if offset as usize > self.doc.text.metrics().len(unit) {
+            return Err(EngineError::OffsetOutOfBounds { offset: offset as usize });
+        }
+        Ok(self.doc.text.to_utf8(offset as usize, unit) as u32)
+    }
+
+    fn ensure_range(&self, range: TextRange) -> Result<()> {
+        let start = range.start_utf8 as usize;
+        let end = range.end_utf8 as usize;
+        if start > end || end > self.doc.text.len_utf8() {
+            return Err(EngineError::InvalidUtf8Range { start, end });
+        }
+        Ok(())
+    }
+
+    fn capture_state(&self) -> Checkpoint {
+        Checkpoint {
+            text: self.doc.text.clone(),
+            selections: self.selections(),
+            revision: self.doc.revision,
+        }
+    }
+
+    /// Rolls back to `state`. A command that failed before editing left the text and
+    /// blocks as they were, so only the selections go back.
+    fn restore_state(&mut self, state: Checkpoint) {
+        if !self.pending.is_empty() {
+            self.doc.text = state.text;
+            self.doc.reparse(state.revision);
+        }
+        self.restore_selections(state.selections);
+    }
+
+    /// Replaces every selection, clamped to the text and normalized.
+    fn restore_selections(&mut self, selections: SelectionSet) {
+        let selections = selections.clamp(self.doc.text.len_utf8() as u32);
+        self.selection = selections.primary;
+        self.secondary = selections.secondary;
+    }
+
+    /// Records a finished edit in the undo history and in the unpublished changes.
+    fn commit(
+        &mut self,
+        changes: ChangeSet,
+        inverse: ChangeSet,
+        edit: (EditKind, Option<TextEdit>),
+        selection_before: SelectionSet,
+        timestamp_ms: Option<u64>,
+    ) {
+        if changes.is_empty() {
+            return;
+        }
+        self.note_published_changes(&changes);
+        let selection_after = self.selections();
+        self.history.record(
+            (changes, inverse),
+            edit,
+            (selection_before, selection_after),
+            timestamp_ms,
+        );
+    }
+
+    fn note_published_changes(&mut self, changes: &ChangeSet) {
+        if let Some(published) = &mut self.published {
+            published.changes = published.changes.compose(changes);
+        }
+    }
+
+    /// Replaces text on behalf of a command, remembering the edit for its change set.
+    fn edit_text(&mut self, range: TextRange, insert: &str) -> Result<()> {
+        self.doc.text.replace_range(range, insert)?;
+        let edit = ChangeSet::single(range, insert);
+        self.pending = self.pending.compose(&edit);
+        self.unparsed = self.unparsed.compose(&edit);
+        Ok(())
+    }
+
+    /// Brings the blocks up to date with the command edits made since the last sync.
+    fn sync_blocks(&mut self, revision: u64) {
+        let changes = std::mem::take(&mut self.unparsed);
+        self.reparse_changes(&changes, revision);
+    }
+
+    fn apply_changes(&mut self, changes: &ChangeSet, revision: u64) -> Result<()> {
+        changes.apply(&mut self.doc.text)?;
+        self.reparse_changes(changes, revision);
+        Ok(())
+    }
+
+    /// Reparses the span `changes`, which the text already reflects, touched.
+    fn reparse_changes(&mut self, changes: &ChangeSet, revision: u64) {
+        match changes.covering_edit() {
+            Some((range, inserted_len)) => self.doc.reparse_edit(range, inserted_len, revision),
+            None => self.doc.revision = revision,
+        }
+    }
+}
+
+/// A single replacement turning `before` into `after`.
+fn text_diff(before: &RopeText, after: &RopeText) -> ChangeSet {
+    let (prefix, suffix) = before.common_affixes(after);
+    let end = after.len_utf8() - suffix;
+    let insert = after.slice(TextRange::new(prefix as u32, end as u32)).unwrap_or_default();
+    let range = TextRange::new(prefix as u32, (before.len_utf8() - suffix) as u32);
+    ChangeSet::single(range, insert)
+}
+
+/// Builds a selection set from selections flagged as primary or not.
+fn selection_set(selections: Vec<(Selection, bool)>) -> SelectionSet {
+    let primary =
+        selections.iter().find(|(_, is_primary)| *is_primary).map(|(selection, _)| *selection);
+    let secondary = selections
+        .into_iter()
+        .filter(|(_, is_primary)| !is_primary)
+        .map(|(selection, _)| selection);
+    SelectionSet::new(primary.unwrap_or_default(), secondary)
+}
+
+/// The lengths of the runs of `marker`'s character at the start and at the end of
+/// `text`. Markers are ASCII, so lengths are in bytes and characters alike.
+fn marker_runs(marker: &str, text: &str) -> (usize, usize) {
+    let ch = marker.as_bytes()[0];
+    let leading = text.bytes().take_while(|byte| *byte == ch).count();
+    let trailing = text.bytes().rev().take_while(|byte| *byte == ch).count();
+    (leading, trailing)
+}
+
+/// Whether runs of `marker`'s character `before` and `after` some text wrap it in
+/// `marker`: both runs are the marker, or for emphasis the three characters of a
+/// strong span around an emphasis one. `**` is strong, so it does not count as
+/// wrapped in `*`.
+fn wraps_with_marker(marker: &str, before: usize, after: usize) -> bool {
+    before == after && (before == marker.len() || (before == 3 && marker.starts_with(['*', '_'])))
+}
+
+fn is_word_char(ch: char) -> bool {
+    ch.is_alphanumeric() || ch == '_'
+}
+
+/// The start and the text, without the newline, of the line containing `offset`.
+fn line_at(text: &RopeText, offset: usize) -> (usize, String) {
+    let (start, end) = text.line_bounds(offset);
+    let line = text.slice(TextRange::new(start as u32, end as u32)).unwrap_or_default();
+    (start, line.into_owned())
+}
+
+/// Whether the text just before `offset` is a newline.
+fn ends_line(text: &RopeText, offset: u32) -> bool {
+    offset > 0 && text.slice(TextRange::new(offset - 1, offset)).is_ok_and(|last| last == "\n")
+}
+
+fn table_cell_caret_offset(block: &BlockNode, position: CellPosition, cursor: u32) -> u32 {
+    let ast::BlockKind::Table { header, rows, .. } = &block.kind else {
+        return 0;
+    };
+    let row = if position.row == 0 { Some(header) } else { rows.get(position.row - 1) };
+    row.and_then(|row| row.cells.get(position.column))
+        .map(|cell| {
+            cursor.clamp(cell.range.start_utf8, cell.range.end_utf8) - cell.range.start_utf8
+        })
+        .unwrap_or(0)
+}
+
+#[derive(Debug, Clone, Copy)]
+struct ListItemContext<'a> {
+    item: &'a ListItemNode,
+    previous: Option<&'a ListItemNode>,
+    parent: Option<&'a ListItemNode>,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct OrderedListContext<'a> {
+    start: u32,
+    items: &'a [ListItemNode],
+    parent: Option<&'a ListItemNode>,
+}
+
+/// Lists every list item in document order, parents before their children.
+fn list_item_contexts(blocks: &[BlockNode]) -> Vec<ListItemContext<'_>> {
+    fn walk<'a>(
+        blocks: &'a [BlockNode],
+        parent: Option<&'a ListItemNode>,
+        out: &mut Vec<ListItemContext<'a>>,
+    ) {
+        for block in blocks {
+            let (BlockKind::BulletList { items } | BlockKind::OrderedList { items, .. }) =
+                &block.kind
+            else {
+                continue;
+            };
+            for (index, item) in items.iter().enumerate() {
+                let previous = index.checked_sub(1).map(|previous| &items[previous]);
+                out.push(ListItemContext { item, previous, parent });
+                walk(&item.children, Some(item), out);
+            }
+        }
+    }
+
+    let mut out = Vec::new();
+    walk(blocks, None, &mut out);
+    out
+}
+
+fn ordered_lists<'a>(
+    blocks: &'a [BlockNode],
+    parent: Option<&'a ListItemNode>,
+) -> Vec<OrderedListContext<'a>> {
+    let mut out = Vec::new();
+    for block in blocks {
+        let items = match &block.kind {
+            BlockKind::OrderedList { start, items } => {
+                out.push(OrderedListContext { start: *start, items, parent });
+                items
+            }
+            BlockKind::BulletList { items } => items,
+            _ => continue,
+        };
+        for item in items {
+            out.extend(ordered_lists(&item.children, Some(item)));
+        }
+    }
+    out
+}
+
+/// Picks the outermost list items whose marker line falls inside the selected lines,
+/// falling back to the innermost item containing the cursor.
+fn selected_list_items<'a>(
+    text: &RopeText,
+    contexts: &[ListItemContext<'a>],
+    selection: TextRange,
+) -> Vec<ListItemContext<'a>> {
+    let lines = selected_lines(text, selection);
+    let first = lines.first().map_or(0, |line| line.0);
+    let last = lines.last().map_or(0, |(start, line)| start + line.len());
+
+    let mut targets: Vec<ListItemContext<'a>> = Vec::new();
+    for context in contexts {
+        // An item's marker line is selected exactly when the item starts on one.
+        let start = context.item.range.start_utf8 as usize;
+        let nested_in_target = targets
+            .last()
+            .is_some_and(|target| context.item.range.end_utf8 <= target.item.range.end_utf8);
+        if (first..=last).contains(&start) && !nested_in_target {
+            targets.push(*context);
+        }
+    }
+
+    if targets.is_empty() {
+        let cursor = selection.start_utf8;
+        targets.extend(
+            contexts
+                .iter()
+                .rev()
+                .find(|context| {
+                    context.item.range.start_utf8 <= cursor && cursor <= context.item.range.end_utf8
+                })
+                .copied(),
+        );
+    }
+    targets
+}
+
+fn leading_space_count(text: &str) -> usize {
+    text.bytes().take_while(|byte| *byte == b' ').count()
+}
+
+/// Length of the quote markers and indentation in front of a line's own content.
+fn container_prefix_len(line: &str) -> usize {
+    let mut len = 0;
+    while let Some(marker) = parse_blockquote(&line[len..]) {
+        len += marker;
+    }
+    len + line[len..].len() - line[len..].trim_start().len()
+}
+
+/// The innermost callout `covers` accepts, looking inside quotes, lists and footnotes.
+fn callout_at<'a>(
+    blocks: &'a [BlockNode],
+    covers: &impl Fn(&BlockNode) -> bool,
+) -> Option<&'a BlockNode> {
+    blocks.iter().filter(|block| covers(block)).find_map(|block| match &block.kind {
+        BlockKind::Callout { children, .. } => callout_at(children, covers).or(Some(block)),
+        BlockKind::Blockquote { children } | BlockKind::FootnoteDefinition { children, .. } => {
+            callout_at(children, covers)
+        }
+        BlockKind::BulletList { items } | BlockKind::OrderedList { items, .. } => {
+            items.iter().find_map(|item| callout_at(&item.children, covers))
+        }
+        _ => None,
+    })
+}
+
+/// Returns the bounds of every line touched by `range`. A non-empty range ending at
+/// the start of a line does not include that line.
+fn selected_lines(text: &RopeText, range: TextRange) -> Vec<(usize, String)> {
+    let start = range.start_utf8 as usize;
+    let mut end = range.end_utf8 as usize;
+    if !range.is_empty() && ends_line(text, range.end_utf8) {
+        end -= 1;
+    }
+
+    let mut lines = Vec::new();
+    let mut line_start = start;
+    loop {
+        let (start, line) = line_at(text, line_start);
+        let line_end = start + line.len();
+        lines.push((start, line));
+        if line_end >= end || line_end >= text.len_utf8() {
+            break;
+        }
+        line_start = line_end + 1;
+    }
+    lines
+}
+
+fn shift_selection(selection: Selection, pivot: u32, delta: i32) -> Selection {
+    Selection {
+        anchor_utf8: shift_offset(selection.anchor_utf8, pivot, delta),
+        head_utf8: shift_offset(selection.head_utf8, pivot, delta),
+    }
+}
+
+fn shift_offset(offset: u32, pivot: u32, delta: i32) -> u32 {
+    if offset < pivot {
+        return offset;
+    }
+    if delta.is_negative() {
+        offset.saturating_sub(delta.unsigned_abs()).max(pivot)
+    } else {
+        offset.saturating_add(delta as u32)
+    }
+}
+
+fn parse_ordered_prefix(line: &str) -> Option<(u32, usize)> {
+    let digits = line.chars().take_while(|ch| ch.is_ascii_digit()).count();
+    if digits == 0 {
+        return None;
+    }
+    if !line[digits..].starts_with(". ") {
+        return None;
+    }
+    Some((line[..digits].parse::<u32>().ok()?, digits + 2))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ast::{BlockKind, InlineKind, InlineNode, TableAlignment};
+    use crate::diagnostics::DiagnosticKind;
+    use crate::parser::{Line, ParseContext};
+    use crate::registry::{BlockSyntaxPlugin, InlineSyntaxPlugin, InputRulePlugin, RenderPlugin};
+    use crate::render_snapshot::{
+        diff_render_blocks, BlockDiff, RenderAttrs, RenderBlockKind, RenderInline, RenderInlineKind,
+    };
+
+    fn edit_range(start: u32, end: u32, insert: &str) -> TextEdit {
+        TextEdit { range: TextRange::new(start, end), insert: insert.to_string() }
+    }
 
     #[test]
     fn parses_core_markdown_blocks() {
@@ -514,6 +1850,1399 @@ mod tests {
         assert!(snapshot.blocks.iter().any(|block| block.kind == RenderBlockKind::FencedCode));
     }
 
+    #[test]
+    fn parses_pipe_tables_with_cell_ranges() {
+        let markdown = "| Name | Score |\n| :--- | ---: |\n| **Ada** | 10 |\n| Bob |\n";
+        let engine = Engine::new(markdown);
+        let table = &engine.doc().blocks[0];
+        let BlockKind::Table { alignments, header, rows } = &table.kind else {
+            panic!("expected a table block, got {:?}", table.kind);
+        };
+
+        assert_eq!(alignments, &vec![TableAlignment::Left, TableAlignment::Right]);
+        assert_eq!(table.range, TextRange::new(0, markdown.len() as u32));
+        let header_text = header
+            .cells
+            .iter()
+            .map(|cell| &markdown[cell.range.start_utf8 as usize..cell.range.end_utf8 as usize])
+            .collect::<Vec<_>>();
+        assert_eq!(header_text, vec!["Name", "Score"]);
+        assert_eq!(rows.len(), 2);
+        assert!(matches!(rows[0].cells[0].inlines[0].kind, InlineKind::Strong { .. }));
+        assert_eq!(rows[1].cells.len(), 2);
+        assert!(rows[1].cells[1].range.is_empty());
+
+        let snapshot = engine.snapshot();
+        let rendered = &snapshot.blocks[0];
+        assert_eq!(rendered.kind, RenderBlockKind::Table);
+        assert_eq!(rendered.children.len(), 3);
+        assert_eq!(rendered.children[0].attrs.table_header, Some(true));
+        assert_eq!(
+            rendered.children[1].children[1].attrs.column_alignment,
+            Some(TableAlignment::Right)
+        );
+    }
+
+    #[test]
+    fn unescapes_pipes_in_table_cells() {
+        // GFM spec example 200.
+        let engine = Engine::new("| f\\|oo  |\n| ------ |\n| b `\\|` az |\n| b **\\|** im |");
+        let BlockKind::Table { header, rows, .. } = &engine.doc().blocks[0].kind else {
+            panic!("expected a table block, got {:?}", engine.doc().blocks[0].kind);
+        };
+        assert_eq!(header.cells.len(), 1);
+        let text = header.cells[0]
+            .inlines
+            .iter()
+            .map(|inline| match &inline.kind {
+                InlineKind::Text { value } => value.as_str(),
+                other => panic!("expected text, got {other:?}"),
+            })
+            .collect::<String>();
+        assert_eq!(text, "f|oo");
+        assert!(matches!(&rows[0].cells[0].inlines[1].kind,
+            InlineKind::InlineCode { value } if value == "|"));
+        let InlineKind::Strong { children } = &rows[1].cells[0].inlines[1].kind else {
+            panic!("expected strong text, got {:?}", rows[1].cells[0].inlines[1].kind);
+        };
+        assert!(matches!(&children[..], [InlineNode { kind: InlineKind::Text { value }, .. }]
+            if value == "|"));
+    }
+
+    #[test]
+    fn table_interrupts_paragraph_but_requires_delimiter_row() {
+        let engine = Engine::new("intro\n| a | b |\n| - | - |\n\nplain | text\nnext");
+        let kinds = engine
+            .doc()
+            .blocks
+            .iter()
+            .map(|block| match block.kind {
+                BlockKind::Table { .. } => "table",
+                BlockKind::Paragraph { .. } => "paragraph",
+                _ => "other",
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(kinds, vec!["paragraph", "table", "paragraph", "paragraph"]);
+    }
+
+    #[test]
+    fn table_commands_repad_columns() {
+        let mut engine = Engine::new("| a | bb |\n|---|:-:|\n| long cell | x |\n\nafter");
+        engine.set_selection(2, 2).unwrap();
+        engine.apply_command(EngineCommand::InsertTableColumnRight).unwrap();
+        assert_eq!(
+            engine.markdown(),
+            "| a         |     | bb  |\n| --------- | --- | :-: |\n| long cell |     |  x  |\n\nafter"
+        );
+        assert_eq!(engine.selection(), Selection::collapsed(14));
+
+        engine
+            .apply_command(EngineCommand::SetTableAlignment { alignment: TableAlignment::Right })
+            .unwrap();
+        engine.apply_command(EngineCommand::InsertTableRowBelow).unwrap();
+        engine.apply_command(EngineCommand::DeleteTableColumn).unwrap();
+        assert_eq!(
+            engine.markdown(),
+            "| a         | bb  |\n| --------- | :-: |\n|           |     |\n| long cell |  x  |\n\nafter"
+        );
+
+        engine.undo().unwrap();
+        engine.undo().unwrap();
+        engine.undo().unwrap();
+        engine.undo().unwrap();
+        assert_eq!(engine.markdown(), "| a | bb |\n|---|:-:|\n| long cell | x |\n\nafter");
+    }
+
+    #[test]
+    fn table_cell_navigation_selects_cells_and_appends_rows() {
+        let mut engine = Engine::new("| a | b |\n| - | - |\n| c | d |");
+        engine.set_selection(2, 2).unwrap();
+        engine.apply_command(EngineCommand::NextTableCell).unwrap();
+        let selected = engine.selection().range();
+        assert_eq!(
+            &engine.markdown()[selected.start_utf8 as usize..selected.end_utf8 as usize],
+            "b"
+        );
+
+        engine.set_selection(selected.end_utf8, selected.end_utf8).unwrap();
+        engine.apply_command(EngineCommand::PreviousTableCell).unwrap();
+        let selected = engine.selection().range();
+        assert_eq!(
+            &engine.markdown()[selected.start_utf8 as usize..selected.end_utf8 as usize],
+            "a"
+        );
+
+        let last = engine.markdown().rfind('d').unwrap() as u32;
+        engine.set_selection(last, last).unwrap();
+        engine.apply_command(EngineCommand::NextTableCell).unwrap();
+        assert_eq!(engine.markdown(), "| a   | b   |\n| --- | --- |\n| c   | d   |\n|     |     |");
+        assert!(engine.selection().is_collapsed());
+
+        let mut outside = Engine::new("plain");
+        let error = outside.apply_command(EngineCommand::DeleteTableRow).unwrap_err();
+        assert!(matches!(error, EngineError::CommandNotApplicable(_)));
+        assert!(outside.undo().is_ok());
+        assert_eq!(outside.markdown(), "plain");
+    }
+
+    #[test]
+    fn table_commands_keep_prefixes_and_extra_cells() {
+        let mut engine = Engine::new("| é | b |\n|---|---|\n| c | d | e |");
+        engine.set_selection(3, 3).unwrap();
+        engine.apply_command(EngineCommand::InsertTableRowBelow).unwrap();
+        assert_eq!(
+            engine.markdown(),
+            "| é   | b   |\n| --- | --- |\n|     |     |\n| c   | d   | e |"
+        );
+
+        let mut indented = Engine::new("  | a |\n  |---|\n  | b |");
+        indented.set_selection(4, 4).unwrap();
+        indented.apply_command(EngineCommand::InsertTableRowBelow).unwrap();
+        assert_eq!(indented.markdown(), "  | a   |\n  | --- |\n  |     |\n  | b   |");
+
+        let mut quoted = Engine::new("> | a | b |\n> |---|---|\n> | c | d |\n");
+        quoted.set_selection(4, 4).unwrap();
+        quoted.apply_command(EngineCommand::InsertTableColumnRight).unwrap();
+        assert_eq!(
+            quoted.markdown(),
+            "> | a   |     | b   |\n> | --- | --- | --- |\n> | c   |     | d   |\n"
+        );
+
+        let mut listed = Engine::new("- | a |\n  | - |\n  | b |\n");
+        let cell = listed.markdown().rfind('b').unwrap() as u32;
+        listed.set_selection(cell, cell).unwrap();
+        listed.apply_command(EngineCommand::NextTableCell).unwrap();
+        assert_eq!(listed.markdown(), "- | a   |\n  | --- |\n  | b   |\n  |     |\n");
+    }
+
+    #[test]
+    fn parses_task_list_items() {
+        let engine = Engine::new("- [ ] todo\n- [x] done\n- plain\n1. [X] first");
+        let snapshot = engine.snapshot();
+        let checked = snapshot.blocks.iter().map(|block| block.attrs.checked).collect::<Vec<_>>();
+        assert_eq!(checked, vec![Some(false), Some(true), None, Some(true)]);
+        let RenderInline { text, range, .. } = &snapshot.blocks[0].children[0];
+        assert_eq!(text.as_deref(), Some("todo"));
+        assert_eq!(range.start_utf8, 6);
+    }
+
+    #[test]
+    fn toggles_tasks_across_selected_lines() {
+        let mut engine = Engine::new("- [ ] one\n- [x] two\n- three\nfour");
+        engine.set_selection(0, 0).unwrap();
+        engine.apply_command(EngineCommand::ToggleTask).unwrap();
+        assert_eq!(engine.markdown(), "- [x] one\n- [x] two\n- three\nfour");
+
+        let end = engine.markdown().len() as u32;
+        engine.set_selection(2, end).unwrap();
+        engine.apply_command(EngineCommand::ToggleTask).unwrap();
+        assert_eq!(engine.markdown(), "- [ ] one\n- [ ] two\n- [ ] three\n- [ ] four");
+        assert_eq!(engine.selection(), Selection { anchor_utf8: 2, head_utf8: end + 10 });
+    }
+
+    #[test]
+    fn toggles_tasks_behind_quote_markers() {
+        let mut engine = Engine::new("> - [ ] x\n>   - y\n> > z\n>\n");
+        let end = engine.markdown().len() as u32;
+        engine.set_selection(0, end).unwrap();
+        engine.apply_command(EngineCommand::ToggleTask).unwrap();
+        assert_eq!(engine.markdown(), "> - [x] x\n>   - [ ] y\n> > - [ ] z\n>\n");
+    }
+
+    #[test]
+    fn continues_task_items_on_enter() {
+        let mut engine = Engine::new("- [x] done");
+        engine.replace_text(10, 10, "\n").unwrap();
+        assert_eq!(engine.markdown(), "- [x] done\n- [ ] ");
+        assert_eq!(engine.selection(), Selection::collapsed(17));
+
+        let mut engine = Engine::new("3. [ ] next");
+        engine.replace_text(11, 11, "\n").unwrap();
+        assert_eq!(engine.markdown(), "3. [ ] next\n4. [ ] ");
+
+        let mut engine = Engine::new("* [ ] item");
+        engine.replace_text(10, 10, "\n").unwrap();
+        assert_eq!(engine.markdown(), "* [ ] item\n* [ ] ");
+
+        let mut engine = Engine::new("+ item");
+        engine.replace_text(6, 6, "\n").unwrap();
+        assert_eq!(engine.markdown(), "+ item\n+ ");
+        assert_eq!(engine.selection(), Selection::collapsed(9));
+    }
+
+    #[test]
+    fn parses_nested_lists_from_indentation() {
+        let markdown = "- top\n  - child\n    1. deep\nlazy line\n- next\n\n  second para\n\n  ```rs\n  let x = 1;\n  ```\n";
+        let engine = Engine::new(markdown);
+        let BlockKind::BulletList { items } = &engine.doc().blocks[0].kind else {
+            panic!("expected a bullet list, got {:?}", engine.doc().blocks[0].kind);
+        };
+        assert_eq!(items.len(), 2);
+
+        let BlockKind::BulletList { items: children } = &items[0].children[1].kind else {
+            panic!("expected a nested list, got {:?}", items[0].children[1].kind);
+        };
+        let BlockKind::OrderedList { start: 1, items: deep } = &children[0].children[1].kind else {
+            panic!("expected a nested ordered list, got {:?}", children[0].children[1].kind);
+        };
+        let deep_range = deep[0].children[0].range;
+        assert_eq!(
+            &markdown[deep_range.start_utf8 as usize..deep_range.end_utf8 as usize],
+            "deep\nlazy line"
+        );
+
+        let next = &items[1].children;
+        assert!(matches!(next[2].kind, BlockKind::Paragraph { .. }));
+        let BlockKind::FencedCode { language, code, .. } = &next[4].kind else {
+            panic!("expected a code block inside the item, got {:?}", next[4].kind);
+        };
+        assert_eq!(language.as_deref(), Some("rs"));
+        assert_eq!(code, "let x = 1;\n");
+
+        let depths = engine
+            .snapshot()
+            .blocks
+            .iter()
+            .map(|block| (block.kind.clone(), block.attrs.list_depth))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            depths[..4],
+            [
+                (RenderBlockKind::BulletListItem, Some(0)),
+                (RenderBlockKind::BulletListItem, Some(1)),
+                (RenderBlockKind::OrderedListItem, Some(2)),
+                (RenderBlockKind::BulletListItem, Some(0)),
+            ]
+        );
+        assert_eq!(depths[5], (RenderBlockKind::Paragraph, Some(0)));
+        assert_eq!(depths[7], (RenderBlockKind::FencedCode, Some(0)));
+    }
+
+    #[test]
+    fn continues_nested_items_at_their_indentation() {
+        let mut engine = Engine::new("- a\n  - b");
+        engine.replace_text(9, 9, "\n").unwrap();
+        assert_eq!(engine.markdown(), "- a\n  - b\n  - ");
+    }
+
+    #[test]
+    fn parses_nested_blockquotes_with_block_content() {
+        let markdown = "> # Title\n> > nested\nlazy\n>\n> - item\n> ```\n> code\n> ```";
+        let engine = Engine::new(markdown);
+        let BlockKind::Blockquote { children } = &engine.doc().blocks[0].kind else {
+            panic!("expected a blockquote, got {:?}", engine.doc().blocks[0].kind);
+        };
+        assert!(matches!(children[0].kind, BlockKind::Heading { level: 1, .. }));
+        let BlockKind::Blockquote { children: nested } = &children[1].kind else {
+            panic!("expected a nested blockquote, got {:?}", children[1].kind);
+        };
+        let BlockKind::Paragraph { inlines } = &nested[0].kind else {
+            panic!("expected a paragraph, got {:?}", nested[0].kind);
+        };
+        assert!(matches!(&inlines[..], [InlineNode { kind: InlineKind::Text { value }, .. }]
+            if value == "nested\nlazy"));
+        assert!(matches!(children[3].kind, BlockKind::BulletList { .. }));
+        assert!(
+            matches!(&children[4].kind, BlockKind::FencedCode { code, .. } if code == "code\n")
+        );
+
+        let blocks = engine
+            .snapshot()
+            .blocks
+            .into_iter()
+            .map(|block| (block.kind, block.attrs.quote_depth))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            blocks,
+            [
+                (RenderBlockKind::Heading, Some(0)),
+                (RenderBlockKind::BlockquoteParagraph, Some(1)),
+                (RenderBlockKind::BlockquoteParagraph, Some(0)),
+                (RenderBlockKind::BulletListItem, Some(0)),
+                (RenderBlockKind::FencedCode, Some(0)),
+            ]
+        );
+    }
+
+    #[test]
+    fn parses_callouts() {
+        let markdown = "> [!warning] Mind the gap\n> body\n>\n> > [!TIP]\n\n> [!NOTE]x\n";
+        let engine = Engine::new(markdown);
+        let BlockKind::Callout { kind, title, children } = &engine.doc().blocks[0].kind else {
+            panic!("expected a callout, got {:?}", engine.doc().blocks[0].kind);
+        };
+        assert_eq!((*kind, title.as_deref()), (CalloutKind::Warning, Some("Mind the gap")));
+        assert!(matches!(children[0].kind, BlockKind::Paragraph { .. }));
+        assert!(matches!(
+            &children[2].kind,
+            BlockKind::Callout { kind: CalloutKind::Tip, title: None, children } if children.is_empty()
+        ));
+        assert!(matches!(engine.doc().blocks[2].kind, BlockKind::Blockquote { .. }));
+
+        let blocks = engine
+            .snapshot()
+            .blocks
+            .into_iter()
+            .map(|block| {
+                (block.kind, block.attrs.callout_kind, block.attrs.raw_markdown.unwrap_or_default())
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            blocks[..2],
+            [
+                (
+                    RenderBlockKind::Callout,
+                    Some(CalloutKind::Warning),
+                    "> [!warning] Mind the gap".to_string()
+                ),
+                (RenderBlockKind::BlockquoteParagraph, None, "body".to_string()),
+            ]
+        );
+        assert_eq!(
+            blocks[3],
+            (RenderBlockKind::Callout, Some(CalloutKind::Tip), "> [!TIP]".to_string())
+        );
+    }
+
+    #[test]
+    fn cycles_callout_kinds() {
+        let mut engine = Engine::new("text");
+        engine.apply_command(EngineCommand::CycleCallout).unwrap();
+        assert_eq!(engine.markdown(), "> [!NOTE]\n> text");
+        assert_eq!(engine.selection(), Selection::collapsed(12));
+
+        engine.apply_command(EngineCommand::CycleCallout).unwrap();
+        assert_eq!(engine.markdown(), "> [!TIP]\n> text");
+        assert_eq!(engine.selection(), Selection::collapsed(11));
+        for _ in 0..3 {
+            engine.apply_command(EngineCommand::CycleCallout).unwrap();
+        }
+        assert_eq!(engine.markdown(), "> [!CAUTION]\n> text");
+        engine.apply_command(EngineCommand::CycleCallout).unwrap();
+        assert_eq!(engine.markdown(), "> [!NOTE]\n> text");
+
+        let mut engine = Engine::new("intro\n\n> quoted\n> more");
+        engine.set_selection(20, 20).unwrap();
+        engine.apply_command(EngineCommand::CycleCallout).unwrap();
+        assert_eq!(engine.markdown(), "intro\n\n> [!NOTE]\n> quoted\n> more");
+        assert_eq!(engine.selection(), Selection::collapsed(30));
+
+        assert_eq!(EngineCommand::parse("cycle_callout"), Some(EngineCommand::CycleCallout));
+    }
+
+    #[test]
+    fn toggles_blockquotes_across_selected_lines() {
+        let mut engine = Engine::new("one\n\n> two");
+        engine.set_selection(0, 9).unwrap();
+        engine.apply_command(EngineCommand::ToggleBlockquote).unwrap();
+        assert_eq!(engine.markdown(), "> one\n>\n> > two");
+        assert_eq!(engine.selection(), Selection { anchor_utf8: 2, head_utf8: 14 });
+
+        engine.apply_command(EngineCommand::ToggleBlockquote).unwrap();
+        assert_eq!(engine.markdown(), "one\n\n> two");
+    }
+
+    #[test]
+    fn reads_front_matter_metadata() {
+        let engine =
+            Engine::new("---\ntitle: \"Notes: draft\"\ntags:\n  - rust\n  - wasm\n---\n# Body");
+        let metadata = engine.metadata().unwrap();
+        assert_eq!(metadata["title"], MetadataValue::Text("Notes: draft".to_string()));
+        assert_eq!(
+            metadata["tags"],
+            MetadataValue::List(vec!["rust".to_string(), "wasm".to_string()])
+        );
+        let snapshot = engine.snapshot();
+        assert_eq!(snapshot.blocks[0].kind, RenderBlockKind::FrontMatter);
+        assert_eq!(snapshot.blocks[1].kind, RenderBlockKind::Heading);
+
+        let engine = Engine::new("+++\ntitle = \"Notes\"\ntags = [\"a\"]\n+++\nBody");
+        let metadata = engine.metadata().unwrap();
+        assert_eq!(metadata["title"], MetadataValue::Text("Notes".to_string()));
+        assert_eq!(metadata["tags"], MetadataValue::List(vec!["a".to_string()]));
+
+        assert!(Engine::new("Body\n---\ntitle: x\n---").metadata().is_none());
+    }
+
+    #[test]
+    fn continues_nested_quotes_on_enter() {
+        let mut engine = Engine::new("> > quoted");
+        engine.replace_text(10, 10, "\n").unwrap();
+        assert_eq!(engine.markdown(), "> > quoted\n> > ");
+
+        let mut engine = Engine::new("> - item");
+        engine.replace_text(8, 8, "\n").unwrap();
+        assert_eq!(engine.markdown(), "> - item\n> - ");
+    }
+
+    #[test]
+    fn indents_and_outdents_list_items_with_children() {
+        let mut engine = Engine::new("- a\n- b\n  - c\n- d");
+        engine.set_selection(6, 7).unwrap();
+        engine.apply_command(EngineCommand::IndentListItem).unwrap();
+        assert_eq!(engine.markdown(), "- a\n  - b\n    - c\n- d");
+        assert_eq!(engine.selection(), Selection { anchor_utf8: 8, head_utf8: 9 });
+
+        engine.apply_command(EngineCommand::OutdentListItem).unwrap();
+        assert_eq!(engine.markdown(), "- a\n- b\n  - c\n- d");
+        assert_eq!(engine.selection(), Selection { anchor_utf8: 6, head_utf8: 7 });
+
+        engine.set_selection(0, 0).unwrap();
+        let error = engine.apply_command(EngineCommand::IndentListItem).unwrap_err();
+        assert!(matches!(error, EngineError::CommandNotApplicable(_)));
+    }
+
+    #[test]
+    fn moving_ordered_items_renumbers_lists() {
+        let mut engine = Engine::new("1. a\n2. b\n3. c\n4. d");
+        let end = engine.markdown().find('c').unwrap() as u32 + 1;
+        engine.set_selection(8, end).unwrap();
+        engine.apply_command(EngineCommand::IndentListItem).unwrap();
+        assert_eq!(engine.markdown(), "1. a\n   1. b\n   2. c\n2. d");
+        let selected = engine.selection().range();
+        assert_eq!(
+            &engine.markdown()[selected.start_utf8 as usize..selected.end_utf8 as usize],
+            "b\n   2. c"
+        );
+
+        let b = engine.markdown().find('b').unwrap() as u32;
+        engine.set_selection(b, b).unwrap();
+        engine.apply_command(EngineCommand::OutdentListItem).unwrap();
+        assert_eq!(engine.markdown(), "1. a\n2. b\n   1. c\n3. d");
+
+        engine.undo().unwrap();
+        assert_eq!(engine.markdown(), "1. a\n   1. b\n   2. c\n2. d");
+    }
+
+    #[test]
+    fn blank_line_without_indented_content_ends_list() {
+        let engine = Engine::new("- a\n\nafter\n  - b");
+        let blocks = &engine.doc().blocks;
+        assert_eq!(blocks.len(), 4);
+        assert!(matches!(&blocks[0].kind, BlockKind::BulletList { items } if items.len() == 1));
+        assert!(matches!(blocks[2].kind, BlockKind::Paragraph { .. }));
+        assert!(matches!(blocks[3].kind, BlockKind::BulletList { .. }));
+    }
+
+    #[test]
+    fn typing_keeps_blocks_in_sync_with_full_parse() {
+        let mut engine = Engine::new("# Notes\n\n- one\n- two\n\n```\nfn main() {}\n```\n\ntail");
+        let two = engine.markdown().find("two").unwrap() as u32 + 3;
+        engine.set_selection(two, two).unwrap();
+
+        engine.replace_text(two, two, "\n").unwrap();
+        engine.replace_text(two + 3, two + 3, "three").unwrap();
+        let fence = engine.markdown().find("```").unwrap() as u32;
+        engine.replace_text(fence, fence + 3, "").unwrap();
+
+        let ranges =
+            |blocks: &[BlockNode]| blocks.iter().map(|block| block.range).collect::<Vec<_>>();
+        assert_eq!(
+            ranges(&engine.doc().blocks),
+            ranges(&parse_document(&engine.markdown(), engine.registry()))
+        );
+        assert_eq!(
+            engine.markdown(),
+            "# Notes\n\n- one\n- two\n- three\n\n\nfn main() {}\n```\n\ntail"
+        );
+    }
+
+    #[test]
+    fn node_ids_survive_edits_and_undo() {
+        let mut engine = Engine::new("# Title\n\nbody text\n\n- a\n- b");
+        let ids = |engine: &Engine| {
+            engine.snapshot().blocks.iter().map(|block| block.node_id).collect::<Vec<_>>()
+        };
+        let before = ids(&engine);
+
+        let end = engine.markdown().find("text").unwrap() as u32 + 4;
+        engine.replace_text(end, end, " more").unwrap();
+        assert_eq!(ids(&engine), before);
+
+        engine.set_selection(0, 0).unwrap();
+        engine.apply_command(EngineCommand::ToggleHeading { level: 1 }).unwrap();
+        assert_eq!(engine.markdown(), "Title\n\nbody text more\n\n- a\n- b");
+        assert_ne!(ids(&engine)[0], before[0]);
+        assert_eq!(ids(&engine)[1..], before[1..]);
+
+        engine.undo().unwrap();
+        assert_eq!(ids(&engine)[1..], before[1..]);
+        engine.undo().unwrap();
+        assert_eq!(engine.markdown(), "# Title\n\nbody text\n\n- a\n- b");
+        assert_eq!(ids(&engine)[1..], before[1..]);
+        let before = ids(&engine);
+
+        let b = engine.markdown().len() as u32;
+        engine.replace_text(b, b, "\n").unwrap();
+        let after = ids(&engine);
+        assert_eq!(after[..after.len() - 1], before[..]);
+        assert!(!before.contains(after.last().unwrap()));
+    }
+
+    #[test]
+    fn patches_rebuild_the_published_snapshot() {
+        let mut engine = Engine::new("# Title\n\nbody text\n\n- a\n- b\n\n```\ncode\n```");
+        let mut published = engine.publish_snapshot();
+        let mut publish = |engine: &mut Engine| {
+            let patch = engine.publish_patch();
+            assert_eq!(patch.base_revision, published.revision);
+            published = patch.apply_to(&published);
+            assert_eq!(published, engine.snapshot());
+            patch
+        };
+
+        let end = engine.markdown().find("text").unwrap() as u32 + 4;
+        engine.replace_text(end, end, " ü").unwrap();
+        let patch = publish(&mut engine);
+        assert_eq!(patch.changes, ChangeSet::single(TextRange::new(end, end), " ü"));
+        assert_eq!(patch.blocks.changed.len(), 1);
+        assert!(patch.blocks.added.is_empty() && patch.blocks.order.is_none());
+        assert_eq!(patch.blocks.offset_shifts.len(), 1);
+        assert_eq!(patch.blocks.offset_shifts[0].delta_utf8, 3);
+
+        let end = engine.markdown().find("- b").unwrap() as u32 + 3;
+        engine.set_selection(end, end).unwrap();
+        engine.replace_text(end, end, "\n").unwrap();
+        let patch = publish(&mut engine);
+        assert_eq!(patch.blocks.added.len(), 1);
+        assert!(patch.blocks.order.is_some());
+
+        engine.apply_command(EngineCommand::ToggleBulletList).unwrap();
+        publish(&mut engine);
+        engine.undo().unwrap();
+        engine.undo().unwrap();
+        publish(&mut engine);
+
+        let patch = publish(&mut engine);
+        assert!(patch.changes.is_empty());
+        assert_eq!(patch.blocks, BlockDiff::default());
+
+        engine.set_markdown("fresh");
+        let patch = publish(&mut engine);
+        assert_eq!(patch.blocks.added.len(), 1);
+        assert!(!patch.blocks.removed.is_empty());
+    }
+
+    #[test]
+    fn render_attrs_leave_unset_fields_out_of_json() {
+        let attrs = RenderAttrs { heading_level: Some(2), ..RenderAttrs::default() };
+        let json = serde_json::to_string(&attrs).unwrap();
+        assert_eq!(json, r#"{"heading_level":2}"#);
+        assert_eq!(serde_json::from_str::<RenderAttrs>(&json).unwrap(), attrs);
+        let with_nulls = r#"{"heading_level":2,"href":null}"#;
+        assert_eq!(serde_json::from_str::<RenderAttrs>(with_nulls).unwrap(), attrs);
+    }
+
+    const PATCH_LINES: &[&str] = &[
+        "",
+        "plain text with **bold** and `code`",
+        "# Heading",
+        "> quoted [link](https://example.com)",
+        "- item",
+        "- [x] done",
+        "1. first",
+        "```rust",
+        "```",
+        "| a | b |",
+        "| --- | --- |",
+        "| c | d |",
+        "[ref]: https://example.com",
+        "see [ref] and ![img](pic.png)",
+        "a note[^1] and [^2]",
+        "[^1]: the note",
+        "> [!NOTE] Title",
+        "see [[Page]] and $x^2$",
+        "~~struck~~ ==marked==",
+        "naïve café ✓",
+    ];
+
+    const PATCH_INSERTS: &[&str] =
+        &["", "x", "é", "\n", "\n\n", "- ", "> ", "# ", "```", "|", "[^1]", "    "];
+
+    proptest::proptest! {
+        #[test]
+        fn patches_rebuild_the_snapshot_after_random_edits(
+            lines in proptest::collection::vec(proptest::sample::select(PATCH_LINES), 0..24),
+            edits in proptest::collection::vec(
+                (
+                    proptest::prelude::any::<usize>(),
+                    0..24_usize,
+                    proptest::sample::select(PATCH_INSERTS),
+                    proptest::prelude::any::<bool>(),
+                ),
+                1..12,
+            ),
+        ) {
+            let mut engine = Engine::new(lines.join("\n"));
+            let mut published = engine.publish_snapshot();
+            for (start, len, insert, publish) in edits {
+                let markdown = engine.markdown();
+                let boundary = |offset: usize| {
+                    (0..=offset).rev().find(|offset| markdown.is_char_boundary(*offset)).unwrap()
+                };
+                let start = boundary(start % (markdown.len() + 1));
+                let end = boundary((start + len).min(markdown.len()));
+                engine.replace_text(start as u32, end as u32, insert).unwrap();
+                if publish {
+                    published = engine.publish_patch().apply_to(&published);
+                    proptest::prop_assert_eq!(&published, &engine.snapshot());
+                }
+            }
+            published = engine.publish_patch().apply_to(&published);
+            proptest::prop_assert_eq!(&published, &engine.snapshot());
+        }
+    }
+
+    #[test]
+    fn patches_match_a_full_diff_when_rendering_only_reparsed_blocks() {
+        let markdown =
+            "# One\n\nSee [[Two]] and [^a].\n\n![img](pic.png)\n\n> quote\n\n- a\n- b\n\n\
+                        | x | y |\n| - | - |\n| 1 | 2 |\n\n[^a]: first\n\n[^b]: second\n";
+        let mut engine = Engine::new(markdown);
+        let mut published = engine.publish_snapshot();
+        let mut publish = |engine: &mut Engine| {
+            let mut patch = engine.publish_patch();
+            let snapshot = engine.snapshot();
+            let mut full = diff_render_blocks(&published.blocks, &snapshot.blocks);
+            full.removed.sort_unstable();
+            patch.blocks.removed.sort_unstable();
+            assert_eq!(patch.blocks, full);
+            published = patch.apply_to(&published);
+            assert_eq!(published, snapshot);
+            patch
+        };
+
+        let offset = engine.markdown().find("One").unwrap() as u32;
+        engine.replace_text(offset, offset, "Big ").unwrap();
+        let end = engine.markdown().find("- b").unwrap() as u32 + 3;
+        engine.replace_text(end, end, "\n- c").unwrap();
+        let patch = publish(&mut engine);
+        assert_eq!(patch.blocks.changed.len() + patch.blocks.added.len(), 2);
+
+        let offset = engine.markdown().find("quote").unwrap() as u32;
+        engine.replace_text(offset, offset, "[^b] ").unwrap();
+        let patch = publish(&mut engine);
+        assert!(patch.blocks.changed.len() > 1);
+
+        engine.set_resolved_wiki_links(Some(HashSet::from(["Two".to_string()])));
+        publish(&mut engine);
+        engine.set_document_path(Some("/notes/today.md".to_string()));
+        publish(&mut engine);
+
+        let offset = engine.markdown().find("| 1").unwrap() as u32 + 2;
+        engine.replace_text(offset, offset + 1, "10").unwrap();
+        let patch = publish(&mut engine);
+        assert_eq!(patch.blocks.changed.len(), 1);
+        assert_eq!(patch.blocks.changed[0].kind, RenderBlockKind::Table);
+    }
+
+    #[test]
+    fn transactions_record_every_edit() {
+        let mut engine = Engine::new("say hello world");
+        engine.set_selection(4, 9).unwrap();
+        let transaction = engine.apply_command(EngineCommand::ToggleStrong).unwrap();
+        assert_eq!(transaction.changes.edits(), [edit_range(4, 4, "**"), edit_range(9, 9, "**")]);
+
+        let mut replayed = RopeText::new("say hello world");
+        transaction.changes.apply(&mut replayed).unwrap();
+        assert_eq!(replayed.to_string(), engine.markdown());
+
+        let undo = engine.apply_command(EngineCommand::Undo).unwrap();
+        assert_eq!(undo.changes.edits(), [edit_range(4, 6, ""), edit_range(11, 13, "")]);
+        assert_eq!(engine.markdown(), "say hello world");
+    }
+
+    #[test]
+    fn edits_and_toggles_apply_at_every_cursor() {
+        let mut engine = Engine::new("one\ntwo\nthree");
+        let cursors = [Selection::collapsed(7), Selection::collapsed(13)];
+        engine.set_selections(SelectionSet::new(Selection::collapsed(3), cursors)).unwrap();
+        engine.replace_text(3, 3, "!").unwrap();
+        assert_eq!(engine.markdown(), "one!\ntwo!\nthree!");
+        let typed = engine.selections();
+        assert_eq!(typed.secondary, [Selection::collapsed(9), Selection::collapsed(16)]);
+
+        engine.replace_text(3, 4, "").unwrap();
+        assert_eq!(engine.markdown(), "one\ntwo\nthree");
+        engine.undo().unwrap();
+        assert_eq!(engine.markdown(), "one!\ntwo!\nthree!");
+        assert_eq!(engine.selections(), typed);
+
+        engine.apply_command(EngineCommand::ToggleHeading { level: 1 }).unwrap();
+        assert_eq!(engine.markdown(), "# one!\n# two!\n# three!");
+        assert_eq!(engine.snapshot().secondary_selections.len(), 2);
+
+        let mut engine = Engine::new("- a\n- b");
+        let cursors = SelectionSet::new(Selection::collapsed(3), [Selection::collapsed(7)]);
+        engine.set_selections(cursors).unwrap();
+        engine.replace_text(3, 3, "\n").unwrap();
+        assert_eq!(engine.markdown(), "- a\n- \n- b\n- ");
+        assert_eq!(engine.selection(), Selection::collapsed(6));
+        assert_eq!(engine.selections().secondary, [Selection::collapsed(13)]);
+
+        let mut engine = Engine::new("one two");
+        let second = Selection { anchor_utf8: 4, head_utf8: 7 };
+        engine
+            .set_selections(SelectionSet::new(Selection { anchor_utf8: 0, head_utf8: 3 }, [second]))
+            .unwrap();
+        let transaction = engine.apply_command(EngineCommand::ToggleStrong).unwrap();
+        assert_eq!(engine.markdown(), "**one** **two**");
+        assert_eq!(transaction.changes.edits().len(), 4);
+        assert_eq!(engine.selection(), Selection { anchor_utf8: 2, head_utf8: 5 });
+        assert_eq!(engine.selections().secondary, [Selection { anchor_utf8: 10, head_utf8: 13 }]);
+
+        engine.apply_command(EngineCommand::ToggleBulletList).unwrap();
+        assert!(engine.selections().secondary.is_empty());
+        assert_eq!(engine.markdown(), "- **one** **two**");
+    }
+
+    #[test]
+    fn occurrence_commands_add_cursors() {
+        let merged = SelectionSet::new(
+            Selection { anchor_utf8: 5, head_utf8: 2 },
+            [Selection::collapsed(3), Selection::collapsed(9), Selection::collapsed(9)],
+        );
+        assert_eq!(merged.primary, Selection { anchor_utf8: 5, head_utf8: 2 });
+        assert_eq!(merged.secondary, [Selection::collapsed(9)]);
+
+        let mut engine = Engine::new("cat dog cat cat");
+        engine.set_selection(1, 1).unwrap();
+        engine.apply_command(EngineCommand::AddNextOccurrence).unwrap();
+        assert_eq!(engine.selection(), Selection { anchor_utf8: 0, head_utf8: 3 });
+        engine.apply_command(EngineCommand::AddNextOccurrence).unwrap();
+        engine.apply_command(EngineCommand::AddNextOccurrence).unwrap();
+        assert_eq!(engine.selection(), Selection { anchor_utf8: 12, head_utf8: 15 });
+        assert_eq!(engine.selections().secondary.len(), 2);
+        assert!(engine.apply_command(EngineCommand::AddNextOccurrence).is_err());
+
+        engine.set_selection(9, 9).unwrap();
+        engine.apply_command(EngineCommand::SelectAllOccurrences).unwrap();
+        assert_eq!(engine.selection(), Selection { anchor_utf8: 8, head_utf8: 11 });
+        assert_eq!(
+            engine.selections().secondary,
+            [
+                Selection { anchor_utf8: 0, head_utf8: 3 },
+                Selection { anchor_utf8: 12, head_utf8: 15 }
+            ]
+        );
+        engine.replace_text(8, 11, "owl").unwrap();
+        assert_eq!(engine.markdown(), "owl dog owl owl");
+        assert_eq!(
+            EngineCommand::parse("select_all_occurrences"),
+            Some(EngineCommand::SelectAllOccurrences)
+        );
+    }
+
+    #[test]
+    fn deletes_and_extends_by_motion_units() {
+        let mut engine = Engine::new("say 👍🏽 hi\nnext line");
+        let end = "say 👍🏽 hi".len() as u32;
+        engine.set_selection(end - 3, end - 3).unwrap();
+        let command = EngineCommand::parse("delete_backward:grapheme").unwrap();
+        let transaction = engine.apply_command_at(command.clone(), 0).unwrap();
+        assert_eq!(engine.markdown(), "say  hi\nnext line");
+        assert_eq!(transaction.changes.edits(), [edit_range(4, end - 3, "")]);
+        engine.apply_command_at(command, 50).unwrap();
+        assert_eq!(engine.markdown(), "say hi\nnext line");
+        engine.undo().unwrap();
+        assert_eq!(engine.markdown(), "say 👍🏽 hi\nnext line");
+
+        engine.set_selection(end, end).unwrap();
+        engine.apply_command(EngineCommand::parse("delete_backward:word").unwrap()).unwrap();
+        assert_eq!(engine.markdown(), "say 👍🏽 \nnext line");
+        let line_start = engine.markdown().find("next").unwrap() as u32;
+        engine.set_selection(line_start + 4, line_start + 4).unwrap();
+        engine.apply_command(EngineCommand::parse("delete_forward:line").unwrap()).unwrap();
+        assert_eq!(engine.markdown(), "say 👍🏽 \nnext");
+
+        let mut engine = Engine::new("ab cd\nef gh");
+        let cursors = SelectionSet::new(Selection::collapsed(5), [Selection::collapsed(11)]);
+        engine.set_selections(cursors).unwrap();
+        engine.apply_command(EngineCommand::parse("extend_backward:word").unwrap()).unwrap();
+        assert_eq!(engine.selection(), Selection { anchor_utf8: 5, head_utf8: 3 });
+        assert_eq!(engine.selections().secondary, [Selection { anchor_utf8: 11, head_utf8: 9 }]);
+        engine.apply_command(EngineCommand::DeleteForward { unit: MotionUnit::Grapheme }).unwrap();
+        assert_eq!(engine.markdown(), "ab \nef ");
+        assert_eq!(engine.selections().secondary, [Selection::collapsed(7)]);
+        assert!(EngineCommand::parse("extend_forward:sentence").is_none());
+    }
+
+    #[test]
+    fn converts_offsets_between_units() {
+        let engine = Engine::new("a🙂\nb");
+        assert_eq!(engine.convert_offset(3, OffsetUnit::Utf16, OffsetUnit::Utf8), Ok(5));
+        assert_eq!(engine.convert_offset(5, OffsetUnit::Utf8, OffsetUnit::Char), Ok(2));
+        assert_eq!(engine.convert_offset(2, OffsetUnit::Utf16, OffsetUnit::Utf8), Ok(1));
+        assert_eq!(engine.line_col(4, OffsetUnit::Utf16), Ok(LineCol { line: 1, column: 0 }));
+        assert_eq!(engine.offset_at(LineCol { line: 1, column: 1 }, OffsetUnit::Utf16), 5);
+        assert_eq!(
+            engine.convert_offset(6, OffsetUnit::Utf16, OffsetUnit::Utf8),
+            Err(EngineError::OffsetOutOfBounds { offset: 6 })
+        );
+    }
+
+    struct MentionSyntax;
+
+    impl InlineSyntaxPlugin for MentionSyntax {
+        fn name(&self) -> &str {
+            "mention"
+        }
+
+        fn parse(
+            &self,
+            cx: &mut ParseContext<'_>,
+            start: usize,
+            limit: usize,
+        ) -> Option<(InlineNode, usize)> {
+            let handle = cx.markdown()[start..limit].strip_prefix('@')?;
+            let len = handle.find(|ch: char| !ch.is_ascii_alphanumeric()).unwrap_or(handle.len());
+            let end = start + 1 + len;
+            let kind = InlineKind::Custom {
+                name: "mention".to_string(),
+                value: handle.get(..len).filter(|handle| !handle.is_empty())?.to_string(),
+            };
+            let node = InlineNode {
+                node_id: cx.next_id(),
+                range: TextRange::new(start as u32, end as u32),
+                kind,
+            };
+            Some((node, end))
+        }
+    }
+
+    struct CommentSyntax;
+
+    impl BlockSyntaxPlugin for CommentSyntax {
+        fn name(&self) -> &str {
+            "comment"
+        }
+
+        fn parse(
+            &self,
+            cx: &mut ParseContext<'_>,
+            lines: &[Line<'_>],
+            index: usize,
+        ) -> Option<(BlockNode, usize)> {
+            let line = lines[index];
+            line.text.strip_prefix("%% ")?;
+            let kind = BlockKind::Custom {
+                name: "comment".to_string(),
+                inlines: cx.parse_inlines(line.start + 3, line.end),
+            };
+            let range = TextRange::new(line.start as u32, line.end as u32);
+            Some((BlockNode { node_id: cx.next_id(), range, kind }, index + 1))
+        }
+
+        fn interrupts_paragraph(&self, lines: &[Line<'_>], index: usize) -> bool {
+            lines[index].text.starts_with("%% ")
+        }
+    }
+
+    struct ArrowRule;
+
+    impl InputRulePlugin for ArrowRule {
+        fn name(&self) -> &str {
+            "arrow"
+        }
+
+        fn apply(
+            &self,
+            text: &RopeText,
+            range: TextRange,
+            insert: &str,
+        ) -> Option<(TextEdit, Selection)> {
+            let start = range.start_utf8.checked_sub(1)?;
+            if insert != ">" || text.slice(TextRange::new(start, range.start_utf8)).ok()? != "-" {
+                return None;
+            }
+            let edit =
+                TextEdit { range: TextRange::new(start, range.end_utf8), insert: "→".into() };
+            Some((edit, Selection::collapsed(start + "→".len() as u32)))
+        }
+    }
+
+    struct MentionClass;
+
+    impl RenderPlugin for MentionClass {
+        fn name(&self) -> &str {
+            "mention-class"
+        }
+
+        fn render_inline(&self, source: &InlineNode, inline: &mut RenderInline) {
+            if matches!(&source.kind, InlineKind::Custom { name, .. } if name == "mention") {
+                inline.attrs.class_name = Some("mention".to_string());
+            }
+        }
+    }
+
+    #[test]
+    fn parses_images_reference_links_and_autolinks() {
+        let markdown =
+            "![a *cat*](cat.png \"The cat\") [docs][Ref] [ref] <https://x.dev> <me@x.dev>\n\n\
+                        [ref]: <https://example.com/a b> 'Example'\n[ref]: https://ignored";
+        let engine = Engine::new(markdown);
+        let BlockKind::Paragraph { inlines } = &engine.doc().blocks[0].kind else {
+            panic!("expected a paragraph, got {:?}", engine.doc().blocks[0].kind);
+        };
+        let targets = inlines
+            .iter()
+            .filter_map(|inline| match &inline.kind {
+                InlineKind::Link { href, title, .. }
+                | InlineKind::Image { src: href, title, .. } => {
+                    Some((href.as_str(), title.as_deref()))
+                }
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            targets,
+            [
+                ("cat.png", Some("The cat")),
+                ("https://example.com/a b", Some("Example")),
+                ("https://example.com/a b", Some("Example")),
+                ("https://x.dev", None),
+                ("mailto:me@x.dev", None),
+            ]
+        );
+
+        let snapshot = engine.snapshot();
+        let image = &snapshot.blocks[0].children[0];
+        assert_eq!(image.kind, RenderInlineKind::Image);
+        assert_eq!(image.attrs.alt.as_deref(), Some("a cat"));
+        assert_eq!(&markdown[image.range.start_utf8 as usize..image.range.end_utf8 as usize], {
+            "a *cat*](cat.png \"The cat\")"
+        });
+        let kinds = snapshot.blocks.iter().map(|block| block.kind.clone()).collect::<Vec<_>>();
+        assert_eq!(kinds[2..], [RenderBlockKind::LinkDefinition, RenderBlockKind::LinkDefinition]);
+
+        let unresolved = Engine::new("[missing] and [text][missing]");
+        let BlockKind::Paragraph { inlines } = &unresolved.doc().blocks[0].kind else {
+            panic!("expected a paragraph");
+        };
+        assert!(matches!(&inlines[..], [InlineNode { kind: InlineKind::Text { .. }, .. }]));
+    }
+
+    #[test]
+    fn parses_block_syntax_from_other_editors() {
+        let markdown = "Title\n=====\n\n***\n\n    let x = 1;\n\n    x += 1;\n\n\
+                        ~~~~ rust extra\n  ```\n~~~~\n\n<div class=\"note\">\n*raw*\n</div>\n\n\
+                        ## Closed ##";
+        let engine = Engine::new(markdown);
+        let blocks = engine
+            .doc()
+            .blocks
+            .iter()
+            .filter(|block| !block.range.is_empty())
+            .map(|block| &block.kind)
+            .collect::<Vec<_>>();
+        assert!(matches!(blocks[0], BlockKind::Heading { level: 1, .. }));
+        assert_eq!(blocks[1], &BlockKind::ThematicBreak);
+        let BlockKind::IndentedCode { code, .. } = blocks[2] else {
+            panic!("expected an indented code block, got {:?}", blocks[2]);
+        };
+        assert_eq!(code, "let x = 1;\n\nx += 1;\n");
+        let BlockKind::FencedCode { language, code, .. } = blocks[3] else {
+            panic!("expected a tilde fence, got {:?}", blocks[3]);
+        };
+        assert_eq!((language.as_deref(), code.as_str()), (Some("rust"), "  ```\n"));
+        assert_eq!(
+            blocks[4],
+            &BlockKind::HtmlBlock { html: "<div class=\"note\">\n*raw*\n</div>".to_string() }
+        );
+        let BlockKind::Heading { level: 2, inlines } = blocks[5] else {
+            panic!("expected an ATX heading, got {:?}", blocks[5]);
+        };
+        assert!(matches!(&inlines[..], [InlineNode { kind: InlineKind::Text { value }, .. }]
+            if value == "Closed"));
+
+        let kinds = engine.snapshot().blocks.into_iter().map(|block| block.kind);
+        let kinds = kinds.filter(|kind| *kind != RenderBlockKind::Paragraph).collect::<Vec<_>>();
+        assert_eq!(
+            kinds,
+            [
+                RenderBlockKind::Heading,
+                RenderBlockKind::ThematicBreak,
+                RenderBlockKind::IndentedCode,
+                RenderBlockKind::FencedCode,
+                RenderBlockKind::HtmlBlock,
+                RenderBlockKind::Heading,
+            ]
+        );
+    }
+
+    #[test]
+    fn parses_inline_and_display_math() {
+        let markdown =
+            "Mass $E = mc^2$ costs $5 and $6, $$\\int$$ `$x$`\n\n$$\n\\frac{a}{b}\n$$\n$$ x $$";
+        let engine = Engine::new(markdown);
+        let blocks = &engine.doc().blocks;
+        let BlockKind::Paragraph { inlines } = &blocks[0].kind else {
+            panic!("expected a paragraph, got {:?}", blocks[0].kind);
+        };
+        let math = inlines
+            .iter()
+            .filter_map(|inline| match &inline.kind {
+                InlineKind::Math { display, tex } => Some((
+                    *display,
+                    tex.as_str(),
+                    &markdown[inline.range.start_utf8 as usize..inline.range.end_utf8 as usize],
+                )),
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(math, [(false, "E = mc^2", "$E = mc^2$"), (true, "\\int", "$$\\int$$")]);
+
+        let display = blocks.iter().filter(|block| !block.range.is_empty()).skip(1);
+        let display = display
+            .map(|block| match &block.kind {
+                BlockKind::MathBlock { text_range, tex } => (
+                    tex.as_str(),
+                    &markdown[text_range.start_utf8 as usize..text_range.end_utf8 as usize],
+                ),
+                kind => panic!("expected a math block, got {kind:?}"),
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(display, [("\\frac{a}{b}", "\\frac{a}{b}"), ("x", "x")]);
+
+        let snapshot = engine.snapshot();
+        let inline = snapshot.blocks[0]
+            .children
+            .iter()
+            .find(|inline| inline.kind == RenderInlineKind::Math)
+            .unwrap();
+        assert_eq!(inline.text.as_deref(), Some("E = mc^2"));
+        assert_eq!(inline.attrs.raw_markdown.as_deref(), Some("$E = mc^2$"));
+        assert_eq!(inline.attrs.display_math, Some(false));
+        let block = snapshot.blocks.iter().find(|block| block.kind == RenderBlockKind::MathBlock);
+        assert_eq!(block.unwrap().children[0].text.as_deref(), Some("\\frac{a}{b}"));
+    }
+
+    #[test]
+    fn links_footnote_references_to_definitions() {
+        let markdown = "One[^b] and two[^a], again[^b] and [^gone].\n\n[^a]: First\n\n\
+                        [^b]: Second\n\n    More\n\n[^c]: Unused";
+        let engine = Engine::new(markdown);
+        let blocks = &engine.doc().blocks;
+        let definitions = blocks
+            .iter()
+            .filter_map(|block| match &block.kind {
+                BlockKind::FootnoteDefinition { label, children } => {
+                    let paragraphs = children.iter().filter(|child| !child.range.is_empty());
+                    Some((label.as_str(), block.node_id, paragraphs.count()))
+                }
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            definitions.iter().map(|(label, ..)| *label).collect::<Vec<_>>(),
+            ["a", "b", "c"]
+        );
+        assert_eq!(definitions[1].2, 2, "indented lines continue the definition");
+
+        let BlockKind::Paragraph { inlines } = &blocks[0].kind else {
+            panic!("expected a paragraph, got {:?}", blocks[0].kind);
+        };
+        let references = inlines
+            .iter()
+            .filter_map(|inline| match &inline.kind {
+                InlineKind::FootnoteReference { label, definition } => {
+                    Some((label.as_str(), *definition))
+                }
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+        let (a, b) = (definitions[0].1, definitions[1].1);
+        assert_eq!(references, [("b", Some(b)), ("a", Some(a)), ("b", Some(b)), ("gone", None)]);
+
+        let snapshot = engine.snapshot();
+        let numbers = snapshot
+            .blocks
+            .iter()
+            .filter(|block| block.kind == RenderBlockKind::FootnoteDefinition)
+            .map(|block| {
+                (block.attrs.footnote_label.as_deref().unwrap(), block.attrs.footnote_number)
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(numbers, [("a", Some(2)), ("b", Some(1)), ("c", None)]);
+        let first = snapshot.blocks[0]
+            .children
+            .iter()
+            .find(|inline| inline.kind == RenderInlineKind::FootnoteReference)
+            .unwrap();
+        assert_eq!(first.attrs.raw_markdown.as_deref(), Some("[^b]"));
+        assert_eq!(first.attrs.footnote_number, Some(1));
+
+        let diagnostics = engine
+            .diagnostics()
+            .into_iter()
+            .map(|diagnostic| {
+                let range =
+                    diagnostic.range.start_utf8 as usize..diagnostic.range.end_utf8 as usize;
+                (diagnostic.kind, &markdown[range])
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            diagnostics,
+            [
+                (DiagnosticKind::UndefinedFootnote, "[^gone]"),
+                (DiagnosticKind::UnusedFootnote, "[^c]: Unused")
+            ]
+        );
+    }
+
+    #[test]
+    fn footnote_definitions_cover_fences_nested_in_them() {
+        let markdown = "[^a]: ```\n    x\n    ```\n\nafter";
+        let mut engine = Engine::new(markdown);
+        let definition = &engine.doc().blocks[0];
+        let BlockKind::FootnoteDefinition { children, .. } = &definition.kind else {
+            panic!("expected a footnote definition, got {:?}", definition.kind);
+        };
+        assert!(matches!(children[0].kind, BlockKind::FencedCode { .. }));
+        assert!(children.iter().all(|child| child.range.end_utf8 <= definition.range.end_utf8));
+
+        // Published blocks are rendered from the definition's own text.
+        assert_eq!(engine.publish_snapshot(), engine.snapshot());
+    }
+
+    #[test]
+    fn footnote_commands_navigate_and_insert() {
+        let mut engine = Engine::new("See[^n] here.\n\n[^n]:  The note");
+        engine.set_selection(5, 5).unwrap();
+        engine.apply_command(EngineCommand::GoToFootnoteDefinition).unwrap();
+        assert_eq!(engine.selection(), Selection::collapsed(22));
+        engine.set_selection(27, 27).unwrap();
+        engine.apply_command(EngineCommand::GoToFootnoteReference).unwrap();
+        assert_eq!(engine.selection(), Selection::collapsed(7));
+        engine.set_selection(0, 0).unwrap();
+        assert!(engine.apply_command(EngineCommand::GoToFootnoteDefinition).is_err());
+
+        engine.replace_text(26, 26, "[^1] and [^3]").unwrap();
+        engine.set_selection(0, 3).unwrap();
+        engine.apply_command(EngineCommand::InsertFootnote).unwrap();
+        let markdown = engine.markdown();
+        assert_eq!(markdown, "See[^2][^n] here.\n\n[^n]:  The [^1] and [^3]note\n\n[^2]: ");
+        assert_eq!(engine.selection(), Selection::collapsed(markdown.len() as u32));
+        assert!(engine.diagnostics().iter().any(|diagnostic| {
+            diagnostic.kind == DiagnosticKind::UndefinedFootnote
+                && diagnostic.message.contains("[^1]")
+        }));
+        engine.undo().unwrap();
+        assert_eq!(engine.markdown(), "See[^n] here.\n\n[^n]:  The [^1] and [^3]note");
+        assert_eq!(
+            EngineCommand::parse("go_to_footnote_reference"),
+            Some(EngineCommand::GoToFootnoteReference)
+        );
+    }
+
+    #[test]
+    fn matches_delimiter_runs_like_commonmark() {
+        let markdown = "2 * 3 * 4 \\*not\\* ***both*** __a__ `` a`b ``  \nnext";
+        let engine = Engine::new(markdown);
+        let BlockKind::Paragraph { inlines } = &engine.doc().blocks[0].kind else {
+            panic!("expected a paragraph");
+        };
+        let text = |range: TextRange| &markdown[range.start_utf8 as usize..range.end_utf8 as usize];
+        assert!(
+            matches!(&inlines[0].kind, InlineKind::Text { value } if value == "2 * 3 * 4 *not* ")
+        );
+        let InlineKind::Emphasis { children } = &inlines[1].kind else {
+            panic!("expected emphasis around strong, got {:?}", inlines[1].kind);
+        };
+        assert!(matches!(children[0].kind, InlineKind::Strong { .. }));
+        assert_eq!(text(children[0].range), "both");
+        assert!(matches!(inlines[3].kind, InlineKind::Strong { .. }));
+        assert!(matches!(&inlines[5].kind, InlineKind::InlineCode { value } if value == "a`b"));
+        assert!(matches!(inlines[6].kind, InlineKind::HardBreak));
+        assert_eq!(engine.snapshot().blocks[0].children[6].kind, RenderInlineKind::HardBreak);
+    }
+
+    #[test]
+    fn parses_strikethrough_highlight_and_scripts() {
+        let markdown = "~~gone~~ ==*marked*== H~2~O x^2^ a ~~~b~~~ ~c~~ ===d=== e == f";
+        let engine = Engine::new(markdown);
+        let BlockKind::Paragraph { inlines } = &engine.doc().blocks[0].kind else {
+            panic!("expected a paragraph");
+        };
+        let text = |range: TextRange| &markdown[range.start_utf8 as usize..range.end_utf8 as usize];
+        let marks = inlines
+            .iter()
+            .filter_map(|inline| {
+                let name = match &inline.kind {
+                    InlineKind::Strikethrough { .. } => "strikethrough",
+                    InlineKind::Highlight { .. } => "highlight",
+                    InlineKind::Subscript { .. } => "subscript",
+                    InlineKind::Superscript { .. } => "superscript",
+                    _ => return None,
+                };
+                Some((name, text(inline.range)))
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            marks,
+            [
+                ("strikethrough", "gone"),
+                ("highlight", "*marked*"),
+                ("subscript", "2"),
+                ("superscript", "2")
+            ]
+        );
+        let InlineKind::Highlight { children } = &inlines[2].kind else {
+            panic!("expected a highlight, got {:?}", inlines[2].kind);
+        };
+        assert!(matches!(children[0].kind, InlineKind::Emphasis { .. }));
+        assert!(matches!(
+            &inlines.last().unwrap().kind,
+            InlineKind::Text { value } if value.ends_with(" a ~~~b~~~ ~c~~ ===d=== e == f")
+        ));
+
+        let kinds = engine.snapshot().blocks[0]
+            .children
+            .iter()
+            .map(|inline| inline.kind.clone())
+            .filter(|kind| *kind != RenderInlineKind::Text)
+            .collect::<Vec<_>>();
+        assert_eq!(
+            kinds,
+            [
+                RenderInlineKind::Strikethrough,
+                RenderInlineKind::Highlight,
+                RenderInlineKind::Subscript,
+                RenderInlineKind::Superscript
+            ]
+        );
+    }
+
+    #[test]
+    fn editing_a_definition_updates_references_elsewhere() {
+        let mut engine = Engine::new("see [site]\n\n[site]: https://a.example");
+        let end = engine.markdown().len() as u32;
+        engine.replace_text(end - 9, end, "b.example").unwrap();
+        let link = &engine.snapshot().blocks[0].children[1];
+        assert_eq!(link.attrs.href.as_deref(), Some("https://b.example"));
+
+        engine.replace_text(13, 14, "x").unwrap();
+        assert_eq!(engine.markdown(), "see [site]\n\n[xite]: https://b.example");
+        assert_eq!(engine.snapshot().blocks[0].children.len(), 1);
+    }
+
+    #[test]
+    fn parses_wiki_links_and_flags_unresolved_targets() {
+        let markdown = "[[Home]], [[Guide#Setup|setup]] and [[#Top]]\n\n\
+                        > [[Missing]] [[Home]] [[]] [[a [b]]]";
+        let mut engine = Engine::new(markdown);
+        let BlockKind::Paragraph { inlines } = &engine.doc().blocks[0].kind else {
+            panic!("expected a paragraph, got {:?}", engine.doc().blocks[0].kind);
+        };
+        assert!(matches!(
+            &inlines[2].kind,
+            InlineKind::WikiLink { target, alias: Some(alias), heading: Some(heading) }
+                if target == "Guide" && alias == "setup" && heading == "Setup"
+        ));
+        assert_eq!(engine.wiki_link_targets(), ["Home", "Guide", "Missing"]);
+
+        let links = |engine: &mut Engine| {
+            let snapshot = engine.snapshot();
+            let mut links = Vec::new();
+            for block in &snapshot.blocks {
+                for inline in &block.children {
+                    if inline.kind == RenderInlineKind::WikiLink {
+                        links.push((inline.text.clone().unwrap(), inline.attrs.unresolved));
+                    }
+                }
+            }
+            links
+        };
+        assert!(links(&mut engine).iter().all(|(_, unresolved)| unresolved.is_none()));
+
+        let resolved = ["Home".to_string(), "Guide".to_string()].into_iter().collect();
+        engine.set_resolved_wiki_links(Some(resolved));
+        assert_eq!(
+            links(&mut engine),
+            [
+                ("Home".to_string(), Some(false)),
+                ("setup".to_string(), Some(false)),
+                ("Top".to_string(), Some(false)),
+                ("Missing".to_string(), Some(true)),
+                ("Home".to_string(), Some(false)),
+            ]
+        );
+    }
+
+    #[test]
+    fn resolves_relative_image_sources_against_the_document() {
+        let mut engine = Engine::new(
+            "![](img/a.png) ![](../b.png) ![](./c.png) ![](https://x.dev/d.png) ![](/e.png)",
+        );
+        let sources = |engine: &Engine| {
+            engine.snapshot().blocks[0]
+                .children
+                .iter()
+                .filter_map(|inline| inline.attrs.src.clone())
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(
+            sources(&engine),
+            ["img/a.png", "../b.png", "./c.png", "https://x.dev/d.png", "/e.png"]
+        );
+
+        engine.set_document_path(Some("/notes/daily/today.md".to_string()));
+        assert_eq!(
+            sources(&engine),
+            [
+                "/notes/daily/img/a.png",
+                "/notes/b.png",
+                "/notes/daily/c.png",
+                "https://x.dev/d.png",
+                "/e.png"
+            ]
+        );
+
+        engine.set_document_path(Some(r"C:\notes\today.md".to_string()));
+        assert_eq!(sources(&engine)[..2], [r"C:\notes\img\a.png", r"C:\b.png"]);
+    }
+
+    #[test]
+    fn registered_plugins_extend_parsing_input_and_rendering() {
+        let mut registry = EnginePluginRegistry::core_markdown();
+        registry
+            .register_block_syntax(CommentSyntax)
+            .register_inline_syntax(MentionSyntax)
+            .register_input_rule(ArrowRule)
+            .register_render(MentionClass);
+        let mut engine = Engine::with_registry("ping @alice\n%% **todo**\n\n# Done", registry);
+
+        let blocks = &engine.doc().blocks;
+        let BlockKind::Paragraph { inlines } = &blocks[0].kind else {
+            panic!("expected paragraph");
+        };
+        assert!(matches!(&inlines[1].kind, InlineKind::Custom { value, .. } if value == "alice"));
+        let BlockKind::Custom { name, inlines } = &blocks[1].kind else {
+            panic!("expected comment block");
+        };
+        assert_eq!(name, "comment");
+        assert!(matches!(inlines[0].kind, InlineKind::Strong { .. }));
+
+        let snapshot = engine.snapshot();
+        let mention = &snapshot.blocks[0].children[1];
+        assert_eq!(mention.kind, RenderInlineKind::Custom);
+        assert_eq!(mention.attrs.class_name.as_deref(), Some("mention"));
+        assert_eq!(snapshot.blocks[1].kind, RenderBlockKind::Custom);
+        assert_eq!(snapshot.blocks[3].attrs.heading_level, Some(1));
+
+        let end = engine.markdown().len() as u32;
+        engine.replace_text(end, end, " -").unwrap();
+        engine.replace_text(end + 2, end + 2, ">").unwrap();
+        engine.replace_text(0, 0, "%% @bob\n").unwrap();
+        assert_eq!(engine.markdown(), "%% @bob\nping @alice\n%% **todo**\n\n# Done →");
+        let ranges =
+            |blocks: &[BlockNode]| blocks.iter().map(|block| block.range).collect::<Vec<_>>();
+        assert_eq!(
+            ranges(&engine.doc().blocks),
+            ranges(&parse_document(&engine.markdown(), engine.registry()))
+        );
+    }
+
     #[test]
     fn preserves_round_trip_markdown() {
         let markdown = "Paragraph with **bold** and `code` and [link](https://example.com).";
@@ -549,6 +3278,40 @@ mod tests {
         assert_eq!(engine.markdown(), "hello");
     }
 
+    #[test]
+    fn toggles_strikethrough_highlight_and_nested_markers() {
+        let mut engine = Engine::new("hello");
+        engine.set_selection(0, 5).unwrap();
+        engine.apply_command(EngineCommand::ToggleStrikethrough).unwrap();
+        assert_eq!(engine.markdown(), "~~hello~~");
+        engine.set_selection(0, 9).unwrap();
+        engine.apply_command(EngineCommand::ToggleStrikethrough).unwrap();
+        assert_eq!(engine.markdown(), "hello");
+        assert_eq!(engine.selection(), Selection { anchor_utf8: 0, head_utf8: 5 });
+
+        engine.set_markdown("**hello** ***both***");
+        engine.set_selection(2, 7).unwrap();
+        engine.apply_command(EngineCommand::ToggleEmphasis).unwrap();
+        assert_eq!(engine.markdown(), "***hello*** ***both***");
+        engine.set_selection(12, 22).unwrap();
+        engine.apply_command(EngineCommand::ToggleStrong).unwrap();
+        assert_eq!(engine.markdown(), "***hello*** *both*");
+        engine.set_selection(0, 11).unwrap();
+        engine.apply_command(EngineCommand::ToggleHighlight).unwrap();
+        assert_eq!(engine.markdown(), "==***hello***== *both*");
+        assert_eq!(EngineCommand::parse("toggle_highlight"), Some(EngineCommand::ToggleHighlight));
+
+        engine.set_markdown("~~one\ntwo~~ three");
+        engine.set_selection(0, 11).unwrap();
+        engine.apply_command(EngineCommand::ToggleStrikethrough).unwrap();
+        assert_eq!(engine.markdown(), "one\ntwo three");
+        engine.set_selection(0, 7).unwrap();
+        engine.apply_command(EngineCommand::ToggleStrikethrough).unwrap();
+        engine.set_selection(2, 9).unwrap();
+        engine.apply_command(EngineCommand::ToggleStrikethrough).unwrap();
+        assert_eq!(engine.markdown(), "one\ntwo three");
+    }
+
     #[test]
     fn supports_undo_redo() {
         let mut engine = Engine::new("hello");
@@ -0,0 +1,232 @@
+use serde::{Deserialize, Serialize};
+use unicode_segmentation::UnicodeSegmentation;
+
+use crate::{rope::RopeText, selection::TextRange};
+
+/// How far a motion or deletion reaches.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum MotionUnit {
+    /// An extended grapheme cluster.
+    Grapheme,
+    /// A word together with the whitespace before it (backward) or after the cursor
+    /// (forward), using Unicode word boundaries.
+    Word,
+    /// To the start or end of the line, or across the line break when already there.
+    Line,
+    /// To the start or end of a run of non-blank lines.
+    Paragraph,
+}
+
+impl MotionUnit {
+    pub fn parse(input: &str) -> Option<Self> {
+        match input {
+            "grapheme" => Some(Self::Grapheme),
+            "word" => Some(Self::Word),
+            "line" => Some(Self::Line),
+            "paragraph" => Some(Self::Paragraph),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum Direction {
+    Backward,
+    Forward,
+}
+
+/// Bytes on each side of the offset that grapheme and word motion look at. Clusters
+/// and whitespace runs longer than this are cut at the window edge.
+const WINDOW: usize = 1024;
+
+/// Where `offset` moves to by one `unit` in `direction`, clamped to the text.
+pub fn move_offset(
+    text: &RopeText,
+    offset: usize,
+    unit: MotionUnit,
+    direction: Direction,
+) -> usize {
+    let offset = offset.min(text.len_utf8());
+    match unit {
+        MotionUnit::Grapheme => with_window(text, offset, |window, local| {
+            let mut boundaries = window.grapheme_indices(true).map(|(index, _)| index);
+            match direction {
+                Direction::Backward => boundaries.rfind(|index| *index < local).unwrap_or(0),
+                Direction::Forward => {
+                    boundaries.find(|index| *index > local).unwrap_or(window.len())
+                }
+            }
+        }),
+        MotionUnit::Word => {
+            with_window(text, offset, |window, local| word_boundary(window, local, direction))
+        }
+        MotionUnit::Line => {
+            let (start, end) = text.line_bounds(offset);
+            match direction {
+                Direction::Backward if offset > start => start,
+                Direction::Forward if offset < end => end,
+                _ => move_offset(text, offset, MotionUnit::Grapheme, direction),
+            }
+        }
+        MotionUnit::Paragraph => paragraph_boundary(text, offset, direction),
+    }
+}
+
+/// The range one `unit` from `offset` in `direction` covers.
+pub fn motion_range(
+    text: &RopeText,
+    offset: usize,
+    unit: MotionUnit,
+    direction: Direction,
+) -> TextRange {
+    let target = move_offset(text, offset, unit, direction);
+    TextRange::new(offset.min(target) as u32, offset.max(target) as u32)
+}
+
+/// Runs `find` over the text around `offset` with the offset made relative to it,
+/// and maps the result back.
+fn with_window(text: &RopeText, offset: usize, find: impl FnOnce(&str, usize) -> usize) -> usize {
+    let mut start = offset.saturating_sub(WINDOW);
+    while !text.is_char_boundary(start) {
+        start -= 1;
+    }
+    let mut end = (offset + WINDOW).min(text.len_utf8());
+    while !text.is_char_boundary(end) {
+        end += 1;
+    }
+    let window = text.slice(TextRange::new(start as u32, end as u32)).unwrap_or_default();
+    start + find(&window, offset - start)
+}
+
+/// Skips whitespace segments, then one word or punctuation segment.
+fn word_boundary(window: &str, local: usize, direction: Direction) -> usize {
+    let is_space = |segment: &str| segment.chars().all(char::is_whitespace);
+    let mut boundary = local;
+    match direction {
+        Direction::Backward => {
+            for (start, segment) in window.split_word_bound_indices().rev() {
+                if start >= local {
+                    continue;
+                }
+                boundary = start;
+                if !is_space(segment) {
+                    break;
+                }
+            }
+        }
+        Direction::Forward => {
+            for (start, segment) in window.split_word_bound_indices() {
+                let end = start + segment.len();
+                if end <= local {
+                    continue;
+                }
+                boundary = end;
+                if !is_space(segment) {
+                    break;
+                }
+            }
+        }
+    }
+    boundary
+}
+
+/// Skips blank lines, then moves to the far edge of the next run of non-blank lines.
+/// Starting at the edge of a line moves past that line first.
+fn paragraph_boundary(text: &RopeText, offset: usize, direction: Direction) -> usize {
+    let last_line = text.len_lines() - 1;
+    let line_range = |line: usize| text.line_bounds(text.line_to_byte(line));
+    let is_blank = |line: usize| {
+        let (start, end) = line_range(line);
+        text.slice(TextRange::new(start as u32, end as u32))
+            .is_ok_and(|content| content.trim().is_empty())
+    };
+
+    let mut line = text.byte_to_line(offset);
+    let (start, end) = line_range(line);
+    match direction {
+        Direction::Forward => {
+            if offset == end {
+                if line == last_line {
+                    return offset;
+                }
+                line += 1;
+            }
+            while line < last_line && is_blank(line) {
+                line += 1;
+            }
+            while line < last_line && !is_blank(line + 1) {
+                line += 1;
+            }
+            line_range(line).1
+        }
+        Direction::Backward => {
+            if offset == start {
+                if line == 0 {
+                    return offset;
+                }
+                line -= 1;
+            }
+            while line > 0 && is_blank(line) {
+                line -= 1;
+            }
+            while line > 0 && !is_blank(line - 1) {
+                line -= 1;
+            }
+            line_range(line).0
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn moves(text: &str, offset: usize, unit: MotionUnit, direction: Direction) -> usize {
+        move_offset(&RopeText::new(text), offset, unit, direction)
+    }
+
+    #[test]
+    fn graphemes_keep_clusters_together() {
+        let family = "👨‍👩‍👧";
+        let text = format!("a{family}e\u{301}中");
+        let after_family = 1 + family.len();
+        let backward = Direction::Backward;
+        let forward = Direction::Forward;
+        assert_eq!(moves(&text, after_family, MotionUnit::Grapheme, backward), 1);
+        assert_eq!(moves(&text, 1, MotionUnit::Grapheme, forward), after_family);
+        assert_eq!(moves(&text, after_family, MotionUnit::Grapheme, forward), after_family + 3);
+        assert_eq!(moves(&text, text.len(), MotionUnit::Grapheme, backward), text.len() - 3);
+        assert_eq!(moves(&text, 0, MotionUnit::Grapheme, backward), 0);
+        assert_eq!(moves(&text, text.len(), MotionUnit::Grapheme, forward), text.len());
+    }
+
+    #[test]
+    fn words_skip_whitespace_then_one_segment() {
+        let text = "hello,  wide world\n  next";
+        let backward = Direction::Backward;
+        let forward = Direction::Forward;
+        assert_eq!(moves(text, 13, MotionUnit::Word, backward), 8);
+        assert_eq!(moves(text, 8, MotionUnit::Word, backward), 5);
+        assert_eq!(moves(text, 5, MotionUnit::Word, backward), 0);
+        assert_eq!(moves(text, 21, MotionUnit::Word, backward), 13);
+        assert_eq!(moves(text, 6, MotionUnit::Word, forward), 12);
+        assert_eq!(moves(text, 18, MotionUnit::Word, forward), text.len());
+        assert_eq!(moves("中文字", 9, MotionUnit::Word, backward), 6);
+    }
+
+    #[test]
+    fn lines_and_paragraphs_stop_at_their_edges() {
+        let text = "one\ntwo\n\n\nthree\nfour";
+        let backward = Direction::Backward;
+        let forward = Direction::Forward;
+        assert_eq!(moves(text, 5, MotionUnit::Line, backward), 4);
+        assert_eq!(moves(text, 4, MotionUnit::Line, backward), 3);
+        assert_eq!(moves(text, 5, MotionUnit::Line, forward), 7);
+        assert_eq!(moves(text, 1, MotionUnit::Paragraph, forward), 7);
+        assert_eq!(moves(text, 7, MotionUnit::Paragraph, forward), text.len());
+        assert_eq!(moves(text, text.len(), MotionUnit::Paragraph, backward), 10);
+        assert_eq!(moves(text, 10, MotionUnit::Paragraph, backward), 0);
+    }
+}
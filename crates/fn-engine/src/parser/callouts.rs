@@ -0,0 +1,77 @@
+//! Callouts: GitHub alerts such as `> [!NOTE]`, which are blockquotes whose first
+//! line names the kind of callout.
+
+use crate::{
+    ast::{BlockKind, BlockNode, CalloutKind},
+    registry::BlockSyntaxPlugin,
+    selection::TextRange,
+};
+
+use super::{parse_blockquote, syntax::quote_lines, Line, ParseContext};
+
+/// A blockquote starting with a `[!KIND]` line, where the kind is one of GitHub's
+/// five and the rest of the line, if any, is the callout's title. The remaining
+/// lines of the quote are parsed as blocks.
+pub struct CalloutSyntax;
+
+impl BlockSyntaxPlugin for CalloutSyntax {
+    fn name(&self) -> &str {
+        "callout"
+    }
+
+    fn parse(
+        &self,
+        cx: &mut ParseContext<'_>,
+        lines: &[Line<'_>],
+        index: usize,
+    ) -> Option<(BlockNode, usize)> {
+        let first = lines[index];
+        let marker = parse_callout_marker(&first.text[parse_blockquote(first.text)?..])?;
+        let (quote_lines, next_index) = quote_lines(cx, lines, index);
+        let quote_end =
+            quote_lines.last().map_or(first.end_with_newline, |line| line.end_with_newline);
+
+        let block = BlockNode {
+            node_id: cx.next_id(),
+            range: TextRange::new(first.start as u32, quote_end as u32),
+            kind: BlockKind::Callout {
+                kind: marker.kind,
+                title: marker.title.map(str::to_string),
+                children: cx.parse_blocks(&quote_lines[1..]),
+            },
+        };
+        Some((block, next_index))
+    }
+
+    fn interrupts_paragraph(&self, lines: &[Line<'_>], index: usize) -> bool {
+        parse_blockquote(lines[index].text).is_some()
+    }
+}
+
+/// A `[!KIND] title` line inside a blockquote.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct CalloutMarker<'a> {
+    pub kind: CalloutKind,
+    /// Where the kind's name sits in the line, between `[!` and `]`.
+    pub name: (usize, usize),
+    pub title: Option<&'a str>,
+}
+
+/// Reads a callout marker from the text of a quote line after its `>`. The name is
+/// matched ignoring case, and a title must be separated from the `]` by whitespace.
+pub(crate) fn parse_callout_marker(text: &str) -> Option<CalloutMarker<'_>> {
+    let rest = text.strip_prefix("[!")?;
+    let name_len = rest.find(']')?;
+    let kind = CalloutKind::from_name(&rest[..name_len])?;
+    let after = &rest[name_len + 1..];
+    if !after.is_empty() && !after.starts_with(char::is_whitespace) {
+        return None;
+    }
+
+    let title = after.trim();
+    Some(CalloutMarker {
+        kind,
+        name: (2, 2 + name_len),
+        title: (!title.is_empty()).then_some(title),
+    })
+}
@@ -0,0 +1,286 @@
+//! Footnotes: `[^label]` references and `[^label]: text` definitions.
+//!
+//! References are linked to their definitions after node IDs are assigned, by
+//! [`Footnotes::link`], since a definition can sit anywhere in the document.
+
+use std::collections::HashMap;
+
+use crate::{
+    ast::{BlockKind, BlockNode, InlineKind, InlineNode, NodeId},
+    registry::{BlockSyntaxPlugin, InlineSyntaxPlugin},
+    selection::TextRange,
+};
+
+use super::{leading_spaces, links::normalize_label, syntax::container_lines, Line, ParseContext};
+
+/// A footnote reference or definition: its node, label and source range.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FootnoteSite {
+    pub node_id: NodeId,
+    pub label: String,
+    pub range: TextRange,
+}
+
+/// The footnote definitions and references of a document, each in document order.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Footnotes {
+    pub definitions: Vec<FootnoteSite>,
+    pub references: Vec<FootnoteSite>,
+}
+
+impl Footnotes {
+    pub fn collect(blocks: &[BlockNode]) -> Self {
+        fn walk_blocks(blocks: &[BlockNode], footnotes: &mut Footnotes) {
+            for block in blocks {
+                match &block.kind {
+                    BlockKind::Paragraph { inlines }
+                    | BlockKind::Heading { inlines, .. }
+                    | BlockKind::Custom { inlines, .. } => walk_inlines(inlines, footnotes),
+                    BlockKind::FootnoteDefinition { label, children } => {
+                        footnotes.definitions.push(FootnoteSite {
+                            node_id: block.node_id,
+                            label: label.clone(),
+                            range: block.range,
+                        });
+                        walk_blocks(children, footnotes);
+                    }
+                    BlockKind::Blockquote { children } | BlockKind::Callout { children, .. } => {
+                        walk_blocks(children, footnotes)
+                    }
+                    BlockKind::BulletList { items } | BlockKind::OrderedList { items, .. } => {
+                        items.iter().for_each(|item| walk_blocks(&item.children, footnotes));
+                    }
+                    BlockKind::Table { header, rows, .. } => {
+                        for cell in std::iter::once(header).chain(rows).flat_map(|row| &row.cells) {
+                            walk_inlines(&cell.inlines, footnotes);
+                        }
+                    }
+                    _ => {}
+                }
+            }
+        }
+
+        fn walk_inlines(inlines: &[InlineNode], footnotes: &mut Footnotes) {
+            for inline in inlines {
+                match &inline.kind {
+                    InlineKind::FootnoteReference { label, .. } => {
+                        footnotes.references.push(FootnoteSite {
+                            node_id: inline.node_id,
+                            label: label.clone(),
+                            range: inline.range,
+                        });
+                    }
+                    InlineKind::Strong { children }
+                    | InlineKind::Emphasis { children }
+                    | InlineKind::Strikethrough { children }
+                    | InlineKind::Highlight { children }
+                    | InlineKind::Subscript { children }
+                    | InlineKind::Superscript { children }
+                    | InlineKind::Link { children, .. }
+                    | InlineKind::Image { children, .. } => walk_inlines(children, footnotes),
+                    _ => {}
+                }
+            }
+        }
+
+        let mut footnotes = Self::default();
+        walk_blocks(blocks, &mut footnotes);
+        footnotes
+    }
+
+    /// Updates the sites after an edit reparsed `window`, the blocks that replaced
+    /// `replaced`. Sites from `old_end`, the old end of the edit, on move by `delta`.
+    /// Returns whether the definitions in the window changed, which can change what
+    /// references anywhere in the document point at.
+    pub fn splice(
+        &mut self,
+        replaced: &[BlockNode],
+        window: &[BlockNode],
+        old_end: usize,
+        delta: i64,
+    ) -> bool {
+        let old = Self::collect(replaced);
+        let new = Self::collect(window);
+        let window_start = window.first().map_or(old_end, |block| block.range.start_utf8 as usize);
+        let splice_sites = |sites: &mut Vec<FootnoteSite>, old: &[FootnoteSite], new| {
+            sites.retain(|site| !old.iter().any(|removed| removed.node_id == site.node_id));
+            for site in sites.iter_mut().filter(|site| site.range.start_utf8 as usize >= old_end) {
+                site.range.start_utf8 = (site.range.start_utf8 as i64 + delta) as u32;
+                site.range.end_utf8 = (site.range.end_utf8 as i64 + delta) as u32;
+            }
+            let at = sites.partition_point(|site| (site.range.start_utf8 as usize) < window_start);
+            sites.splice(at..at, new);
+        };
+
+        let definitions_changed = old.definitions.len() != new.definitions.len()
+            || old.definitions.iter().zip(&new.definitions).any(|(old, new)| {
+                old.node_id != new.node_id
+                    || normalize_label(&old.label) != normalize_label(&new.label)
+            });
+        splice_sites(&mut self.definitions, &old.definitions, new.definitions);
+        splice_sites(&mut self.references, &old.references, new.references);
+        definitions_changed
+    }
+
+    /// The definition a reference to `label` points at: the first one of the label.
+    pub fn definition(&self, label: &str) -> Option<&FootnoteSite> {
+        let label = normalize_label(label);
+        self.definitions.iter().find(|definition| normalize_label(&definition.label) == label)
+    }
+
+    /// Numbers the definitions that are referenced, from 1 in the order of their first
+    /// reference, as exports number them.
+    pub fn numbers(&self) -> HashMap<NodeId, u32> {
+        let mut numbers = HashMap::new();
+        for reference in &self.references {
+            if let Some(definition) = self.definition(&reference.label) {
+                let next = numbers.len() as u32 + 1;
+                numbers.entry(definition.node_id).or_insert(next);
+            }
+        }
+        numbers
+    }
+
+    /// Points every footnote reference in `blocks`, which these footnotes were
+    /// collected from, at the node ID of its definition.
+    pub fn link(&self, blocks: &mut [BlockNode]) {
+        fn walk_blocks(blocks: &mut [BlockNode], footnotes: &Footnotes) {
+            for block in blocks {
+                match &mut block.kind {
+                    BlockKind::Paragraph { inlines }
+                    | BlockKind::Heading { inlines, .. }
+                    | BlockKind::Custom { inlines, .. } => walk_inlines(inlines, footnotes),
+                    BlockKind::Blockquote { children }
+                    | BlockKind::Callout { children, .. }
+                    | BlockKind::FootnoteDefinition { children, .. } => {
+                        walk_blocks(children, footnotes)
+                    }
+                    BlockKind::BulletList { items } | BlockKind::OrderedList { items, .. } => {
+                        items
+                            .iter_mut()
+                            .for_each(|item| walk_blocks(&mut item.children, footnotes));
+                    }
+                    BlockKind::Table { header, rows, .. } => {
+                        for cell in
+                            std::iter::once(header).chain(rows).flat_map(|row| &mut row.cells)
+                        {
+                            walk_inlines(&mut cell.inlines, footnotes);
+                        }
+                    }
+                    _ => {}
+                }
+            }
+        }
+
+        fn walk_inlines(inlines: &mut [InlineNode], footnotes: &Footnotes) {
+            for inline in inlines {
+                match &mut inline.kind {
+                    InlineKind::FootnoteReference { label, definition } => {
+                        *definition = footnotes.definition(label).map(|site| site.node_id);
+                    }
+                    InlineKind::Strong { children }
+                    | InlineKind::Emphasis { children }
+                    | InlineKind::Strikethrough { children }
+                    | InlineKind::Highlight { children }
+                    | InlineKind::Subscript { children }
+                    | InlineKind::Superscript { children }
+                    | InlineKind::Link { children, .. }
+                    | InlineKind::Image { children, .. } => walk_inlines(children, footnotes),
+                    _ => {}
+                }
+            }
+        }
+
+        walk_blocks(blocks, self);
+    }
+}
+
+/// `[^label]: text`, whose content continues on lines indented by four spaces and,
+/// lazily, on paragraph continuation lines. The content is parsed as blocks.
+pub struct FootnoteDefinitionSyntax;
+
+impl BlockSyntaxPlugin for FootnoteDefinitionSyntax {
+    fn name(&self) -> &str {
+        "footnote-definition"
+    }
+
+    fn parse(
+        &self,
+        cx: &mut ParseContext<'_>,
+        lines: &[Line<'_>],
+        index: usize,
+    ) -> Option<(BlockNode, usize)> {
+        let first = lines[index];
+        let indent = leading_spaces(first.text);
+        if indent > 3 {
+            return None;
+        }
+        let label_len = footnote_label(&first.text[indent..])?;
+        let marker_end = indent + label_len + 4;
+        if first.text.as_bytes().get(marker_end - 1) != Some(&b':') {
+            return None;
+        }
+
+        let content_offset = marker_end + leading_spaces(&first.text[marker_end..]);
+        let content = Line {
+            start: first.start + content_offset,
+            text: &first.text[content_offset..],
+            ..first
+        };
+        let (content_lines, next_index) = container_lines(cx, lines, index, content, 4);
+        let last = content_lines.last().copied().unwrap_or(content);
+        let children = cx.parse_blocks(&content_lines);
+        // A child such as a fenced block can take in the newline after the last line.
+        let end = children
+            .iter()
+            .map(|child| child.range.end_utf8 as usize)
+            .fold(last.end.max(first.end), usize::max);
+
+        let block = BlockNode {
+            node_id: cx.next_id(),
+            range: TextRange::new(first.start as u32, end as u32),
+            kind: BlockKind::FootnoteDefinition {
+                label: first.text[indent + 2..indent + 2 + label_len].to_string(),
+                children,
+            },
+        };
+        Some((block, next_index))
+    }
+}
+
+/// `[^label]`. The node's range covers the brackets.
+pub struct FootnoteReferenceSyntax;
+
+impl InlineSyntaxPlugin for FootnoteReferenceSyntax {
+    fn name(&self) -> &str {
+        "footnote-reference"
+    }
+
+    fn parse(
+        &self,
+        cx: &mut ParseContext<'_>,
+        start: usize,
+        limit: usize,
+    ) -> Option<(InlineNode, usize)> {
+        let markdown = cx.markdown();
+        let label_len = footnote_label(&markdown[start..limit])?;
+        let end = start + label_len + 3;
+        let node = InlineNode {
+            node_id: cx.next_id(),
+            range: TextRange::new(start as u32, end as u32),
+            kind: InlineKind::FootnoteReference {
+                label: markdown[start + 2..start + 2 + label_len].to_string(),
+                definition: None,
+            },
+        };
+        Some((node, end))
+    }
+}
+
+/// The length of the label of a `[^label]` that `text` starts with. Labels are not
+/// empty and contain no whitespace or brackets.
+fn footnote_label(text: &str) -> Option<usize> {
+    let rest = text.strip_prefix("[^")?;
+    let len = rest.find(|ch: char| ch.is_whitespace() || ch == '[' || ch == ']')?;
+    (len > 0 && rest[len..].starts_with(']')).then_some(len)
+}
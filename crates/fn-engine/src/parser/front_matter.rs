@@ -0,0 +1,206 @@
+//! Front matter: YAML between `---` lines or TOML between `+++` lines at the very
+//! start of the document. Only top-level keys with scalar or list values make it
+//! into the metadata; nested tables and block scalars are left in the raw text.
+
+use std::collections::BTreeMap;
+
+use crate::{
+    ast::{BlockKind, BlockNode, FrontMatterFormat, MetadataValue},
+    registry::BlockSyntaxPlugin,
+    selection::TextRange,
+};
+
+use super::{scan_lines, Line, ParseContext};
+
+/// Front matter and where the document body after it starts.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FrontMatter {
+    pub format: FrontMatterFormat,
+    /// The text between the delimiter lines.
+    pub raw: String,
+    pub metadata: BTreeMap<String, MetadataValue>,
+    pub body_start: usize,
+}
+
+/// The front matter `markdown` starts with, if any.
+pub fn parse_front_matter(markdown: &str) -> Option<FrontMatter> {
+    front_matter(markdown, &scan_lines(markdown), 0).map(|(front_matter, _)| front_matter)
+}
+
+/// Front matter, recognised only on the document's first line.
+pub struct FrontMatterSyntax;
+
+impl BlockSyntaxPlugin for FrontMatterSyntax {
+    fn name(&self) -> &str {
+        "front-matter"
+    }
+
+    fn parse(
+        &self,
+        cx: &mut ParseContext<'_>,
+        lines: &[Line<'_>],
+        index: usize,
+    ) -> Option<(BlockNode, usize)> {
+        if cx.document_offset() != 0 {
+            return None;
+        }
+        let (FrontMatter { format, raw, metadata, body_start }, next_index) =
+            front_matter(cx.markdown(), lines, index)?;
+        let block = BlockNode {
+            node_id: cx.next_id(),
+            range: TextRange::new(0, body_start as u32),
+            kind: BlockKind::FrontMatter { format, raw, metadata },
+        };
+        Some((block, next_index))
+    }
+}
+
+/// Whether the first line of `markdown` is a front matter delimiter.
+pub(super) fn opens_front_matter(markdown: &str) -> bool {
+    let first_line = markdown.split('\n').next().unwrap_or_default();
+    front_matter_format(first_line).is_some()
+}
+
+fn front_matter_format(line: &str) -> Option<(FrontMatterFormat, &'static [&'static str])> {
+    match line.trim_end() {
+        "---" => Some((FrontMatterFormat::Yaml, &["---", "..."])),
+        "+++" => Some((FrontMatterFormat::Toml, &["+++"])),
+        _ => None,
+    }
+}
+
+fn front_matter(markdown: &str, lines: &[Line<'_>], index: usize) -> Option<(FrontMatter, usize)> {
+    let first = lines[index];
+    if first.start != 0 {
+        return None;
+    }
+    let (format, closers) = front_matter_format(first.text)?;
+    let closing = index
+        + 1
+        + lines[index + 1..].iter().position(|line| closers.contains(&line.text.trim_end()))?;
+
+    let raw = markdown[first.end_with_newline..lines[closing].start].to_string();
+    let metadata = match format {
+        FrontMatterFormat::Yaml => parse_yaml(&raw),
+        FrontMatterFormat::Toml => parse_toml(&raw),
+    };
+    let body_start = lines[closing].end_with_newline;
+    Some((FrontMatter { format, raw, metadata, body_start }, closing + 1))
+}
+
+/// `key: value` lines, with `[a, b]` flow lists and `- item` lines below an empty
+/// value read as lists.
+fn parse_yaml(raw: &str) -> BTreeMap<String, MetadataValue> {
+    let mut metadata = BTreeMap::new();
+    let mut last_key: Option<String> = None;
+
+    for line in raw.lines() {
+        let content = line.trim();
+        if content.is_empty() || content.starts_with('#') {
+            continue;
+        }
+
+        if line.starts_with([' ', '\t', '-']) {
+            let item =
+                content.strip_prefix('-').filter(|rest| rest.is_empty() || rest.starts_with(' '));
+            let entry = last_key.as_ref().and_then(|key| metadata.get_mut(key));
+            if let (Some(item), Some(entry)) = (item, entry) {
+                match entry {
+                    MetadataValue::Text(text) if text.is_empty() => {
+                        *entry = MetadataValue::List(vec![yaml_scalar(item.trim())]);
+                    }
+                    MetadataValue::List(items) => items.push(yaml_scalar(item.trim())),
+                    MetadataValue::Text(_) => {}
+                }
+            }
+            continue;
+        }
+
+        last_key = None;
+        let Some((key, value)) = content.split_once(':') else {
+            continue;
+        };
+        if !(value.is_empty() || value.starts_with([' ', '\t'])) {
+            continue;
+        }
+        let key = yaml_scalar(key.trim());
+        let value = value.trim();
+        let value = match value.strip_prefix('[').and_then(|list| list.strip_suffix(']')) {
+            Some(list) => MetadataValue::List(
+                list.split(',')
+                    .map(|item| yaml_scalar(item.trim()))
+                    .filter(|item| !item.is_empty())
+                    .collect(),
+            ),
+            None => MetadataValue::Text(yaml_scalar(value)),
+        };
+        metadata.insert(key.clone(), value);
+        last_key = Some(key);
+    }
+    metadata
+}
+
+/// A quoted or plain scalar, without a trailing comment.
+fn yaml_scalar(value: &str) -> String {
+    if let Some(rest) = value.strip_prefix('"') {
+        let mut out = String::new();
+        let mut chars = rest.chars();
+        while let Some(ch) = chars.next() {
+            match ch {
+                '"' => break,
+                '\\' => match chars.next() {
+                    Some('n') => out.push('\n'),
+                    Some('t') => out.push('\t'),
+                    Some(escaped) => out.push(escaped),
+                    None => {}
+                },
+                ch => out.push(ch),
+            }
+        }
+        return out;
+    }
+    if let Some(rest) = value.strip_prefix('\'') {
+        let mut out = String::new();
+        let mut rest = rest;
+        while let Some(quote) = rest.find('\'') {
+            out.push_str(&rest[..quote]);
+            if rest[quote + 1..].starts_with('\'') {
+                out.push('\'');
+                rest = &rest[quote + 2..];
+            } else {
+                return out;
+            }
+        }
+        out.push_str(rest);
+        return out;
+    }
+
+    let value = value.find(" #").map_or(value, |comment| &value[..comment]);
+    value.trim_end().to_string()
+}
+
+fn parse_toml(raw: &str) -> BTreeMap<String, MetadataValue> {
+    let Ok(table) = raw.parse::<toml::Table>() else {
+        return BTreeMap::new();
+    };
+    table
+        .into_iter()
+        .filter_map(|(key, value)| {
+            let value = match value {
+                toml::Value::Array(items) => {
+                    MetadataValue::List(items.into_iter().filter_map(toml_scalar).collect())
+                }
+                value => MetadataValue::Text(toml_scalar(value)?),
+            };
+            Some((key, value))
+        })
+        .collect()
+}
+
+fn toml_scalar(value: toml::Value) -> Option<String> {
+    match value {
+        toml::Value::String(text) => Some(text),
+        toml::Value::Array(_) | toml::Value::Table(_) => None,
+        value => Some(value.to_string()),
+    }
+}
@@ -0,0 +1,260 @@
+//! Raw HTML blocks. Their lines are kept as source text; which lines belong to the
+//! block follows the seven start and end conditions of the CommonMark spec.
+
+use crate::{
+    ast::{BlockKind, BlockNode},
+    registry::BlockSyntaxPlugin,
+    selection::TextRange,
+};
+
+use super::{leading_spaces, Line, ParseContext};
+
+/// Tags whose content is taken verbatim up to their closing tag.
+const RAW_TAGS: &[&str] = &["pre", "script", "style", "textarea"];
+
+/// Tags that start an HTML block running to the next blank line.
+const BLOCK_TAGS: &[&str] = &[
+    "address",
+    "article",
+    "aside",
+    "base",
+    "basefont",
+    "blockquote",
+    "body",
+    "caption",
+    "center",
+    "col",
+    "colgroup",
+    "dd",
+    "details",
+    "dialog",
+    "dir",
+    "div",
+    "dl",
+    "dt",
+    "fieldset",
+    "figcaption",
+    "figure",
+    "footer",
+    "form",
+    "frame",
+    "frameset",
+    "h1",
+    "h2",
+    "h3",
+    "h4",
+    "h5",
+    "h6",
+    "head",
+    "header",
+    "hr",
+    "html",
+    "iframe",
+    "legend",
+    "li",
+    "link",
+    "main",
+    "menu",
+    "menuitem",
+    "nav",
+    "noframes",
+    "ol",
+    "optgroup",
+    "option",
+    "p",
+    "param",
+    "search",
+    "section",
+    "summary",
+    "table",
+    "tbody",
+    "td",
+    "tfoot",
+    "th",
+    "thead",
+    "title",
+    "tr",
+    "track",
+    "ul",
+];
+
+/// The line an HTML block ends on.
+#[derive(Debug, Clone, Copy)]
+enum HtmlBlockEnd {
+    /// The first line containing a closing tag of [`RAW_TAGS`].
+    RawClose,
+    /// The first line containing the marker.
+    Marker(&'static str),
+    /// The line before the next blank line.
+    BlankLine,
+}
+
+/// Raw HTML blocks.
+pub struct HtmlBlockSyntax;
+
+impl BlockSyntaxPlugin for HtmlBlockSyntax {
+    fn name(&self) -> &str {
+        "html-block"
+    }
+
+    fn parse(
+        &self,
+        cx: &mut ParseContext<'_>,
+        lines: &[Line<'_>],
+        index: usize,
+    ) -> Option<(BlockNode, usize)> {
+        let (end, _) = html_block_start(lines[index].text)?;
+        let mut last = index;
+        match end {
+            HtmlBlockEnd::BlankLine => {
+                while last + 1 < lines.len() && !lines[last + 1].text.trim().is_empty() {
+                    last += 1;
+                }
+            }
+            HtmlBlockEnd::RawClose | HtmlBlockEnd::Marker(_) => {
+                while last + 1 < lines.len() && !ends_block(lines[last].text, end) {
+                    last += 1;
+                }
+            }
+        }
+
+        let html = lines[index..=last].iter().map(|line| line.text).collect::<Vec<_>>().join("\n");
+        let block = BlockNode {
+            node_id: cx.next_id(),
+            range: TextRange::new(lines[index].start as u32, lines[last].end as u32),
+            kind: BlockKind::HtmlBlock { html },
+        };
+        Some((block, last + 1))
+    }
+
+    fn interrupts_paragraph(&self, lines: &[Line<'_>], index: usize) -> bool {
+        html_block_start(lines[index].text).is_some_and(|(_, interrupts)| interrupts)
+    }
+}
+
+/// The end condition of the HTML block `line` starts, and whether that kind of block
+/// may interrupt a paragraph.
+fn html_block_start(line: &str) -> Option<(HtmlBlockEnd, bool)> {
+    let indent = leading_spaces(line);
+    if indent > 3 {
+        return None;
+    }
+
+    let rest = &line[indent..];
+    if !rest.starts_with('<') {
+        return None;
+    }
+    if let Some(name) = tag_name(&rest[1..]) {
+        let after = rest[1 + name.len()..].bytes().next();
+        if is_one_of(name, RAW_TAGS) && matches!(after, None | Some(b' ' | b'\t' | b'>')) {
+            return Some((HtmlBlockEnd::RawClose, true));
+        }
+    }
+
+    let markers = [("<!--", "-->"), ("<?", "?>"), ("<![CDATA[", "]]>")];
+    if let Some((_, end)) = markers.iter().find(|(start, _)| rest.starts_with(start)) {
+        return Some((HtmlBlockEnd::Marker(end), true));
+    }
+    if rest.starts_with("<!") && rest.as_bytes().get(2).is_some_and(u8::is_ascii_alphabetic) {
+        return Some((HtmlBlockEnd::Marker(">"), true));
+    }
+
+    let name_start = if rest.starts_with("</") { 2 } else { 1 };
+    if let Some(name) = tag_name(&rest[name_start..]) {
+        let after = &rest[name_start + name.len()..];
+        let boundary =
+            after.is_empty() || after.starts_with([' ', '\t', '>']) || after.starts_with("/>");
+        if boundary && is_one_of(name, BLOCK_TAGS) {
+            return Some((HtmlBlockEnd::BlankLine, true));
+        }
+    }
+
+    let (len, name) = complete_tag(rest)?;
+    let only_whitespace = rest[len..].trim_matches([' ', '\t']).is_empty();
+    (only_whitespace && !is_one_of(name, RAW_TAGS)).then_some((HtmlBlockEnd::BlankLine, false))
+}
+
+fn ends_block(line: &str, end: HtmlBlockEnd) -> bool {
+    match end {
+        HtmlBlockEnd::RawClose => {
+            let line = line.to_ascii_lowercase();
+            RAW_TAGS.iter().any(|tag| line.contains(&format!("</{tag}>")))
+        }
+        HtmlBlockEnd::Marker(marker) => line.contains(marker),
+        HtmlBlockEnd::BlankLine => line.trim().is_empty(),
+    }
+}
+
+fn is_one_of(name: &str, tags: &[&str]) -> bool {
+    tags.iter().any(|tag| tag.eq_ignore_ascii_case(name))
+}
+
+/// An ASCII letter followed by letters, digits and hyphens.
+fn tag_name(text: &str) -> Option<&str> {
+    if !text.as_bytes().first()?.is_ascii_alphabetic() {
+        return None;
+    }
+    let len = text.bytes().take_while(|byte| byte.is_ascii_alphanumeric() || *byte == b'-').count();
+    Some(&text[..len])
+}
+
+/// The byte length and tag name of the complete open or closing tag `text` starts
+/// with.
+fn complete_tag(text: &str) -> Option<(usize, &str)> {
+    let bytes = text.as_bytes();
+    let closing = text.starts_with("</");
+    let mut cursor = if closing { 2 } else { 1 };
+    let name = tag_name(text.get(cursor..)?)?;
+    cursor += name.len();
+
+    if closing {
+        cursor += whitespace_len(&text[cursor..]);
+        return (bytes.get(cursor) == Some(&b'>')).then_some((cursor + 1, name));
+    }
+
+    loop {
+        let space = whitespace_len(&text[cursor..]);
+        let next = cursor + space;
+        match bytes.get(next) {
+            Some(b'>') => return Some((next + 1, name)),
+            Some(b'/') if bytes.get(next + 1) == Some(&b'>') => return Some((next + 2, name)),
+            _ if space == 0 => return None,
+            _ => cursor = next + attribute_len(&text[next..])?,
+        }
+    }
+}
+
+/// An attribute name with an optional unquoted, single- or double-quoted value.
+fn attribute_len(text: &str) -> Option<usize> {
+    let bytes = text.as_bytes();
+    let first = *bytes.first()?;
+    if !(first.is_ascii_alphabetic() || first == b'_' || first == b':') {
+        return None;
+    }
+    let name_len = 1 + bytes[1..]
+        .iter()
+        .take_while(|byte| byte.is_ascii_alphanumeric() || b"_.:-".contains(byte))
+        .count();
+
+    let equals = name_len + whitespace_len(&text[name_len..]);
+    if bytes.get(equals) != Some(&b'=') {
+        return Some(name_len);
+    }
+    let value = equals + 1 + whitespace_len(&text[equals + 1..]);
+    let value_len = match *bytes.get(value)? {
+        quote @ (b'"' | b'\'') => text[value + 1..].find(quote as char)? + 2,
+        _ => match bytes[value..]
+            .iter()
+            .take_while(|byte| !byte.is_ascii_whitespace() && !b"\"'=<>`".contains(byte))
+            .count()
+        {
+            0 => return None,
+            len => len,
+        },
+    };
+    Some(value + value_len)
+}
+
+fn whitespace_len(text: &str) -> usize {
+    text.bytes().take_while(|byte| matches!(byte, b' ' | b'\t' | b'\n')).count()
+}
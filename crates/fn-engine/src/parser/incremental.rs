@@ -0,0 +1,470 @@
+use std::ops::Range;
+
+use crate::{
+    ast::{BlockKind, BlockNode, InlineKind, InlineNode},
+    registry::EnginePluginRegistry,
+    rope::RopeText,
+    selection::TextRange,
+};
+
+use super::{
+    front_matter::opens_front_matter, parse_block, reconcile_node_ids, scan_lines, LinkDefinitions,
+    NodeIdGenerator, ParseContext,
+};
+
+/// How far past the edit the first window of text to reparse reaches. A window that
+/// turns out too short for the blocks around the edit is doubled until it fits, which
+/// tests exercise with a tiny margin.
+const WINDOW_MARGIN: usize = if cfg!(test) { 16 } else { 4 * 1024 };
+
+/// The blocks after a [`reparse_document`], and which of them were reparsed.
+#[derive(Debug, Clone)]
+pub struct Reparse {
+    pub blocks: Vec<BlockNode>,
+    /// The reparsed top-level blocks, as indices into `blocks`.
+    pub window: Range<usize>,
+    /// The old blocks the reparsed ones replaced, at their old offsets.
+    pub replaced: Vec<BlockNode>,
+}
+
+/// Reparses `text` after the text in `edit` (old offsets) was replaced with
+/// `inserted_len` bytes, reusing the unaffected blocks of `previous`.
+///
+/// Parsing restarts at the top-level block before the edit and stops as soon as a
+/// new block boundary lines up with a shifted old one past the edit; the remaining
+/// old blocks are shifted into place. Only a window of text around the edit is read,
+/// grown while a block could reach past it. Blocks outside the reparsed window keep
+/// their IDs and the window is reconciled against the blocks it replaces, so apart
+/// from IDs the result is identical to [`parse_document`](super::parse_document) as
+/// long as the edit leaves `definitions`, the document's link definitions, unchanged.
+pub fn reparse_document(
+    text: &RopeText,
+    registry: &EnginePluginRegistry,
+    definitions: &LinkDefinitions,
+    mut previous: Vec<BlockNode>,
+    edit: TextRange,
+    inserted_len: usize,
+    id_gen: &mut NodeIdGenerator,
+) -> Reparse {
+    let edit_start = edit.start_utf8 as usize;
+    let delta = inserted_len as i64 - edit.len() as i64;
+
+    // A block's extent depends on the lines after it (lazy continuation, table
+    // delimiters, list items resuming after blank lines), so the previous content
+    // block and any blank lines in between are reparsed too.
+    let containing = previous
+        .partition_point(|block| block.range.start_utf8 as usize <= edit_start)
+        .saturating_sub(1);
+    let mut restart = containing.saturating_sub(1);
+    while restart > 0 && is_blank_line(&previous[restart]) {
+        restart -= 1;
+    }
+    // Front matter ends at a closing line anywhere below the document's first line,
+    // so while the first line opens front matter that is not closed yet, any edit
+    // can turn the top of the document into front matter.
+    let open_front_matter =
+        !matches!(previous.first().map(|block| &block.kind), Some(BlockKind::FrontMatter { .. }));
+    if open_front_matter {
+        let first_line_end = text.line_bounds(0).1 as u32;
+        let first_line = text.slice(TextRange::new(0, first_line_end)).unwrap_or_default();
+        if opens_front_matter(&first_line) {
+            restart = 0;
+        }
+    }
+    let restart_offset = previous.get(restart).map_or(0, |block| block.range.start_utf8 as usize);
+
+    let window = Window { text, start: restart_offset, edit, inserted_len, first: containing };
+    let mut margin = WINDOW_MARGIN;
+    let (mut reparsed, resync) = loop {
+        let end = edit_start + inserted_len + margin;
+        let end = if end >= text.len_utf8() {
+            text.len_utf8()
+        } else {
+            // The margin can end inside a character, the line it is on cannot.
+            let end = (end..).find(|end| text.is_char_boundary(*end)).unwrap_or(end);
+            text.line_bounds(end).1
+        };
+        if let Some(parsed) = window.parse(registry, definitions, &previous, end) {
+            break parsed;
+        }
+        margin *= 2;
+    };
+
+    let mut suffix = previous.split_off(resync.unwrap_or(previous.len()));
+    for block in &mut suffix {
+        shift_block(block, delta);
+    }
+    reconcile_node_ids(&previous[restart..], &mut reparsed, id_gen);
+
+    let replaced = previous.split_off(restart);
+    let window = restart..restart + reparsed.len();
+    previous.extend(reparsed);
+    previous.extend(suffix);
+    Reparse { blocks: previous, window, replaced }
+}
+
+/// The text an incremental reparse reads, from the start of the first reparsed block.
+struct Window<'a> {
+    text: &'a RopeText,
+    start: usize,
+    edit: TextRange,
+    inserted_len: usize,
+    /// The first old block that may line up with a reparsed one after the edit.
+    first: usize,
+}
+
+impl Window<'_> {
+    /// Parses `text[start..end]` into blocks until one ends where a shifted block of
+    /// `previous` past the edit starts, returning the blocks and the index of that
+    /// old block. Returns `None` when the window ends first and, with only blank
+    /// lines left in it, the last block could have run on past its end.
+    fn parse(
+        &self,
+        registry: &EnginePluginRegistry,
+        definitions: &LinkDefinitions,
+        previous: &[BlockNode],
+        end: usize,
+    ) -> Option<(Vec<BlockNode>, Option<usize>)> {
+        let old_edit_end = self.edit.end_utf8 as usize;
+        let new_edit_end = self.edit.start_utf8 as usize + self.inserted_len;
+        let delta = self.inserted_len as i64 - self.edit.len() as i64;
+        let complete = end == self.text.len_utf8();
+
+        let markdown = self.text.slice(TextRange::new(self.start as u32, end as u32)).ok()?;
+        let mut lines = scan_lines(&markdown);
+        if self.start > 0 && markdown.is_empty() {
+            lines.clear();
+        }
+
+        let mut scratch_ids = NodeIdGenerator::default();
+        let mut cx = ParseContext::new(&markdown, registry, definitions, &mut scratch_ids)
+            .at_offset(self.start);
+        let mut reparsed = Vec::new();
+        let mut candidate = self.first;
+        let mut index = 0;
+
+        while index < lines.len() {
+            let (mut block, next_index) = parse_block(&mut cx, &lines, index);
+            if !complete && lines[next_index..].iter().all(|line| line.text.trim().is_empty()) {
+                return None;
+            }
+            shift_block(&mut block, self.start as i64);
+            reparsed.push(block);
+            index = next_index;
+
+            let Some(line) = lines.get(index) else {
+                break;
+            };
+            let line_start = self.start + line.start;
+            if line_start < new_edit_end {
+                continue;
+            }
+            while previous.get(candidate).is_some_and(|block| {
+                let start = block.range.start_utf8 as usize;
+                start < old_edit_end || shift(start, delta) < line_start
+            }) {
+                candidate += 1;
+            }
+            if previous
+                .get(candidate)
+                .is_some_and(|block| shift(block.range.start_utf8 as usize, delta) == line_start)
+            {
+                return Some((reparsed, Some(candidate)));
+            }
+        }
+        Some((reparsed, None))
+    }
+}
+
+fn is_blank_line(block: &BlockNode) -> bool {
+    matches!(&block.kind, BlockKind::Paragraph { inlines } if inlines.is_empty())
+        && block.range.is_empty()
+}
+
+fn shift(offset: usize, delta: i64) -> usize {
+    (offset as i64 + delta) as usize
+}
+
+fn shift_range(range: &mut TextRange, delta: i64) {
+    range.start_utf8 = (range.start_utf8 as i64 + delta) as u32;
+    range.end_utf8 = (range.end_utf8 as i64 + delta) as u32;
+}
+
+fn shift_block(block: &mut BlockNode, delta: i64) {
+    shift_range(&mut block.range, delta);
+
+    match &mut block.kind {
+        BlockKind::Paragraph { inlines }
+        | BlockKind::Heading { inlines, .. }
+        | BlockKind::Custom { inlines, .. } => {
+            shift_inlines(inlines, delta);
+        }
+        BlockKind::Blockquote { children } | BlockKind::Callout { children, .. } => {
+            children.iter_mut().for_each(|child| shift_block(child, delta));
+        }
+        BlockKind::FootnoteDefinition { children, .. } => {
+            children.iter_mut().for_each(|child| shift_block(child, delta));
+        }
+        BlockKind::BulletList { items } | BlockKind::OrderedList { items, .. } => {
+            for item in items {
+                shift_range(&mut item.range, delta);
+                item.children.iter_mut().for_each(|child| shift_block(child, delta));
+            }
+        }
+        BlockKind::FencedCode { text_range, .. }
+        | BlockKind::IndentedCode { text_range, .. }
+        | BlockKind::MathBlock { text_range, .. } => shift_range(text_range, delta),
+        BlockKind::FrontMatter { .. }
+        | BlockKind::ThematicBreak
+        | BlockKind::HtmlBlock { .. }
+        | BlockKind::LinkDefinition { .. } => {}
+        BlockKind::Table { header, rows, .. } => {
+            for row in std::iter::once(header).chain(rows) {
+                shift_range(&mut row.range, delta);
+                for cell in &mut row.cells {
+                    shift_range(&mut cell.range, delta);
+                    shift_inlines(&mut cell.inlines, delta);
+                }
+            }
+        }
+    }
+}
+
+fn shift_inlines(inlines: &mut [InlineNode], delta: i64) {
+    for inline in inlines {
+        shift_range(&mut inline.range, delta);
+        if let InlineKind::Strong { children }
+        | InlineKind::Emphasis { children }
+        | InlineKind::Strikethrough { children }
+        | InlineKind::Highlight { children }
+        | InlineKind::Subscript { children }
+        | InlineKind::Superscript { children }
+        | InlineKind::Link { children, .. }
+        | InlineKind::Image { children, .. } = &mut inline.kind
+        {
+            shift_inlines(children, delta);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use proptest::prelude::*;
+
+    use super::*;
+    use crate::parser::{footnotes::Footnotes, parse_document};
+
+    fn core() -> EnginePluginRegistry {
+        EnginePluginRegistry::core_markdown()
+    }
+
+    const LINES: &[&str] = &[
+        "",
+        "plain text",
+        "**bold** and *em* with `code`",
+        "# Heading",
+        "> quoted [link](https://example.com)",
+        "- item",
+        "- [x] done",
+        "  continued",
+        "    - nested",
+        "1. first",
+        "2. second",
+        "```rust",
+        "```",
+        "| a | b |",
+        "| --- | :-: |",
+        "| c | d |",
+        "[ref]: https://example.com \"Title\"",
+        "see [ref] and ![alt *text*][ref] or <https://example.com>",
+        "Setext",
+        "===",
+        "---",
+        "* * *",
+        "    indented code",
+        "~~~ text",
+        "<div>",
+        "<!-- comment -->",
+        "> > nested quote",
+        ">",
+        "title: x",
+        "+++",
+        "$$",
+        "costs $5 and $x^2$ or $$\\sum$$",
+        "a note[^1] and [^missing]",
+        "[^1]: the note",
+        "~~struck~~ ==marked== H~2~O x^2^",
+        "> [!WARNING] Careful",
+        "see [[Page#Intro|the intro]] and [[#Top]]",
+    ];
+
+    const INSERTS: &[&str] = &["", "x", "\n", "\n\n", "- ", "> ", "# ", "```", "|", "  ", "**"];
+
+    fn document() -> impl Strategy<Value = String> {
+        prop::collection::vec(prop::sample::select(LINES), 0..24).prop_map(|lines| lines.join("\n"))
+    }
+
+    fn edit(markdown: &str, start: usize, len: usize, insert: &str) -> (TextRange, String) {
+        let start = start % (markdown.len() + 1);
+        let end = (start + len).min(markdown.len());
+        let mut next = markdown.to_string();
+        next.replace_range(start..end, insert);
+        (TextRange::new(start as u32, end as u32), next)
+    }
+
+    fn initial(markdown: &str, id_gen: &mut NodeIdGenerator) -> Vec<BlockNode> {
+        let mut blocks = parse_document(markdown, &core());
+        reconcile_node_ids(&[], &mut blocks, id_gen);
+        blocks
+    }
+
+    fn without_ids(blocks: &[BlockNode]) -> serde_json::Value {
+        fn strip(value: &mut serde_json::Value) {
+            match value {
+                serde_json::Value::Object(map) => {
+                    map.remove("node_id");
+                    map.values_mut().for_each(strip);
+                }
+                serde_json::Value::Array(items) => items.iter_mut().for_each(strip),
+                _ => {}
+            }
+        }
+
+        let mut value = serde_json::to_value(blocks).unwrap();
+        strip(&mut value);
+        value
+    }
+
+    fn all_ids(value: &serde_json::Value, out: &mut Vec<u64>) {
+        match value {
+            serde_json::Value::Object(map) => {
+                out.extend(map.get("node_id").and_then(serde_json::Value::as_u64));
+                map.values().for_each(|child| all_ids(child, out));
+            }
+            serde_json::Value::Array(items) => items.iter().for_each(|item| all_ids(item, out)),
+            _ => {}
+        }
+    }
+
+    proptest! {
+        #[test]
+        fn matches_full_reparse(
+            markdown in document(),
+            edits in prop::collection::vec(
+                (any::<usize>(), 0..24_usize, prop::sample::select(INSERTS)),
+                1..6,
+            ),
+        ) {
+            let mut current = markdown;
+            let mut id_gen = NodeIdGenerator::default();
+            let mut blocks = initial(&current, &mut id_gen);
+            let mut footnotes = Footnotes::collect(&blocks);
+
+            for (start, len, insert) in edits {
+                let (range, next) = edit(&current, start, len, insert);
+                let definitions = LinkDefinitions::collect(&blocks);
+                let reparse = reparse_document(
+                    &RopeText::new(next.clone()),
+                    &core(),
+                    &definitions,
+                    blocks,
+                    range,
+                    insert.len(),
+                    &mut id_gen,
+                );
+                let delta = insert.len() as i64 - range.len() as i64;
+                let window = &reparse.blocks[reparse.window.clone()];
+                footnotes.splice(&reparse.replaced, window, range.end_utf8 as usize, delta);
+                blocks = reparse.blocks;
+                if LinkDefinitions::collect(&blocks) != definitions {
+                    let mut reparsed = parse_document(&next, &core());
+                    reconcile_node_ids(&blocks, &mut reparsed, &mut id_gen);
+                    blocks = reparsed;
+                    footnotes = Footnotes::collect(&blocks);
+                }
+                prop_assert_eq!(&footnotes, &Footnotes::collect(&blocks));
+                prop_assert_eq!(
+                    without_ids(&blocks),
+                    without_ids(&parse_document(&next, &core())),
+                    "after editing {:?}",
+                    range
+                );
+
+                let mut ids = Vec::new();
+                all_ids(&serde_json::to_value(&blocks).unwrap(), &mut ids);
+                ids.sort_unstable();
+                let count = ids.len();
+                ids.dedup();
+                prop_assert_eq!(ids.len(), count, "duplicate node ids");
+                current = next;
+            }
+        }
+    }
+
+    #[test]
+    fn reuses_blocks_after_the_edit() {
+        let markdown = "# Title\n\nfirst\n\n- a\n- b\n\n```\ncode\n```";
+        let mut id_gen = NodeIdGenerator::default();
+        let before = initial(markdown, &mut id_gen);
+        let (range, next) = edit(markdown, 9, 5, "changed");
+        let definitions = LinkDefinitions::default();
+        let text = RopeText::new(next.clone());
+        let reparse =
+            reparse_document(&text, &core(), &definitions, before.clone(), range, 7, &mut id_gen);
+        let blocks = reparse.blocks;
+
+        assert_eq!(without_ids(&blocks), without_ids(&parse_document(&next, &core())));
+        assert_eq!(
+            blocks.iter().map(|block| block.node_id).collect::<Vec<_>>(),
+            before.iter().map(|block| block.node_id).collect::<Vec<_>>()
+        );
+        let BlockKind::FencedCode { text_range, .. } = &blocks.last().unwrap().kind else {
+            panic!("expected trailing code block");
+        };
+        assert_eq!(&next[text_range.start_utf8 as usize..text_range.end_utf8 as usize], "code\n");
+    }
+
+    #[test]
+    fn grows_the_window_for_blocks_past_it() {
+        let markdown = format!("intro\n\n{}\nafter", "- item\n".repeat(2_000));
+        for (start, insert) in [(0, "```\n"), (7, "text\n"), (markdown.len() - 5, "- ")] {
+            let mut id_gen = NodeIdGenerator::default();
+            let before = initial(&markdown, &mut id_gen);
+            let (range, next) = edit(&markdown, start, 0, insert);
+            let definitions = LinkDefinitions::default();
+            let text = RopeText::new(next.clone());
+            let reparse = reparse_document(
+                &text,
+                &core(),
+                &definitions,
+                before,
+                range,
+                insert.len(),
+                &mut id_gen,
+            );
+            assert_eq!(
+                without_ids(&reparse.blocks),
+                without_ids(&parse_document(&next, &core())),
+                "after inserting {insert:?} at {start}"
+            );
+        }
+    }
+
+    #[test]
+    fn ends_the_window_on_a_char_boundary() {
+        let markdown = format!("xx\n{}", "é".repeat(40));
+        let mut id_gen = NodeIdGenerator::default();
+        let before = initial(&markdown, &mut id_gen);
+        let (range, next) = edit(&markdown, 0, 0, "y");
+        let reparse = reparse_document(
+            &RopeText::new(next.clone()),
+            &core(),
+            &LinkDefinitions::default(),
+            before,
+            range,
+            1,
+            &mut id_gen,
+        );
+        assert_eq!(without_ids(&reparse.blocks), without_ids(&parse_document(&next, &core())));
+    }
+}
@@ -0,0 +1,284 @@
+//! Inline content. The inline syntax plugins claim spans left to right, runs of a
+//! delimiter plugin's marker are collected on the way, and once the content is
+//! scanned the runs are matched with the CommonMark delimiter algorithm.
+
+use std::collections::HashMap;
+
+use crate::{
+    ast::{InlineKind, InlineNode},
+    registry::{DelimiterRun, EnginePluginRegistry, Flanking},
+    selection::TextRange,
+};
+
+use super::ParseContext;
+
+enum Item {
+    Node(InlineNode),
+    Run(Run),
+}
+
+/// A delimiter run and the spans it ended up opening and closing.
+struct Run {
+    plugin: usize,
+    start: usize,
+    /// Bytes per marker character.
+    width: usize,
+    state: DelimiterRun,
+    /// Whether the run is still on the delimiter stack.
+    active: bool,
+    /// Lengths of the spans the run closes, innermost first.
+    closes: Vec<usize>,
+    /// Lengths of the spans the run opens, innermost first.
+    opens: Vec<usize>,
+}
+
+pub(super) fn parse_inlines(
+    cx: &mut ParseContext<'_>,
+    start: usize,
+    end: usize,
+) -> Vec<InlineNode> {
+    let mut items = scan(cx, start, end);
+    match_delimiters(cx.registry, &mut items);
+    build(cx, items)
+}
+
+fn scan(cx: &mut ParseContext<'_>, start: usize, end: usize) -> Vec<Item> {
+    let registry = cx.registry;
+    let markdown = cx.markdown;
+    let mut items = Vec::new();
+    let mut cursor = start;
+    let mut literal_start = start;
+
+    while cursor < end {
+        let parsed =
+            registry.inline_syntax_plugins.iter().find_map(|plugin| plugin.parse(cx, cursor, end));
+        if let Some((node, next)) = parsed {
+            debug_assert!(next > cursor && next <= end, "inline plugin returned a bad end");
+            push_text_segment(cx, &mut items, literal_start, cursor);
+            items.push(Item::Node(node));
+            cursor = next;
+            literal_start = cursor;
+            continue;
+        }
+
+        let ch = markdown[cursor..].chars().next().unwrap_or_default();
+        let delimiter =
+            registry.delimiter_syntax_plugins.iter().position(|plugin| plugin.marker() == ch);
+        if let Some(plugin) = delimiter {
+            push_text_segment(cx, &mut items, literal_start, cursor);
+            let run = delimiter_run(cx, plugin, start..end, cursor);
+            cursor = run.start + run.state.len * run.width;
+            literal_start = cursor;
+            items.push(Item::Run(run));
+            continue;
+        }
+
+        cursor += ch.len_utf8();
+    }
+
+    push_text_segment(cx, &mut items, literal_start, end);
+    items
+}
+
+fn delimiter_run(
+    cx: &ParseContext<'_>,
+    plugin: usize,
+    content: std::ops::Range<usize>,
+    start: usize,
+) -> Run {
+    let plugin_ref = &cx.registry.delimiter_syntax_plugins[plugin];
+    let marker = plugin_ref.marker();
+    let len = cx.markdown[start..content.end].chars().take_while(|ch| *ch == marker).count();
+    let end = start + len * marker.len_utf8();
+
+    let before = cx.markdown[content.start..start].chars().next_back();
+    let after = cx.markdown[end..content.end].chars().next();
+    let whitespace = |ch: Option<char>| ch.map_or(true, char::is_whitespace);
+    let punctuation = |ch: Option<char>| ch.is_some_and(is_punctuation);
+    let flanking = Flanking {
+        left: !whitespace(after)
+            && (!punctuation(after) || whitespace(before) || punctuation(before)),
+        right: !whitespace(before)
+            && (!punctuation(before) || whitespace(after) || punctuation(after)),
+        preceded_by_punctuation: punctuation(before),
+        followed_by_punctuation: punctuation(after),
+    };
+    let (can_open, can_close) = plugin_ref.can_open_close(flanking);
+
+    Run {
+        plugin,
+        start,
+        width: marker.len_utf8(),
+        state: DelimiterRun { len, original_len: len, can_open, can_close },
+        active: true,
+        closes: Vec::new(),
+        opens: Vec::new(),
+    }
+}
+
+/// CommonMark counts the Unicode `P` and `S` categories as punctuation. Outside ASCII
+/// this approximates them as whatever is not alphanumeric, whitespace or control.
+fn is_punctuation(ch: char) -> bool {
+    ch.is_ascii_punctuation()
+        || (!ch.is_ascii() && !ch.is_alphanumeric() && !ch.is_whitespace() && !ch.is_control())
+}
+
+/// The CommonMark "process emphasis" procedure. Every closer, left to right, looks
+/// back for the nearest opener of its plugin that it can close; runs in between are
+/// taken off the stack. `openers_bottom` remembers where a failed search stopped so
+/// the next closer of the same kind does not search that far again.
+fn match_delimiters(registry: &EnginePluginRegistry, items: &mut [Item]) {
+    let mut openers_bottom: HashMap<(usize, bool, usize), usize> = HashMap::new();
+    let mut closer = 0;
+
+    while closer < items.len() {
+        let Some((plugin, closer_state)) = run(items, closer)
+            .filter(|run| run.active && run.state.can_close)
+            .map(|run| (run.plugin, run.state))
+        else {
+            closer += 1;
+            continue;
+        };
+
+        let key = (plugin, closer_state.can_open, closer_state.original_len % 3);
+        let bottom = openers_bottom.get(&key).copied().unwrap_or(0);
+        let syntax = &registry.delimiter_syntax_plugins[plugin];
+        let found = (bottom..closer).rev().find_map(|index| {
+            let opener = run(items, index)
+                .filter(|run| run.active && run.plugin == plugin && run.state.can_open)?;
+            let len = syntax.match_len(&opener.state, &closer_state)?;
+            (len > 0).then_some((index, len.min(opener.state.len).min(closer_state.len)))
+        });
+
+        let Some((opener, len)) = found else {
+            openers_bottom.insert(key, closer);
+            if !closer_state.can_open {
+                run_mut(items, closer).active = false;
+            }
+            closer += 1;
+            continue;
+        };
+
+        for item in &mut items[opener + 1..closer] {
+            if let Item::Run(run) = item {
+                run.active = false;
+            }
+        }
+        let opener = run_mut(items, opener);
+        opener.state.len -= len;
+        opener.opens.push(len);
+        opener.active = opener.state.len > 0;
+        let closing = run_mut(items, closer);
+        closing.state.len -= len;
+        closing.closes.push(len);
+        if closing.state.len == 0 {
+            closing.active = false;
+            closer += 1;
+        }
+    }
+}
+
+fn run(items: &[Item], index: usize) -> Option<&Run> {
+    match &items[index] {
+        Item::Run(run) => Some(run),
+        Item::Node(_) => None,
+    }
+}
+
+fn run_mut(items: &mut [Item], index: usize) -> &mut Run {
+    match &mut items[index] {
+        Item::Run(run) => run,
+        Item::Node(_) => unreachable!("index of a delimiter run"),
+    }
+}
+
+/// Nests the items into nodes. Matched spans nest properly, so every closed span is
+/// the one opened last; unmatched markers stay literal text.
+fn build(cx: &mut ParseContext<'_>, items: Vec<Item>) -> Vec<InlineNode> {
+    let registry = cx.registry;
+    let mut stack: Vec<(usize, Vec<InlineNode>)> = vec![(0, Vec::new())];
+
+    for item in items {
+        let run = match item {
+            Item::Node(node) => {
+                push_node(&mut stack.last_mut().expect("root frame").1, node);
+                continue;
+            }
+            Item::Run(run) => run,
+        };
+
+        let syntax = &registry.delimiter_syntax_plugins[run.plugin];
+        let mut cursor = run.start;
+        for len in run.closes {
+            let (content_start, children) = stack.pop().expect("closed span was opened");
+            let node = InlineNode {
+                node_id: cx.next_id(),
+                range: TextRange::new(content_start as u32, cursor as u32),
+                kind: syntax.wrap(len, children),
+            };
+            push_node(&mut stack.last_mut().expect("root frame").1, node);
+            cursor += len * run.width;
+        }
+
+        let literal_end = cursor + run.state.len * run.width;
+        if literal_end > cursor {
+            let literal = InlineNode {
+                node_id: cx.next_id(),
+                range: TextRange::new(cursor as u32, literal_end as u32),
+                kind: InlineKind::Text { value: cx.markdown[cursor..literal_end].to_string() },
+            };
+            push_node(&mut stack.last_mut().expect("root frame").1, literal);
+        }
+        cursor = literal_end;
+
+        for len in run.opens.iter().rev() {
+            cursor += len * run.width;
+            stack.push((cursor, Vec::new()));
+        }
+    }
+
+    debug_assert_eq!(stack.len(), 1, "every opened span is closed");
+    stack.pop().map(|(_, nodes)| nodes).unwrap_or_default()
+}
+
+/// Appends `node`, merging it into a directly preceding text node.
+fn push_node(nodes: &mut Vec<InlineNode>, node: InlineNode) {
+    if let (Some(last), InlineKind::Text { value }) = (nodes.last_mut(), &node.kind) {
+        if let InlineKind::Text { value: last_value } = &mut last.kind {
+            if last.range.end_utf8 == node.range.start_utf8 {
+                last_value.push_str(value);
+                last.range.end_utf8 = node.range.end_utf8;
+                return;
+            }
+        }
+    }
+    nodes.push(node);
+}
+
+/// Literal text between spans. Spaces around line endings are not part of the text.
+fn push_text_segment(cx: &mut ParseContext<'_>, items: &mut Vec<Item>, start: usize, end: usize) {
+    if start >= end {
+        return;
+    }
+
+    let raw = cx.content_text(start, end);
+    let last = raw.matches('\n').count();
+    let value = raw
+        .split('\n')
+        .enumerate()
+        .map(|(index, line)| {
+            let line = if index > 0 { line.trim_start_matches([' ', '\t']) } else { line };
+            if index < last {
+                line.trim_end_matches([' ', '\t'])
+            } else {
+                line
+            }
+        })
+        .collect::<Vec<_>>()
+        .join("\n");
+    items.push(Item::Node(InlineNode {
+        node_id: cx.next_id(),
+        range: TextRange::new(start as u32, end as u32),
+        kind: InlineKind::Text { value },
+    }));
+}
@@ -0,0 +1,434 @@
+//! Links, images, autolinks and link reference definitions.
+//!
+//! Reference links resolve against the definitions anywhere in the document, so
+//! [`parse_document`](super::parse_document) collects them from a first pass and
+//! parses again when there are any.
+
+use std::collections::HashMap;
+
+use crate::{
+    ast::{BlockKind, BlockNode, InlineKind, InlineNode},
+    registry::{BlockSyntaxPlugin, InlineSyntaxPlugin},
+    selection::TextRange,
+};
+
+use super::{leading_spaces, syntax::unescape, Line, ParseContext};
+
+/// Where a link or image points.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct LinkTarget {
+    pub href: String,
+    pub title: Option<String>,
+}
+
+/// The link reference definitions of a document by normalized label. The first
+/// definition of a label wins.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct LinkDefinitions {
+    targets: HashMap<String, LinkTarget>,
+}
+
+impl LinkDefinitions {
+    pub fn collect(blocks: &[BlockNode]) -> Self {
+        fn walk(blocks: &[BlockNode], targets: &mut HashMap<String, LinkTarget>) {
+            for block in blocks {
+                match &block.kind {
+                    BlockKind::LinkDefinition { label, href, title } => {
+                        targets.entry(normalize_label(label)).or_insert_with(|| LinkTarget {
+                            href: href.clone(),
+                            title: title.clone(),
+                        });
+                    }
+                    BlockKind::BulletList { items } | BlockKind::OrderedList { items, .. } => {
+                        for item in items {
+                            walk(&item.children, targets);
+                        }
+                    }
+                    _ => {}
+                }
+            }
+        }
+
+        let mut targets = HashMap::new();
+        walk(blocks, &mut targets);
+        Self { targets }
+    }
+
+    pub fn get(&self, label: &str) -> Option<&LinkTarget> {
+        self.targets.get(&normalize_label(label))
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.targets.is_empty()
+    }
+}
+
+/// Labels match case-insensitively with runs of whitespace collapsed.
+pub(super) fn normalize_label(label: &str) -> String {
+    label.split_whitespace().collect::<Vec<_>>().join(" ").to_lowercase()
+}
+
+/// `[label]: href "title"` on one line.
+pub struct LinkDefinitionSyntax;
+
+impl BlockSyntaxPlugin for LinkDefinitionSyntax {
+    fn name(&self) -> &str {
+        "link-definition"
+    }
+
+    fn parse(
+        &self,
+        cx: &mut ParseContext<'_>,
+        lines: &[Line<'_>],
+        index: usize,
+    ) -> Option<(BlockNode, usize)> {
+        let line = lines[index];
+        let markdown = cx.markdown();
+        let indent = leading_spaces(line.text);
+        if indent > 3 {
+            return None;
+        }
+
+        let open = line.start + indent;
+        let (label_end, colon) = reference_label(markdown, open, line.end)?;
+        if markdown.as_bytes().get(colon) != Some(&b':') {
+            return None;
+        }
+        let (href, after_href) =
+            destination(markdown, skip_spaces(markdown, colon + 1, line.end), line.end)?;
+        let (title, after_title) =
+            match title(markdown, skip_spaces(markdown, after_href, line.end), line.end) {
+                Some((title, end)) if end > after_href => (Some(title), end),
+                _ => (None, after_href),
+            };
+        if !markdown[after_title..line.end].trim().is_empty() || after_href == colon + 1 {
+            return None;
+        }
+
+        let block = BlockNode {
+            node_id: cx.next_id(),
+            range: TextRange::new(line.start as u32, line.end as u32),
+            kind: BlockKind::LinkDefinition {
+                label: markdown[open + 1..label_end].to_string(),
+                href,
+                title,
+            },
+        };
+        Some((block, index + 1))
+    }
+}
+
+/// `[label](href "title")` and the reference forms `[label][ref]`, `[label][]` and
+/// `[label]`. The node's range runs from the label to the end of the link.
+pub struct LinkSyntax;
+
+impl InlineSyntaxPlugin for LinkSyntax {
+    fn name(&self) -> &str {
+        "link"
+    }
+
+    fn parse(
+        &self,
+        cx: &mut ParseContext<'_>,
+        start: usize,
+        limit: usize,
+    ) -> Option<(InlineNode, usize)> {
+        if cx.markdown().as_bytes().get(start) != Some(&b'[') {
+            return None;
+        }
+        let (label_end, target, end) = link_parts(cx, start, limit)?;
+        let children = cx.parse_inlines(start + 1, label_end);
+        if contains_link(&children) {
+            return None;
+        }
+        let node = InlineNode {
+            node_id: cx.next_id(),
+            range: TextRange::new((start + 1) as u32, end as u32),
+            kind: InlineKind::Link { href: target.href, title: target.title, children },
+        };
+        Some((node, end))
+    }
+}
+
+/// `![alt](src "title")` and its reference forms. The node's range runs from the
+/// alt text to the end of the image.
+pub struct ImageSyntax;
+
+impl InlineSyntaxPlugin for ImageSyntax {
+    fn name(&self) -> &str {
+        "image"
+    }
+
+    fn parse(
+        &self,
+        cx: &mut ParseContext<'_>,
+        start: usize,
+        limit: usize,
+    ) -> Option<(InlineNode, usize)> {
+        if !cx.markdown()[start..limit].starts_with("![") {
+            return None;
+        }
+        let (alt_end, target, end) = link_parts(cx, start + 1, limit)?;
+        let children = cx.parse_inlines(start + 2, alt_end);
+        let node = InlineNode {
+            node_id: cx.next_id(),
+            range: TextRange::new((start + 2) as u32, end as u32),
+            kind: InlineKind::Image { src: target.href, title: target.title, children },
+        };
+        Some((node, end))
+    }
+}
+
+/// `<https://example.com>` and `<someone@example.com>`. The node's range runs from
+/// the address to the closing `>`.
+pub struct AutolinkSyntax;
+
+impl InlineSyntaxPlugin for AutolinkSyntax {
+    fn name(&self) -> &str {
+        "autolink"
+    }
+
+    fn parse(
+        &self,
+        cx: &mut ParseContext<'_>,
+        start: usize,
+        limit: usize,
+    ) -> Option<(InlineNode, usize)> {
+        let rest = cx.markdown()[start..limit].strip_prefix('<')?;
+        let close = rest.find('>')?;
+        let address = &rest[..close];
+        if address.is_empty() || address.contains(|ch: char| ch.is_whitespace() || ch == '<') {
+            return None;
+        }
+        let href = if is_absolute_uri(address) {
+            address.to_string()
+        } else if is_email(address) {
+            format!("mailto:{address}")
+        } else {
+            return None;
+        };
+
+        let (inner_start, inner_end) = (start + 1, start + 1 + close);
+        let text = InlineNode {
+            node_id: cx.next_id(),
+            range: TextRange::new(inner_start as u32, inner_end as u32),
+            kind: InlineKind::Text { value: address.to_string() },
+        };
+        let node = InlineNode {
+            node_id: cx.next_id(),
+            range: TextRange::new(inner_start as u32, (inner_end + 1) as u32),
+            kind: InlineKind::Link { href, title: None, children: vec![text] },
+        };
+        Some((node, inner_end + 1))
+    }
+}
+
+/// Links cannot contain links; the innermost one wins.
+fn contains_link(inlines: &[InlineNode]) -> bool {
+    inlines.iter().any(|inline| match &inline.kind {
+        InlineKind::Link { .. } | InlineKind::WikiLink { .. } => true,
+        InlineKind::Strong { children }
+        | InlineKind::Emphasis { children }
+        | InlineKind::Strikethrough { children }
+        | InlineKind::Highlight { children }
+        | InlineKind::Subscript { children }
+        | InlineKind::Superscript { children }
+        | InlineKind::Image { children, .. } => contains_link(children),
+        _ => false,
+    })
+}
+
+/// Parses what follows the label opened at `open`: an inline target in parentheses
+/// or a reference to a definition. Returns the label end, the target and the end of
+/// the whole link.
+fn link_parts(
+    cx: &ParseContext<'_>,
+    open: usize,
+    limit: usize,
+) -> Option<(usize, LinkTarget, usize)> {
+    let markdown = cx.markdown();
+    let label_end = label_end(markdown, open, limit)?;
+    let after_label = label_end + 1;
+
+    if markdown[after_label..limit].starts_with('(') {
+        if let Some((target, end)) = inline_target(markdown, after_label, limit) {
+            return Some((label_end, target, end));
+        }
+    }
+
+    if let Some((ref_end, end)) = reference_label(markdown, after_label, limit) {
+        let reference = &markdown[after_label + 1..ref_end];
+        let reference =
+            if reference.is_empty() { &markdown[open + 1..label_end] } else { reference };
+        return cx.link_definition(reference).map(|target| (label_end, target.clone(), end));
+    }
+
+    cx.link_definition(&markdown[open + 1..label_end])
+        .map(|target| (label_end, target.clone(), after_label))
+}
+
+/// The matching `]` for the `[` at `open`, allowing nested brackets and skipping
+/// escaped characters and code spans.
+fn label_end(markdown: &str, open: usize, limit: usize) -> Option<usize> {
+    let bytes = markdown.as_bytes();
+    let mut depth = 0;
+    let mut cursor = open;
+    while cursor < limit {
+        match bytes[cursor] {
+            b'\\' => cursor += 1,
+            b'`' => {
+                let run = bytes[cursor..limit].iter().take_while(|byte| **byte == b'`').count();
+                let fence = &markdown[cursor..cursor + run];
+                if let Some(close) = markdown[cursor + run..limit].find(fence) {
+                    cursor += run + close + run;
+                    continue;
+                }
+                cursor += run - 1;
+            }
+            b'[' => depth += 1,
+            b']' => {
+                depth -= 1;
+                if depth == 0 {
+                    return Some(cursor);
+                }
+            }
+            _ => {}
+        }
+        cursor += 1;
+    }
+    None
+}
+
+/// A `[reference]` label at `open` without nested brackets, returning the position of
+/// its `]` and the offset after it. Empty labels (`[]`) are allowed here.
+fn reference_label(markdown: &str, open: usize, limit: usize) -> Option<(usize, usize)> {
+    let rest = markdown.get(open..limit)?.strip_prefix('[')?;
+    let bytes = rest.as_bytes();
+    let mut cursor = 0;
+    while cursor < bytes.len() && cursor < 1000 {
+        match bytes[cursor] {
+            b'\\' => cursor += 1,
+            b'[' => return None,
+            b']' => return Some((open + 1 + cursor, open + 2 + cursor)),
+            _ => {}
+        }
+        cursor += 1;
+    }
+    None
+}
+
+/// `(href "title")` starting at the `(` at `open`.
+fn inline_target(markdown: &str, open: usize, limit: usize) -> Option<(LinkTarget, usize)> {
+    let start = skip_spaces(markdown, open + 1, limit);
+    let (href, after_href) = if markdown[start..limit].starts_with(')') {
+        (String::new(), start)
+    } else {
+        destination(markdown, start, limit)?
+    };
+    let cursor = skip_spaces(markdown, after_href, limit);
+    let (title, cursor) = match title(markdown, cursor, limit) {
+        Some((title, end)) if cursor > after_href => {
+            (Some(title), skip_spaces(markdown, end, limit))
+        }
+        _ => (None, cursor),
+    };
+    markdown[cursor..limit].starts_with(')').then_some((LinkTarget { href, title }, cursor + 1))
+}
+
+/// A link destination: `<...>` on one line, or a run without spaces or control
+/// characters whose parentheses balance.
+fn destination(markdown: &str, start: usize, limit: usize) -> Option<(String, usize)> {
+    let bytes = markdown.as_bytes();
+    if bytes.get(start) == Some(&b'<') {
+        let mut cursor = start + 1;
+        while cursor < limit {
+            match bytes[cursor] {
+                b'\\' => cursor += 1,
+                b'\n' | b'<' => return None,
+                b'>' => return Some((unescape(&markdown[start + 1..cursor]), cursor + 1)),
+                _ => {}
+            }
+            cursor += 1;
+        }
+        return None;
+    }
+
+    let mut depth = 0_usize;
+    let mut cursor = start;
+    while cursor < limit {
+        match bytes[cursor] {
+            b'\\' if cursor + 1 < limit => cursor += 1,
+            b'(' => depth += 1,
+            b')' if depth == 0 => break,
+            b')' => depth -= 1,
+            byte if byte <= b' ' || byte == 0x7f => break,
+            _ => {}
+        }
+        cursor += 1;
+    }
+    (cursor > start && depth == 0).then(|| (unescape(&markdown[start..cursor]), cursor))
+}
+
+/// A link title in double quotes, single quotes or parentheses.
+fn title(markdown: &str, start: usize, limit: usize) -> Option<(String, usize)> {
+    let bytes = markdown.as_bytes();
+    let close = match bytes.get(start).filter(|_| start < limit)? {
+        b'"' => b'"',
+        b'\'' => b'\'',
+        b'(' => b')',
+        _ => return None,
+    };
+    let mut cursor = start + 1;
+    while cursor < limit {
+        match bytes[cursor] {
+            b'\\' => cursor += 1,
+            byte if byte == close => {
+                return Some((unescape(&markdown[start + 1..cursor]), cursor + 1));
+            }
+            b'(' if close == b')' => return None,
+            _ => {}
+        }
+        cursor += 1;
+    }
+    None
+}
+
+/// Skips spaces and tabs, and at most one line break.
+fn skip_spaces(markdown: &str, mut cursor: usize, limit: usize) -> usize {
+    let bytes = markdown.as_bytes();
+    let mut newline = false;
+    while cursor < limit {
+        match bytes[cursor] {
+            b' ' | b'\t' => {}
+            b'\n' if !newline => newline = true,
+            _ => break,
+        }
+        cursor += 1;
+    }
+    cursor
+}
+
+/// A scheme of 2 to 32 characters followed by `:`.
+fn is_absolute_uri(address: &str) -> bool {
+    let Some((scheme, _)) = address.split_once(':') else {
+        return false;
+    };
+    (2..=32).contains(&scheme.len())
+        && scheme.starts_with(|ch: char| ch.is_ascii_alphabetic())
+        && scheme.chars().all(|ch| ch.is_ascii_alphanumeric() || matches!(ch, '+' | '.' | '-'))
+}
+
+fn is_email(address: &str) -> bool {
+    let Some((local, domain)) = address.split_once('@') else {
+        return false;
+    };
+    !local.is_empty()
+        && local.chars().all(|ch| ch.is_ascii_alphanumeric() || ".!#$%&'*+/=?^_`{|}~-".contains(ch))
+        && !domain.is_empty()
+        && domain.split('.').all(|label| {
+            !label.is_empty()
+                && !label.starts_with('-')
+                && !label.ends_with('-')
+                && label.chars().all(|ch| ch.is_ascii_alphanumeric() || ch == '-')
+        })
+}
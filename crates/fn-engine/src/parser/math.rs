@@ -0,0 +1,169 @@
+//! TeX math: `$inline$` and `$$display$$` spans, and `$$` blocks. Spans follow the
+//! Pandoc rules, so prices like `$5 and $6` stay text.
+
+use crate::{
+    ast::{BlockKind, BlockNode, InlineKind, InlineNode},
+    registry::{BlockSyntaxPlugin, InlineSyntaxPlugin},
+    selection::TextRange,
+};
+
+use super::{leading_spaces, Line, ParseContext};
+
+/// Display math between `$$` lines, which runs to the closing line or the end of the
+/// document like a fenced code block, or `$$ tex $$` on a line of its own.
+pub struct MathBlockSyntax;
+
+impl BlockSyntaxPlugin for MathBlockSyntax {
+    fn name(&self) -> &str {
+        "math-block"
+    }
+
+    fn parse(
+        &self,
+        cx: &mut ParseContext<'_>,
+        lines: &[Line<'_>],
+        index: usize,
+    ) -> Option<(BlockNode, usize)> {
+        let line = lines[index];
+        let open = math_block_open(line.text)?;
+        let rest = &line.text[open..];
+
+        if !rest.trim().is_empty() {
+            let inner = single_line_tex(rest)?;
+            let tex_start = line.start + open + (inner.len() - inner.trim_start().len());
+            let tex = inner.trim();
+            let block = BlockNode {
+                node_id: cx.next_id(),
+                range: TextRange::new(line.start as u32, line.end_with_newline as u32),
+                kind: BlockKind::MathBlock {
+                    text_range: TextRange::new(tex_start as u32, (tex_start + tex.len()) as u32),
+                    tex: tex.to_string(),
+                },
+            };
+            return Some((block, index + 1));
+        }
+
+        let closing = lines[index + 1..]
+            .iter()
+            .position(|line| line.text.trim() == "$$")
+            .map(|offset| index + 1 + offset);
+        let content = &lines[index + 1..closing.unwrap_or(lines.len())];
+        let text_range = match (content.first(), content.last()) {
+            (Some(first), Some(last)) => TextRange::new(first.start as u32, last.end as u32),
+            _ => TextRange::new(line.end_with_newline as u32, line.end_with_newline as u32),
+        };
+        let tex = content.iter().map(|line| line.text).collect::<Vec<_>>().join("\n");
+        let (range_end, next_index) = match closing {
+            Some(closing) => (lines[closing].end_with_newline, closing + 1),
+            None => (
+                lines.last().map_or(line.end_with_newline, |line| line.end_with_newline),
+                lines.len(),
+            ),
+        };
+
+        let block = BlockNode {
+            node_id: cx.next_id(),
+            range: TextRange::new(line.start as u32, range_end as u32),
+            kind: BlockKind::MathBlock { text_range, tex },
+        };
+        Some((block, next_index))
+    }
+
+    fn interrupts_paragraph(&self, lines: &[Line<'_>], index: usize) -> bool {
+        let text = lines[index].text;
+        math_block_open(text).is_some_and(|open| {
+            let rest = &text[open..];
+            rest.trim().is_empty() || single_line_tex(rest).is_some()
+        })
+    }
+}
+
+/// The TeX of a one-line block, from the rest of the line after its opening `$$`.
+fn single_line_tex(rest: &str) -> Option<&str> {
+    rest.trim_end().strip_suffix("$$").filter(|inner| !inner.trim().is_empty())
+}
+
+/// The offset past the `$$` a math block line starts with, after up to three spaces.
+fn math_block_open(line: &str) -> Option<usize> {
+    let indent = leading_spaces(line);
+    (indent <= 3 && line[indent..].starts_with("$$")).then_some(indent + 2)
+}
+
+/// `$tex$` and `$$tex$$`, matched the way comrak matches them so exports agree with
+/// the editor. An opening `$` must not be followed by whitespace, and a closing one
+/// must not be preceded by whitespace or followed by a digit. A run without a closer
+/// stays literal as a whole.
+pub struct MathSyntax;
+
+impl InlineSyntaxPlugin for MathSyntax {
+    fn name(&self) -> &str {
+        "math"
+    }
+
+    fn parse(
+        &self,
+        cx: &mut ParseContext<'_>,
+        start: usize,
+        limit: usize,
+    ) -> Option<(InlineNode, usize)> {
+        let markdown = cx.markdown();
+        let run = dollar_run(markdown, start, limit);
+        if run == 0 {
+            return None;
+        }
+
+        let inner_start = start + run;
+        let Some(inner_end) = closing_dollars(markdown, inner_start, limit, run) else {
+            let literal = InlineKind::Text { value: markdown[start..inner_start].to_string() };
+            let range = TextRange::new(start as u32, inner_start as u32);
+            return Some((InlineNode { node_id: cx.next_id(), range, kind: literal }, inner_start));
+        };
+
+        let node = InlineNode {
+            node_id: cx.next_id(),
+            range: TextRange::new(start as u32, (inner_end + run) as u32),
+            kind: InlineKind::Math {
+                display: run == 2,
+                tex: cx.content_text(inner_start, inner_end).trim().to_string(),
+            },
+        };
+        Some((node, inner_end + run))
+    }
+}
+
+fn dollar_run(markdown: &str, start: usize, limit: usize) -> usize {
+    markdown.as_bytes()[start..limit].iter().take_while(|byte| **byte == b'$').count()
+}
+
+/// Where the run of `open` dollar signs closing a span begins. Runs of other lengths
+/// are skipped, as is a `\$` inside inline math.
+fn closing_dollars(markdown: &str, inner_start: usize, limit: usize, open: usize) -> Option<usize> {
+    let bytes = markdown.as_bytes();
+    if open > 2 || (open == 1 && bytes.get(inner_start).is_some_and(u8::is_ascii_whitespace)) {
+        return None;
+    }
+
+    let mut cursor = inner_start;
+    loop {
+        cursor += markdown[cursor..limit].find('$')?;
+        if open == 1 {
+            match bytes[cursor - 1] {
+                byte if byte.is_ascii_whitespace() => return None,
+                b'\\' => {
+                    cursor += 1;
+                    continue;
+                }
+                _ => {}
+            }
+        }
+        let run = dollar_run(markdown, cursor, limit);
+        let after = cursor + run;
+        if open == 1 && after < limit && bytes[after].is_ascii_digit() {
+            return None;
+        }
+        if run == open {
+            return Some(cursor);
+        }
+        cursor = after;
+    }
+}
@@ -1,28 +1,178 @@
+pub mod callouts;
+pub mod footnotes;
+pub mod front_matter;
+pub mod html;
+mod incremental;
+mod inlines;
+pub mod links;
+pub mod math;
+mod reconcile;
+pub mod syntax;
+pub mod wiki_links;
+
+pub use incremental::{reparse_document, Reparse};
+use inlines::parse_inlines;
+pub use links::{LinkDefinitions, LinkTarget};
+pub use reconcile::reconcile_node_ids;
+
+use std::borrow::Cow;
+
 use crate::{
-    ast::{BlockKind, BlockNode, InlineKind, InlineNode, ListItemNode, NodeId},
+    ast::{BlockKind, BlockNode, InlineNode, NodeId, TableAlignment},
+    registry::EnginePluginRegistry,
     selection::TextRange,
 };
 
+/// One source line. Its offsets point into the whole document, also when `text` is
+/// the stripped content of a container line such as a list item's.
 #[derive(Debug, Clone, Copy)]
-struct Line<'a> {
-    start: usize,
-    end: usize,
-    end_with_newline: usize,
-    text: &'a str,
+pub struct Line<'a> {
+    pub start: usize,
+    pub end: usize,
+    pub end_with_newline: usize,
+    pub text: &'a str,
 }
 
-#[derive(Debug, Default)]
-struct NodeIdGenerator {
+/// Hands out node IDs. A document keeps one generator for its whole lifetime so an
+/// ID is never reused for a different node.
+#[derive(Debug, Clone, Default)]
+pub struct NodeIdGenerator {
     next: NodeId,
 }
 
 impl NodeIdGenerator {
-    fn next(&mut self) -> NodeId {
+    pub fn next_id(&mut self) -> NodeId {
         self.next += 1;
         self.next
     }
 }
 
+/// What the parser and the syntax plugins it dispatches to share: the document, the
+/// registry nested content is parsed with, the document's link definitions, and the
+/// node ID generator.
+pub struct ParseContext<'a> {
+    markdown: &'a str,
+    registry: &'a EnginePluginRegistry,
+    definitions: &'a LinkDefinitions,
+    id_gen: &'a mut NodeIdGenerator,
+    /// Container prefixes inside the paragraph whose inlines are being parsed.
+    line_prefixes: Vec<(usize, usize)>,
+    /// Whether the inlines being parsed are a table cell's, where `\|` stands for a
+    /// pipe even inside code spans.
+    table_cell: bool,
+    /// Where `markdown` starts in the document.
+    offset: usize,
+}
+
+impl<'a> ParseContext<'a> {
+    pub fn new(
+        markdown: &'a str,
+        registry: &'a EnginePluginRegistry,
+        definitions: &'a LinkDefinitions,
+        id_gen: &'a mut NodeIdGenerator,
+    ) -> Self {
+        Self {
+            markdown,
+            registry,
+            definitions,
+            id_gen,
+            line_prefixes: Vec::new(),
+            table_cell: false,
+            offset: 0,
+        }
+    }
+
+    /// A context for a part of the document starting at `offset`, such as the window
+    /// an incremental reparse reads. Offsets in the parsed tree stay relative to it.
+    pub(crate) fn at_offset(self, offset: usize) -> Self {
+        Self { offset, ..self }
+    }
+
+    /// The text being parsed, which every offset in the tree points into: the whole
+    /// document, or the part of it starting at [`document_offset`](Self::document_offset).
+    pub fn markdown(&self) -> &'a str {
+        self.markdown
+    }
+
+    /// Where [`markdown`](Self::markdown) starts in the document, for syntax that
+    /// only applies at the document's start.
+    pub fn document_offset(&self) -> usize {
+        self.offset
+    }
+
+    pub fn next_id(&mut self) -> NodeId {
+        self.id_gen.next_id()
+    }
+
+    /// `markdown[start..end]` without the container prefixes in front of the lines of
+    /// the paragraph being parsed, such as a blockquote's `>` or a list item's
+    /// indentation. In a table cell, `\|` is unescaped to `|`.
+    pub fn content_text(&self, start: usize, end: usize) -> Cow<'a, str> {
+        let text = self.unprefixed_text(start, end);
+        if self.table_cell && text.contains("\\|") {
+            return Cow::Owned(text.replace("\\|", "|"));
+        }
+        text
+    }
+
+    fn unprefixed_text(&self, start: usize, end: usize) -> Cow<'a, str> {
+        let markdown = self.markdown;
+        let inside = |(prefix_start, prefix_end): &&(usize, usize)| {
+            *prefix_start < end && *prefix_end > start
+        };
+        if !self.line_prefixes.iter().any(|prefix| inside(&prefix)) {
+            return Cow::Borrowed(&markdown[start..end]);
+        }
+
+        let mut text = String::with_capacity(end - start);
+        let mut cursor = start;
+        for (prefix_start, prefix_end) in self.line_prefixes.iter().filter(inside) {
+            text.push_str(&markdown[cursor..(*prefix_start).max(cursor)]);
+            cursor = (*prefix_end).min(end);
+        }
+        text.push_str(&markdown[cursor..end]);
+        Cow::Owned(text)
+    }
+
+    /// The target of the link reference definition for `label`, if the document has
+    /// one.
+    pub fn link_definition(&self, label: &str) -> Option<&'a LinkTarget> {
+        self.definitions.get(label)
+    }
+
+    /// Parses `lines`, such as the content lines of a container, as blocks.
+    pub fn parse_blocks(&mut self, lines: &[Line<'_>]) -> Vec<BlockNode> {
+        parse_blocks(self, lines)
+    }
+
+    /// Parses `markdown[start..end]` as inline content.
+    pub fn parse_inlines(&mut self, start: usize, end: usize) -> Vec<InlineNode> {
+        parse_inlines(self, start, end)
+    }
+
+    /// Parses `markdown[start..end]` as the inline content of a table cell.
+    pub fn parse_table_cell(&mut self, start: usize, end: usize) -> Vec<InlineNode> {
+        self.table_cell = true;
+        let inlines = parse_inlines(self, start, end);
+        self.table_cell = false;
+        inlines
+    }
+
+    /// Whether `line` is blank or opens a block that ends a paragraph. Only `line`
+    /// itself is looked at, so syntax that needs the lines below it (tables) does not
+    /// count.
+    pub fn starts_block(&self, line: Line<'_>) -> bool {
+        line.text.trim().is_empty() || self.interrupts_paragraph(std::slice::from_ref(&line), 0)
+    }
+
+    fn interrupts_paragraph(&self, lines: &[Line<'_>], index: usize) -> bool {
+        self.registry
+            .block_syntax_plugins
+            .iter()
+            .any(|plugin| plugin.interrupts_paragraph(lines, index))
+    }
+}
+
 pub fn normalize_code_language(input: &str) -> Option<String> {
     let trimmed = input.trim().to_ascii_lowercase();
     if trimmed.is_empty() {
@@ -41,353 +191,157 @@ pub fn normalize_code_language(input: &str) -> Option<String> {
     Some(canonical.to_string())
 }
 
-pub fn parse_document(markdown: &str) -> Vec<BlockNode> {
+/// Reference links can point at a definition further down, so a document with link
+/// definitions is parsed a second time with them known.
+pub fn parse_document(markdown: &str, registry: &EnginePluginRegistry) -> Vec<BlockNode> {
     let lines = scan_lines(markdown);
-    let mut index = 0;
     let mut id_gen = NodeIdGenerator::default();
-    let mut blocks = Vec::new();
+    let none = LinkDefinitions::default();
+    let blocks =
+        parse_blocks(&mut ParseContext::new(markdown, registry, &none, &mut id_gen), &lines);
+    let definitions = LinkDefinitions::collect(&blocks);
+    if definitions.is_empty() {
+        return blocks;
+    }
 
-    while index < lines.len() {
-        let line = lines[index];
+    let mut id_gen = NodeIdGenerator::default();
+    parse_blocks(&mut ParseContext::new(markdown, registry, &definitions, &mut id_gen), &lines)
+}
 
-        if line.text.trim().is_empty() {
-            blocks.push(BlockNode {
-                node_id: id_gen.next(),
-                range: TextRange::new(line.start as u32, line.start as u32),
-                kind: BlockKind::Paragraph { inlines: Vec::new() },
-            });
-            index += 1;
-            continue;
-        }
+/// Parses block structure over `lines`, which may be the stripped content lines of
+/// a container such as a list item. Line offsets always point into `markdown`.
+fn parse_blocks(cx: &mut ParseContext<'_>, lines: &[Line<'_>]) -> Vec<BlockNode> {
+    let mut index = 0;
+    let mut blocks = Vec::new();
 
-        if let Some((language, fence_len)) = parse_fence_open(line.text) {
-            let mut closing_index = index + 1;
-            while closing_index < lines.len()
-                && !is_closing_fence(lines[closing_index].text, fence_len)
-            {
-                closing_index += 1;
-            }
+    while index < lines.len() {
+        let (block, next_index) = parse_block(cx, lines, index);
+        blocks.push(block);
+        index = next_index;
+    }
 
-            let content_start = line.end_with_newline.min(markdown.len());
-            let content_end = if closing_index < lines.len() {
-                lines[closing_index].start
-            } else {
-                markdown.len()
-            };
-            let code = markdown[content_start..content_end].to_string();
-            let range_end = if closing_index < lines.len() {
-                lines[closing_index].end_with_newline
-            } else {
-                line.end_with_newline.max(content_end)
-            };
+    blocks
+}
 
-            blocks.push(BlockNode {
-                node_id: id_gen.next(),
-                range: TextRange::new(line.start as u32, range_end as u32),
-                kind: BlockKind::FencedCode {
-                    language,
-                    text_range: TextRange::new(content_start as u32, content_end as u32),
-                    code,
-                },
-            });
+/// Parses the block starting at `lines[index]` and returns it with the index of the
+/// first line after it. The result depends only on `lines[index..]`. Lines no plugin
+/// claims form a paragraph, or a setext heading when an underline ends them.
+fn parse_block(cx: &mut ParseContext<'_>, lines: &[Line<'_>], index: usize) -> (BlockNode, usize) {
+    let line = lines[index];
+
+    if line.text.trim().is_empty() {
+        let block = BlockNode {
+            node_id: cx.next_id(),
+            range: TextRange::new(line.start as u32, line.start as u32),
+            kind: BlockKind::Paragraph { inlines: Vec::new() },
+        };
+        return (block, index + 1);
+    }
 
-            index = if closing_index < lines.len() { closing_index + 1 } else { lines.len() };
-            continue;
-        }
+    let registry = cx.registry;
+    if let Some(parsed) =
+        registry.block_syntax_plugins.iter().find_map(|plugin| plugin.parse(cx, lines, index))
+    {
+        return parsed;
+    }
 
-        if let Some((level, prefix_len)) = parse_heading(line.text) {
-            let content_offset = line.start + prefix_len;
-            let content = &markdown[content_offset..line.end];
-            blocks.push(BlockNode {
-                node_id: id_gen.next(),
-                range: TextRange::new(line.start as u32, line.end as u32),
+    let mut next_index = index + 1;
+    while next_index < lines.len() {
+        let current = lines[next_index];
+        if let Some(level) = parse_setext_underline(current.text) {
+            let block = BlockNode {
+                node_id: cx.next_id(),
+                range: TextRange::new(line.start as u32, current.end as u32),
                 kind: BlockKind::Heading {
                     level,
-                    inlines: parse_inlines(content, content_offset, &mut id_gen),
+                    inlines: paragraph_inlines(cx, &lines[index..next_index]),
                 },
-            });
-            index += 1;
-            continue;
+            };
+            return (block, next_index + 1);
         }
-
-        if parse_blockquote(line.text).is_some() {
-            let mut children = Vec::new();
-            let quote_start = line.start;
-            let mut quote_end = line.end;
-
-            while index < lines.len() {
-                let current = lines[index];
-                let Some(prefix_len) = parse_blockquote(current.text) else {
-                    break;
-                };
-                let content_offset = current.start + prefix_len;
-                let content = &markdown[content_offset..current.end];
-                children.push(BlockNode {
-                    node_id: id_gen.next(),
-                    range: TextRange::new(current.start as u32, current.end as u32),
-                    kind: BlockKind::Paragraph {
-                        inlines: parse_inlines(content, content_offset, &mut id_gen),
-                    },
-                });
-                quote_end = current.end_with_newline;
-                index += 1;
-            }
-
-            blocks.push(BlockNode {
-                node_id: id_gen.next(),
-                range: TextRange::new(quote_start as u32, quote_end as u32),
-                kind: BlockKind::Blockquote { children },
-            });
-            continue;
+        if current.text.trim().is_empty() || cx.interrupts_paragraph(lines, next_index) {
+            break;
         }
+        next_index += 1;
+    }
 
-        if parse_bullet_marker(line.text).is_some() {
-            let list_start = line.start;
-            let mut list_end = line.end;
-            let mut items = Vec::new();
-
-            while index < lines.len() {
-                let current = lines[index];
-                let Some(prefix_len) = parse_bullet_marker(current.text) else {
-                    break;
-                };
-                let content_offset = current.start + prefix_len;
-                let content = &markdown[content_offset..current.end];
-                let child = BlockNode {
-                    node_id: id_gen.next(),
-                    range: TextRange::new(current.start as u32, current.end as u32),
-                    kind: BlockKind::Paragraph {
-                        inlines: parse_inlines(content, content_offset, &mut id_gen),
-                    },
-                };
-                items.push(ListItemNode {
-                    node_id: id_gen.next(),
-                    range: TextRange::new(current.start as u32, current.end as u32),
-                    children: vec![child],
-                });
-                list_end = current.end_with_newline;
-                index += 1;
-            }
-
-            blocks.push(BlockNode {
-                node_id: id_gen.next(),
-                range: TextRange::new(list_start as u32, list_end as u32),
-                kind: BlockKind::BulletList { items },
-            });
-            continue;
-        }
-
-        if let Some((start_number, _)) = parse_ordered_marker(line.text) {
-            let list_start = line.start;
-            let mut list_end = line.end;
-            let mut items = Vec::new();
-            let mut next_number = start_number;
-
-            while index < lines.len() {
-                let current = lines[index];
-                let Some((_number, prefix_len)) = parse_ordered_marker(current.text) else {
-                    break;
-                };
-                let content_offset = current.start + prefix_len;
-                let content = &markdown[content_offset..current.end];
-                let child = BlockNode {
-                    node_id: id_gen.next(),
-                    range: TextRange::new(current.start as u32, current.end as u32),
-                    kind: BlockKind::Paragraph {
-                        inlines: parse_inlines(content, content_offset, &mut id_gen),
-                    },
-                };
-                items.push(ListItemNode {
-                    node_id: id_gen.next(),
-                    range: TextRange::new(current.start as u32, current.end as u32),
-                    children: vec![child],
-                });
-                next_number += 1;
-                list_end = current.end_with_newline;
-                index += 1;
-            }
-
-            let list_start_number =
-                start_number.max(next_number.saturating_sub(items.len() as u32));
-            blocks.push(BlockNode {
-                node_id: id_gen.next(),
-                range: TextRange::new(list_start as u32, list_end as u32),
-                kind: BlockKind::OrderedList { start: list_start_number, items },
-            });
-            continue;
-        }
+    let paragraph_end = lines[next_index - 1].end;
+    let block = BlockNode {
+        node_id: cx.next_id(),
+        range: TextRange::new(line.start as u32, paragraph_end as u32),
+        kind: BlockKind::Paragraph { inlines: paragraph_inlines(cx, &lines[index..next_index]) },
+    };
+    (block, next_index)
+}
 
-        let paragraph_start = line.start;
-        let mut paragraph_end = line.end;
-        let mut next_index = index + 1;
-
-        while next_index < lines.len() {
-            let current = lines[next_index];
-            if current.text.trim().is_empty()
-                || parse_fence_open(current.text).is_some()
-                || parse_heading(current.text).is_some()
-                || parse_blockquote(current.text).is_some()
-                || parse_bullet_marker(current.text).is_some()
-                || parse_ordered_marker(current.text).is_some()
-            {
-                break;
-            }
-            paragraph_end = current.end;
-            next_index += 1;
-        }
+/// The inline content of the paragraph `lines`. What a container strips from the
+/// start of a continuation line, such as a blockquote's `>`, is not part of the text.
+fn paragraph_inlines(cx: &mut ParseContext<'_>, lines: &[Line<'_>]) -> Vec<InlineNode> {
+    let (first, last) = (lines[0], lines[lines.len() - 1]);
+    cx.line_prefixes = lines
+        .windows(2)
+        .map(|pair| (pair[0].end_with_newline, pair[1].start))
+        .filter(|(start, end)| start < end)
+        .collect();
+    let (content_start, content_end) = trim_content(cx.markdown, first.start, last.end);
+    let inlines = cx.parse_inlines(content_start, content_end);
+    cx.line_prefixes.clear();
+    inlines
+}
 
-        let content = &markdown[paragraph_start..paragraph_end];
-        blocks.push(BlockNode {
-            node_id: id_gen.next(),
-            range: TextRange::new(paragraph_start as u32, paragraph_end as u32),
-            kind: BlockKind::Paragraph {
-                inlines: parse_inlines(content, paragraph_start, &mut id_gen),
-            },
-        });
-        index = next_index;
-    }
+/// `markdown[start..end]` without leading and trailing spaces and tabs, which are
+/// not part of a paragraph's inline content.
+fn trim_content(markdown: &str, start: usize, end: usize) -> (usize, usize) {
+    let content = &markdown[start..end];
+    let trimmed = content.trim_start_matches([' ', '\t']);
+    let content_start = start + content.len() - trimmed.len();
+    (content_start, content_start + trimmed.trim_end_matches([' ', '\t']).len())
+}
 
-    blocks
+fn strip_indent(line: Line<'_>, width: usize) -> Line<'_> {
+    let width = width.min(leading_spaces(line.text));
+    Line { start: line.start + width, text: &line.text[width..], ..line }
 }
 
-fn parse_inlines(text: &str, base_offset: usize, id_gen: &mut NodeIdGenerator) -> Vec<InlineNode> {
-    let mut nodes = Vec::new();
-    let mut cursor = 0;
-    let mut literal_start = 0;
-
-    while cursor < text.len() {
-        let rest = &text[cursor..];
-
-        if let Some(stripped) = rest.strip_prefix("**") {
-            if let Some(close_rel) = stripped.find("**") {
-                push_text_segment(&mut nodes, text, literal_start, cursor, base_offset, id_gen);
-                let inner_start = cursor + 2;
-                let inner_end = inner_start + close_rel;
-                let children =
-                    parse_inlines(&text[inner_start..inner_end], base_offset + inner_start, id_gen);
-                nodes.push(InlineNode {
-                    node_id: id_gen.next(),
-                    range: TextRange::new(
-                        (base_offset + inner_start) as u32,
-                        (base_offset + inner_end) as u32,
-                    ),
-                    kind: InlineKind::Strong { children },
-                });
-                cursor = inner_end + 2;
-                literal_start = cursor;
-                continue;
-            }
-        }
+fn leading_spaces(text: &str) -> usize {
+    text.bytes().take_while(|byte| *byte == b' ').count()
+}
 
-        if let Some(stripped) = rest.strip_prefix('*') {
-            if let Some(close_rel) = stripped.find('*') {
-                push_text_segment(&mut nodes, text, literal_start, cursor, base_offset, id_gen);
-                let inner_start = cursor + 1;
-                let inner_end = inner_start + close_rel;
-                let children =
-                    parse_inlines(&text[inner_start..inner_end], base_offset + inner_start, id_gen);
-                nodes.push(InlineNode {
-                    node_id: id_gen.next(),
-                    range: TextRange::new(
-                        (base_offset + inner_start) as u32,
-                        (base_offset + inner_end) as u32,
-                    ),
-                    kind: InlineKind::Emphasis { children },
-                });
-                cursor = inner_end + 1;
-                literal_start = cursor;
-                continue;
-            }
+/// Width of the indentation of `text` in columns, with tab stops every four columns.
+fn indent_columns(text: &str) -> usize {
+    let mut column = 0;
+    for byte in text.bytes() {
+        match byte {
+            b' ' => column += 1,
+            b'\t' => column += 4 - column % 4,
+            _ => break,
         }
+    }
+    column
+}
 
-        if let Some(stripped) = rest.strip_prefix('`') {
-            if let Some(close_rel) = stripped.find('`') {
-                push_text_segment(&mut nodes, text, literal_start, cursor, base_offset, id_gen);
-                let inner_start = cursor + 1;
-                let inner_end = inner_start + close_rel;
-                nodes.push(InlineNode {
-                    node_id: id_gen.next(),
-                    range: TextRange::new(
-                        (base_offset + inner_start) as u32,
-                        (base_offset + inner_end) as u32,
-                    ),
-                    kind: InlineKind::InlineCode {
-                        value: text[inner_start..inner_end].to_string(),
-                    },
-                });
-                cursor = inner_end + 1;
-                literal_start = cursor;
-                continue;
-            }
+/// `text` without up to `columns` columns of indentation. A tab that reaches past
+/// `columns` leaves the spaces it covers beyond them.
+fn strip_columns(text: &str, columns: usize) -> Cow<'_, str> {
+    let mut column = 0;
+    for (offset, byte) in text.bytes().enumerate() {
+        if column >= columns {
+            return Cow::Borrowed(&text[offset..]);
         }
-
-        if rest.starts_with('[') {
-            if let Some(close_bracket) = rest.find(']') {
-                let after_bracket = cursor + close_bracket + 1;
-                if text[after_bracket..].starts_with('(') {
-                    if let Some(close_paren_rel) = text[after_bracket + 1..].find(')') {
-                        push_text_segment(
-                            &mut nodes,
-                            text,
-                            literal_start,
-                            cursor,
-                            base_offset,
-                            id_gen,
-                        );
-                        let label_start = cursor + 1;
-                        let label_end = cursor + close_bracket;
-                        let href_start = after_bracket + 1;
-                        let href_end = href_start + close_paren_rel;
-                        let children = parse_inlines(
-                            &text[label_start..label_end],
-                            base_offset + label_start,
-                            id_gen,
-                        );
-                        nodes.push(InlineNode {
-                            node_id: id_gen.next(),
-                            range: TextRange::new(
-                                (base_offset + label_start) as u32,
-                                (base_offset + href_end + 1) as u32,
-                            ),
-                            kind: InlineKind::Link {
-                                href: text[href_start..href_end].to_string(),
-                                title: None,
-                                children,
-                            },
-                        });
-                        cursor = href_end + 1;
-                        literal_start = cursor;
-                        continue;
-                    }
+        match byte {
+            b' ' => column += 1,
+            b'\t' => {
+                let next = column + 4 - column % 4;
+                if next > columns {
+                    let rest = &text[offset + 1..];
+                    return Cow::Owned(format!("{}{rest}", " ".repeat(next - columns)));
                 }
+                column = next;
             }
+            _ => return Cow::Borrowed(&text[offset..]),
         }
-
-        let ch = rest.chars().next().unwrap_or_default();
-        cursor += ch.len_utf8();
-    }
-
-    push_text_segment(&mut nodes, text, literal_start, text.len(), base_offset, id_gen);
-    nodes
-}
-
-fn push_text_segment(
-    nodes: &mut Vec<InlineNode>,
-    text: &str,
-    start: usize,
-    end: usize,
-    base_offset: usize,
-    id_gen: &mut NodeIdGenerator,
-) {
-    if start >= end {
-        return;
     }
-
-    nodes.push(InlineNode {
-        node_id: id_gen.next(),
-        range: TextRange::new((base_offset + start) as u32, (base_offset + end) as u32),
-        kind: InlineKind::Text { value: text[start..end].to_string() },
-    });
+    Cow::Borrowed("")
 }
 
 fn scan_lines(markdown: &str) -> Vec<Line<'_>> {
@@ -417,30 +371,118 @@ fn scan_lines(markdown: &str) -> Vec<Line<'_>> {
     lines
 }
 
-fn parse_heading(line: &str) -> Option<(u8, usize)> {
-    let hashes = line.chars().take_while(|ch| *ch == '#').count();
+/// An ATX heading: up to three spaces, one to six `#` and a space, tab or the line
+/// end. Returns the level and the content range within `line`, without the optional
+/// closing sequence of `#`.
+fn parse_heading(line: &str) -> Option<(u8, usize, usize)> {
+    let indent = leading_spaces(line);
+    if indent > 3 {
+        return None;
+    }
+
+    let rest = &line[indent..];
+    let hashes = rest.bytes().take_while(|byte| *byte == b'#').count();
     if hashes == 0 || hashes > 6 {
         return None;
     }
+    if !matches!(rest.as_bytes().get(hashes), None | Some(b' ' | b'\t')) {
+        return None;
+    }
 
-    if line.as_bytes().get(hashes) != Some(&b' ') {
+    let start = indent + hashes;
+    let content = line[start..].trim_end_matches([' ', '\t']);
+    let without_closing = content.trim_end_matches('#');
+    let end = if without_closing.len() == content.len() {
+        start + content.len()
+    } else if without_closing.trim_start_matches([' ', '\t']).is_empty() {
+        start
+    } else if without_closing.ends_with([' ', '\t']) {
+        start + without_closing.len()
+    } else {
+        start + content.len()
+    };
+    Some((hashes as u8, start, end))
+}
+
+/// A setext heading underline: a run of `=` (level 1) or `-` (level 2) with up to
+/// three spaces before it and only spaces or tabs after it.
+fn parse_setext_underline(line: &str) -> Option<u8> {
+    let indent = leading_spaces(line);
+    if indent > 3 {
         return None;
     }
 
-    Some((hashes as u8, hashes + 1))
+    let rest = line[indent..].trim_end_matches([' ', '\t']);
+    let level = match rest.as_bytes().first() {
+        Some(b'=') => 1,
+        Some(b'-') => 2,
+        _ => return None,
+    };
+    rest.bytes().all(|byte| byte == rest.as_bytes()[0]).then_some(level)
 }
 
-fn parse_blockquote(line: &str) -> Option<usize> {
-    if line.starts_with("> ") {
-        Some(2)
-    } else if line == ">" {
-        Some(1)
-    } else {
-        None
+/// A thematic break: three or more `*`, `-` or `_`, optionally separated by spaces
+/// or tabs, after at most three spaces.
+fn is_thematic_break(line: &str) -> bool {
+    let indent = leading_spaces(line);
+    if indent > 3 {
+        return false;
     }
+
+    let rest = &line[indent..];
+    let Some(marker) = rest.bytes().next().filter(|byte| matches!(byte, b'*' | b'-' | b'_')) else {
+        return false;
+    };
+    rest.bytes().all(|byte| byte == marker || byte == b' ' || byte == b'\t')
+        && rest.bytes().filter(|byte| *byte == marker).count() >= 3
 }
 
-fn parse_bullet_marker(line: &str) -> Option<usize> {
+/// A blockquote marker: `>` after at most three spaces, with the space or tab after
+/// it. Returns the length of the marker.
+pub(crate) fn parse_blockquote(line: &str) -> Option<usize> {
+    let indent = leading_spaces(line);
+    if indent > 3 || line.as_bytes().get(indent) != Some(&b'>') {
+        return None;
+    }
+    let len = indent + 1;
+    Some(if matches!(line.as_bytes().get(len), Some(b' ' | b'\t')) { len + 1 } else { len })
+}
+
+#[derive(Debug, Clone, Copy)]
+struct ListMarker {
+    number: Option<u32>,
+    /// Column where item content starts, counting indentation, marker and padding.
+    content_indent: usize,
+}
+
+fn parse_list_marker(line: &str) -> Option<ListMarker> {
+    let indent = leading_spaces(line);
+    if indent > 3 {
+        return None;
+    }
+
+    let rest = &line[indent..];
+    let (number, marker_width) = if parse_bullet_marker(rest).is_some() {
+        (None, 1)
+    } else {
+        let (number, marker_len) = parse_ordered_marker(rest)?;
+        (Some(number), marker_len - 1)
+    };
+
+    let after_marker = &rest[marker_width..];
+    let padding = leading_spaces(after_marker);
+    let padding = if padding > 4 || padding == after_marker.len() { 1 } else { padding };
+    Some(ListMarker { number, content_indent: indent + marker_width + padding })
+}
+
+/// Column where the content of the list item starting on `line` begins, allowing
+/// any amount of nesting indentation before the marker.
+pub(crate) fn list_content_column(line: &str) -> Option<usize> {
+    let indent = leading_spaces(line);
+    parse_list_marker(&line[indent..]).map(|marker| indent + marker.content_indent)
+}
+
+pub(crate) fn parse_bullet_marker(line: &str) -> Option<usize> {
     let bytes = line.as_bytes();
     if bytes.len() >= 2 && matches!(bytes[0], b'-' | b'*' | b'+') && bytes[1] == b' ' {
         Some(2)
@@ -449,7 +491,7 @@ fn parse_bullet_marker(line: &str) -> Option<usize> {
     }
 }
 
-fn parse_ordered_marker(line: &str) -> Option<(u32, usize)> {
+pub(crate) fn parse_ordered_marker(line: &str) -> Option<(u32, usize)> {
     let digit_count = line.chars().take_while(|ch| ch.is_ascii_digit()).count();
     if digit_count == 0 {
         return None;
@@ -464,11 +506,151 @@ fn parse_ordered_marker(line: &str) -> Option<(u32, usize)> {
     Some((number, digit_count + 2))
 }
 
-fn parse_fence_open(line: &str) -> Option<(Option<String>, usize)> {
-    let fence = line.strip_prefix("```")?;
-    Some((normalize_code_language(fence), 3))
+fn parse_table_start(lines: &[Line<'_>], index: usize) -> Option<Vec<TableAlignment>> {
+    let header = lines.get(index)?;
+    let delimiter = lines.get(index + 1)?;
+    if !header.text.contains('|') {
+        return None;
+    }
+
+    let alignments = parse_table_delimiter(delimiter.text)?;
+    if split_table_cells(header.text).len() != alignments.len() {
+        return None;
+    }
+    Some(alignments)
+}
+
+fn parse_table_delimiter(line: &str) -> Option<Vec<TableAlignment>> {
+    if !line.contains('|') && !line.contains(':') {
+        return None;
+    }
+
+    let mut alignments = Vec::new();
+    for (start, end) in split_table_cells(line) {
+        let cell = &line[start..end];
+        let dashes = cell.trim_start_matches(':').trim_end_matches(':');
+        if dashes.is_empty() || !dashes.bytes().all(|byte| byte == b'-') {
+            return None;
+        }
+        let alignment = match (cell.starts_with(':'), cell.ends_with(':')) {
+            (true, true) => TableAlignment::Center,
+            (true, false) => TableAlignment::Left,
+            (false, true) => TableAlignment::Right,
+            (false, false) => TableAlignment::None,
+        };
+        alignments.push(alignment);
+    }
+
+    if alignments.is_empty() {
+        None
+    } else {
+        Some(alignments)
+    }
 }
 
-fn is_closing_fence(line: &str, fence_len: usize) -> bool {
-    line.trim() == "`".repeat(fence_len)
+/// Splits a pipe table row into trimmed cell content ranges relative to `line`.
+/// Escaped pipes (`\|`) stay inside their cell; empty cells collapse to a caret
+/// position just after the opening pipe's padding.
+pub(crate) fn split_table_cells(line: &str) -> Vec<(usize, usize)> {
+    let bytes = line.as_bytes();
+    let mut cursor = line.len() - line.trim_start().len();
+    let content_end = line.trim_end().len();
+    if bytes.get(cursor) == Some(&b'|') {
+        cursor += 1;
+    }
+
+    let mut cells = Vec::new();
+    let mut cell_start = cursor;
+    while cursor < content_end {
+        match bytes[cursor] {
+            b'\\' if bytes.get(cursor + 1) == Some(&b'|') => cursor += 2,
+            b'|' => {
+                cells.push(trim_table_cell(line, cell_start, cursor));
+                cursor += 1;
+                cell_start = cursor;
+            }
+            _ => cursor += 1,
+        }
+    }
+
+    if cell_start < content_end || (cells.is_empty() && cell_start == content_end) {
+        cells.push(trim_table_cell(line, cell_start, content_end.max(cell_start)));
+    }
+    cells
+}
+
+fn trim_table_cell(line: &str, start: usize, end: usize) -> (usize, usize) {
+    let raw = &line[start..end];
+    let leading = raw.len() - raw.trim_start().len();
+    let trimmed = raw.trim();
+    if trimmed.is_empty() {
+        let caret = start + raw.len().min(1);
+        return (caret, caret);
+    }
+    (start + leading, start + leading + trimmed.len())
+}
+
+/// Parses a GFM task checkbox at the start of list item content, returning the
+/// checked state and the byte length of the checkbox including its trailing space.
+pub(crate) fn parse_task_marker(content: &str) -> Option<(bool, usize)> {
+    let bytes = content.as_bytes();
+    if bytes.len() < 3 || bytes[0] != b'[' || bytes[2] != b']' {
+        return None;
+    }
+
+    let checked = match bytes[1] {
+        b' ' => false,
+        b'x' | b'X' => true,
+        _ => return None,
+    };
+    match bytes.get(3) {
+        None => Some((checked, 3)),
+        Some(b' ') => Some((checked, 4)),
+        Some(_) => None,
+    }
+}
+
+/// An opening code fence: its marker, length and indentation.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Fence {
+    marker: u8,
+    len: usize,
+    indent: usize,
+}
+
+/// An opening fence of three or more backticks or tildes after at most three spaces,
+/// and the language named by the first word of its info string. The info string of
+/// a backtick fence may not contain a backtick.
+fn parse_fence_open(line: &str) -> Option<(Fence, Option<String>)> {
+    let indent = leading_spaces(line);
+    if indent > 3 {
+        return None;
+    }
+
+    let rest = &line[indent..];
+    let marker = rest.bytes().next().filter(|byte| matches!(byte, b'`' | b'~'))?;
+    let len = rest.bytes().take_while(|byte| *byte == marker).count();
+    if len < 3 {
+        return None;
+    }
+
+    let info = &rest[len..];
+    if marker == b'`' && info.contains('`') {
+        return None;
+    }
+    let language = info.split_whitespace().next().unwrap_or_default();
+    Some((Fence { marker, len, indent }, normalize_code_language(&syntax::unescape(language))))
+}
+
+/// Whether `line` closes `fence`: at least as many of the same marker after at most
+/// three spaces, followed only by spaces or tabs.
+fn is_closing_fence(line: &str, fence: Fence) -> bool {
+    let indent = leading_spaces(line);
+    if indent > 3 {
+        return false;
+    }
+
+    let rest = &line[indent..];
+    let len = rest.bytes().take_while(|byte| *byte == fence.marker).count();
+    len >= fence.len && rest[len..].bytes().all(|byte| byte == b' ' || byte == b'\t')
 }
@@ -0,0 +1,470 @@
+use std::{
+    collections::{hash_map::DefaultHasher, HashMap, VecDeque},
+    hash::{Hash, Hasher},
+    mem::discriminant,
+};
+
+use crate::ast::{
+    BlockKind, BlockNode, InlineKind, InlineNode, ListItemNode, NodeId, TableAlignment,
+    TableCellNode, TableRowNode,
+};
+
+use super::NodeIdGenerator;
+
+/// Gives the freshly parsed `next` tree the IDs of the nodes it replaces in
+/// `previous`, so the renderer can key DOM nodes across reparses.
+///
+/// Siblings are matched by content first (same text and structure, wherever they
+/// moved), then left-over siblings of the same kind are paired in order so an
+/// edited paragraph keeps its ID. Children are only matched against the children of
+/// their matched parent. Unmatched nodes get fresh IDs from `id_gen`.
+pub fn reconcile_node_ids(
+    previous: &[BlockNode],
+    next: &mut [BlockNode],
+    id_gen: &mut NodeIdGenerator,
+) {
+    reconcile_sequence(previous, next, id_gen);
+}
+
+trait IdNode: Sized {
+    fn node_id(&self) -> NodeId;
+    fn set_node_id(&mut self, node_id: NodeId);
+    fn hash_content(&self, state: &mut DefaultHasher);
+    fn same_kind(&self, other: &Self) -> bool;
+    fn reconcile_children(&mut self, previous: &Self, id_gen: &mut NodeIdGenerator);
+    fn fresh_children(&mut self, id_gen: &mut NodeIdGenerator);
+
+    fn fingerprint(&self) -> u64 {
+        let mut state = DefaultHasher::new();
+        self.hash_content(&mut state);
+        state.finish()
+    }
+}
+
+fn reconcile_sequence<T: IdNode>(previous: &[T], next: &mut [T], id_gen: &mut NodeIdGenerator) {
+    let prefix = previous
+        .iter()
+        .zip(next.iter())
+        .take_while(|(old, new)| old.fingerprint() == new.fingerprint())
+        .count();
+    let suffix = previous[prefix..]
+        .iter()
+        .rev()
+        .zip(next[prefix..].iter().rev())
+        .take_while(|(old, new)| old.fingerprint() == new.fingerprint())
+        .count();
+
+    for index in 0..prefix {
+        reconcile_pair(&mut next[index], &previous[index], id_gen);
+    }
+    for offset in 1..=suffix {
+        let (old, new) = (previous.len() - offset, next.len() - offset);
+        reconcile_pair(&mut next[new], &previous[old], id_gen);
+    }
+
+    let old_middle = &previous[prefix..previous.len() - suffix];
+    let new_end = next.len() - suffix;
+    let new_middle = &mut next[prefix..new_end];
+
+    let mut by_content = HashMap::<u64, VecDeque<usize>>::new();
+    for (index, node) in old_middle.iter().enumerate() {
+        by_content.entry(node.fingerprint()).or_default().push_back(index);
+    }
+    let mut matches = new_middle
+        .iter()
+        .map(|node| by_content.get_mut(&node.fingerprint()).and_then(VecDeque::pop_front))
+        .collect::<Vec<_>>();
+
+    let mut used = vec![false; old_middle.len()];
+    matches.iter().flatten().for_each(|old| used[*old] = true);
+    let mut unused = (0..old_middle.len()).filter(|old| !used[*old]).peekable();
+    for (index, node) in new_middle.iter().enumerate() {
+        if matches[index].is_some() {
+            continue;
+        }
+        if let Some(old) = unused.next_if(|old| old_middle[*old].same_kind(node)) {
+            matches[index] = Some(old);
+        }
+    }
+
+    for (node, old) in new_middle.iter_mut().zip(matches) {
+        match old {
+            Some(old) => reconcile_pair(node, &old_middle[old], id_gen),
+            None => assign_fresh(node, id_gen),
+        }
+    }
+}
+
+fn reconcile_pair<T: IdNode>(next: &mut T, previous: &T, id_gen: &mut NodeIdGenerator) {
+    next.set_node_id(previous.node_id());
+    next.reconcile_children(previous, id_gen);
+}
+
+fn assign_fresh<T: IdNode>(node: &mut T, id_gen: &mut NodeIdGenerator) {
+    node.set_node_id(id_gen.next_id());
+    node.fresh_children(id_gen);
+}
+
+fn hash_sequence<T: IdNode>(nodes: &[T], state: &mut DefaultHasher) {
+    nodes.len().hash(state);
+    nodes.iter().for_each(|node| node.hash_content(state));
+}
+
+impl IdNode for BlockNode {
+    fn node_id(&self) -> NodeId {
+        self.node_id
+    }
+
+    fn set_node_id(&mut self, node_id: NodeId) {
+        self.node_id = node_id;
+    }
+
+    fn hash_content(&self, state: &mut DefaultHasher) {
+        discriminant(&self.kind).hash(state);
+        match &self.kind {
+            BlockKind::Paragraph { inlines } => hash_sequence(inlines, state),
+            BlockKind::FrontMatter { format, raw, .. } => {
+                format.hash(state);
+                raw.hash(state);
+            }
+            BlockKind::Heading { level, inlines } => {
+                level.hash(state);
+                hash_sequence(inlines, state);
+            }
+            BlockKind::Blockquote { children } => hash_sequence(children, state),
+            BlockKind::Callout { kind, title, children } => {
+                kind.hash(state);
+                title.hash(state);
+                hash_sequence(children, state);
+            }
+            BlockKind::BulletList { items } => hash_sequence(items, state),
+            BlockKind::OrderedList { start, items } => {
+                start.hash(state);
+                hash_sequence(items, state);
+            }
+            BlockKind::FencedCode { language, code, .. } => {
+                language.hash(state);
+                code.hash(state);
+            }
+            BlockKind::IndentedCode { code, .. } => code.hash(state),
+            BlockKind::MathBlock { tex, .. } => tex.hash(state),
+            BlockKind::ThematicBreak => {}
+            BlockKind::HtmlBlock { html } => html.hash(state),
+            BlockKind::Table { alignments, header, rows } => {
+                for alignment in alignments {
+                    discriminant::<TableAlignment>(alignment).hash(state);
+                }
+                header.hash_content(state);
+                hash_sequence(rows, state);
+            }
+            BlockKind::FootnoteDefinition { label, children, .. } => {
+                label.hash(state);
+                hash_sequence(children, state);
+            }
+            BlockKind::LinkDefinition { label, href, title } => {
+                label.hash(state);
+                href.hash(state);
+                title.hash(state);
+            }
+            BlockKind::Custom { name, inlines } => {
+                name.hash(state);
+                hash_sequence(inlines, state);
+            }
+        }
+    }
+
+    fn same_kind(&self, other: &Self) -> bool {
+        discriminant(&self.kind) == discriminant(&other.kind)
+    }
+
+    fn reconcile_children(&mut self, previous: &Self, id_gen: &mut NodeIdGenerator) {
+        match (&mut self.kind, &previous.kind) {
+            (BlockKind::Paragraph { inlines }, BlockKind::Paragraph { inlines: old })
+            | (BlockKind::Heading { inlines, .. }, BlockKind::Heading { inlines: old, .. })
+            | (BlockKind::Custom { inlines, .. }, BlockKind::Custom { inlines: old, .. }) => {
+                reconcile_sequence(old, inlines, id_gen);
+            }
+            (BlockKind::Blockquote { children }, BlockKind::Blockquote { children: old })
+            | (BlockKind::Callout { children, .. }, BlockKind::Callout { children: old, .. })
+            | (
+                BlockKind::FootnoteDefinition { children, .. },
+                BlockKind::FootnoteDefinition { children: old, .. },
+            ) => {
+                reconcile_sequence(old, children, id_gen);
+            }
+            (BlockKind::BulletList { items }, BlockKind::BulletList { items: old })
+            | (BlockKind::OrderedList { items, .. }, BlockKind::OrderedList { items: old, .. }) => {
+                reconcile_sequence(old, items, id_gen)
+            }
+            (
+                BlockKind::Table { header, rows, .. },
+                BlockKind::Table { header: old_header, rows: old_rows, .. },
+            ) => {
+                reconcile_pair(header, old_header, id_gen);
+                reconcile_sequence(old_rows, rows, id_gen);
+            }
+            _ => self.fresh_children(id_gen),
+        }
+    }
+
+    fn fresh_children(&mut self, id_gen: &mut NodeIdGenerator) {
+        match &mut self.kind {
+            BlockKind::Paragraph { inlines }
+            | BlockKind::Heading { inlines, .. }
+            | BlockKind::Custom { inlines, .. } => {
+                inlines.iter_mut().for_each(|inline| assign_fresh(inline, id_gen));
+            }
+            BlockKind::Blockquote { children }
+            | BlockKind::Callout { children, .. }
+            | BlockKind::FootnoteDefinition { children, .. } => {
+                children.iter_mut().for_each(|child| assign_fresh(child, id_gen));
+            }
+            BlockKind::BulletList { items } | BlockKind::OrderedList { items, .. } => {
+                items.iter_mut().for_each(|item| assign_fresh(item, id_gen));
+            }
+            BlockKind::FrontMatter { .. }
+            | BlockKind::FencedCode { .. }
+            | BlockKind::IndentedCode { .. }
+            | BlockKind::MathBlock { .. }
+            | BlockKind::ThematicBreak
+            | BlockKind::HtmlBlock { .. }
+            | BlockKind::LinkDefinition { .. } => {}
+            BlockKind::Table { header, rows, .. } => {
+                assign_fresh(header, id_gen);
+                rows.iter_mut().for_each(|row| assign_fresh(row, id_gen));
+            }
+        }
+    }
+}
+
+impl IdNode for ListItemNode {
+    fn node_id(&self) -> NodeId {
+        self.node_id
+    }
+
+    fn set_node_id(&mut self, node_id: NodeId) {
+        self.node_id = node_id;
+    }
+
+    fn hash_content(&self, state: &mut DefaultHasher) {
+        self.checked.hash(state);
+        hash_sequence(&self.children, state);
+    }
+
+    fn same_kind(&self, _other: &Self) -> bool {
+        true
+    }
+
+    fn reconcile_children(&mut self, previous: &Self, id_gen: &mut NodeIdGenerator) {
+        reconcile_sequence(&previous.children, &mut self.children, id_gen);
+    }
+
+    fn fresh_children(&mut self, id_gen: &mut NodeIdGenerator) {
+        self.children.iter_mut().for_each(|child| assign_fresh(child, id_gen));
+    }
+}
+
+impl IdNode for TableRowNode {
+    fn node_id(&self) -> NodeId {
+        self.node_id
+    }
+
+    fn set_node_id(&mut self, node_id: NodeId) {
+        self.node_id = node_id;
+    }
+
+    fn hash_content(&self, state: &mut DefaultHasher) {
+        hash_sequence(&self.cells, state);
+    }
+
+    fn same_kind(&self, _other: &Self) -> bool {
+        true
+    }
+
+    fn reconcile_children(&mut self, previous: &Self, id_gen: &mut NodeIdGenerator) {
+        reconcile_sequence(&previous.cells, &mut self.cells, id_gen);
+    }
+
+    fn fresh_children(&mut self, id_gen: &mut NodeIdGenerator) {
+        self.cells.iter_mut().for_each(|cell| assign_fresh(cell, id_gen));
+    }
+}
+
+impl IdNode for TableCellNode {
+    fn node_id(&self) -> NodeId {
+        self.node_id
+    }
+
+    fn set_node_id(&mut self, node_id: NodeId) {
+        self.node_id = node_id;
+    }
+
+    fn hash_content(&self, state: &mut DefaultHasher) {
+        hash_sequence(&self.inlines, state);
+    }
+
+    fn same_kind(&self, _other: &Self) -> bool {
+        true
+    }
+
+    fn reconcile_children(&mut self, previous: &Self, id_gen: &mut NodeIdGenerator) {
+        reconcile_sequence(&previous.inlines, &mut self.inlines, id_gen);
+    }
+
+    fn fresh_children(&mut self, id_gen: &mut NodeIdGenerator) {
+        self.inlines.iter_mut().for_each(|inline| assign_fresh(inline, id_gen));
+    }
+}
+
+impl IdNode for InlineNode {
+    fn node_id(&self) -> NodeId {
+        self.node_id
+    }
+
+    fn set_node_id(&mut self, node_id: NodeId) {
+        self.node_id = node_id;
+    }
+
+    fn hash_content(&self, state: &mut DefaultHasher) {
+        discriminant(&self.kind).hash(state);
+        match &self.kind {
+            InlineKind::Text { value } | InlineKind::InlineCode { value } => value.hash(state),
+            InlineKind::Math { display, tex } => {
+                display.hash(state);
+                tex.hash(state);
+            }
+            // The definition a reference points at is linked after reconciling.
+            InlineKind::FootnoteReference { label, .. } => label.hash(state),
+            InlineKind::WikiLink { target, alias, heading } => {
+                target.hash(state);
+                alias.hash(state);
+                heading.hash(state);
+            }
+            InlineKind::HardBreak => {}
+            InlineKind::Custom { name, value } => {
+                name.hash(state);
+                value.hash(state);
+            }
+            InlineKind::Strong { children }
+            | InlineKind::Emphasis { children }
+            | InlineKind::Strikethrough { children }
+            | InlineKind::Highlight { children }
+            | InlineKind::Subscript { children }
+            | InlineKind::Superscript { children } => {
+                hash_sequence(children, state);
+            }
+            InlineKind::Link { href, title, children }
+            | InlineKind::Image { src: href, title, children } => {
+                href.hash(state);
+                title.hash(state);
+                hash_sequence(children, state);
+            }
+        }
+    }
+
+    fn same_kind(&self, other: &Self) -> bool {
+        discriminant(&self.kind) == discriminant(&other.kind)
+    }
+
+    fn reconcile_children(&mut self, previous: &Self, id_gen: &mut NodeIdGenerator) {
+        match (inline_children_mut(&mut self.kind), inline_children(&previous.kind)) {
+            (Some(children), Some(old)) => reconcile_sequence(old, children, id_gen),
+            _ => self.fresh_children(id_gen),
+        }
+    }
+
+    fn fresh_children(&mut self, id_gen: &mut NodeIdGenerator) {
+        if let Some(children) = inline_children_mut(&mut self.kind) {
+            children.iter_mut().for_each(|child| assign_fresh(child, id_gen));
+        }
+    }
+}
+
+fn inline_children(kind: &InlineKind) -> Option<&[InlineNode]> {
+    match kind {
+        InlineKind::Strong { children }
+        | InlineKind::Emphasis { children }
+        | InlineKind::Strikethrough { children }
+        | InlineKind::Highlight { children }
+        | InlineKind::Subscript { children }
+        | InlineKind::Superscript { children }
+        | InlineKind::Link { children, .. }
+        | InlineKind::Image { children, .. } => Some(children),
+        InlineKind::Text { .. }
+        | InlineKind::InlineCode { .. }
+        | InlineKind::Math { .. }
+        | InlineKind::FootnoteReference { .. }
+        | InlineKind::WikiLink { .. }
+        | InlineKind::HardBreak
+        | InlineKind::Custom { .. } => None,
+    }
+}
+
+fn inline_children_mut(kind: &mut InlineKind) -> Option<&mut Vec<InlineNode>> {
+    match kind {
+        InlineKind::Strong { children }
+        | InlineKind::Emphasis { children }
+        | InlineKind::Strikethrough { children }
+        | InlineKind::Highlight { children }
+        | InlineKind::Subscript { children }
+        | InlineKind::Superscript { children }
+        | InlineKind::Link { children, .. }
+        | InlineKind::Image { children, .. } => Some(children),
+        InlineKind::Text { .. }
+        | InlineKind::InlineCode { .. }
+        | InlineKind::Math { .. }
+        | InlineKind::FootnoteReference { .. }
+        | InlineKind::WikiLink { .. }
+        | InlineKind::HardBreak
+        | InlineKind::Custom { .. } => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{parser::parse_document, registry::EnginePluginRegistry};
+
+    fn parse_with(
+        previous: &[BlockNode],
+        markdown: &str,
+        id_gen: &mut NodeIdGenerator,
+    ) -> Vec<BlockNode> {
+        let mut blocks = parse_document(markdown, &EnginePluginRegistry::core_markdown());
+        reconcile_node_ids(previous, &mut blocks, id_gen);
+        blocks
+    }
+
+    fn ids(blocks: &[BlockNode]) -> Vec<NodeId> {
+        blocks.iter().map(|block| block.node_id).collect()
+    }
+
+    #[test]
+    fn keeps_ids_of_unchanged_and_edited_blocks() {
+        let mut id_gen = NodeIdGenerator::default();
+        let before = parse_with(&[], "# Title\n\nfirst **bold**\n\n- a\n- b", &mut id_gen);
+        let after = parse_with(&before, "# Title\n\nfirst **bold** more\n\n- a\n- b", &mut id_gen);
+
+        assert_eq!(ids(&after), ids(&before));
+        let (BlockKind::Paragraph { inlines: old }, BlockKind::Paragraph { inlines: new }) =
+            (&before[2].kind, &after[2].kind)
+        else {
+            panic!("expected paragraphs");
+        };
+        assert_eq!(new[1].node_id, old[1].node_id);
+        assert_eq!(new.len(), 3);
+        assert!(new[2].node_id > old.iter().map(|inline| inline.node_id).max().unwrap());
+    }
+
+    #[test]
+    fn follows_moved_blocks_and_gives_new_blocks_fresh_ids() {
+        let mut id_gen = NodeIdGenerator::default();
+        let before = parse_with(&[], "alpha\n\nbeta\n\ngamma", &mut id_gen);
+        let after = parse_with(&before, "gamma\n\n# new\n\nalpha\n\nbeta", &mut id_gen);
+
+        assert_eq!(after[0].node_id, before[4].node_id);
+        assert_eq!(after[4].node_id, before[0].node_id);
+        assert_eq!(after[6].node_id, before[2].node_id);
+        assert!(after[2].node_id > before.iter().map(|block| block.node_id).max().unwrap());
+    }
+}
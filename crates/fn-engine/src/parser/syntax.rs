@@ -0,0 +1,829 @@
+//! The block and inline syntax [`EnginePluginRegistry::core_markdown`] registers.
+//!
+//! [`EnginePluginRegistry::core_markdown`]: crate::registry::EnginePluginRegistry::core_markdown
+
+use crate::{
+    ast::{
+        BlockKind, BlockNode, InlineKind, InlineNode, ListItemNode, TableCellNode, TableRowNode,
+    },
+    registry::{
+        BlockSyntaxPlugin, DelimiterRun, DelimiterSyntaxPlugin, Flanking, InlineSyntaxPlugin,
+    },
+    selection::TextRange,
+};
+
+use super::{
+    indent_columns, is_closing_fence, is_thematic_break, leading_spaces, parse_blockquote,
+    parse_fence_open, parse_heading, parse_list_marker, parse_table_start, parse_task_marker,
+    split_table_cells, strip_columns, strip_indent, trim_content, Fence, Line, ListMarker,
+    ParseContext,
+};
+
+/// Fenced code blocks, which run to the closing fence or the end of the document.
+pub struct FencedCodeSyntax;
+
+impl BlockSyntaxPlugin for FencedCodeSyntax {
+    fn name(&self) -> &str {
+        "fenced-code"
+    }
+
+    fn parse(
+        &self,
+        cx: &mut ParseContext<'_>,
+        lines: &[Line<'_>],
+        index: usize,
+    ) -> Option<(BlockNode, usize)> {
+        let line = lines[index];
+        let (fence, language) = parse_fence_open(line.text)?;
+        let source_end = lines.last().map_or(0, |line| line.end_with_newline);
+        let mut closing_index = index + 1;
+        while closing_index < lines.len() && !is_closing_fence(lines[closing_index].text, fence) {
+            closing_index += 1;
+        }
+
+        let content_start = line.end_with_newline.min(source_end);
+        let content_end = if closing_index < lines.len() {
+            lines[closing_index - 1].end_with_newline.max(content_start)
+        } else {
+            source_end
+        };
+        let code = code_text(&lines[index + 1..closing_index], fence.indent);
+        let range_end = if closing_index < lines.len() {
+            lines[closing_index].end_with_newline
+        } else {
+            line.end_with_newline.max(content_end)
+        };
+
+        let block = BlockNode {
+            node_id: cx.next_id(),
+            range: TextRange::new(line.start as u32, range_end as u32),
+            kind: BlockKind::FencedCode {
+                language,
+                text_range: TextRange::new(content_start as u32, content_end as u32),
+                code,
+            },
+        };
+        let next_index = if closing_index < lines.len() { closing_index + 1 } else { lines.len() };
+        Some((block, next_index))
+    }
+
+    fn interrupts_paragraph(&self, lines: &[Line<'_>], index: usize) -> bool {
+        parse_fence_open(lines[index].text).is_some()
+    }
+}
+
+/// Indented code blocks: lines indented by four or more columns, and the blank lines
+/// between them.
+pub struct IndentedCodeSyntax;
+
+impl BlockSyntaxPlugin for IndentedCodeSyntax {
+    fn name(&self) -> &str {
+        "indented-code"
+    }
+
+    fn parse(
+        &self,
+        cx: &mut ParseContext<'_>,
+        lines: &[Line<'_>],
+        index: usize,
+    ) -> Option<(BlockNode, usize)> {
+        let is_code =
+            |line: &Line<'_>| indent_columns(line.text) >= 4 && !line.text.trim().is_empty();
+        if !is_code(&lines[index]) {
+            return None;
+        }
+
+        let mut last = index;
+        for (offset, line) in lines[index + 1..].iter().enumerate() {
+            if is_code(line) {
+                last = index + 1 + offset;
+            } else if !line.text.trim().is_empty() {
+                break;
+            }
+        }
+
+        let start = lines[index].start;
+        let end = lines[last].end_with_newline;
+        let block = BlockNode {
+            node_id: cx.next_id(),
+            range: TextRange::new(start as u32, end as u32),
+            kind: BlockKind::IndentedCode {
+                text_range: TextRange::new(start as u32, end as u32),
+                code: code_text(&lines[index..=last], 4),
+            },
+        };
+        Some((block, last + 1))
+    }
+}
+
+/// The text of code block lines with `indent` columns of indentation removed.
+fn code_text(lines: &[Line<'_>], indent: usize) -> String {
+    let mut code = String::new();
+    for line in lines {
+        code.push_str(&strip_columns(line.text, indent));
+        if line.end_with_newline > line.end {
+            code.push('\n');
+        }
+    }
+    code
+}
+
+/// ATX headings.
+pub struct HeadingSyntax;
+
+impl BlockSyntaxPlugin for HeadingSyntax {
+    fn name(&self) -> &str {
+        "heading"
+    }
+
+    fn parse(
+        &self,
+        cx: &mut ParseContext<'_>,
+        lines: &[Line<'_>],
+        index: usize,
+    ) -> Option<(BlockNode, usize)> {
+        let line = lines[index];
+        let (level, start, end) = parse_heading(line.text)?;
+        let (content_start, content_end) =
+            trim_content(cx.markdown(), line.start + start, line.start + end);
+        let block = BlockNode {
+            node_id: cx.next_id(),
+            range: TextRange::new(line.start as u32, line.end as u32),
+            kind: BlockKind::Heading {
+                level,
+                inlines: cx.parse_inlines(content_start, content_end),
+            },
+        };
+        Some((block, index + 1))
+    }
+
+    fn interrupts_paragraph(&self, lines: &[Line<'_>], index: usize) -> bool {
+        parse_heading(lines[index].text).is_some()
+    }
+}
+
+/// Thematic breaks (`***`, `---`, `___`).
+pub struct ThematicBreakSyntax;
+
+impl BlockSyntaxPlugin for ThematicBreakSyntax {
+    fn name(&self) -> &str {
+        "thematic-break"
+    }
+
+    fn parse(
+        &self,
+        cx: &mut ParseContext<'_>,
+        lines: &[Line<'_>],
+        index: usize,
+    ) -> Option<(BlockNode, usize)> {
+        let line = lines[index];
+        if !is_thematic_break(line.text) {
+            return None;
+        }
+        let block = BlockNode {
+            node_id: cx.next_id(),
+            range: TextRange::new(line.start as u32, line.end as u32),
+            kind: BlockKind::ThematicBreak,
+        };
+        Some((block, index + 1))
+    }
+
+    fn interrupts_paragraph(&self, lines: &[Line<'_>], index: usize) -> bool {
+        is_thematic_break(lines[index].text)
+    }
+}
+
+/// Blockquotes, whose content is parsed as blocks. A line without `>` still belongs
+/// to the quote when it lazily continues a paragraph.
+pub struct BlockquoteSyntax;
+
+impl BlockSyntaxPlugin for BlockquoteSyntax {
+    fn name(&self) -> &str {
+        "blockquote"
+    }
+
+    fn parse(
+        &self,
+        cx: &mut ParseContext<'_>,
+        lines: &[Line<'_>],
+        index: usize,
+    ) -> Option<(BlockNode, usize)> {
+        parse_blockquote(lines[index].text)?;
+        let quote_start = lines[index].start;
+        let (quote_lines, next_index) = quote_lines(cx, lines, index);
+        let quote_end = quote_lines.last().map_or(quote_start, |line| line.end_with_newline);
+        let block = BlockNode {
+            node_id: cx.next_id(),
+            range: TextRange::new(quote_start as u32, quote_end as u32),
+            kind: BlockKind::Blockquote { children: cx.parse_blocks(&quote_lines) },
+        };
+        Some((block, next_index))
+    }
+
+    fn interrupts_paragraph(&self, lines: &[Line<'_>], index: usize) -> bool {
+        parse_blockquote(lines[index].text).is_some()
+    }
+}
+
+/// The lines of the blockquote starting at `index` with their markers stripped, and
+/// the index of the line after it. Paragraph text may continue lazily without a marker.
+pub(super) fn quote_lines<'a>(
+    cx: &ParseContext<'_>,
+    lines: &[Line<'a>],
+    mut index: usize,
+) -> (Vec<Line<'a>>, usize) {
+    let mut quote_lines: Vec<Line<'a>> = Vec::new();
+    let mut open_fence = None;
+
+    while index < lines.len() {
+        let line = lines[index];
+        let stripped = match parse_blockquote(line.text) {
+            Some(len) => Line { start: line.start + len, text: &line.text[len..], ..line },
+            None if open_fence.is_none()
+                && allows_lazy_continuation(quote_lines.last())
+                && !cx.starts_block(line) =>
+            {
+                line
+            }
+            None => break,
+        };
+        open_fence = next_open_fence(open_fence, stripped.text);
+        quote_lines.push(stripped);
+        index += 1;
+    }
+    (quote_lines, index)
+}
+
+/// Bullet, ordered and task lists with nested block content.
+pub struct ListSyntax;
+
+impl BlockSyntaxPlugin for ListSyntax {
+    fn name(&self) -> &str {
+        "list"
+    }
+
+    fn parse(
+        &self,
+        cx: &mut ParseContext<'_>,
+        lines: &[Line<'_>],
+        index: usize,
+    ) -> Option<(BlockNode, usize)> {
+        let marker = parse_list_marker(lines[index].text)?;
+        Some(parse_list(cx, lines, index, marker))
+    }
+
+    fn interrupts_paragraph(&self, lines: &[Line<'_>], index: usize) -> bool {
+        parse_list_marker(lines[index].text).is_some()
+    }
+}
+
+fn parse_list(
+    cx: &mut ParseContext<'_>,
+    lines: &[Line<'_>],
+    mut index: usize,
+    first: ListMarker,
+) -> (BlockNode, usize) {
+    let list_start = lines[index].start;
+    let mut list_end = lines[index].end;
+    let mut items = Vec::new();
+
+    while index < lines.len() {
+        let Some(marker) =
+            parse_list_marker(lines[index].text).filter(|_| !is_thematic_break(lines[index].text))
+        else {
+            break;
+        };
+        if marker.number.is_some() != first.number.is_some() {
+            break;
+        }
+
+        let (item, next_index) = parse_list_item(cx, lines, index, marker);
+        items.push(item);
+        list_end = lines[next_index - 1].end_with_newline;
+        index = next_index;
+    }
+
+    let kind = match first.number {
+        Some(start) => BlockKind::OrderedList { start, items },
+        None => BlockKind::BulletList { items },
+    };
+    let list = BlockNode {
+        node_id: cx.next_id(),
+        range: TextRange::new(list_start as u32, list_end as u32),
+        kind,
+    };
+    (list, index)
+}
+
+/// One list item, whose lines are stripped to the item's content column and parsed
+/// as nested block content.
+fn parse_list_item(
+    cx: &mut ParseContext<'_>,
+    lines: &[Line<'_>],
+    index: usize,
+    marker: ListMarker,
+) -> (ListItemNode, usize) {
+    let first = lines[index];
+    let marker_end = marker.content_indent.min(first.text.len());
+    let (checked, task_len) = task_state(&first.text[marker_end..]);
+    let content_offset = marker_end + task_len;
+    let content =
+        Line { start: first.start + content_offset, text: &first.text[content_offset..], ..first };
+
+    let (item_lines, next_index) =
+        container_lines(cx, lines, index, content, marker.content_indent);
+    let last = item_lines.last().copied().unwrap_or(content);
+    let children = cx.parse_blocks(&item_lines);
+    let item = ListItemNode {
+        node_id: cx.next_id(),
+        range: TextRange::new(first.start as u32, last.end.max(first.end) as u32),
+        checked,
+        children,
+    };
+    (item, next_index)
+}
+
+/// Collects the lines of a container that starts with `content` on `lines[index]`
+/// and continues on lines indented by `content_indent` spaces: indented content,
+/// blank lines followed by indented content, and lazy paragraph continuation lines.
+/// Returns them stripped to the content column, with the index of the first line
+/// after the container.
+pub(super) fn container_lines<'a>(
+    cx: &ParseContext<'_>,
+    lines: &[Line<'a>],
+    index: usize,
+    content: Line<'a>,
+    content_indent: usize,
+) -> (Vec<Line<'a>>, usize) {
+    let mut open_fence = parse_fence_open(content.text).map(|(fence, _)| fence);
+    let mut container = vec![content];
+    let mut next_index = index + 1;
+
+    while next_index < lines.len() {
+        let line = lines[next_index];
+
+        if line.text.trim().is_empty() {
+            let resume = lines[next_index..]
+                .iter()
+                .position(|candidate| !candidate.text.trim().is_empty())
+                .map(|offset| next_index + offset);
+            match resume {
+                Some(resume) if leading_spaces(lines[resume].text) >= content_indent => {
+                    container.extend(
+                        lines[next_index..resume]
+                            .iter()
+                            .map(|blank| strip_indent(*blank, content_indent)),
+                    );
+                    next_index = resume;
+                    continue;
+                }
+                _ => break,
+            }
+        }
+
+        let indent = leading_spaces(line.text);
+        let stripped = if indent >= content_indent {
+            strip_indent(line, content_indent)
+        } else if open_fence.is_none()
+            && allows_lazy_continuation(container.last())
+            && !cx.starts_block(line)
+        {
+            strip_indent(line, indent)
+        } else {
+            break;
+        };
+
+        open_fence = next_open_fence(open_fence, stripped.text);
+        container.push(stripped);
+        next_index += 1;
+    }
+
+    (container, next_index)
+}
+
+/// The fence still open after the container content line `text`, given the one open
+/// before it.
+fn next_open_fence(open_fence: Option<Fence>, text: &str) -> Option<Fence> {
+    match open_fence {
+        Some(fence) if is_closing_fence(text, fence) => None,
+        Some(fence) => Some(fence),
+        None => parse_fence_open(text).map(|(fence, _)| fence),
+    }
+}
+
+/// Whether a line below `previous`, the last content line of a container, can
+/// continue the container's paragraph without the container's marker or indentation.
+fn allows_lazy_continuation(previous: Option<&Line<'_>>) -> bool {
+    previous.is_some_and(|line| {
+        !line.text.trim().is_empty()
+            && indent_columns(line.text) < 4
+            && parse_heading(line.text).is_none()
+            && parse_fence_open(line.text).is_none()
+    })
+}
+
+fn task_state(content: &str) -> (Option<bool>, usize) {
+    parse_task_marker(content).map_or((None, 0), |(checked, len)| (Some(checked), len))
+}
+
+/// GFM pipe tables, which need a delimiter row below the header.
+pub struct TableSyntax;
+
+impl BlockSyntaxPlugin for TableSyntax {
+    fn name(&self) -> &str {
+        "table"
+    }
+
+    fn parse(
+        &self,
+        cx: &mut ParseContext<'_>,
+        lines: &[Line<'_>],
+        mut index: usize,
+    ) -> Option<(BlockNode, usize)> {
+        let alignments = parse_table_start(lines, index)?;
+        let line = lines[index];
+        let table_start = line.start;
+        let mut table_end = lines[index + 1].end_with_newline;
+        let header = parse_table_row(cx, line, alignments.len());
+        let mut rows = Vec::new();
+        index += 2;
+
+        while index < lines.len() {
+            let current = lines[index];
+            if cx.starts_block(current) {
+                break;
+            }
+            rows.push(parse_table_row(cx, current, alignments.len()));
+            table_end = current.end_with_newline;
+            index += 1;
+        }
+
+        let block = BlockNode {
+            node_id: cx.next_id(),
+            range: TextRange::new(table_start as u32, table_end as u32),
+            kind: BlockKind::Table { alignments, header, rows },
+        };
+        Some((block, index))
+    }
+
+    fn interrupts_paragraph(&self, lines: &[Line<'_>], index: usize) -> bool {
+        parse_table_start(lines, index).is_some()
+    }
+}
+
+fn parse_table_row(cx: &mut ParseContext<'_>, line: Line<'_>, column_count: usize) -> TableRowNode {
+    let mut cells = split_table_cells(line.text)
+        .into_iter()
+        .take(column_count)
+        .map(|(start, end)| {
+            let content_start = line.start + start;
+            let content_end = line.start + end;
+            TableCellNode {
+                node_id: cx.next_id(),
+                range: TextRange::new(content_start as u32, content_end as u32),
+                inlines: cx.parse_table_cell(content_start, content_end),
+            }
+        })
+        .collect::<Vec<_>>();
+
+    while cells.len() < column_count {
+        cells.push(TableCellNode {
+            node_id: cx.next_id(),
+            range: TextRange::new(line.end as u32, line.end as u32),
+            inlines: Vec::new(),
+        });
+    }
+
+    TableRowNode {
+        node_id: cx.next_id(),
+        range: TextRange::new(line.start as u32, line.end as u32),
+        cells,
+    }
+}
+
+/// `*emphasis*` and `**strong**`, or the same with `_`.
+pub struct EmphasisSyntax {
+    marker: char,
+}
+
+impl EmphasisSyntax {
+    pub const ASTERISK: Self = Self { marker: '*' };
+    pub const UNDERSCORE: Self = Self { marker: '_' };
+}
+
+impl DelimiterSyntaxPlugin for EmphasisSyntax {
+    fn name(&self) -> &str {
+        "emphasis"
+    }
+
+    fn marker(&self) -> char {
+        self.marker
+    }
+
+    /// `_` does not open or close inside a word.
+    fn can_open_close(&self, flanking: Flanking) -> (bool, bool) {
+        if self.marker != '_' {
+            return (flanking.left, flanking.right);
+        }
+        (
+            flanking.left && (!flanking.right || flanking.preceded_by_punctuation),
+            flanking.right && (!flanking.left || flanking.followed_by_punctuation),
+        )
+    }
+
+    /// Strong when both runs have two characters left. A run that can both open and
+    /// close only matches when the lengths do not add up to a multiple of 3, unless
+    /// both are multiples of 3, so `*foo**bar*` stays one emphasis.
+    fn match_len(&self, opener: &DelimiterRun, closer: &DelimiterRun) -> Option<usize> {
+        let sum = opener.original_len + closer.original_len;
+        let both_multiples = opener.original_len % 3 == 0 && closer.original_len % 3 == 0;
+        if (opener.can_close || closer.can_open) && sum % 3 == 0 && !both_multiples {
+            return None;
+        }
+        Some(if opener.len >= 2 && closer.len >= 2 { 2 } else { 1 })
+    }
+
+    fn wrap(&self, len: usize, children: Vec<InlineNode>) -> InlineKind {
+        if len == 2 {
+            InlineKind::Strong { children }
+        } else {
+            InlineKind::Emphasis { children }
+        }
+    }
+}
+
+/// `~~strikethrough~~` and `~subscript~`. Runs only match runs of their own length,
+/// so a `~` never closes a `~~`.
+pub struct StrikethroughSyntax;
+
+impl DelimiterSyntaxPlugin for StrikethroughSyntax {
+    fn name(&self) -> &str {
+        "strikethrough"
+    }
+
+    fn marker(&self) -> char {
+        '~'
+    }
+
+    fn match_len(&self, opener: &DelimiterRun, closer: &DelimiterRun) -> Option<usize> {
+        (opener.original_len == closer.original_len && opener.original_len <= 2)
+            .then_some(opener.original_len)
+    }
+
+    fn wrap(&self, len: usize, children: Vec<InlineNode>) -> InlineKind {
+        if len == 2 {
+            InlineKind::Strikethrough { children }
+        } else {
+            InlineKind::Subscript { children }
+        }
+    }
+}
+
+/// `==highlight==`, between runs of exactly two `=`.
+pub struct HighlightSyntax;
+
+impl DelimiterSyntaxPlugin for HighlightSyntax {
+    fn name(&self) -> &str {
+        "highlight"
+    }
+
+    fn marker(&self) -> char {
+        '='
+    }
+
+    fn match_len(&self, opener: &DelimiterRun, closer: &DelimiterRun) -> Option<usize> {
+        (opener.original_len == 2 && closer.original_len == 2).then_some(2)
+    }
+
+    fn wrap(&self, _len: usize, children: Vec<InlineNode>) -> InlineKind {
+        InlineKind::Highlight { children }
+    }
+}
+
+/// `^superscript^`, between single `^`.
+pub struct SuperscriptSyntax;
+
+impl DelimiterSyntaxPlugin for SuperscriptSyntax {
+    fn name(&self) -> &str {
+        "superscript"
+    }
+
+    fn marker(&self) -> char {
+        '^'
+    }
+
+    fn match_len(&self, opener: &DelimiterRun, closer: &DelimiterRun) -> Option<usize> {
+        (opener.original_len == 1 && closer.original_len == 1).then_some(1)
+    }
+
+    fn wrap(&self, _len: usize, children: Vec<InlineNode>) -> InlineKind {
+        InlineKind::Superscript { children }
+    }
+}
+
+/// `` `code` ``, opened and closed by backtick runs of the same length. A run
+/// without a closer stays literal as a whole.
+pub struct InlineCodeSyntax;
+
+impl InlineSyntaxPlugin for InlineCodeSyntax {
+    fn name(&self) -> &str {
+        "inline-code"
+    }
+
+    fn parse(
+        &self,
+        cx: &mut ParseContext<'_>,
+        start: usize,
+        limit: usize,
+    ) -> Option<(InlineNode, usize)> {
+        let markdown = cx.markdown();
+        let run = backtick_run(markdown, start, limit);
+        if run == 0 {
+            return None;
+        }
+
+        let inner_start = start + run;
+        let mut cursor = inner_start;
+        let inner_end = loop {
+            let Some(offset) = markdown[cursor..limit].find('`') else {
+                let literal = InlineKind::Text { value: markdown[start..inner_start].to_string() };
+                let range = TextRange::new(start as u32, inner_start as u32);
+                return Some((
+                    InlineNode { node_id: cx.next_id(), range, kind: literal },
+                    inner_start,
+                ));
+            };
+            cursor += offset;
+            let close = backtick_run(markdown, cursor, limit);
+            if close == run {
+                break cursor;
+            }
+            cursor += close;
+        };
+
+        let node = InlineNode {
+            node_id: cx.next_id(),
+            range: TextRange::new(inner_start as u32, inner_end as u32),
+            kind: InlineKind::InlineCode {
+                value: code_span_value(&cx.content_text(inner_start, inner_end)),
+            },
+        };
+        Some((node, inner_end + run))
+    }
+}
+
+fn backtick_run(markdown: &str, start: usize, limit: usize) -> usize {
+    markdown.as_bytes()[start..limit].iter().take_while(|byte| **byte == b'`').count()
+}
+
+/// Line endings become spaces, and one space is stripped from each side when both
+/// sides have one and the content is not all spaces.
+fn code_span_value(content: &str) -> String {
+    let value = content.replace("\r\n", " ").replace('\n', " ");
+    let padded = value.len() >= 2 && value.starts_with(' ') && value.ends_with(' ');
+    if padded && !value.bytes().all(|byte| byte == b' ') {
+        value[1..value.len() - 1].to_string()
+    } else {
+        value
+    }
+}
+
+/// A line break kept in the output: two or more spaces or a backslash before the
+/// line ending. The node's range covers the break and the next line's indentation.
+pub struct HardBreakSyntax;
+
+impl InlineSyntaxPlugin for HardBreakSyntax {
+    fn name(&self) -> &str {
+        "hard-break"
+    }
+
+    fn parse(
+        &self,
+        cx: &mut ParseContext<'_>,
+        start: usize,
+        limit: usize,
+    ) -> Option<(InlineNode, usize)> {
+        let bytes = &cx.markdown().as_bytes()[start..limit];
+        let spaces = bytes.iter().take_while(|byte| **byte == b' ').count();
+        let newline = match bytes.first()? {
+            b'\\' => 1,
+            b' ' if spaces >= 2 => spaces,
+            _ => return None,
+        };
+        if bytes.get(newline) != Some(&b'\n') {
+            return None;
+        }
+
+        let indent =
+            bytes[newline + 1..].iter().take_while(|byte| matches!(byte, b' ' | b'\t')).count();
+        let end = start + newline + 1 + indent;
+        let node = InlineNode {
+            node_id: cx.next_id(),
+            range: TextRange::new(start as u32, end as u32),
+            kind: InlineKind::HardBreak,
+        };
+        Some((node, end))
+    }
+}
+
+/// A backslash before ASCII punctuation, which makes the character literal.
+pub struct EscapeSyntax;
+
+impl InlineSyntaxPlugin for EscapeSyntax {
+    fn name(&self) -> &str {
+        "escape"
+    }
+
+    fn parse(
+        &self,
+        cx: &mut ParseContext<'_>,
+        start: usize,
+        limit: usize,
+    ) -> Option<(InlineNode, usize)> {
+        let escaped = cx.markdown()[start..limit].strip_prefix('\\')?.chars().next()?;
+        if !escaped.is_ascii_punctuation() {
+            return None;
+        }
+        let node = InlineNode {
+            node_id: cx.next_id(),
+            range: TextRange::new(start as u32, (start + 2) as u32),
+            kind: InlineKind::Text { value: escaped.to_string() },
+        };
+        Some((node, start + 2))
+    }
+}
+
+/// `&amp;`, `&#35;` and `&#x23;`, decoded to the character they name.
+pub struct EntitySyntax;
+
+impl InlineSyntaxPlugin for EntitySyntax {
+    fn name(&self) -> &str {
+        "entity"
+    }
+
+    fn parse(
+        &self,
+        cx: &mut ParseContext<'_>,
+        start: usize,
+        limit: usize,
+    ) -> Option<(InlineNode, usize)> {
+        let (value, len) = decode_entity(&cx.markdown()[start..limit])?;
+        let node = InlineNode {
+            node_id: cx.next_id(),
+            range: TextRange::new(start as u32, (start + len) as u32),
+            kind: InlineKind::Text { value },
+        };
+        Some((node, start + len))
+    }
+}
+
+/// Decodes the entity or numeric character reference `text` starts with, returning
+/// the characters and the reference's length.
+pub(super) fn decode_entity(text: &str) -> Option<(String, usize)> {
+    let body = text.strip_prefix('&')?;
+    let semicolon = body.find(';')?;
+    let name = &body[..semicolon];
+    let len = semicolon + 2;
+
+    if let Some(number) = name.strip_prefix('#') {
+        let (digits, radix, max) = match number.strip_prefix(['x', 'X']) {
+            Some(hex) => (hex, 16, 6),
+            None => (number, 10, 7),
+        };
+        if digits.is_empty() || digits.len() > max || !digits.chars().all(|ch| ch.is_digit(radix)) {
+            return None;
+        }
+        let code = u32::from_str_radix(digits, radix).ok()?;
+        let ch = char::from_u32(code).filter(|ch| *ch != '\0').unwrap_or('\u{FFFD}');
+        return Some((ch.to_string(), len));
+    }
+
+    if name.is_empty() || !name.chars().all(|ch| ch.is_ascii_alphanumeric()) {
+        return None;
+    }
+    let entity = &text[..len];
+    entities::ENTITIES
+        .iter()
+        .find(|candidate| candidate.entity == entity)
+        .map(|candidate| (candidate.characters.to_string(), len))
+}
+
+/// Resolves backslash escapes and entity references.
+pub(super) fn unescape(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    let mut cursor = 0;
+    while let Some(ch) = text[cursor..].chars().next() {
+        let escaped = (ch == '\\').then(|| text[cursor + 1..].chars().next()).flatten();
+        if let Some(escaped) = escaped.filter(char::is_ascii_punctuation) {
+            out.push(escaped);
+            cursor += 2;
+        } else if let Some((value, len)) = decode_entity(&text[cursor..]) {
+            out.push_str(&value);
+            cursor += len;
+        } else {
+            out.push(ch);
+            cursor += ch.len_utf8();
+        }
+    }
+    out
+}
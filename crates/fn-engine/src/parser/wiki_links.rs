@@ -0,0 +1,117 @@
+//! Wiki-links: `[[Page]]`, `[[Page#Heading]]` and `[[Page|alias]]`, which name
+//! another note of the workspace instead of a URL.
+//!
+//! The engine does not know the workspace, so resolving targets to files is left to
+//! the host: it reads [`wiki_link_targets`] and reports back which ones resolved.
+
+use crate::{
+    ast::{BlockKind, BlockNode, InlineKind, InlineNode},
+    registry::InlineSyntaxPlugin,
+    selection::TextRange,
+};
+
+use super::ParseContext;
+
+/// `[[target#heading|alias]]` on one line, where the heading and alias are optional
+/// and the target may be left out for a heading of the same note. The node's range
+/// covers the brackets.
+pub struct WikiLinkSyntax;
+
+impl InlineSyntaxPlugin for WikiLinkSyntax {
+    fn name(&self) -> &str {
+        "wiki-link"
+    }
+
+    fn parse(
+        &self,
+        cx: &mut ParseContext<'_>,
+        start: usize,
+        limit: usize,
+    ) -> Option<(InlineNode, usize)> {
+        let markdown = cx.markdown();
+        let inner_start = start + 2;
+        if !markdown[start..limit].starts_with("[[") {
+            return None;
+        }
+        let inner_end = inner_start + markdown[inner_start..limit].find("]]")?;
+        let inner = &markdown[inner_start..inner_end];
+        if inner.contains(['[', ']', '\n']) {
+            return None;
+        }
+
+        let (link, alias) = match inner.split_once('|') {
+            Some((link, alias)) => (link, non_empty(alias)),
+            None => (inner, None),
+        };
+        let (target, heading) = match link.split_once('#') {
+            Some((target, heading)) => (target.trim(), non_empty(heading)),
+            None => (link.trim(), None),
+        };
+        if target.is_empty() && heading.is_none() {
+            return None;
+        }
+
+        let node = InlineNode {
+            node_id: cx.next_id(),
+            range: TextRange::new(start as u32, (inner_end + 2) as u32),
+            kind: InlineKind::WikiLink { target: target.to_string(), alias, heading },
+        };
+        Some((node, inner_end + 2))
+    }
+}
+
+fn non_empty(text: &str) -> Option<String> {
+    let text = text.trim();
+    (!text.is_empty()).then(|| text.to_string())
+}
+
+/// The distinct targets of the wiki-links in `blocks`, in document order. Links to
+/// a heading of the same note have no target and are left out.
+pub fn wiki_link_targets(blocks: &[BlockNode]) -> Vec<String> {
+    fn walk_blocks(blocks: &[BlockNode], targets: &mut Vec<String>) {
+        for block in blocks {
+            match &block.kind {
+                BlockKind::Paragraph { inlines }
+                | BlockKind::Heading { inlines, .. }
+                | BlockKind::Custom { inlines, .. } => walk_inlines(inlines, targets),
+                BlockKind::Blockquote { children }
+                | BlockKind::Callout { children, .. }
+                | BlockKind::FootnoteDefinition { children, .. } => walk_blocks(children, targets),
+                BlockKind::BulletList { items } | BlockKind::OrderedList { items, .. } => {
+                    items.iter().for_each(|item| walk_blocks(&item.children, targets));
+                }
+                BlockKind::Table { header, rows, .. } => {
+                    for cell in std::iter::once(header).chain(rows).flat_map(|row| &row.cells) {
+                        walk_inlines(&cell.inlines, targets);
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+
+    fn walk_inlines(inlines: &[InlineNode], targets: &mut Vec<String>) {
+        for inline in inlines {
+            match &inline.kind {
+                InlineKind::WikiLink { target, .. }
+                    if !target.is_empty() && !targets.contains(target) =>
+                {
+                    targets.push(target.clone());
+                }
+                InlineKind::Strong { children }
+                | InlineKind::Emphasis { children }
+                | InlineKind::Strikethrough { children }
+                | InlineKind::Highlight { children }
+                | InlineKind::Subscript { children }
+                | InlineKind::Superscript { children }
+                | InlineKind::Link { children, .. }
+                | InlineKind::Image { children, .. } => walk_inlines(children, targets),
+                _ => {}
+            }
+        }
+    }
+
+    let mut targets = Vec::new();
+    walk_blocks(blocks, &mut targets);
+    targets
+}
@@ -1,42 +1,235 @@
-use serde::{Deserialize, Serialize};
+use std::{fmt, sync::Arc};
 
-#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq, Eq)]
-pub struct SyntaxPluginDescriptor {
-    pub name: String,
+use crate::{
+    ast::{BlockNode, InlineKind, InlineNode},
+    input_rules::{FenceAutoClose, ListContinuation},
+    parser::{
+        callouts::CalloutSyntax,
+        footnotes::{FootnoteDefinitionSyntax, FootnoteReferenceSyntax},
+        front_matter::FrontMatterSyntax,
+        html::HtmlBlockSyntax,
+        links::{AutolinkSyntax, ImageSyntax, LinkDefinitionSyntax, LinkSyntax},
+        math::{MathBlockSyntax, MathSyntax},
+        syntax::{
+            BlockquoteSyntax, EmphasisSyntax, EntitySyntax, EscapeSyntax, FencedCodeSyntax,
+            HardBreakSyntax, HeadingSyntax, HighlightSyntax, IndentedCodeSyntax, InlineCodeSyntax,
+            ListSyntax, StrikethroughSyntax, SuperscriptSyntax, TableSyntax, ThematicBreakSyntax,
+        },
+        wiki_links::WikiLinkSyntax,
+        Line, ParseContext,
+    },
+    render_snapshot::{CodeBlockBadge, HeadingScale, RenderBlock, RenderInline},
+    rope::RopeText,
+    selection::{Selection, TextRange},
+    transaction::TextEdit,
+};
+
+/// Recognizes one kind of block. At the start of every block the registered block
+/// plugins are tried in order and the first match wins; lines no plugin claims
+/// become paragraphs.
+pub trait BlockSyntaxPlugin: Send + Sync {
+    fn name(&self) -> &str;
+
+    /// Parses the block starting at `lines[index]` and returns it with the index of
+    /// the first line after it. The result may only depend on `lines[index..]`, which
+    /// incremental reparsing relies on.
+    fn parse(
+        &self,
+        cx: &mut ParseContext<'_>,
+        lines: &[Line<'_>],
+        index: usize,
+    ) -> Option<(BlockNode, usize)>;
+
+    /// Whether the block starting at `lines[index]` ends a paragraph running into it.
+    fn interrupts_paragraph(&self, _lines: &[Line<'_>], _index: usize) -> bool {
+        false
+    }
+}
+
+/// Recognizes one kind of inline span. At every position the registered inline
+/// plugins are tried in order and the first match wins; text no plugin claims stays
+/// literal.
+pub trait InlineSyntaxPlugin: Send + Sync {
+    fn name(&self) -> &str;
+
+    /// Parses a span starting at byte `start` of [`ParseContext::markdown`] and ending
+    /// by `limit`, and returns it with the offset just past it.
+    fn parse(
+        &self,
+        cx: &mut ParseContext<'_>,
+        start: usize,
+        limit: usize,
+    ) -> Option<(InlineNode, usize)>;
+}
+
+/// Recognizes emphasis-like spans between runs of a marker character, such as
+/// `*emphasis*`. Runs are collected while the inline plugins parse the content and
+/// matched afterwards with the CommonMark delimiter algorithm, so spans nest and
+/// interleave the way CommonMark specifies.
+pub trait DelimiterSyntaxPlugin: Send + Sync {
+    fn name(&self) -> &str;
+
+    /// The character whose runs this plugin matches.
+    fn marker(&self) -> char;
+
+    /// Whether a run can open and whether it can close a span. The default is the
+    /// CommonMark rule for `*`: left-flanking runs open, right-flanking runs close.
+    fn can_open_close(&self, flanking: Flanking) -> (bool, bool) {
+        (flanking.left, flanking.right)
+    }
+
+    /// How many characters of each run a span between `opener` and `closer` uses, or
+    /// `None` when `closer` cannot close `opener`.
+    fn match_len(&self, opener: &DelimiterRun, closer: &DelimiterRun) -> Option<usize>;
+
+    /// The node for content enclosed by `len` markers on each side.
+    fn wrap(&self, len: usize, children: Vec<InlineNode>) -> InlineKind;
+}
+
+/// Where a delimiter run sits relative to the text around it. The start and end of
+/// the inline content count as whitespace.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Flanking {
+    /// Not followed by whitespace, and not followed by punctuation unless preceded by
+    /// whitespace or punctuation.
+    pub left: bool,
+    /// The mirror image of `left`.
+    pub right: bool,
+    pub preceded_by_punctuation: bool,
+    pub followed_by_punctuation: bool,
+}
+
+/// A delimiter run as the delimiter algorithm sees it while matching.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DelimiterRun {
+    /// Characters not used by a span yet.
+    pub len: usize,
+    /// Characters in the run as written.
+    pub original_len: usize,
+    pub can_open: bool,
+    pub can_close: bool,
+}
+
+/// Rewrites typed text before it is applied, e.g. to continue a list on Enter.
+pub trait InputRulePlugin: Send + Sync {
+    fn name(&self) -> &str;
+
+    /// The edit to make instead of replacing `range` with `insert`, and the caret
+    /// after it, or `None` to leave the input to the next rule.
+    fn apply(
+        &self,
+        text: &RopeText,
+        range: TextRange,
+        insert: &str,
+    ) -> Option<(TextEdit, Selection)>;
+}
+
+/// Adjusts the render data built for the snapshot.
+pub trait RenderPlugin: Send + Sync {
+    fn name(&self) -> &str;
+
+    /// Called for every render block built from `source`. List items pass their list
+    /// and blockquote lines their own paragraph.
+    fn render_block(&self, _source: &BlockNode, _block: &mut RenderBlock) {}
+
+    fn render_inline(&self, _source: &InlineNode, _inline: &mut RenderInline) {}
 }
 
-#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq, Eq)]
+/// The plugins a document is parsed, edited and rendered with, each list in the
+/// order it is tried.
+#[derive(Clone, Default)]
 pub struct EnginePluginRegistry {
-    pub block_syntax_plugins: Vec<SyntaxPluginDescriptor>,
-    pub inline_syntax_plugins: Vec<SyntaxPluginDescriptor>,
-    pub input_rule_plugins: Vec<SyntaxPluginDescriptor>,
-    pub render_plugins: Vec<SyntaxPluginDescriptor>,
+    pub block_syntax_plugins: Vec<Arc<dyn BlockSyntaxPlugin>>,
+    pub inline_syntax_plugins: Vec<Arc<dyn InlineSyntaxPlugin>>,
+    pub delimiter_syntax_plugins: Vec<Arc<dyn DelimiterSyntaxPlugin>>,
+    pub input_rule_plugins: Vec<Arc<dyn InputRulePlugin>>,
+    pub render_plugins: Vec<Arc<dyn RenderPlugin>>,
 }
 
 impl EnginePluginRegistry {
     pub fn core_markdown() -> Self {
-        Self {
-            block_syntax_plugins: vec![
-                SyntaxPluginDescriptor { name: "heading".to_string() },
-                SyntaxPluginDescriptor { name: "blockquote".to_string() },
-                SyntaxPluginDescriptor { name: "bullet-list".to_string() },
-                SyntaxPluginDescriptor { name: "ordered-list".to_string() },
-                SyntaxPluginDescriptor { name: "fenced-code".to_string() },
-            ],
-            inline_syntax_plugins: vec![
-                SyntaxPluginDescriptor { name: "strong".to_string() },
-                SyntaxPluginDescriptor { name: "emphasis".to_string() },
-                SyntaxPluginDescriptor { name: "inline-code".to_string() },
-                SyntaxPluginDescriptor { name: "link".to_string() },
-            ],
-            input_rule_plugins: vec![
-                SyntaxPluginDescriptor { name: "enter".to_string() },
-                SyntaxPluginDescriptor { name: "toggle-prefix".to_string() },
-            ],
-            render_plugins: vec![
-                SyntaxPluginDescriptor { name: "heading-scale".to_string() },
-                SyntaxPluginDescriptor { name: "code-block-badge".to_string() },
-            ],
-        }
+        let mut registry = Self::default();
+        registry
+            .register_block_syntax(FrontMatterSyntax)
+            .register_block_syntax(FencedCodeSyntax)
+            .register_block_syntax(MathBlockSyntax)
+            .register_block_syntax(IndentedCodeSyntax)
+            .register_block_syntax(HeadingSyntax)
+            .register_block_syntax(ThematicBreakSyntax)
+            .register_block_syntax(CalloutSyntax)
+            .register_block_syntax(BlockquoteSyntax)
+            .register_block_syntax(ListSyntax)
+            .register_block_syntax(HtmlBlockSyntax)
+            .register_block_syntax(TableSyntax)
+            .register_block_syntax(FootnoteDefinitionSyntax)
+            .register_block_syntax(LinkDefinitionSyntax)
+            .register_inline_syntax(HardBreakSyntax)
+            .register_inline_syntax(EscapeSyntax)
+            .register_inline_syntax(EntitySyntax)
+            .register_inline_syntax(InlineCodeSyntax)
+            .register_inline_syntax(MathSyntax)
+            .register_inline_syntax(FootnoteReferenceSyntax)
+            .register_inline_syntax(WikiLinkSyntax)
+            .register_inline_syntax(ImageSyntax)
+            .register_inline_syntax(LinkSyntax)
+            .register_inline_syntax(AutolinkSyntax)
+            .register_delimiter_syntax(EmphasisSyntax::ASTERISK)
+            .register_delimiter_syntax(EmphasisSyntax::UNDERSCORE)
+            .register_delimiter_syntax(StrikethroughSyntax)
+            .register_delimiter_syntax(HighlightSyntax)
+            .register_delimiter_syntax(SuperscriptSyntax)
+            .register_input_rule(FenceAutoClose)
+            .register_input_rule(ListContinuation)
+            .register_render(HeadingScale)
+            .register_render(CodeBlockBadge);
+        registry
+    }
+
+    pub fn register_block_syntax(&mut self, plugin: impl BlockSyntaxPlugin + 'static) -> &mut Self {
+        self.block_syntax_plugins.push(Arc::new(plugin));
+        self
+    }
+
+    pub fn register_inline_syntax(
+        &mut self,
+        plugin: impl InlineSyntaxPlugin + 'static,
+    ) -> &mut Self {
+        self.inline_syntax_plugins.push(Arc::new(plugin));
+        self
+    }
+
+    pub fn register_delimiter_syntax(
+        &mut self,
+        plugin: impl DelimiterSyntaxPlugin + 'static,
+    ) -> &mut Self {
+        self.delimiter_syntax_plugins.push(Arc::new(plugin));
+        self
+    }
+
+    pub fn register_input_rule(&mut self, plugin: impl InputRulePlugin + 'static) -> &mut Self {
+        self.input_rule_plugins.push(Arc::new(plugin));
+        self
+    }
+
+    pub fn register_render(&mut self, plugin: impl RenderPlugin + 'static) -> &mut Self {
+        self.render_plugins.push(Arc::new(plugin));
+        self
+    }
+}
+
+impl fmt::Debug for EnginePluginRegistry {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let block = self.block_syntax_plugins.iter().map(|plugin| plugin.name());
+        let inline = self.inline_syntax_plugins.iter().map(|plugin| plugin.name());
+        let delimiter = self.delimiter_syntax_plugins.iter().map(|plugin| plugin.name());
+        let input_rule = self.input_rule_plugins.iter().map(|plugin| plugin.name());
+        let render = self.render_plugins.iter().map(|plugin| plugin.name());
+        f.debug_struct("EnginePluginRegistry")
+            .field("block_syntax_plugins", &block.collect::<Vec<_>>())
+            .field("inline_syntax_plugins", &inline.collect::<Vec<_>>())
+            .field("delimiter_syntax_plugins", &delimiter.collect::<Vec<_>>())
+            .field("input_rule_plugins", &input_rule.collect::<Vec<_>>())
+            .field("render_plugins", &render.collect::<Vec<_>>())
+            .finish()
     }
 }
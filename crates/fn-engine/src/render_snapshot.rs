@@ -1,8 +1,18 @@
 use serde::{Deserialize, Serialize};
+use std::{
+    collections::{HashMap, HashSet},
+    ops::Range,
+    sync::Arc,
+};
 
 use crate::{
-    ast::{BlockKind, BlockNode, InlineKind, InlineNode, NodeId},
-    selection::{Selection, TextRange},
+    ast::{
+        BlockKind, BlockNode, CalloutKind, InlineKind, InlineNode, ListItemNode, NodeId,
+        TableAlignment, TableRowNode,
+    },
+    change_set::ChangeSet,
+    registry::RenderPlugin,
+    selection::{Selection, SelectionSet, TextRange},
     Doc,
 };
 
@@ -11,7 +21,98 @@ pub struct EditorSnapshot {
     pub revision: u64,
     pub markdown: String,
     pub blocks: Vec<RenderBlock>,
+    /// The primary selection.
     pub selection: Selection,
+    /// Further cursors and ranges, in document order.
+    pub secondary_selections: Vec<Selection>,
+}
+
+/// Changes between a previously published snapshot (`base_revision`) and the
+/// current one, for clients that keep the earlier snapshot around.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct SnapshotPatch {
+    pub base_revision: u64,
+    pub revision: u64,
+    /// Turns the base markdown into the current markdown.
+    pub changes: ChangeSet,
+    pub blocks: BlockDiff,
+    pub selection: Selection,
+    pub secondary_selections: Vec<Selection>,
+}
+
+impl SnapshotPatch {
+    /// Rebuilds the current snapshot from the snapshot the patch was diffed against.
+    pub fn apply_to(&self, base: &EditorSnapshot) -> EditorSnapshot {
+        let mut markdown = base.markdown.clone();
+        for edit in self.changes.edits().iter().rev() {
+            let range = edit.range.start_utf8 as usize..edit.range.end_utf8 as usize;
+            markdown.replace_range(range, &edit.insert);
+        }
+
+        let mut replaced: HashMap<NodeId, &RenderBlock> = HashMap::new();
+        for block in self.blocks.added.iter().chain(&self.blocks.changed) {
+            replaced.insert(block.node_id, block);
+        }
+        let removed: HashSet<NodeId> = self.blocks.removed.iter().copied().collect();
+        let mut kept: HashMap<NodeId, RenderBlock> = HashMap::new();
+        for block in &base.blocks {
+            if replaced.contains_key(&block.node_id) || removed.contains(&block.node_id) {
+                continue;
+            }
+            let delta = self
+                .blocks
+                .offset_shifts
+                .iter()
+                .take_while(|shift| shift.from_utf8 <= block_start(block))
+                .last()
+                .map_or(0, |shift| shift.delta_utf8);
+            let mut moved = block.clone();
+            shift_render_block(&mut moved, delta);
+            kept.insert(block.node_id, moved);
+        }
+
+        let order = match &self.blocks.order {
+            Some(order) => order.clone(),
+            None => base.blocks.iter().map(|block| block.node_id).collect(),
+        };
+        let blocks = order
+            .into_iter()
+            .filter_map(|node_id| {
+                kept.remove(&node_id)
+                    .or_else(|| replaced.get(&node_id).map(|block| (*block).clone()))
+            })
+            .collect();
+
+        EditorSnapshot {
+            revision: self.revision,
+            markdown,
+            blocks,
+            selection: self.selection,
+            secondary_selections: self.secondary_selections.clone(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq, Eq)]
+pub struct BlockDiff {
+    /// Blocks whose node ID is new, in document order.
+    pub added: Vec<RenderBlock>,
+    /// Blocks whose render data changed beyond moving by a uniform offset.
+    pub changed: Vec<RenderBlock>,
+    pub removed: Vec<NodeId>,
+    /// Every block ID in document order; only set when blocks were added, removed or
+    /// reordered.
+    pub order: Option<Vec<NodeId>>,
+    /// Offset moves for the remaining base blocks, sorted by `from_utf8`.
+    pub offset_shifts: Vec<OffsetShift>,
+}
+
+/// Base blocks starting at or after `from_utf8` (and before the next shift) moved by
+/// `delta_utf8` bytes, along with every offset they carry.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+pub struct OffsetShift {
+    pub from_utf8: u32,
+    pub delta_utf8: i64,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
@@ -26,11 +127,21 @@ pub struct RenderBlock {
 #[serde(rename_all = "snake_case")]
 pub enum RenderBlockKind {
     Paragraph,
+    FrontMatter,
     Heading,
     BlockquoteParagraph,
+    Callout,
     BulletListItem,
     OrderedListItem,
     FencedCode,
+    IndentedCode,
+    MathBlock,
+    ThematicBreak,
+    HtmlBlock,
+    Table,
+    FootnoteDefinition,
+    LinkDefinition,
+    Custom,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
@@ -49,149 +160,797 @@ pub enum RenderInlineKind {
     Text,
     Strong,
     Emphasis,
+    Strikethrough,
+    Highlight,
+    Subscript,
+    Superscript,
     InlineCode,
+    Math,
+    FootnoteReference,
+    WikiLink,
     Link,
+    Image,
+    HardBreak,
     CodeText,
+    TableRow,
+    TableCell,
+    Custom,
 }
 
+/// Render data of a block or inline; unset fields are left out when serialized.
 #[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(default)]
 pub struct RenderAttrs {
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub heading_level: Option<u8>,
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub href: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub title: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub language: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub class_name: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub start_utf8: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub end_utf8: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub list_index: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub raw_markdown: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub table_header: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub column_index: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub column_alignment: Option<TableAlignment>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub checked: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub list_depth: Option<u32>,
+    /// Depth of the innermost blockquote the block sits in, 0 for a top-level quote.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub quote_depth: Option<u32>,
+    /// Image source, resolved against the document's directory when relative.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub src: Option<String>,
+    /// Plain text of an image's alt content.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub alt: Option<String>,
+    /// Whether math is display math rather than inline.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub display_math: Option<bool>,
+    /// Label of a footnote reference or definition.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub footnote_label: Option<String>,
+    /// Number of a referenced footnote, counted in the order of first references.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub footnote_number: Option<u32>,
+    /// Target and heading of a wiki-link; an empty target is the note itself.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub wiki_target: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub wiki_heading: Option<String>,
+    /// Whether a wiki-link's target resolved to no note. Unset until the host has
+    /// resolved the document's targets.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub unresolved: Option<bool>,
+    /// Kind of a callout, whose title, if it has one, is in `title`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub callout_kind: Option<CalloutKind>,
+    /// Name of the syntax plugin behind a custom block or inline.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub syntax: Option<String>,
 }
 
-pub fn build_snapshot(doc: &Doc, selection: Selection) -> EditorSnapshot {
+pub fn build_snapshot(doc: &Doc, selections: &SelectionSet) -> EditorSnapshot {
+    let markdown = doc.text.to_string();
+    let blocks = render_blocks(doc, &markdown);
+    EditorSnapshot {
+        revision: doc.revision,
+        markdown,
+        blocks,
+        selection: selections.primary,
+        secondary_selections: selections.secondary.clone(),
+    }
+}
+
+pub(crate) fn render_blocks(doc: &Doc, markdown: &str) -> Vec<RenderBlock> {
+    let footnote_numbers = doc.footnotes().numbers();
+    let cx = RenderContext::new(doc, Source { text: markdown, start: 0 }, &footnote_numbers);
     let mut render_blocks = Vec::new();
     for block in &doc.blocks {
-        flatten_block(block, doc.text.as_str(), &mut render_blocks);
+        flatten_block(&cx, block, Nesting::default(), &mut render_blocks);
     }
+    render_blocks
+}
 
-    EditorSnapshot {
-        revision: doc.revision,
-        markdown: doc.text.to_string(),
-        blocks: render_blocks,
-        selection,
+/// The render blocks of one top-level block as last published, kept so the next patch
+/// only renders the top-level blocks that changed again.
+#[derive(Debug, Clone)]
+pub(crate) struct RenderedBlock {
+    node_id: NodeId,
+    /// Where the top-level block started in the published snapshot.
+    start: u32,
+    /// How far the published blocks sit from the offsets in `blocks`, which are not
+    /// rewritten when only the text before them changed.
+    shift: i64,
+    blocks: Vec<RenderBlock>,
+}
+
+impl RenderedBlock {
+    /// The blocks as the client last received them.
+    fn published(&self) -> impl Iterator<Item = RenderBlock> + '_ {
+        self.blocks.iter().map(|block| {
+            let mut block = block.clone();
+            shift_render_block(&mut block, self.shift);
+            block
+        })
     }
 }
 
-fn flatten_block(block: &BlockNode, markdown: &str, out: &mut Vec<RenderBlock>) {
-    match &block.kind {
-        BlockKind::Paragraph { inlines } => out.push(RenderBlock {
-            node_id: block.node_id,
-            kind: RenderBlockKind::Paragraph,
-            children: render_inlines(inlines),
-            attrs: range_attrs(block.range, markdown),
-        }),
-        BlockKind::Heading { level, inlines } => out.push(RenderBlock {
-            node_id: block.node_id,
-            kind: RenderBlockKind::Heading,
-            children: render_inlines(inlines),
-            attrs: RenderAttrs {
-                heading_level: Some(*level),
-                ..range_attrs(block.range, markdown)
-            },
-        }),
-        BlockKind::Blockquote { children } => {
-            for child in children {
-                let inlines = match &child.kind {
-                    BlockKind::Paragraph { inlines } | BlockKind::Heading { inlines, .. } => {
-                        render_inlines(inlines)
+/// Published renderings, taken by node ID in about the order they were published in.
+struct PublishedBlocks {
+    pending: std::vec::IntoIter<RenderedBlock>,
+    /// Renderings passed over while looking for a later one.
+    skipped: HashMap<NodeId, RenderedBlock>,
+}
+
+impl PublishedBlocks {
+    fn take(&mut self, node_id: NodeId) -> Option<RenderedBlock> {
+        if let Some(rendered) = self.skipped.remove(&node_id) {
+            return Some(rendered);
+        }
+        for rendered in self.pending.by_ref() {
+            if rendered.node_id == node_id {
+                return Some(rendered);
+            }
+            self.skipped.insert(rendered.node_id, rendered);
+        }
+        None
+    }
+}
+
+/// Renders the top-level `block` of `doc`, reading only its own text from the rope.
+fn render_block(
+    doc: &Doc,
+    block: &BlockNode,
+    footnote_numbers: &HashMap<NodeId, u32>,
+) -> RenderedBlock {
+    let text = doc.text.slice(block.range).unwrap_or_default();
+    let source = Source { text: &text, start: block.range.start_utf8 as usize };
+    let cx = RenderContext::new(doc, source, footnote_numbers);
+    let mut blocks = Vec::new();
+    flatten_block(&cx, block, Nesting::default(), &mut blocks);
+    RenderedBlock { node_id: block.node_id, start: block.range.start_utf8, shift: 0, blocks }
+}
+
+/// Renders every top-level block of `doc`.
+pub(crate) fn render_each_block(
+    doc: &Doc,
+    footnote_numbers: &HashMap<NodeId, u32>,
+) -> Vec<RenderedBlock> {
+    doc.blocks.iter().map(|block| render_block(doc, block, footnote_numbers)).collect()
+}
+
+/// The render blocks of `rendered`, in document order.
+pub(crate) fn flatten_rendered(rendered: &[RenderedBlock]) -> Vec<RenderBlock> {
+    rendered.iter().flat_map(RenderedBlock::published).collect()
+}
+
+/// Diffs `doc` against `base`, its rendering as last published. Only the top-level
+/// blocks in `dirty` (all of them when it is `None`) and those missing from `base` are
+/// rendered and diffed again; the others keep their render blocks and are described by
+/// offset shifts. Returns the diff and the rendering to diff the next patch against.
+pub(crate) fn diff_rendered_blocks(
+    doc: &Doc,
+    base: Vec<RenderedBlock>,
+    dirty: Option<&HashSet<NodeId>>,
+    footnote_numbers: &HashMap<NodeId, u32>,
+) -> (BlockDiff, Vec<RenderedBlock>) {
+    let mut base = PublishedBlocks { pending: base.into_iter(), skipped: HashMap::new() };
+    let mut rendered = Vec::with_capacity(doc.blocks.len());
+    let mut next_blocks = Vec::new();
+    let mut moves = Vec::new();
+    // The delta of the last carried-over block, while no rendered block followed it.
+    let mut last_delta = None;
+    for block in &doc.blocks {
+        let clean = dirty.is_some_and(|dirty| !dirty.contains(&block.node_id));
+        match clean.then(|| base.take(block.node_id)).flatten() {
+            Some(previous) => {
+                let delta = i64::from(block.range.start_utf8) - i64::from(previous.start);
+                if last_delta != Some(delta) {
+                    if let Some(first) = previous.blocks.first() {
+                        moves.push((shift_offset(block_start(first), previous.shift), delta));
+                        last_delta = Some(delta);
                     }
-                    _ => Vec::new(),
-                };
-                out.push(RenderBlock {
-                    node_id: child.node_id,
-                    kind: RenderBlockKind::BlockquoteParagraph,
-                    children: inlines,
-                    attrs: range_attrs(child.range, markdown),
+                }
+                rendered.push(RenderedBlock {
+                    start: block.range.start_utf8,
+                    shift: previous.shift + delta,
+                    ..previous
                 });
             }
+            None => {
+                last_delta = None;
+                let next = render_block(doc, block, footnote_numbers);
+                next_blocks.extend(next.blocks.iter().cloned());
+                rendered.push(next);
+            }
+        }
+    }
+    // What was not carried over is what the dirty blocks replaced, in published order.
+    let mut replaced: Vec<RenderedBlock> = base.skipped.into_values().chain(base.pending).collect();
+    replaced.sort_unstable_by_key(|rendered| rendered.start);
+    let base_blocks: Vec<RenderBlock> =
+        replaced.iter().flat_map(RenderedBlock::published).collect();
+
+    let mut diff = BlockDiff::default();
+    if !diff_blocks_into(&base_blocks, &next_blocks, &mut diff, &mut moves) {
+        diff.order = Some(
+            rendered
+                .iter()
+                .flat_map(|rendered| &rendered.blocks)
+                .map(|block| block.node_id)
+                .collect(),
+        );
+    }
+    diff.offset_shifts = offset_shifts(moves);
+    (diff, rendered)
+}
+
+/// Sets `heading_level` on headings, for the renderer to scale them by.
+pub struct HeadingScale;
+
+impl RenderPlugin for HeadingScale {
+    fn name(&self) -> &str {
+        "heading-scale"
+    }
+
+    fn render_block(&self, source: &BlockNode, block: &mut RenderBlock) {
+        if let (BlockKind::Heading { level, .. }, RenderBlockKind::Heading) =
+            (&source.kind, &block.kind)
+        {
+            block.attrs.heading_level = Some(*level);
+        }
+    }
+}
+
+/// Sets `language` on fenced code blocks and their code, for the language badge.
+pub struct CodeBlockBadge;
+
+impl RenderPlugin for CodeBlockBadge {
+    fn name(&self) -> &str {
+        "code-block-badge"
+    }
+
+    fn render_block(&self, source: &BlockNode, block: &mut RenderBlock) {
+        let BlockKind::FencedCode { language, .. } = &source.kind else {
+            return;
+        };
+        block.attrs.language.clone_from(language);
+        for child in &mut block.children {
+            child.attrs.language.clone_from(language);
+        }
+    }
+}
+
+/// The source text, document path and render plugins the snapshot is built with,
+/// the footnote numbers by definition node ID and the resolved wiki-link targets.
+struct RenderContext<'a> {
+    markdown: Source<'a>,
+    path: Option<&'a str>,
+    plugins: &'a [Arc<dyn RenderPlugin>],
+    footnote_numbers: &'a HashMap<NodeId, u32>,
+    resolved_wiki_links: Option<&'a HashSet<String>>,
+}
+
+/// Source text starting `start` bytes into the document, sliced by document offsets
+/// so a single block can be rendered from its own text.
+#[derive(Debug, Clone, Copy)]
+struct Source<'a> {
+    text: &'a str,
+    start: usize,
+}
+
+impl<'a> Source<'a> {
+    fn get(self, range: Range<usize>) -> Option<&'a str> {
+        let start = range.start.checked_sub(self.start)?;
+        let end = range.end.checked_sub(self.start)?;
+        self.text.get(start..end)
+    }
+}
+
+impl<'a> From<&'a str> for Source<'a> {
+    fn from(text: &'a str) -> Self {
+        Self { text, start: 0 }
+    }
+}
+
+impl<'a> RenderContext<'a> {
+    fn new(doc: &'a Doc, markdown: Source<'a>, footnote_numbers: &'a HashMap<NodeId, u32>) -> Self {
+        Self {
+            markdown,
+            path: doc.path(),
+            plugins: &doc.registry().render_plugins,
+            footnote_numbers,
+            resolved_wiki_links: doc.resolved_wiki_links(),
+        }
+    }
+
+    fn push_block(&self, source: &BlockNode, mut block: RenderBlock, out: &mut Vec<RenderBlock>) {
+        for plugin in self.plugins {
+            plugin.render_block(source, &mut block);
+        }
+        out.push(block);
+    }
+}
+
+/// Diffs two render block lists by node ID. A block that kept its ID and only moved
+/// is described by an offset shift instead of being resent.
+pub fn diff_render_blocks(base: &[RenderBlock], next: &[RenderBlock]) -> BlockDiff {
+    let mut diff = BlockDiff::default();
+    let mut moves = Vec::new();
+    if !diff_blocks_into(base, next, &mut diff, &mut moves) {
+        diff.order = Some(next.iter().map(|block| block.node_id).collect());
+    }
+    diff.offset_shifts = offset_shifts(moves);
+    diff
+}
+
+/// Adds the added, changed and removed blocks between `base` and `next` to `diff`,
+/// and the `(base start, delta)` of every block that only moved to `moves`. Returns
+/// whether the blocks kept their order, with none added or removed.
+fn diff_blocks_into(
+    base: &[RenderBlock],
+    next: &[RenderBlock],
+    diff: &mut BlockDiff,
+    moves: &mut Vec<(u32, i64)>,
+) -> bool {
+    let base_by_id: HashMap<NodeId, &RenderBlock> =
+        base.iter().map(|block| (block.node_id, block)).collect();
+    let next_ids: HashSet<NodeId> = next.iter().map(|block| block.node_id).collect();
+
+    let (added, removed) = (diff.added.len(), diff.removed.len());
+    for block in next {
+        let Some(previous) = base_by_id.get(&block.node_id) else {
+            diff.added.push(block.clone());
+            continue;
+        };
+        let delta = i64::from(block_start(block)) - i64::from(block_start(previous));
+        let mut moved = (*previous).clone();
+        shift_render_block(&mut moved, delta);
+        if moved == *block {
+            moves.push((block_start(previous), delta));
+        } else {
+            diff.changed.push(block.clone());
+        }
+    }
+    diff.removed.extend(
+        base.iter().map(|block| block.node_id).filter(|node_id| !next_ids.contains(node_id)),
+    );
+
+    let same_order = base
+        .iter()
+        .map(|block| block.node_id)
+        .filter(|node_id| next_ids.contains(node_id))
+        .eq(next.iter().map(|block| block.node_id));
+    same_order && diff.added.len() == added && diff.removed.len() == removed
+}
+
+/// Offset shifts for blocks that moved by `moves`, merging neighbours that moved alike.
+fn offset_shifts(mut moves: Vec<(u32, i64)>) -> Vec<OffsetShift> {
+    moves.sort_unstable();
+    let mut shifts = Vec::new();
+    let mut current = 0;
+    for (from_utf8, delta_utf8) in moves {
+        if delta_utf8 != current {
+            shifts.push(OffsetShift { from_utf8, delta_utf8 });
+            current = delta_utf8;
+        }
+    }
+    shifts
+}
+
+fn block_start(block: &RenderBlock) -> u32 {
+    block.attrs.start_utf8.unwrap_or_default()
+}
+
+fn shift_offset(offset: u32, delta: i64) -> u32 {
+    (i64::from(offset) + delta) as u32
+}
+
+fn shift_attrs(attrs: &mut RenderAttrs, delta: i64) {
+    for offset in [&mut attrs.start_utf8, &mut attrs.end_utf8].into_iter().flatten() {
+        *offset = shift_offset(*offset, delta);
+    }
+}
+
+fn shift_render_block(block: &mut RenderBlock, delta: i64) {
+    shift_attrs(&mut block.attrs, delta);
+    block.children.iter_mut().for_each(|child| shift_render_inline(child, delta));
+}
+
+fn shift_render_inline(inline: &mut RenderInline, delta: i64) {
+    inline.range = TextRange::new(
+        shift_offset(inline.range.start_utf8, delta),
+        shift_offset(inline.range.end_utf8, delta),
+    );
+    shift_attrs(&mut inline.attrs, delta);
+    inline.children.iter_mut().for_each(|child| shift_render_inline(child, delta));
+}
+
+/// How deep a block sits in lists and in blockquotes, `None` outside of them.
+#[derive(Debug, Clone, Copy, Default)]
+struct Nesting {
+    list_depth: Option<u32>,
+    quote_depth: Option<u32>,
+}
+
+impl Nesting {
+    /// The nesting of the content of a blockquote at this nesting.
+    fn inside_quote(self) -> Self {
+        Self { quote_depth: Some(self.quote_depth.map_or(0, |depth| depth + 1)), ..self }
+    }
+}
+
+fn flatten_block(
+    cx: &RenderContext<'_>,
+    block: &BlockNode,
+    nesting: Nesting,
+    out: &mut Vec<RenderBlock>,
+) {
+    let markdown = cx.markdown;
+    match &block.kind {
+        BlockKind::Paragraph { inlines } => cx.push_block(
+            block,
+            RenderBlock {
+                node_id: block.node_id,
+                kind: RenderBlockKind::Paragraph,
+                children: render_inlines(cx, inlines),
+                attrs: block_attrs(block.range, markdown, nesting),
+            },
+            out,
+        ),
+        BlockKind::FrontMatter { .. } => {
+            let source = markdown
+                .get(block.range.start_utf8 as usize..block.range.end_utf8 as usize)
+                .unwrap_or_default();
+            cx.push_block(
+                block,
+                RenderBlock {
+                    node_id: block.node_id,
+                    kind: RenderBlockKind::FrontMatter,
+                    children: vec![code_text(block, block.range, source, markdown)],
+                    attrs: block_attrs(block.range, markdown, nesting),
+                },
+                out,
+            )
+        }
+        BlockKind::Heading { inlines, .. } => cx.push_block(
+            block,
+            RenderBlock {
+                node_id: block.node_id,
+                kind: RenderBlockKind::Heading,
+                children: render_inlines(cx, inlines),
+                attrs: block_attrs(block.range, markdown, nesting),
+            },
+            out,
+        ),
+        BlockKind::Blockquote { children } => {
+            flatten_quote_children(cx, children, nesting.inside_quote(), out);
+        }
+        BlockKind::Callout { kind, title, children } => {
+            let nesting = nesting.inside_quote();
+            let start = block.range.start_utf8 as usize;
+            let end = block.range.end_utf8 as usize;
+            let marker_end = markdown
+                .get(start..end)
+                .and_then(|text| text.find('\n'))
+                .map_or(end, |offset| start + offset);
+            cx.push_block(
+                block,
+                RenderBlock {
+                    node_id: block.node_id,
+                    kind: RenderBlockKind::Callout,
+                    children: Vec::new(),
+                    attrs: RenderAttrs {
+                        callout_kind: Some(*kind),
+                        title: title.clone(),
+                        ..block_attrs(
+                            TextRange::new(start as u32, marker_end as u32),
+                            markdown,
+                            nesting,
+                        )
+                    },
+                },
+                out,
+            );
+            flatten_quote_children(cx, children, nesting, out);
         }
         BlockKind::BulletList { items } => {
-            for item in items {
-                if let Some(first) = item.children.first() {
-                    let inlines = match &first.kind {
-                        BlockKind::Paragraph { inlines } | BlockKind::Heading { inlines, .. } => {
-                            render_inlines(inlines)
-                        }
-                        _ => Vec::new(),
-                    };
-                    out.push(RenderBlock {
-                        node_id: item.node_id,
-                        kind: RenderBlockKind::BulletListItem,
-                        children: inlines,
-                        attrs: range_attrs(item.range, markdown),
-                    });
-                }
-            }
+            flatten_list_items(cx, block, items, None, nesting, out);
         }
         BlockKind::OrderedList { start, items } => {
-            for (offset, item) in items.iter().enumerate() {
-                if let Some(first) = item.children.first() {
-                    let inlines = match &first.kind {
-                        BlockKind::Paragraph { inlines } | BlockKind::Heading { inlines, .. } => {
-                            render_inlines(inlines)
-                        }
-                        _ => Vec::new(),
-                    };
-                    out.push(RenderBlock {
-                        node_id: item.node_id,
-                        kind: RenderBlockKind::OrderedListItem,
-                        children: inlines,
-                        attrs: RenderAttrs {
-                            list_index: Some(*start + offset as u32),
-                            ..range_attrs(item.range, markdown)
-                        },
-                    });
-                }
-            }
+            flatten_list_items(cx, block, items, Some(*start), nesting, out);
         }
-        BlockKind::FencedCode { language, text_range, code } => out.push(RenderBlock {
-            node_id: block.node_id,
-            kind: RenderBlockKind::FencedCode,
-            children: vec![RenderInline {
+        BlockKind::FencedCode { text_range, code, .. } => cx.push_block(
+            block,
+            RenderBlock {
                 node_id: block.node_id,
-                kind: RenderInlineKind::CodeText,
-                text: Some(code.clone()),
-                range: *text_range,
+                kind: RenderBlockKind::FencedCode,
+                children: vec![code_text(block, *text_range, code, markdown)],
+                attrs: block_attrs(block.range, markdown, nesting),
+            },
+            out,
+        ),
+        BlockKind::IndentedCode { text_range, code } => cx.push_block(
+            block,
+            RenderBlock {
+                node_id: block.node_id,
+                kind: RenderBlockKind::IndentedCode,
+                children: vec![code_text(block, *text_range, code, markdown)],
+                attrs: block_attrs(block.range, markdown, nesting),
+            },
+            out,
+        ),
+        BlockKind::MathBlock { text_range, tex } => cx.push_block(
+            block,
+            RenderBlock {
+                node_id: block.node_id,
+                kind: RenderBlockKind::MathBlock,
+                children: vec![code_text(block, *text_range, tex, markdown)],
+                attrs: RenderAttrs {
+                    display_math: Some(true),
+                    ..block_attrs(block.range, markdown, nesting)
+                },
+            },
+            out,
+        ),
+        BlockKind::ThematicBreak => cx.push_block(
+            block,
+            RenderBlock {
+                node_id: block.node_id,
+                kind: RenderBlockKind::ThematicBreak,
                 children: Vec::new(),
+                attrs: block_attrs(block.range, markdown, nesting),
+            },
+            out,
+        ),
+        BlockKind::HtmlBlock { html } => cx.push_block(
+            block,
+            RenderBlock {
+                node_id: block.node_id,
+                kind: RenderBlockKind::HtmlBlock,
+                children: vec![code_text(block, block.range, html, markdown)],
+                attrs: block_attrs(block.range, markdown, nesting),
+            },
+            out,
+        ),
+        BlockKind::Table { alignments, header, rows } => cx.push_block(
+            block,
+            RenderBlock {
+                node_id: block.node_id,
+                kind: RenderBlockKind::Table,
+                children: std::iter::once(render_table_row(cx, header, alignments, true))
+                    .chain(rows.iter().map(|row| render_table_row(cx, row, alignments, false)))
+                    .collect(),
+                attrs: block_attrs(block.range, markdown, nesting),
+            },
+            out,
+        ),
+        BlockKind::FootnoteDefinition { label, children, .. } => {
+            flatten_footnote_definition(cx, block, label, children, nesting, out);
+        }
+        BlockKind::LinkDefinition { href, title, .. } => cx.push_block(
+            block,
+            RenderBlock {
+                node_id: block.node_id,
+                kind: RenderBlockKind::LinkDefinition,
+                children: Vec::new(),
+                attrs: RenderAttrs {
+                    href: Some(href.clone()),
+                    title: title.clone(),
+                    ..block_attrs(block.range, markdown, nesting)
+                },
+            },
+            out,
+        ),
+        BlockKind::Custom { name, inlines } => cx.push_block(
+            block,
+            RenderBlock {
+                node_id: block.node_id,
+                kind: RenderBlockKind::Custom,
+                children: render_inlines(cx, inlines),
+                attrs: RenderAttrs {
+                    syntax: Some(name.clone()),
+                    ..block_attrs(block.range, markdown, nesting)
+                },
+            },
+            out,
+        ),
+    }
+}
+
+/// Emits the content of a blockquote or callout. Paragraphs become blockquote
+/// paragraphs; other blocks flatten as usual at the quote's nesting.
+fn flatten_quote_children(
+    cx: &RenderContext<'_>,
+    children: &[BlockNode],
+    nesting: Nesting,
+    out: &mut Vec<RenderBlock>,
+) {
+    for child in children {
+        let BlockKind::Paragraph { inlines } = &child.kind else {
+            flatten_block(cx, child, nesting, out);
+            continue;
+        };
+        cx.push_block(
+            child,
+            RenderBlock {
+                node_id: child.node_id,
+                kind: RenderBlockKind::BlockquoteParagraph,
+                children: render_inlines(cx, inlines),
+                attrs: block_attrs(child.range, cx.markdown, nesting),
+            },
+            out,
+        );
+    }
+}
+
+/// Emits one render block per list item one level below `parent` (depth 0 for a
+/// top-level list). The item's leading paragraph becomes the item's inline content;
+/// the rest of its children follow as blocks carrying the item's depth.
+fn flatten_list_items(
+    cx: &RenderContext<'_>,
+    list: &BlockNode,
+    items: &[ListItemNode],
+    start: Option<u32>,
+    parent: Nesting,
+    out: &mut Vec<RenderBlock>,
+) {
+    let depth = parent.list_depth.map_or(0, |depth| depth + 1);
+    let nesting = Nesting { list_depth: Some(depth), ..parent };
+    for (offset, item) in items.iter().enumerate() {
+        let (inlines, head_end, rest) = match item.children.split_first() {
+            Some((first, rest)) => match &first.kind {
+                BlockKind::Paragraph { inlines } | BlockKind::Heading { inlines, .. } => {
+                    (render_inlines(cx, inlines), first.range.end_utf8, rest)
+                }
+                _ => (Vec::new(), first.range.start_utf8, item.children.as_slice()),
+            },
+            None => (Vec::new(), item.range.end_utf8, &[][..]),
+        };
+
+        cx.push_block(
+            list,
+            RenderBlock {
+                node_id: item.node_id,
+                kind: if start.is_some() {
+                    RenderBlockKind::OrderedListItem
+                } else {
+                    RenderBlockKind::BulletListItem
+                },
+                children: inlines,
                 attrs: RenderAttrs {
-                    language: language.clone(),
-                    ..range_attrs(*text_range, markdown)
+                    list_index: start.map(|start| start + offset as u32),
+                    checked: item.checked,
+                    ..block_attrs(
+                        TextRange::new(item.range.start_utf8, head_end),
+                        cx.markdown,
+                        nesting,
+                    )
                 },
-            }],
-            attrs: RenderAttrs { language: language.clone(), ..range_attrs(block.range, markdown) },
-        }),
+            },
+            out,
+        );
+
+        for child in rest {
+            flatten_block(cx, child, nesting, out);
+        }
+    }
+}
+
+/// Emits the definition as one render block holding its leading paragraph, like a
+/// list item, followed by the rest of its content.
+fn flatten_footnote_definition(
+    cx: &RenderContext<'_>,
+    block: &BlockNode,
+    label: &str,
+    children: &[BlockNode],
+    nesting: Nesting,
+    out: &mut Vec<RenderBlock>,
+) {
+    let (inlines, head_end, rest) = match children.split_first() {
+        Some((first, rest)) => match &first.kind {
+            BlockKind::Paragraph { inlines } => {
+                (render_inlines(cx, inlines), first.range.end_utf8, rest)
+            }
+            _ => (Vec::new(), first.range.start_utf8, children),
+        },
+        None => (Vec::new(), block.range.end_utf8, &[][..]),
+    };
+
+    cx.push_block(
+        block,
+        RenderBlock {
+            node_id: block.node_id,
+            kind: RenderBlockKind::FootnoteDefinition,
+            children: inlines,
+            attrs: RenderAttrs {
+                footnote_label: Some(label.to_string()),
+                footnote_number: cx.footnote_numbers.get(&block.node_id).copied(),
+                ..block_attrs(
+                    TextRange::new(block.range.start_utf8, head_end),
+                    cx.markdown,
+                    nesting,
+                )
+            },
+        },
+        out,
+    );
+
+    for child in rest {
+        flatten_block(cx, child, nesting, out);
+    }
+}
+
+fn render_table_row(
+    cx: &RenderContext<'_>,
+    row: &TableRowNode,
+    alignments: &[TableAlignment],
+    is_header: bool,
+) -> RenderInline {
+    let cells = row
+        .cells
+        .iter()
+        .enumerate()
+        .map(|(column, cell)| RenderInline {
+            node_id: cell.node_id,
+            kind: RenderInlineKind::TableCell,
+            text: None,
+            range: cell.range,
+            children: render_inlines(cx, &cell.inlines),
+            attrs: RenderAttrs {
+                table_header: Some(is_header),
+                column_index: Some(column as u32),
+                column_alignment: alignments.get(column).copied(),
+                ..range_attrs(cell.range, cx.markdown)
+            },
+        })
+        .collect();
+
+    RenderInline {
+        node_id: row.node_id,
+        kind: RenderInlineKind::TableRow,
+        text: None,
+        range: row.range,
+        children: cells,
+        attrs: RenderAttrs { table_header: Some(is_header), ..range_attrs(row.range, cx.markdown) },
     }
 }
 
-fn render_inlines(inlines: &[InlineNode]) -> Vec<RenderInline> {
-    inlines.iter().map(render_inline).collect()
+fn render_inlines(cx: &RenderContext<'_>, inlines: &[InlineNode]) -> Vec<RenderInline> {
+    inlines.iter().map(|inline| render_inline(cx, inline)).collect()
 }
 
-fn render_inline(inline: &InlineNode) -> RenderInline {
-    match &inline.kind {
+fn render_inline(cx: &RenderContext<'_>, inline: &InlineNode) -> RenderInline {
+    let mut rendered = match &inline.kind {
         InlineKind::Text { value } => RenderInline {
             node_id: inline.node_id,
             kind: RenderInlineKind::Text,
             text: Some(value.clone()),
             range: inline.range,
             children: Vec::new(),
-            attrs: range_attrs(inline.range, value),
+            attrs: range_attrs(inline.range, cx.markdown),
         },
         InlineKind::Strong { children } => RenderInline {
             node_id: inline.node_id,
             kind: RenderInlineKind::Strong,
             text: None,
             range: inline.range,
-            children: render_inlines(children),
+            children: render_inlines(cx, children),
             attrs: range_attrs(inline.range, ""),
         },
         InlineKind::Emphasis { children } => RenderInline {
@@ -199,7 +958,39 @@ fn render_inline(inline: &InlineNode) -> RenderInline {
             kind: RenderInlineKind::Emphasis,
             text: None,
             range: inline.range,
-            children: render_inlines(children),
+            children: render_inlines(cx, children),
+            attrs: range_attrs(inline.range, ""),
+        },
+        InlineKind::Strikethrough { children } => RenderInline {
+            node_id: inline.node_id,
+            kind: RenderInlineKind::Strikethrough,
+            text: None,
+            range: inline.range,
+            children: render_inlines(cx, children),
+            attrs: range_attrs(inline.range, ""),
+        },
+        InlineKind::Highlight { children } => RenderInline {
+            node_id: inline.node_id,
+            kind: RenderInlineKind::Highlight,
+            text: None,
+            range: inline.range,
+            children: render_inlines(cx, children),
+            attrs: range_attrs(inline.range, ""),
+        },
+        InlineKind::Subscript { children } => RenderInline {
+            node_id: inline.node_id,
+            kind: RenderInlineKind::Subscript,
+            text: None,
+            range: inline.range,
+            children: render_inlines(cx, children),
+            attrs: range_attrs(inline.range, ""),
+        },
+        InlineKind::Superscript { children } => RenderInline {
+            node_id: inline.node_id,
+            kind: RenderInlineKind::Superscript,
+            text: None,
+            range: inline.range,
+            children: render_inlines(cx, children),
             attrs: range_attrs(inline.range, ""),
         },
         InlineKind::InlineCode { value } => RenderInline {
@@ -208,27 +999,197 @@ fn render_inline(inline: &InlineNode) -> RenderInline {
             text: Some(value.clone()),
             range: inline.range,
             children: Vec::new(),
-            attrs: range_attrs(inline.range, value),
+            attrs: range_attrs(inline.range, cx.markdown),
+        },
+        InlineKind::Math { display, tex } => RenderInline {
+            node_id: inline.node_id,
+            kind: RenderInlineKind::Math,
+            text: Some(tex.clone()),
+            range: inline.range,
+            children: Vec::new(),
+            attrs: RenderAttrs {
+                display_math: Some(*display),
+                ..range_attrs(inline.range, cx.markdown)
+            },
+        },
+        InlineKind::FootnoteReference { label, definition } => RenderInline {
+            node_id: inline.node_id,
+            kind: RenderInlineKind::FootnoteReference,
+            text: Some(label.clone()),
+            range: inline.range,
+            children: Vec::new(),
+            attrs: RenderAttrs {
+                footnote_label: Some(label.clone()),
+                footnote_number: definition
+                    .and_then(|definition| cx.footnote_numbers.get(&definition).copied()),
+                ..range_attrs(inline.range, cx.markdown)
+            },
+        },
+        InlineKind::WikiLink { target, alias, heading } => RenderInline {
+            node_id: inline.node_id,
+            kind: RenderInlineKind::WikiLink,
+            text: Some(wiki_link_text(target, alias.as_deref(), heading.as_deref())),
+            range: inline.range,
+            children: Vec::new(),
+            attrs: RenderAttrs {
+                wiki_target: Some(target.clone()),
+                wiki_heading: heading.clone(),
+                unresolved: cx
+                    .resolved_wiki_links
+                    .map(|resolved| !target.is_empty() && !resolved.contains(target)),
+                ..range_attrs(inline.range, cx.markdown)
+            },
+        },
+        InlineKind::HardBreak => RenderInline {
+            node_id: inline.node_id,
+            kind: RenderInlineKind::HardBreak,
+            text: Some("\n".to_string()),
+            range: inline.range,
+            children: Vec::new(),
+            attrs: range_attrs(inline.range, ""),
         },
         InlineKind::Link { href, title, children } => RenderInline {
             node_id: inline.node_id,
             kind: RenderInlineKind::Link,
             text: None,
             range: inline.range,
-            children: render_inlines(children),
+            children: render_inlines(cx, children),
             attrs: RenderAttrs {
                 href: Some(href.clone()),
                 title: title.clone(),
                 ..range_attrs(inline.range, "")
             },
         },
+        InlineKind::Image { src, title, children } => RenderInline {
+            node_id: inline.node_id,
+            kind: RenderInlineKind::Image,
+            text: None,
+            range: inline.range,
+            children: render_inlines(cx, children),
+            attrs: RenderAttrs {
+                src: Some(resolve_src(src, cx.path)),
+                alt: Some(plain_text(children)),
+                title: title.clone(),
+                ..range_attrs(inline.range, "")
+            },
+        },
+        InlineKind::Custom { name, value } => RenderInline {
+            node_id: inline.node_id,
+            kind: RenderInlineKind::Custom,
+            text: Some(value.clone()),
+            range: inline.range,
+            children: Vec::new(),
+            attrs: RenderAttrs {
+                syntax: Some(name.clone()),
+                ..range_attrs(inline.range, cx.markdown)
+            },
+        },
+    };
+    for plugin in cx.plugins {
+        plugin.render_inline(inline, &mut rendered);
+    }
+    rendered
+}
+
+/// The text of `inlines` with the markup dropped, as used for image alt text.
+fn plain_text(inlines: &[InlineNode]) -> String {
+    let mut text = String::new();
+    for inline in inlines {
+        match &inline.kind {
+            InlineKind::Text { value }
+            | InlineKind::InlineCode { value }
+            | InlineKind::Math { tex: value, .. }
+            | InlineKind::FootnoteReference { label: value, .. }
+            | InlineKind::Custom { value, .. } => text.push_str(value),
+            InlineKind::WikiLink { target, alias, heading } => {
+                text.push_str(&wiki_link_text(target, alias.as_deref(), heading.as_deref()))
+            }
+            InlineKind::HardBreak => text.push('\n'),
+            InlineKind::Strong { children }
+            | InlineKind::Emphasis { children }
+            | InlineKind::Strikethrough { children }
+            | InlineKind::Highlight { children }
+            | InlineKind::Subscript { children }
+            | InlineKind::Superscript { children }
+            | InlineKind::Link { children, .. }
+            | InlineKind::Image { children, .. } => text.push_str(&plain_text(children)),
+        }
+    }
+    text
+}
+
+/// The text a wiki-link shows: its alias, or else its target and heading.
+fn wiki_link_text(target: &str, alias: Option<&str>, heading: Option<&str>) -> String {
+    match (alias, heading) {
+        (Some(alias), _) => alias.to_string(),
+        (None, Some(heading)) if target.is_empty() => heading.to_string(),
+        (None, Some(heading)) => format!("{target} > {heading}"),
+        (None, None) => target.to_string(),
+    }
+}
+
+/// Resolves a relative image source against the directory of `document_path`. URLs,
+/// absolute paths (including Windows drive paths) and fragments are returned as they
+/// are, as is everything when the document has no path.
+fn resolve_src(src: &str, document_path: Option<&str>) -> String {
+    let has_scheme = src.split_once(':').is_some_and(|(scheme, _)| {
+        !scheme.is_empty()
+            && scheme.chars().all(|ch| ch.is_ascii_alphanumeric() || matches!(ch, '+' | '.' | '-'))
+    });
+    let Some(path) = document_path else {
+        return src.to_string();
+    };
+    if src.is_empty() || has_scheme || src.starts_with(['/', '\\', '#']) {
+        return src.to_string();
+    }
+
+    let separator = if path.contains('\\') && !path.contains('/') { '\\' } else { '/' };
+    let directory = path.rfind(['/', '\\']).map_or("", |index| &path[..index]);
+    let mut segments: Vec<&str> = directory.split(['/', '\\']).collect();
+    if directory.is_empty() {
+        segments.clear();
+    }
+    for segment in src.split(['/', '\\']) {
+        match segment {
+            "." | "" => {}
+            ".." if segments.last().is_some_and(|last| !last.is_empty() && *last != "..") => {
+                segments.pop();
+            }
+            _ => segments.push(segment),
+        }
+    }
+    segments.join(&separator.to_string())
+}
+
+/// The verbatim content of a code or HTML block, carrying the block's node ID.
+fn code_text(
+    block: &BlockNode,
+    range: TextRange,
+    text: &str,
+    markdown: Source<'_>,
+) -> RenderInline {
+    RenderInline {
+        node_id: block.node_id,
+        kind: RenderInlineKind::CodeText,
+        text: Some(text.to_string()),
+        range,
+        children: Vec::new(),
+        attrs: range_attrs(range, markdown),
+    }
+}
+
+fn block_attrs(range: TextRange, markdown: Source<'_>, nesting: Nesting) -> RenderAttrs {
+    RenderAttrs {
+        list_depth: nesting.list_depth,
+        quote_depth: nesting.quote_depth,
+        ..range_attrs(range, markdown)
     }
 }
 
-fn range_attrs(range: TextRange, raw_markdown: &str) -> RenderAttrs {
+fn range_attrs<'a>(range: TextRange, raw_markdown: impl Into<Source<'a>>) -> RenderAttrs {
     let start = range.start_utf8 as usize;
     let end = range.end_utf8 as usize;
-    let slice = raw_markdown.get(start..end).map(ToString::to_string).unwrap_or_default();
+    let slice = raw_markdown.into().get(start..end).map(ToString::to_string).unwrap_or_default();
 
     RenderAttrs {
         start_utf8: Some(range.start_utf8),
@@ -1,43 +1,865 @@
-use serde::{Deserialize, Serialize};
-use std::fmt;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::{borrow::Cow, fmt, sync::Arc};
 
-use crate::{selection::TextRange, EngineError, Result};
+use crate::{
+    selection::{LineCol, OffsetUnit, TextRange},
+    EngineError, Result,
+};
 
-#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq, Eq)]
-pub struct RopeText(String);
+/// Leaves split once they grow past this many bytes and neighbouring leaves merge
+/// while they fit in one.
+const MAX_CHUNK: usize = 1024;
+const MAX_CHILDREN: usize = 16;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct TextMetrics {
+    pub bytes: usize,
+    pub chars: usize,
+    pub utf16: usize,
+    pub newlines: usize,
+}
+
+impl TextMetrics {
+    pub fn len(&self, unit: OffsetUnit) -> usize {
+        match unit {
+            OffsetUnit::Utf8 => self.bytes,
+            OffsetUnit::Utf16 => self.utf16,
+            OffsetUnit::Char => self.chars,
+        }
+    }
+
+    fn of(text: &str) -> Self {
+        Self {
+            bytes: text.len(),
+            chars: text.chars().count(),
+            utf16: OffsetUnit::Utf16.count(text),
+            newlines: text.bytes().filter(|byte| *byte == b'\n').count(),
+        }
+    }
+
+    fn sum<'a>(nodes: impl Iterator<Item = &'a Arc<Node>>) -> Self {
+        nodes.fold(Self::default(), |total, node| {
+            let metrics = node.metrics();
+            Self {
+                bytes: total.bytes + metrics.bytes,
+                chars: total.chars + metrics.chars,
+                utf16: total.utf16 + metrics.utf16,
+                newlines: total.newlines + metrics.newlines,
+            }
+        })
+    }
+}
+
+#[derive(Debug, Clone)]
+enum Node {
+    Leaf { text: String, metrics: TextMetrics },
+    Branch { children: Vec<Arc<Node>>, metrics: TextMetrics },
+}
+
+impl Node {
+    fn leaf(text: String) -> Self {
+        Self::Leaf { metrics: TextMetrics::of(&text), text }
+    }
+
+    fn branch(children: Vec<Arc<Node>>) -> Self {
+        Self::Branch { metrics: TextMetrics::sum(children.iter()), children }
+    }
+
+    fn metrics(&self) -> TextMetrics {
+        match self {
+            Self::Leaf { metrics, .. } | Self::Branch { metrics, .. } => *metrics,
+        }
+    }
+
+    fn is_leaf(&self) -> bool {
+        matches!(self, Self::Leaf { .. })
+    }
+
+    /// Replaces `start..end` (relative to this node) and returns any sibling nodes of
+    /// the same height that no longer fit, in order.
+    fn replace(&mut self, start: usize, end: usize, insert: &str) -> Vec<Arc<Node>> {
+        match self {
+            Self::Leaf { text, metrics } => {
+                text.replace_range(start..end, insert);
+                let mut pieces = split_chunks(text).into_iter();
+                let first = pieces.next().unwrap_or_default();
+                let overflow = pieces.map(|piece| Arc::new(Node::leaf(piece))).collect();
+                *metrics = TextMetrics::of(&first);
+                *text = first;
+                overflow
+            }
+            Self::Branch { children, metrics } => {
+                let (first, first_offset) = child_at(children, start);
+                let (last, last_offset) = child_at(children, end);
+                let first_len = children[first].metrics().bytes;
+
+                let overflow = if first == last {
+                    Arc::make_mut(&mut children[first]).replace(
+                        start - first_offset,
+                        end - first_offset,
+                        insert,
+                    )
+                } else {
+                    Arc::make_mut(&mut children[last]).replace(0, end - last_offset, "");
+                    children.drain(first + 1..last);
+                    Arc::make_mut(&mut children[first]).replace(
+                        start - first_offset,
+                        first_len,
+                        insert,
+                    )
+                };
+                children.splice(first + 1..first + 1, overflow);
+                children.retain(|child| child.metrics().bytes > 0);
+                merge_small_leaves(children);
+
+                let overflow = if children.len() > MAX_CHILDREN {
+                    let mut groups = group_children(std::mem::take(children)).into_iter();
+                    *children = groups.next().unwrap_or_default();
+                    groups.map(|group| Arc::new(Node::branch(group))).collect()
+                } else {
+                    Vec::new()
+                };
+                *metrics = TextMetrics::sum(children.iter());
+                overflow
+            }
+        }
+    }
+}
+
+/// Persistent B-tree rope over UTF-8 text. Every leaf sits at the same depth, and
+/// nodes are shared between clones, so cloning is O(1) and an edit copies only the
+/// path it touches.
+#[derive(Clone)]
+pub struct RopeText {
+    root: Arc<Node>,
+}
+
+impl Default for RopeText {
+    fn default() -> Self {
+        Self::new(String::new())
+    }
+}
 
 impl RopeText {
     pub fn new(text: impl Into<String>) -> Self {
-        Self(text.into())
+        let text = text.into();
+        let leaves = split_chunks(&text).into_iter().map(|chunk| Arc::new(Node::leaf(chunk)));
+        Self { root: build_tree(leaves.collect()) }
     }
 
-    pub fn as_str(&self) -> &str {
-        &self.0
+    pub fn len_utf8(&self) -> usize {
+        self.root.metrics().bytes
     }
 
-    pub fn len_utf8(&self) -> usize {
-        self.0.len()
+    pub fn len_chars(&self) -> usize {
+        self.root.metrics().chars
+    }
+
+    pub fn len_lines(&self) -> usize {
+        self.root.metrics().newlines + 1
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len_utf8() == 0
+    }
+
+    pub fn metrics(&self) -> TextMetrics {
+        self.root.metrics()
     }
 
     pub fn replace_range(&mut self, range: TextRange, insert: &str) -> Result<()> {
         let start = range.start_utf8 as usize;
         let end = range.end_utf8 as usize;
+        self.check_range(start, end)?;
 
-        if start > end || end > self.0.len() {
-            return Err(EngineError::InvalidUtf8Range { start, end });
+        let overflow = Arc::make_mut(&mut self.root).replace(start, end, insert);
+        if !overflow.is_empty() {
+            let root = std::mem::replace(&mut self.root, Arc::new(Node::leaf(String::new())));
+            self.root = build_tree(std::iter::once(root).chain(overflow).collect());
         }
 
-        if !self.0.is_char_boundary(start) || !self.0.is_char_boundary(end) {
-            return Err(EngineError::InvalidUtf8Range { start, end });
+        loop {
+            let next = match self.root.as_ref() {
+                Node::Branch { children, .. } if children.len() == 1 => children[0].clone(),
+                Node::Branch { children, .. } if children.is_empty() => {
+                    Arc::new(Node::leaf(String::new()))
+                }
+                _ => break,
+            };
+            self.root = next;
+        }
+        Ok(())
+    }
+
+    /// Returns the text in `range`, borrowing when it lies inside a single chunk.
+    pub fn slice(&self, range: TextRange) -> Result<Cow<'_, str>> {
+        let start = range.start_utf8 as usize;
+        let end = range.end_utf8 as usize;
+        self.check_range(start, end)?;
+
+        let mut node = self.root.as_ref();
+        let mut local_start = start;
+        let mut local_end = end;
+        loop {
+            match node {
+                Node::Leaf { text, .. } => return Ok(Cow::Borrowed(&text[local_start..local_end])),
+                Node::Branch { children, .. } => {
+                    let (index, offset) = child_at(children, local_start);
+                    let child = &children[index];
+                    if local_end - offset > child.metrics().bytes {
+                        break;
+                    }
+                    local_start -= offset;
+                    local_end -= offset;
+                    node = child.as_ref();
+                }
+            }
         }
 
-        self.0.replace_range(start..end, insert);
+        let mut out = String::with_capacity(end - start);
+        collect_range(node, local_start, local_end, &mut out);
+        Ok(Cow::Owned(out))
+    }
+
+    pub fn chunks(&self) -> Chunks<'_> {
+        Chunks { stack: vec![self.root.as_ref()] }
+    }
+
+    pub fn is_char_boundary(&self, offset: usize) -> bool {
+        if offset == 0 || offset == self.len_utf8() {
+            return true;
+        }
+        if offset > self.len_utf8() {
+            return false;
+        }
+
+        let (text, local) = self.leaf_at(offset);
+        text.is_char_boundary(local)
+    }
+
+    /// Zero-based line index of the line containing byte `offset`.
+    pub fn byte_to_line(&self, offset: usize) -> usize {
+        let mut node = self.root.as_ref();
+        let mut local = offset.min(self.len_utf8());
+        let mut line = 0;
+        loop {
+            match node {
+                Node::Leaf { text, .. } => {
+                    return line + text.as_bytes()[..local].iter().filter(|b| **b == b'\n').count()
+                }
+                Node::Branch { children, .. } => {
+                    let mut next = children.len() - 1;
+                    for (index, child) in children.iter().enumerate() {
+                        let metrics = child.metrics();
+                        if local < metrics.bytes || index + 1 == children.len() {
+                            next = index;
+                            break;
+                        }
+                        local -= metrics.bytes;
+                        line += metrics.newlines;
+                    }
+                    node = children[next].as_ref();
+                }
+            }
+        }
+    }
+
+    /// Byte offset where zero-based `line` starts, clamped to the end of the text.
+    pub fn line_to_byte(&self, line: usize) -> usize {
+        if line == 0 {
+            return 0;
+        }
+        if line > self.root.metrics().newlines {
+            return self.len_utf8();
+        }
+
+        let mut node = self.root.as_ref();
+        let mut remaining = line;
+        let mut offset = 0;
+        loop {
+            match node {
+                Node::Leaf { text, .. } => {
+                    let newline = text
+                        .match_indices('\n')
+                        .nth(remaining - 1)
+                        .map_or(text.len(), |(index, _)| index + 1);
+                    return offset + newline;
+                }
+                Node::Branch { children, .. } => {
+                    let mut next = children.len() - 1;
+                    for (index, child) in children.iter().enumerate() {
+                        let metrics = child.metrics();
+                        if remaining <= metrics.newlines {
+                            next = index;
+                            break;
+                        }
+                        remaining -= metrics.newlines;
+                        offset += metrics.bytes;
+                    }
+                    node = children[next].as_ref();
+                }
+            }
+        }
+    }
+
+    /// Start and end (excluding the newline) of the line containing `offset`.
+    pub fn line_bounds(&self, offset: usize) -> (usize, usize) {
+        let offset = offset.min(self.len_utf8());
+        let (text, local) = self.leaf_at(offset);
+        let leaf_start = offset - local;
+        let start = match text[..local].rfind('\n') {
+            Some(index) => leaf_start + index + 1,
+            None => self.line_to_byte(self.byte_to_line(offset)),
+        };
+        let end = match text[local..].find('\n') {
+            Some(index) => offset + index,
+            None => {
+                let line = self.byte_to_line(offset);
+                if line + 1 < self.len_lines() {
+                    self.line_to_byte(line + 1) - 1
+                } else {
+                    self.len_utf8()
+                }
+            }
+        };
+        (start, end)
+    }
+
+    /// Byte offset of the position `offset` units into the text, clamped to the end.
+    /// An offset inside a character, such as between the halves of a UTF-16 surrogate
+    /// pair, rounds down to the character's start.
+    pub fn to_utf8(&self, offset: usize, unit: OffsetUnit) -> usize {
+        let mut node = self.root.as_ref();
+        let mut remaining = offset;
+        let mut bytes = 0;
+        loop {
+            match node {
+                Node::Leaf { text, .. } => {
+                    for (index, ch) in text.char_indices() {
+                        let width = unit.width(ch);
+                        if remaining < width {
+                            return bytes + index;
+                        }
+                        remaining -= width;
+                    }
+                    return bytes + text.len();
+                }
+                Node::Branch { children, .. } => {
+                    let mut next = children.len() - 1;
+                    for (index, child) in children.iter().enumerate() {
+                        let metrics = child.metrics();
+                        if remaining < metrics.len(unit) || index + 1 == children.len() {
+                            next = index;
+                            break;
+                        }
+                        remaining -= metrics.len(unit);
+                        bytes += metrics.bytes;
+                    }
+                    node = children[next].as_ref();
+                }
+            }
+        }
+    }
+
+    /// Number of `unit`s before byte `offset`, rounded down to a char boundary and
+    /// clamped to the end.
+    pub fn from_utf8(&self, offset: usize, unit: OffsetUnit) -> usize {
+        let mut node = self.root.as_ref();
+        let mut local = offset.min(self.len_utf8());
+        let mut count = 0;
+        loop {
+            match node {
+                Node::Leaf { text, .. } => {
+                    while !text.is_char_boundary(local) {
+                        local -= 1;
+                    }
+                    return count + unit.count(&text[..local]);
+                }
+                Node::Branch { children, .. } => {
+                    let mut next = children.len() - 1;
+                    for (index, child) in children.iter().enumerate() {
+                        let metrics = child.metrics();
+                        if local < metrics.bytes || index + 1 == children.len() {
+                            next = index;
+                            break;
+                        }
+                        local -= metrics.bytes;
+                        count += metrics.len(unit);
+                    }
+                    node = children[next].as_ref();
+                }
+            }
+        }
+    }
+
+    /// Line and column of byte `offset`, with the column counted in `unit`.
+    pub fn line_col(&self, offset: usize, unit: OffsetUnit) -> LineCol {
+        let line = self.byte_to_line(offset);
+        let line_start = self.line_to_byte(line);
+        let column = self.from_utf8(offset, unit) - self.from_utf8(line_start, unit);
+        LineCol { line: line as u32, column: column as u32 }
+    }
+
+    /// Byte offset of `position`, whose column is counted in `unit`. Lines past the end
+    /// clamp to the end of the text and columns past the end of a line to that line's end.
+    pub fn line_col_to_utf8(&self, position: LineCol, unit: OffsetUnit) -> usize {
+        let line_start = self.line_to_byte(position.line as usize);
+        let (_start, line_end) = self.line_bounds(line_start);
+        let line_start_units = self.from_utf8(line_start, unit);
+        self.to_utf8(line_start_units + position.column as usize, unit).min(line_end)
+    }
+
+    /// Byte lengths of the longest common prefix and suffix of `self` and `other`,
+    /// trimmed to char boundaries and never overlapping. Subtrees the two ropes still
+    /// share are skipped without comparing their text.
+    pub fn common_affixes(&self, other: &Self) -> (usize, usize) {
+        let limit = self.len_utf8().min(other.len_utf8());
+        let mut prefix = shared_prefix(&self.root, &other.root).min(limit);
+        while prefix < limit {
+            let (left, left_local) = self.leaf_at(prefix + 1);
+            let (right, right_local) = other.leaf_at(prefix + 1);
+            let left = &left.as_bytes()[left_local - 1..];
+            let right = &right.as_bytes()[right_local - 1..];
+            let len = left.len().min(right.len()).min(limit - prefix);
+            let same = left[..len].iter().zip(&right[..len]).take_while(|(a, b)| a == b).count();
+            prefix += same;
+            if same < len {
+                break;
+            }
+        }
+        while !self.is_char_boundary(prefix) {
+            prefix -= 1;
+        }
+
+        let limit = limit - prefix;
+        let mut suffix = shared_suffix(&self.root, &other.root).min(limit);
+        while suffix < limit {
+            let (left, left_local) = self.leaf_at(self.len_utf8() - suffix);
+            let (right, right_local) = other.leaf_at(other.len_utf8() - suffix);
+            let left = &left.as_bytes()[..left_local];
+            let right = &right.as_bytes()[..right_local];
+            let len = left.len().min(right.len()).min(limit - suffix);
+            let same = left
+                .iter()
+                .rev()
+                .zip(right.iter().rev())
+                .take(len)
+                .take_while(|(a, b)| a == b)
+                .count();
+            suffix += same;
+            if same < len {
+                break;
+            }
+        }
+        while !self.is_char_boundary(self.len_utf8() - suffix) {
+            suffix -= 1;
+        }
+
+        (prefix, suffix)
+    }
+
+    /// Byte offsets of the non-overlapping occurrences of `needle`, in order. Chunks
+    /// are searched in turn, carrying over only the end of each that a match could
+    /// still start in.
+    pub fn match_indices(&self, needle: &str) -> Vec<usize> {
+        let mut matches = Vec::new();
+        if needle.is_empty() {
+            return matches;
+        }
+
+        let mut window = String::new();
+        let mut window_start = 0;
+        for chunk in self.chunks() {
+            window.push_str(chunk);
+            let mut from = 0;
+            while let Some(index) = window[from..].find(needle) {
+                matches.push(window_start + from + index);
+                from += index + needle.len();
+            }
+
+            let mut keep = window.len().saturating_sub(needle.len() - 1).max(from);
+            while !window.is_char_boundary(keep) {
+                keep += 1;
+            }
+            window.drain(..keep);
+            window_start += keep;
+        }
+        matches
+    }
+
+    fn check_range(&self, start: usize, end: usize) -> Result<()> {
+        if start > end
+            || end > self.len_utf8()
+            || !self.is_char_boundary(start)
+            || !self.is_char_boundary(end)
+        {
+            return Err(EngineError::InvalidUtf8Range { start, end });
+        }
         Ok(())
     }
+
+    fn leaf_at(&self, offset: usize) -> (&str, usize) {
+        let mut node = self.root.as_ref();
+        let mut local = offset;
+        loop {
+            match node {
+                Node::Leaf { text, .. } => return (text, local),
+                Node::Branch { children, .. } => {
+                    let (index, child_offset) = child_at(children, local);
+                    local -= child_offset;
+                    node = children[index].as_ref();
+                }
+            }
+        }
+    }
+}
+
+pub struct Chunks<'a> {
+    stack: Vec<&'a Node>,
+}
+
+impl<'a> Iterator for Chunks<'a> {
+    type Item = &'a str;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while let Some(node) = self.stack.pop() {
+            match node {
+                Node::Leaf { text, .. } if text.is_empty() => continue,
+                Node::Leaf { text, .. } => return Some(text),
+                Node::Branch { children, .. } => {
+                    self.stack.extend(children.iter().rev().map(AsRef::as_ref));
+                }
+            }
+        }
+        None
+    }
 }
 
 impl fmt::Display for RopeText {
     fn fmt(&self, formatter: &mut fmt::Formatter<'_>) -> fmt::Result {
-        formatter.write_str(&self.0)
+        self.chunks().try_for_each(|chunk| formatter.write_str(chunk))
+    }
+}
+
+impl fmt::Debug for RopeText {
+    fn fmt(&self, formatter: &mut fmt::Formatter<'_>) -> fmt::Result {
+        formatter.debug_tuple("RopeText").field(&self.to_string()).finish()
+    }
+}
+
+impl PartialEq for RopeText {
+    fn eq(&self, other: &Self) -> bool {
+        self.len_utf8() == other.len_utf8()
+            && self.chunks().flat_map(str::bytes).eq(other.chunks().flat_map(str::bytes))
+    }
+}
+
+impl Eq for RopeText {}
+
+impl Serialize for RopeText {
+    fn serialize<S: Serializer>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+impl<'de> Deserialize<'de> for RopeText {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> std::result::Result<Self, D::Error> {
+        String::deserialize(deserializer).map(Self::new)
+    }
+}
+
+/// Bytes at the start of `left` and `right` covered by subtrees both ropes share.
+fn shared_prefix(left: &Arc<Node>, right: &Arc<Node>) -> usize {
+    if Arc::ptr_eq(left, right) {
+        return left.metrics().bytes;
+    }
+    let (Node::Branch { children: left, .. }, Node::Branch { children: right, .. }) =
+        (left.as_ref(), right.as_ref())
+    else {
+        return 0;
+    };
+
+    let mut total = 0;
+    for (left, right) in left.iter().zip(right) {
+        if !Arc::ptr_eq(left, right) {
+            return total + shared_prefix(left, right);
+        }
+        total += left.metrics().bytes;
+    }
+    total
+}
+
+/// Bytes at the end of `left` and `right` covered by subtrees both ropes share.
+fn shared_suffix(left: &Arc<Node>, right: &Arc<Node>) -> usize {
+    if Arc::ptr_eq(left, right) {
+        return left.metrics().bytes;
+    }
+    let (Node::Branch { children: left, .. }, Node::Branch { children: right, .. }) =
+        (left.as_ref(), right.as_ref())
+    else {
+        return 0;
+    };
+
+    let mut total = 0;
+    for (left, right) in left.iter().rev().zip(right.iter().rev()) {
+        if !Arc::ptr_eq(left, right) {
+            return total + shared_suffix(left, right);
+        }
+        total += left.metrics().bytes;
+    }
+    total
+}
+
+/// Finds the child containing byte `offset`; an offset on a boundary resolves to the
+/// earlier child so inserts extend existing chunks.
+fn child_at(children: &[Arc<Node>], offset: usize) -> (usize, usize) {
+    let mut child_offset = 0;
+    for (index, child) in children.iter().enumerate() {
+        let len = child.metrics().bytes;
+        if offset <= child_offset + len || index + 1 == children.len() {
+            return (index, child_offset);
+        }
+        child_offset += len;
+    }
+    (0, 0)
+}
+
+fn collect_range(node: &Node, start: usize, end: usize, out: &mut String) {
+    match node {
+        Node::Leaf { text, .. } => out.push_str(&text[start..end]),
+        Node::Branch { children, .. } => {
+            let mut offset = 0;
+            for child in children {
+                let len = child.metrics().bytes;
+                if offset + len > start && offset < end {
+                    let child_end = (end - offset).min(len);
+                    collect_range(child, start.saturating_sub(offset), child_end, out);
+                }
+                if offset + len >= end {
+                    break;
+                }
+                offset += len;
+            }
+        }
+    }
+}
+
+fn split_chunks(text: &str) -> Vec<String> {
+    if text.len() <= MAX_CHUNK {
+        return vec![text.to_string()];
+    }
+
+    let pieces = text.len().div_ceil(MAX_CHUNK);
+    let target = text.len().div_ceil(pieces);
+    let mut chunks = Vec::with_capacity(pieces);
+    let mut start = 0;
+    while start < text.len() {
+        let mut end = (start + target).min(text.len());
+        while !text.is_char_boundary(end) {
+            end += 1;
+        }
+        chunks.push(text[start..end].to_string());
+        start = end;
+    }
+    chunks
+}
+
+fn merge_small_leaves(children: &mut Vec<Arc<Node>>) {
+    let mut index = 0;
+    while index + 1 < children.len() {
+        let fits = children[index].is_leaf()
+            && children[index + 1].is_leaf()
+            && children[index].metrics().bytes + children[index + 1].metrics().bytes <= MAX_CHUNK;
+        if !fits {
+            index += 1;
+            continue;
+        }
+
+        let next = children.remove(index + 1);
+        if let (Node::Leaf { text, metrics }, Node::Leaf { text: tail, .. }) =
+            (Arc::make_mut(&mut children[index]), next.as_ref())
+        {
+            text.push_str(tail);
+            *metrics = TextMetrics::of(text);
+        }
+    }
+}
+
+fn group_children(children: Vec<Arc<Node>>) -> Vec<Vec<Arc<Node>>> {
+    let groups = children.len().div_ceil(MAX_CHILDREN);
+    let size = children.len().div_ceil(groups);
+    let mut children = children.into_iter().peekable();
+    let mut out = Vec::with_capacity(groups);
+    while children.peek().is_some() {
+        out.push(children.by_ref().take(size).collect());
+    }
+    out
+}
+
+fn build_tree(mut nodes: Vec<Arc<Node>>) -> Arc<Node> {
+    while nodes.len() > 1 {
+        nodes =
+            group_children(nodes).into_iter().map(|group| Arc::new(Node::branch(group))).collect();
+    }
+    nodes.pop().unwrap_or_else(|| Arc::new(Node::leaf(String::new())))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn next_random(seed: &mut u64) -> usize {
+        *seed = seed.wrapping_mul(6364136223846793005).wrapping_add(1442695040888963407);
+        (*seed >> 33) as usize
+    }
+
+    fn floor_boundary(text: &str, mut offset: usize) -> usize {
+        while !text.is_char_boundary(offset) {
+            offset -= 1;
+        }
+        offset
+    }
+
+    #[test]
+    fn common_affixes_match_string_model() {
+        fn naive(left: &str, right: &str) -> (usize, usize) {
+            let limit = left.len().min(right.len());
+            let prefix = left.bytes().zip(right.bytes()).take_while(|(a, b)| a == b).count();
+            let prefix = floor_boundary(left, prefix.min(limit));
+            let suffix = left.bytes().rev().zip(right.bytes().rev()).take_while(|(a, b)| a == b);
+            let mut suffix = suffix.count().min(limit - prefix);
+            while !left.is_char_boundary(left.len() - suffix) {
+                suffix -= 1;
+            }
+            (prefix, suffix)
+        }
+
+        let model = "line one\nzweite Zeile ü\n第三行\n".repeat(300);
+        let base = RopeText::new(model.clone());
+        let inserts = ["", "x", "ü", "Zeile", "\n", "行"];
+        let mut seed = 11;
+
+        for _ in 0..500 {
+            let a = floor_boundary(&model, next_random(&mut seed) % (model.len() + 1));
+            let b = floor_boundary(&model, (a + next_random(&mut seed) % 16).min(model.len()));
+            let insert = inserts[next_random(&mut seed) % inserts.len()];
+
+            let mut edited = base.clone();
+            edited.replace_range(TextRange::new(a as u32, b as u32), insert).unwrap();
+            let mut expected = model.clone();
+            expected.replace_range(a..b, insert);
+
+            assert_eq!(base.common_affixes(&edited), naive(&model, &expected));
+        }
+        assert_eq!(base.common_affixes(&base.clone()), (model.len(), 0));
+        assert_eq!(RopeText::new("aéb").common_affixes(&RopeText::new("aèb")), (1, 1));
+    }
+
+    #[test]
+    fn random_edits_match_string_model() {
+        let mut model = "line one\nzweite Zeile ü\n第三行\n".repeat(200);
+        let mut rope = RopeText::new(model.clone());
+        let large = "block text\n".repeat(300);
+        let inserts = ["", "", "", "x", "\n", "ü", "大段落\n", &large];
+        let mut seed = 7;
+
+        for _ in 0..2_000 {
+            let a = floor_boundary(&model, next_random(&mut seed) % (model.len() + 1));
+            let span = next_random(&mut seed) % 64;
+            let b = floor_boundary(&model, (a + span).min(model.len()));
+            let insert = inserts[next_random(&mut seed) % inserts.len()];
+
+            model.replace_range(a..b, insert);
+            rope.replace_range(TextRange::new(a as u32, b as u32), insert).unwrap();
+            assert_eq!(rope.len_utf8(), model.len());
+        }
+
+        assert_eq!(rope.to_string(), model);
+        assert_eq!(rope.len_chars(), model.chars().count());
+        assert_eq!(rope.len_lines(), model.matches('\n').count() + 1);
+    }
+
+    #[test]
+    fn slices_and_line_queries_span_chunks() {
+        let text = (0..500).map(|line| format!("line {line} ü\n")).collect::<String>();
+        let rope = RopeText::new(text.clone());
+
+        let range = TextRange::new(10, (text.len() - 10) as u32);
+        assert_eq!(rope.slice(range).unwrap(), &text[10..text.len() - 10]);
+        assert!(matches!(rope.slice(TextRange::new(0, 4)).unwrap(), Cow::Borrowed("line")));
+
+        let offset = text.find("line 321").unwrap();
+        assert_eq!(rope.byte_to_line(offset + 3), 321);
+        assert_eq!(rope.line_to_byte(321), offset);
+        let (start, end) = rope.line_bounds(offset + 3);
+        assert_eq!(&text[start..end], "line 321 ü");
+        assert_eq!(rope.line_bounds(text.len()), (text.len(), text.len()));
+
+        for offset in (0..text.len()).filter(|offset| text.is_char_boundary(*offset)) {
+            let start = text[..offset].rfind('\n').map_or(0, |index| index + 1);
+            let end = text[offset..].find('\n').map_or(text.len(), |index| offset + index);
+            assert_eq!(rope.line_bounds(offset), (start, end), "offset {offset}");
+        }
+    }
+
+    #[test]
+    fn finds_matches_across_chunks() {
+        let text = (0..500).map(|line| format!("line {line} ü\n")).collect::<String>();
+        let rope = RopeText::new(text.clone());
+        for needle in ["line", "ü\nline 1", "\n", "9 ü", "absent"] {
+            let expected = text.match_indices(needle).map(|(index, _)| index).collect::<Vec<_>>();
+            assert_eq!(rope.match_indices(needle), expected, "needle {needle:?}");
+        }
+        assert_eq!(RopeText::new("aaaa").match_indices("aa"), vec![0, 2]);
+        assert!(rope.match_indices("").is_empty());
+    }
+
+    #[test]
+    fn converts_between_offset_units() {
+        let text = (0..400).map(|line| format!("{line} a中🙂\n")).collect::<String>();
+        let rope = RopeText::new(text.clone());
+        assert_eq!(rope.metrics().utf16, text.encode_utf16().count());
+
+        let mut utf16 = 0;
+        let boundaries = text.char_indices().chain([(text.len(), '\0')]);
+        for (chars, (offset, ch)) in boundaries.enumerate() {
+            assert_eq!(rope.from_utf8(offset, OffsetUnit::Utf16), utf16, "offset {offset}");
+            assert_eq!(rope.from_utf8(offset, OffsetUnit::Char), chars);
+            assert_eq!(rope.to_utf8(utf16, OffsetUnit::Utf16), offset);
+            assert_eq!(rope.to_utf8(chars, OffsetUnit::Char), offset);
+            utf16 += ch.len_utf16();
+        }
+
+        let emoji = text.find('🙂').unwrap();
+        let emoji_utf16 = rope.from_utf8(emoji, OffsetUnit::Utf16);
+        assert_eq!(rope.to_utf8(emoji_utf16 + 1, OffsetUnit::Utf16), emoji);
+        assert_eq!(rope.from_utf8(emoji + 2, OffsetUnit::Utf16), emoji_utf16);
+        assert_eq!(rope.to_utf8(usize::MAX, OffsetUnit::Char), text.len());
+
+        let line = text.find("321 a").unwrap();
+        let position = LineCol { line: 321, column: 6 };
+        assert_eq!(rope.line_col(line + 8, OffsetUnit::Utf16), position);
+        assert_eq!(rope.line_col_to_utf8(position, OffsetUnit::Utf16), line + 8);
+        let past_end = LineCol { line: 321, column: 99 };
+        assert_eq!(rope.line_col_to_utf8(past_end, OffsetUnit::Char), line + 12);
+    }
+
+    #[test]
+    fn rejects_ranges_off_char_boundaries() {
+        let mut rope = RopeText::new("aü");
+        let error = rope.replace_range(TextRange::new(2, 2), "x").unwrap_err();
+        assert_eq!(error, EngineError::InvalidUtf8Range { start: 2, end: 2 });
+        assert!(rope.slice(TextRange::new(1, 2)).is_err());
+    }
+
+    #[test]
+    fn clones_share_structure_and_stay_independent() {
+        let original = RopeText::new("abc\n".repeat(10_000));
+        let mut edited = original.clone();
+        edited.replace_range(TextRange::new(0, 0), "start ").unwrap();
+
+        assert!(original.to_string().starts_with("abc"));
+        assert!(edited.to_string().starts_with("start abc"));
+        assert_eq!(edited.len_utf8(), original.len_utf8() + 6);
     }
 }
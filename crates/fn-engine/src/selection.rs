@@ -1,5 +1,7 @@
 use serde::{Deserialize, Serialize};
 
+use crate::change_set::{Assoc, ChangeSet};
+
 #[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq, Eq)]
 pub struct TextRange {
     pub start_utf8: u32,
@@ -20,6 +22,51 @@ impl TextRange {
     }
 }
 
+/// The unit a text offset or column is counted in. Engine offsets are UTF-8 bytes;
+/// the DOM counts UTF-16 code units.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum OffsetUnit {
+    Utf8,
+    Utf16,
+    /// Unicode scalar values.
+    Char,
+}
+
+impl OffsetUnit {
+    pub fn parse(input: &str) -> Option<Self> {
+        match input {
+            "utf8" => Some(Self::Utf8),
+            "utf16" => Some(Self::Utf16),
+            "char" => Some(Self::Char),
+            _ => None,
+        }
+    }
+
+    pub fn width(self, ch: char) -> usize {
+        match self {
+            Self::Utf8 => ch.len_utf8(),
+            Self::Utf16 => ch.len_utf16(),
+            Self::Char => 1,
+        }
+    }
+
+    pub fn count(self, text: &str) -> usize {
+        match self {
+            Self::Utf8 => text.len(),
+            Self::Utf16 => text.chars().map(char::len_utf16).sum(),
+            Self::Char => text.chars().count(),
+        }
+    }
+}
+
+/// Zero-based line and column, with the column counted in some [`OffsetUnit`].
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq, Eq)]
+pub struct LineCol {
+    pub line: u32,
+    pub column: u32,
+}
+
 #[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq, Eq)]
 pub struct Selection {
     pub anchor_utf8: u32,
@@ -46,4 +93,92 @@ impl Selection {
     pub fn clamp(self, max: u32) -> Self {
         Self { anchor_utf8: self.anchor_utf8.min(max), head_utf8: self.head_utf8.min(max) }
     }
+
+    /// Maps both ends through `changes`; ends at an insertion point move past it.
+    pub fn map(self, changes: &ChangeSet) -> Self {
+        Self {
+            anchor_utf8: changes.map_pos(self.anchor_utf8, Assoc::After),
+            head_utf8: changes.map_pos(self.head_utf8, Assoc::After),
+        }
+    }
+}
+
+/// The primary selection plus any secondary cursors or ranges. [`SelectionSet::new`]
+/// keeps the secondaries sorted and merges ranges that overlap.
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq, Eq)]
+pub struct SelectionSet {
+    pub primary: Selection,
+    pub secondary: Vec<Selection>,
+}
+
+impl SelectionSet {
+    pub fn single(selection: Selection) -> Self {
+        Self { primary: selection, secondary: Vec::new() }
+    }
+
+    pub fn new(primary: Selection, secondary: impl IntoIterator<Item = Selection>) -> Self {
+        let mut ranges: Vec<(Selection, bool)> = std::iter::once((primary, true))
+            .chain(secondary.into_iter().map(|selection| (selection, false)))
+            .collect();
+        ranges.sort_by_key(|(selection, _)| {
+            let range = selection.range();
+            (range.start_utf8, range.end_utf8)
+        });
+
+        let mut merged: Vec<(Selection, bool)> = Vec::with_capacity(ranges.len());
+        for (selection, is_primary) in ranges {
+            let Some((last, last_primary)) = merged.last_mut() else {
+                merged.push((selection, is_primary));
+                continue;
+            };
+            let (previous, next) = (last.range(), selection.range());
+            let overlaps = next.start_utf8 < previous.end_utf8
+                || (next.start_utf8 == previous.end_utf8
+                    && (next.is_empty() || previous.is_empty()));
+            if !overlaps {
+                merged.push((selection, is_primary));
+                continue;
+            }
+
+            let end = previous.end_utf8.max(next.end_utf8);
+            let backward = if is_primary { selection } else { *last };
+            *last = if backward.anchor_utf8 > backward.head_utf8 && (*last_primary || is_primary) {
+                Selection { anchor_utf8: end, head_utf8: previous.start_utf8 }
+            } else {
+                Selection { anchor_utf8: previous.start_utf8, head_utf8: end }
+            };
+            *last_primary |= is_primary;
+        }
+
+        let mut set = Self::default();
+        for (selection, is_primary) in merged {
+            if is_primary {
+                set.primary = selection;
+            } else {
+                set.secondary.push(selection);
+            }
+        }
+        set
+    }
+
+    /// Every selection in document order, paired with whether it is the primary.
+    pub fn iter(&self) -> impl Iterator<Item = (Selection, bool)> + '_ {
+        let primary_start = self.primary.range().start_utf8;
+        let split = self
+            .secondary
+            .partition_point(|selection| selection.range().start_utf8 < primary_start);
+        let (before, after) = self.secondary.split_at(split);
+        before
+            .iter()
+            .map(|selection| (*selection, false))
+            .chain(std::iter::once((self.primary, true)))
+            .chain(after.iter().map(|selection| (*selection, false)))
+    }
+
+    pub fn clamp(&self, max: u32) -> Self {
+        Self::new(
+            self.primary.clamp(max),
+            self.secondary.iter().map(|selection| selection.clamp(max)),
+        )
+    }
 }
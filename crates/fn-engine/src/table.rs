@@ -0,0 +1,263 @@
+use std::borrow::Cow;
+
+use unicode_width::UnicodeWidthStr;
+
+use crate::{
+    ast::{BlockKind, BlockNode, TableAlignment},
+    parser::split_table_cells,
+    rope::RopeText,
+    selection::TextRange,
+};
+
+const MIN_COLUMN_WIDTH: usize = 3;
+
+/// Editable grid view of a pipe table. Row 0 is the header row.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct TableGrid {
+    pub alignments: Vec<TableAlignment>,
+    /// Cells past the delimiter row's columns are kept as written, so rewriting the
+    /// table does not drop them.
+    pub rows: Vec<Vec<String>>,
+    /// What precedes each row on its line: indentation and the markers of the quotes
+    /// and list items around the table.
+    prefixes: Vec<String>,
+    /// The same for the delimiter row, which new rows copy.
+    delimiter_prefix: String,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct CellPosition {
+    pub row: usize,
+    pub column: usize,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct RenderedTable {
+    pub text: String,
+    /// Content range of every cell, relative to the start of `text`.
+    pub cells: Vec<Vec<TextRange>>,
+}
+
+impl TableGrid {
+    pub fn from_block(block: &BlockNode, text: &RopeText) -> Option<Self> {
+        let BlockKind::Table { alignments, header, rows } = &block.kind else {
+            return None;
+        };
+
+        let mut grid_rows = Vec::with_capacity(rows.len() + 1);
+        let mut prefixes = Vec::with_capacity(rows.len() + 1);
+        for row in std::iter::once(header).chain(rows) {
+            let source = text.slice(row.range).ok()?;
+            let indent = source.len() - source.trim_start().len();
+            let line_start = text.line_bounds(row.range.start_utf8 as usize).0;
+            let content_start = row.range.start_utf8 as usize + indent;
+            prefixes.push(text.slice(range(line_start, content_start)).ok()?.into_owned());
+
+            let mut cells = split_table_cells(&source)
+                .into_iter()
+                .map(|(start, end)| source[start..end].to_string())
+                .collect::<Vec<_>>();
+            if cells.len() < alignments.len() {
+                cells.resize(alignments.len(), String::new());
+            }
+            grid_rows.push(cells);
+        }
+
+        let (delimiter_start, delimiter_end) = text.line_bounds(header.range.end_utf8 as usize + 1);
+        let delimiter = text.slice(range(delimiter_start, delimiter_end)).ok()?;
+        let prefix_len = delimiter.len() - delimiter.trim_start_matches([' ', '\t', '>']).len();
+
+        Some(Self {
+            alignments: alignments.clone(),
+            rows: grid_rows,
+            prefixes,
+            delimiter_prefix: delimiter[..prefix_len].to_string(),
+        })
+    }
+
+    pub fn column_count(&self) -> usize {
+        self.alignments.len()
+    }
+
+    pub fn insert_row(&mut self, at: usize) {
+        let at = at.clamp(1, self.rows.len());
+        self.rows.insert(at, vec![String::new(); self.column_count()]);
+        self.prefixes.insert(at, self.delimiter_prefix.clone());
+    }
+
+    pub fn remove_row(&mut self, row: usize) {
+        if row < self.rows.len() {
+            self.rows.remove(row);
+            // The first line keeps its prefix, which can hold a list marker.
+            self.prefixes.remove(row.max(1).min(self.prefixes.len() - 1));
+        }
+    }
+
+    pub fn insert_column(&mut self, at: usize) {
+        let at = at.min(self.column_count());
+        self.alignments.insert(at, TableAlignment::None);
+        for row in &mut self.rows {
+            row.insert(at, String::new());
+        }
+    }
+
+    pub fn remove_column(&mut self, column: usize) {
+        if column >= self.column_count() {
+            return;
+        }
+        self.alignments.remove(column);
+        for row in &mut self.rows {
+            row.remove(column);
+        }
+    }
+
+    /// Renders the grid with every pipe column lined up by display width, each line
+    /// behind its original prefix. Cells past the last column follow unpadded.
+    pub fn render(&self) -> RenderedTable {
+        let widths = (0..self.column_count())
+            .map(|column| {
+                self.rows
+                    .iter()
+                    .map(|row| row.get(column).map_or(0, |cell| cell.width()))
+                    .max()
+                    .unwrap_or_default()
+                    .max(MIN_COLUMN_WIDTH)
+            })
+            .collect::<Vec<_>>();
+
+        let mut text = String::new();
+        let mut cells = Vec::with_capacity(self.rows.len());
+
+        for (row_index, row) in self.rows.iter().enumerate() {
+            if row_index > 0 {
+                text.push('\n');
+            }
+
+            let mut ranges = Vec::with_capacity(row.len());
+            text.push_str(&self.prefixes[row_index]);
+            text.push('|');
+            for (column, cell) in row.iter().enumerate() {
+                let padding = widths.get(column).map_or(0, |width| width - cell.width());
+                let alignment =
+                    self.alignments.get(column).copied().unwrap_or(TableAlignment::None);
+                let (left, right) = match alignment {
+                    TableAlignment::Right => (padding, 0),
+                    TableAlignment::Center => (padding / 2, padding - padding / 2),
+                    TableAlignment::None | TableAlignment::Left => (0, padding),
+                };
+                text.push(' ');
+                text.push_str(&" ".repeat(left));
+                let start = text.len();
+                text.push_str(cell);
+                ranges.push(TextRange::new(start as u32, text.len() as u32));
+                text.push_str(&" ".repeat(right));
+                text.push_str(" |");
+            }
+            cells.push(ranges);
+
+            if row_index == 0 {
+                text.push('\n');
+                text.push_str(&self.delimiter_prefix);
+                text.push_str(&self.render_delimiter(&widths));
+            }
+        }
+
+        RenderedTable { text, cells }
+    }
+
+    fn render_delimiter(&self, widths: &[usize]) -> String {
+        let mut line = String::from("|");
+        for (alignment, width) in self.alignments.iter().zip(widths) {
+            let cell = match alignment {
+                TableAlignment::None => "-".repeat(*width),
+                TableAlignment::Left => format!(":{}", "-".repeat(width - 1)),
+                TableAlignment::Right => format!("{}:", "-".repeat(width - 1)),
+                TableAlignment::Center => format!(":{}:", "-".repeat(width - 2)),
+            };
+            line.push(' ');
+            line.push_str(&cell);
+            line.push_str(" |");
+        }
+        line
+    }
+}
+
+/// Returns the innermost table block whose rows contain `offset`, looking inside
+/// quotes, lists and footnotes.
+pub(crate) fn table_at<'a>(
+    blocks: &'a [BlockNode],
+    text: &RopeText,
+    offset: u32,
+) -> Option<&'a BlockNode> {
+    blocks
+        .iter()
+        .filter(|block| block.range.start_utf8 <= offset && offset <= block.range.end_utf8)
+        .find_map(|block| match &block.kind {
+            BlockKind::Table { .. } => (offset <= table_text_end(block, text)).then_some(block),
+            BlockKind::Blockquote { children }
+            | BlockKind::Callout { children, .. }
+            | BlockKind::FootnoteDefinition { children, .. } => table_at(children, text, offset),
+            BlockKind::BulletList { items } | BlockKind::OrderedList { items, .. } => {
+                items.iter().find_map(|item| table_at(&item.children, text, offset))
+            }
+            _ => None,
+        })
+}
+
+/// Maps a byte offset inside `block` to the grid cell it falls in. Offsets on the
+/// delimiter row resolve to the header row, offsets inside a character to that
+/// character, and offsets past the last column to the last column.
+pub(crate) fn cell_at(block: &BlockNode, text: &RopeText, offset: u32) -> CellPosition {
+    let BlockKind::Table { alignments, header, rows } = &block.kind else {
+        return CellPosition { row: 0, column: 0 };
+    };
+
+    let mut offset = (offset as usize).min(text.len_utf8());
+    while !text.is_char_boundary(offset) {
+        offset -= 1;
+    }
+    let row = rows
+        .iter()
+        .rposition(|row| row.range.start_utf8 as usize <= offset)
+        .map(|index| index + 1)
+        .unwrap_or(0);
+
+    let row_start = if row == 0 { header } else { &rows[row - 1] }.range.start_utf8 as usize;
+    let line_start = text.line_bounds(offset).0.max(row_start.min(offset));
+    let before = text.slice(range(line_start, offset)).unwrap_or(Cow::Borrowed(""));
+    let bytes = before.as_bytes();
+    let mut pipes = 0_usize;
+    let mut cursor = 0;
+    while cursor < bytes.len() {
+        match bytes[cursor] {
+            b'\\' if bytes.get(cursor + 1) == Some(&b'|') => cursor += 2,
+            b'|' => {
+                pipes += 1;
+                cursor += 1;
+            }
+            _ => cursor += 1,
+        }
+    }
+    // The delimiter row is read from the start of its line, behind any quote markers.
+    let leading_pipe = usize::from(before.trim_start_matches([' ', '\t', '>']).starts_with('|'));
+    let column = pipes.saturating_sub(leading_pipe).min(alignments.len().saturating_sub(1));
+
+    CellPosition { row, column }
+}
+
+/// End of the table source excluding the trailing newline, so re-rendered text can
+/// replace it without touching the following block.
+pub(crate) fn table_text_end(block: &BlockNode, text: &RopeText) -> u32 {
+    let end = block.range.end_utf8;
+    let ends_with_newline = end > block.range.start_utf8
+        && text.slice(TextRange::new(end - 1, end)).is_ok_and(|last| last == "\n");
+    if ends_with_newline {
+        end - 1
+    } else {
+        end
+    }
+}
+
+fn range(start: usize, end: usize) -> TextRange {
+    TextRange::new(start as u32, end as u32)
+}
@@ -1,16 +1,30 @@
 use serde::{Deserialize, Serialize};
 
-use crate::selection::{Selection, TextRange};
+use crate::{
+    ast::TableAlignment,
+    change_set::ChangeSet,
+    motion::{Direction, MotionUnit},
+    selection::{Selection, TextRange},
+};
 
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
 pub struct EngineTransaction {
     pub kind: TransactionKind,
+    /// Every replacement the transaction made, in the coordinates of the text before it.
+    pub changes: ChangeSet,
     pub selection_before: Selection,
     pub selection_after: Selection,
     pub revision_before: u64,
     pub revision_after: u64,
 }
 
+/// Replacement of `range` (UTF-8 offsets into the old text) with `insert`.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct TextEdit {
+    pub range: TextRange,
+    pub insert: String,
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
 pub enum TransactionKind {
     ReplaceText { range: TextRange, insert: String },
@@ -22,11 +36,56 @@ pub enum EngineCommand {
     ToggleStrong,
     ToggleEmphasis,
     ToggleInlineCode,
-    ToggleHeading { level: u8 },
+    ToggleStrikethrough,
+    ToggleHighlight,
+    ToggleHeading {
+        level: u8,
+    },
     ToggleBlockquote,
+    /// Steps the callout at the cursor to the next kind, or starts a `[!NOTE]`
+    /// callout on the blockquote or the lines at the cursor.
+    CycleCallout,
     ToggleBulletList,
     ToggleOrderedList,
-    InsertFence { language: Option<String> },
+    ToggleTask,
+    IndentListItem,
+    OutdentListItem,
+    InsertFence {
+        language: Option<String>,
+    },
+    InsertTableRowAbove,
+    InsertTableRowBelow,
+    InsertTableColumnLeft,
+    InsertTableColumnRight,
+    DeleteTableRow,
+    DeleteTableColumn,
+    SetTableAlignment {
+        alignment: TableAlignment,
+    },
+    NextTableCell,
+    PreviousTableCell,
+    /// Deletes the selection, or one unit before the cursor when it is collapsed.
+    DeleteBackward {
+        unit: MotionUnit,
+    },
+    DeleteForward {
+        unit: MotionUnit,
+    },
+    /// Moves the selection head by one unit, keeping the anchor.
+    ExtendSelection {
+        unit: MotionUnit,
+        direction: Direction,
+    },
+    /// Selects the word at the cursor, then adds each further occurrence as a cursor.
+    AddNextOccurrence,
+    SelectAllOccurrences,
+    /// Moves the cursor from a footnote reference into its definition.
+    GoToFootnoteDefinition,
+    /// Moves the cursor from a footnote definition to its first reference.
+    GoToFootnoteReference,
+    /// Inserts a reference with the next free numeric label after the selection and
+    /// appends its definition to the document.
+    InsertFootnote,
     Undo,
     Redo,
 }
@@ -37,15 +96,60 @@ impl EngineCommand {
             "toggle_strong" => Some(Self::ToggleStrong),
             "toggle_emphasis" => Some(Self::ToggleEmphasis),
             "toggle_inline_code" => Some(Self::ToggleInlineCode),
+            "toggle_strikethrough" => Some(Self::ToggleStrikethrough),
+            "toggle_highlight" => Some(Self::ToggleHighlight),
             "toggle_blockquote" => Some(Self::ToggleBlockquote),
+            "cycle_callout" => Some(Self::CycleCallout),
             "toggle_bullet_list" => Some(Self::ToggleBulletList),
             "toggle_ordered_list" => Some(Self::ToggleOrderedList),
+            "toggle_task" => Some(Self::ToggleTask),
+            "indent_list_item" => Some(Self::IndentListItem),
+            "outdent_list_item" => Some(Self::OutdentListItem),
+            "table_insert_row_above" => Some(Self::InsertTableRowAbove),
+            "table_insert_row_below" => Some(Self::InsertTableRowBelow),
+            "table_insert_column_left" => Some(Self::InsertTableColumnLeft),
+            "table_insert_column_right" => Some(Self::InsertTableColumnRight),
+            "table_delete_row" => Some(Self::DeleteTableRow),
+            "table_delete_column" => Some(Self::DeleteTableColumn),
+            "table_next_cell" => Some(Self::NextTableCell),
+            "table_previous_cell" => Some(Self::PreviousTableCell),
+            "add_next_occurrence" => Some(Self::AddNextOccurrence),
+            "select_all_occurrences" => Some(Self::SelectAllOccurrences),
+            "go_to_footnote_definition" => Some(Self::GoToFootnoteDefinition),
+            "go_to_footnote_reference" => Some(Self::GoToFootnoteReference),
+            "insert_footnote" => Some(Self::InsertFootnote),
             "undo" => Some(Self::Undo),
             "redo" => Some(Self::Redo),
             _ if input.starts_with("toggle_heading:") => {
                 let level = input.split(':').nth(1)?.parse::<u8>().ok()?;
                 Some(Self::ToggleHeading { level })
             }
+            _ if input.starts_with("delete_backward:") => {
+                let unit = MotionUnit::parse(input.split(':').nth(1)?)?;
+                Some(Self::DeleteBackward { unit })
+            }
+            _ if input.starts_with("delete_forward:") => {
+                let unit = MotionUnit::parse(input.split(':').nth(1)?)?;
+                Some(Self::DeleteForward { unit })
+            }
+            _ if input.starts_with("extend_backward:") => {
+                let unit = MotionUnit::parse(input.split(':').nth(1)?)?;
+                Some(Self::ExtendSelection { unit, direction: Direction::Backward })
+            }
+            _ if input.starts_with("extend_forward:") => {
+                let unit = MotionUnit::parse(input.split(':').nth(1)?)?;
+                Some(Self::ExtendSelection { unit, direction: Direction::Forward })
+            }
+            _ if input.starts_with("table_align:") => {
+                let alignment = match input.split(':').nth(1)? {
+                    "none" => TableAlignment::None,
+                    "left" => TableAlignment::Left,
+                    "center" => TableAlignment::Center,
+                    "right" => TableAlignment::Right,
+                    _ => return None,
+                };
+                Some(Self::SetTableAlignment { alignment })
+            }
             _ if input.starts_with("insert_fence") => {
                 let language = input.split_once(':').and_then(|(_, value)| {
                     let trimmed = value.trim();
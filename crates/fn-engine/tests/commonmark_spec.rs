@@ -0,0 +1,366 @@
+//! Conformance against the CommonMark spec examples. `commonmark/spec.json` holds
+//! the examples of spec version 0.31.2 (CC-BY-SA 4.0, https://spec.commonmark.org);
+//! each example's markdown is parsed with the core registry (see [`commonmark`]),
+//! rendered to HTML the way the reference implementation renders it, and compared
+//! with the spec's HTML.
+
+use fn_engine::{
+    ast::{BlockKind, BlockNode, InlineKind, InlineNode, ListItemNode},
+    parser::parse_document,
+    registry::EnginePluginRegistry,
+};
+use serde::Deserialize;
+
+/// Sections the parser is expected to pass, apart from [`KNOWN_FAILURES`].
+const SECTIONS: &[&str] = &[
+    "Backslash escapes",
+    "Entity and numeric character references",
+    "Precedence",
+    "Thematic breaks",
+    "ATX headings",
+    "Setext headings",
+    "Indented code blocks",
+    "Fenced code blocks",
+    "HTML blocks",
+    "Paragraphs",
+    "Blank lines",
+    "Block quotes",
+    "Code spans",
+    "Emphasis and strong emphasis",
+    "Hard line breaks",
+    "Soft line breaks",
+    "Textual content",
+];
+
+/// Examples in [`SECTIONS`] that need syntax the parser does not handle yet.
+const KNOWN_FAILURES: &[(u32, &str)] = &[
+    (93, "lazy blockquote continuation"),
+    (148, "inline raw HTML"),
+    (168, "inline raw HTML"),
+    (187, "inline raw HTML"),
+    (344, "inline raw HTML"),
+    (475, "inline raw HTML"),
+    (476, "inline raw HTML"),
+    (477, "inline raw HTML"),
+    (642, "inline raw HTML"),
+    (643, "inline raw HTML"),
+];
+
+#[derive(Debug, Deserialize)]
+struct Example {
+    markdown: String,
+    html: String,
+    example: u32,
+    section: String,
+}
+
+/// The core registry without the extensions that are not CommonMark: front matter
+/// would take the first lines of a document starting with `---`, math the text
+/// between dollar signs, footnotes link labels starting with `^`, callouts quotes
+/// starting with `[!`, wiki-links nested brackets, and the strikethrough, highlight
+/// and superscript marks literal `~`, `=` and `^`.
+fn commonmark() -> EnginePluginRegistry {
+    let mut registry = EnginePluginRegistry::core_markdown();
+    registry.block_syntax_plugins.retain(|plugin| {
+        !matches!(plugin.name(), "front-matter" | "math-block" | "callout" | "footnote-definition")
+    });
+    registry
+        .inline_syntax_plugins
+        .retain(|plugin| !matches!(plugin.name(), "math" | "footnote-reference" | "wiki-link"));
+    registry.delimiter_syntax_plugins.retain(|plugin| plugin.name() == "emphasis");
+    registry
+}
+
+fn examples() -> Vec<Example> {
+    serde_json::from_str(include_str!("commonmark/spec.json")).expect("spec examples parse")
+}
+
+#[test]
+fn supported_sections_match_the_spec() {
+    let registry = commonmark();
+    let mut unexpected = Vec::new();
+    let mut fixed = Vec::new();
+
+    for example in examples().iter().filter(|example| SECTIONS.contains(&example.section.as_str()))
+    {
+        let html = render_html(&example.markdown, &parse_document(&example.markdown, &registry));
+        let known = KNOWN_FAILURES.iter().any(|(number, _)| *number == example.example);
+        match (html == example.html, known) {
+            (false, false) => unexpected.push(format!(
+                "example {} ({}):\n{:?}\nexpected {:?}\n     got {:?}",
+                example.example, example.section, example.markdown, example.html, html
+            )),
+            (true, true) => fixed.push(example.example),
+            _ => {}
+        }
+    }
+
+    assert!(
+        unexpected.is_empty(),
+        "{} failing examples:\n\n{}",
+        unexpected.len(),
+        unexpected.join("\n\n")
+    );
+    assert!(fixed.is_empty(), "examples now pass, remove them from KNOWN_FAILURES: {fixed:?}");
+}
+
+/// Examples passed per section, out of the section's examples, in spec order.
+/// Sections outside [`SECTIONS`] need block or inline syntax the parser does not
+/// handle completely; the counts record how far along each one is.
+const SECTION_PASSES: &[(&str, usize, usize)] = &[
+    ("Tabs", 6, 11),
+    ("Backslash escapes", 13, 13),
+    ("Entity and numeric character references", 17, 17),
+    ("Precedence", 1, 1),
+    ("Thematic breaks", 19, 19),
+    ("ATX headings", 18, 18),
+    ("Setext headings", 26, 27),
+    ("Indented code blocks", 12, 12),
+    ("Fenced code blocks", 29, 29),
+    ("HTML blocks", 41, 44),
+    ("Link reference definitions", 19, 27),
+    ("Paragraphs", 8, 8),
+    ("Blank lines", 1, 1),
+    ("Block quotes", 25, 25),
+    ("List items", 39, 48),
+    ("Lists", 15, 26),
+    ("Inlines", 1, 1),
+    ("Code spans", 21, 22),
+    ("Emphasis and strong emphasis", 129, 132),
+    ("Links", 81, 90),
+    ("Images", 22, 22),
+    ("Autolinks", 19, 19),
+    ("Raw HTML", 7, 20),
+    ("Hard line breaks", 13, 15),
+    ("Soft line breaks", 2, 2),
+    ("Textual content", 3, 3),
+];
+
+#[test]
+fn section_pass_rates_are_tracked() {
+    let registry = commonmark();
+    let mut passes: Vec<(String, usize, usize)> = Vec::new();
+    for example in examples() {
+        let html = render_html(&example.markdown, &parse_document(&example.markdown, &registry));
+        if passes.last().map_or(true, |(section, ..)| *section != example.section) {
+            passes.push((example.section.clone(), 0, 0));
+        }
+        let (_, passed, total) = passes.last_mut().expect("section was pushed");
+        *passed += usize::from(html == example.html);
+        *total += 1;
+    }
+
+    let tracked = SECTION_PASSES
+        .iter()
+        .map(|(section, passed, total)| (section.to_string(), *passed, *total))
+        .collect::<Vec<_>>();
+    let report = passes
+        .iter()
+        .map(|(section, passed, total)| {
+            format!("    ({section:?}, {passed}, {total}), // {}%", passed * 100 / total)
+        })
+        .collect::<Vec<_>>();
+    assert!(
+        passes == tracked,
+        "spec pass counts changed, update SECTION_PASSES:\n{}",
+        report.join("\n")
+    );
+}
+
+fn render_html(markdown: &str, blocks: &[BlockNode]) -> String {
+    let mut out = String::new();
+    render_blocks(markdown, blocks, &mut out);
+    out
+}
+
+fn render_blocks(markdown: &str, blocks: &[BlockNode], out: &mut String) {
+    for block in blocks {
+        render_block(markdown, block, false, out);
+    }
+}
+
+/// Renders `block`; in a tight list item a paragraph is written without `<p>`.
+fn render_block(markdown: &str, block: &BlockNode, tight: bool, out: &mut String) {
+    match &block.kind {
+        BlockKind::Paragraph { inlines } if inlines.is_empty() && block.range.is_empty() => {}
+        BlockKind::Paragraph { inlines } if tight => render_inlines(inlines, out),
+        BlockKind::Paragraph { inlines } => {
+            out.push_str("<p>");
+            render_inlines(inlines, out);
+            out.push_str("</p>\n");
+        }
+        BlockKind::Heading { level, inlines } => {
+            out.push_str(&format!("<h{level}>"));
+            render_inlines(inlines, out);
+            out.push_str(&format!("</h{level}>\n"));
+        }
+        BlockKind::Blockquote { children } => {
+            out.push_str("<blockquote>\n");
+            render_blocks(markdown, children, out);
+            out.push_str("</blockquote>\n");
+        }
+        BlockKind::BulletList { items } => {
+            out.push_str("<ul>\n");
+            render_items(markdown, items, out);
+            out.push_str("</ul>\n");
+        }
+        BlockKind::OrderedList { start, items } => {
+            match start {
+                1 => out.push_str("<ol>\n"),
+                start => out.push_str(&format!("<ol start=\"{start}\">\n")),
+            }
+            render_items(markdown, items, out);
+            out.push_str("</ol>\n");
+        }
+        BlockKind::FencedCode { language, code, .. } => {
+            match language {
+                Some(language) => {
+                    out.push_str(&format!("<pre><code class=\"language-{}\">", escape(language)))
+                }
+                None => out.push_str("<pre><code>"),
+            }
+            out.push_str(&escape(code));
+            out.push_str("</code></pre>\n");
+        }
+        BlockKind::IndentedCode { code, .. } => {
+            out.push_str(&format!("<pre><code>{}</code></pre>\n", escape(code)));
+        }
+        BlockKind::MathBlock { .. } => unreachable!("math is not registered"),
+        BlockKind::Callout { .. } => unreachable!("callouts are not registered"),
+        BlockKind::FootnoteDefinition { .. } => unreachable!("footnotes are not registered"),
+        BlockKind::ThematicBreak => out.push_str("<hr />\n"),
+        BlockKind::HtmlBlock { html } => {
+            out.push_str(html);
+            out.push('\n');
+        }
+        BlockKind::FrontMatter { .. }
+        | BlockKind::Table { .. }
+        | BlockKind::LinkDefinition { .. }
+        | BlockKind::Custom { .. } => {}
+    }
+}
+
+/// A list is tight when no item holds a blank line between its blocks.
+fn render_items(markdown: &str, items: &[ListItemNode], out: &mut String) {
+    let blank = |block: &BlockNode| {
+        matches!(&block.kind, BlockKind::Paragraph { inlines } if inlines.is_empty())
+            && block.range.is_empty()
+    };
+    let tight = !items.iter().any(|item| {
+        let content = item.children.iter().rposition(|child| !blank(child)).unwrap_or(0);
+        item.children[..content].iter().any(blank)
+    });
+
+    for item in items {
+        out.push_str("<li>");
+        let children = item.children.iter().filter(|child| !blank(child)).collect::<Vec<_>>();
+        for child in children {
+            let inline = tight && matches!(child.kind, BlockKind::Paragraph { .. });
+            if !inline && !out.ends_with('\n') {
+                out.push('\n');
+            }
+            render_block(markdown, child, tight, out);
+        }
+        out.push_str("</li>\n");
+    }
+}
+
+fn render_inlines(inlines: &[InlineNode], out: &mut String) {
+    for inline in inlines {
+        match &inline.kind {
+            InlineKind::Text { value } | InlineKind::Custom { value, .. } => {
+                out.push_str(&escape(value))
+            }
+            InlineKind::Strong { children } => {
+                out.push_str("<strong>");
+                render_inlines(children, out);
+                out.push_str("</strong>");
+            }
+            InlineKind::Emphasis { children } => {
+                out.push_str("<em>");
+                render_inlines(children, out);
+                out.push_str("</em>");
+            }
+            InlineKind::InlineCode { value } => {
+                out.push_str(&format!("<code>{}</code>", escape(value)));
+            }
+            InlineKind::Math { .. } => unreachable!("math is not registered"),
+            InlineKind::FootnoteReference { .. } => {
+                unreachable!("footnotes are not registered")
+            }
+            InlineKind::WikiLink { .. } => unreachable!("wiki-links are not registered"),
+            InlineKind::Strikethrough { .. }
+            | InlineKind::Highlight { .. }
+            | InlineKind::Subscript { .. }
+            | InlineKind::Superscript { .. } => unreachable!("marks are not registered"),
+            InlineKind::HardBreak => out.push_str("<br />\n"),
+            InlineKind::Link { href, title, children } => {
+                out.push_str(&format!("<a href=\"{}\"", escape(&normalize_url(href))));
+                if let Some(title) = title {
+                    out.push_str(&format!(" title=\"{}\"", escape(title)));
+                }
+                out.push('>');
+                render_inlines(children, out);
+                out.push_str("</a>");
+            }
+            InlineKind::Image { src, title, children } => {
+                let mut alt = String::new();
+                plain_text(children, &mut alt);
+                out.push_str(&format!(
+                    "<img src=\"{}\" alt=\"{}\"",
+                    escape(&normalize_url(src)),
+                    escape(&alt)
+                ));
+                if let Some(title) = title {
+                    out.push_str(&format!(" title=\"{}\"", escape(title)));
+                }
+                out.push_str(" />");
+            }
+        }
+    }
+}
+
+fn plain_text(inlines: &[InlineNode], out: &mut String) {
+    for inline in inlines {
+        match &inline.kind {
+            InlineKind::Text { value }
+            | InlineKind::InlineCode { value }
+            | InlineKind::Math { tex: value, .. }
+            | InlineKind::FootnoteReference { label: value, .. }
+            | InlineKind::Custom { value, .. } => out.push_str(value),
+            InlineKind::WikiLink { .. } => unreachable!("wiki-links are not registered"),
+            InlineKind::HardBreak => out.push('\n'),
+            InlineKind::Strong { children }
+            | InlineKind::Emphasis { children }
+            | InlineKind::Strikethrough { children }
+            | InlineKind::Highlight { children }
+            | InlineKind::Subscript { children }
+            | InlineKind::Superscript { children }
+            | InlineKind::Link { children, .. }
+            | InlineKind::Image { children, .. } => plain_text(children, out),
+        }
+    }
+}
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;").replace('"', "&quot;")
+}
+
+/// Percent-encodes what may not appear in a URL, keeping existing escapes.
+fn normalize_url(url: &str) -> String {
+    let bytes = url.as_bytes();
+    let mut out = String::new();
+    for (index, byte) in bytes.iter().enumerate() {
+        let escape_sequence = *byte == b'%'
+            && bytes
+                .get(index + 1..index + 3)
+                .is_some_and(|hex| hex.iter().all(u8::is_ascii_hexdigit));
+        if byte.is_ascii_alphanumeric() || b"-_.!~*'();/?:@&=+$,#".contains(byte) || escape_sequence
+        {
+            out.push(*byte as char);
+        } else {
+            out.push_str(&format!("%{byte:02X}"));
+        }
+    }
+    out
+}
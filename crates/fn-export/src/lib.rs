@@ -1,3 +1,5 @@
+mod mathml;
+
 use std::fs;
 use std::io::Write;
 use std::path::{Path, PathBuf};
@@ -5,8 +7,14 @@ use std::process::Command;
 
 use comrak::{markdown_to_html, Options};
 use fn_core::ExportResponse;
+use fn_engine::{
+    ast::{CalloutKind, MetadataValue},
+    parser::front_matter::parse_front_matter,
+};
 use thiserror::Error;
 
+use mathml::tex_to_mathml;
+
 #[derive(Debug, Error)]
 pub enum ExportError {
     #[error("invalid path: {0}")]
@@ -19,7 +27,7 @@ pub enum ExportError {
 
 pub fn export_html(path: &str, content: &str) -> Result<ExportResponse, ExportError> {
     let target = normalize_target(path)?;
-    let html = markdown_to_html(content, &Options::default());
+    let html = render_document(&target, content);
     let bytes_written = atomic_write(&target, html.as_bytes())?;
 
     Ok(ExportResponse { output_path: target.display().to_string(), bytes_written })
@@ -27,7 +35,7 @@ pub fn export_html(path: &str, content: &str) -> Result<ExportResponse, ExportEr
 
 pub fn export_pdf(path: &str, content: &str) -> Result<ExportResponse, ExportError> {
     let target = normalize_target(path)?;
-    let html = markdown_to_html(content, &Options::default());
+    let html = render_document(&target, content);
 
     let tmp_html = target.with_extension("ferrumnote-export.html");
     atomic_write(&tmp_html, html.as_bytes())?;
@@ -53,6 +61,144 @@ pub fn export_pdf(path: &str, content: &str) -> Result<ExportResponse, ExportErr
     }
 }
 
+/// A standalone HTML document for `content`, titled by the `title` in its front
+/// matter or else by the target's file name. The front matter is not rendered,
+/// `$` and `$$` math becomes MathML, footnotes are numbered in the order they are
+/// referenced and link back to their references, and `> [!NOTE]` style callouts
+/// become styled boxes.
+fn render_document(target: &Path, content: &str) -> String {
+    let front_matter = parse_front_matter(content);
+    let body =
+        front_matter.as_ref().map_or(content, |front_matter| &content[front_matter.body_start..]);
+    let title = front_matter
+        .as_ref()
+        .and_then(|front_matter| match front_matter.metadata.get("title") {
+            Some(MetadataValue::Text(title)) if !title.trim().is_empty() => Some(title.clone()),
+            _ => None,
+        })
+        .or_else(|| target.file_stem().map(|stem| stem.to_string_lossy().into_owned()))
+        .unwrap_or_default();
+
+    let mut options = Options::default();
+    options.extension.math_dollars = true;
+    options.extension.footnotes = true;
+    format!(
+        "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n<title>{}</title>\n\
+         <style>\n{CALLOUT_STYLE}</style>\n</head>\n<body>\n{}</body>\n</html>\n",
+        escape_html(&title),
+        callouts_to_html(&math_to_mathml(&markdown_to_html(body, &options)))
+    )
+}
+
+/// Replaces the `<span data-math-style="…">` elements comrak renders math as with
+/// MathML.
+fn math_to_mathml(html: &str) -> String {
+    const OPEN: &str = "<span data-math-style=\"";
+    let mut out = String::with_capacity(html.len());
+    let mut rest = html;
+    while let Some(start) = rest.find(OPEN) {
+        let after = &rest[start + OPEN.len()..];
+        let parsed = after.split_once("\">").and_then(|(style, after)| {
+            let (tex, after) = after.split_once("</span>")?;
+            Some((style == "display", tex, after))
+        });
+        let Some((display, tex, after)) = parsed else {
+            break;
+        };
+        out.push_str(&rest[..start]);
+        out.push_str(&tex_to_mathml(unescape_html(tex).trim(), display));
+        rest = after;
+    }
+    out.push_str(rest);
+    out
+}
+
+/// Colors callouts by kind, as GitHub does.
+const CALLOUT_STYLE: &str = "\
+.callout { margin: 1em 0; padding: 0.5em 1em; border-left: 0.25em solid var(--callout-color); }
+.callout > :last-child { margin-bottom: 0; }
+.callout-title { margin-top: 0; font-weight: 600; color: var(--callout-color); }
+.callout-note { --callout-color: #0969da; }
+.callout-tip { --callout-color: #1a7f37; }
+.callout-important { --callout-color: #8250df; }
+.callout-warning { --callout-color: #9a6700; }
+.callout-caution { --callout-color: #cf222e; }
+";
+
+/// Turns the blockquotes comrak renders for callouts, whose first paragraph starts
+/// with a `[!KIND]` line, into `<div class="callout callout-kind">` elements headed
+/// by the marker's title or else the kind's name.
+fn callouts_to_html(html: &str) -> String {
+    const OPEN: &str = "<blockquote>\n";
+    const CLOSE: &str = "</blockquote>\n";
+    let mut out = String::with_capacity(html.len());
+    // Whether each blockquote still open became a callout.
+    let mut open_quotes = Vec::new();
+    let mut rest = html;
+    loop {
+        match (rest.find(OPEN), rest.find(CLOSE)) {
+            (Some(open), close) if close.map_or(true, |close| open < close) => {
+                out.push_str(&rest[..open]);
+                rest = &rest[open + OPEN.len()..];
+                let callout = callout_open(rest);
+                open_quotes.push(callout.is_some());
+                match callout {
+                    Some((head, content)) => {
+                        out.push_str(&head);
+                        rest = content;
+                    }
+                    None => out.push_str(OPEN),
+                }
+            }
+            (_, Some(close)) => {
+                out.push_str(&rest[..close]);
+                out.push_str(if open_quotes.pop().unwrap_or(false) { "</div>\n" } else { CLOSE });
+                rest = &rest[close + CLOSE.len()..];
+            }
+            _ => break,
+        }
+    }
+    out.push_str(rest);
+    out
+}
+
+/// The opening markup for a blockquote whose content is `html`, if it is a callout,
+/// and the content after its marker line.
+fn callout_open(html: &str) -> Option<(String, &str)> {
+    let (name, after) = html.strip_prefix("<p>[!")?.split_once(']')?;
+    let kind = CalloutKind::from_name(name)?;
+    let paragraph_end = after.find("</p>\n")?;
+    let line_end = after[..paragraph_end].find('\n').unwrap_or(paragraph_end);
+    let title = &after[..line_end];
+    if !title.is_empty() && !title.starts_with(char::is_whitespace) {
+        return None;
+    }
+
+    let title = match title.trim() {
+        "" => kind.label(),
+        title => title,
+    };
+    let mut head = format!(
+        "<div class=\"callout callout-{}\">\n<p class=\"callout-title\">{title}</p>\n",
+        kind.name().to_ascii_lowercase()
+    );
+    let content = if line_end == paragraph_end {
+        &after[paragraph_end + "</p>\n".len()..]
+    } else {
+        head.push_str("<p>");
+        &after[line_end + 1..]
+    };
+    Some((head, content))
+}
+
+fn escape_html(text: &str) -> String {
+    text.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;").replace('"', "&quot;")
+}
+
+fn unescape_html(text: &str) -> String {
+    text.replace("&lt;", "<").replace("&gt;", ">").replace("&quot;", "\"").replace("&amp;", "&")
+}
+
 fn normalize_target(path: &str) -> Result<PathBuf, ExportError> {
     if path.trim().is_empty() {
         return Err(ExportError::InvalidPath("path is empty".to_string()));
@@ -93,3 +239,97 @@ fn atomic_write(path: &Path, bytes: &[u8]) -> Result<u64, ExportError> {
 
     Ok(bytes.len() as u64)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn titles_documents_by_front_matter() {
+        let html = render_document(
+            Path::new("/tmp/notes.html"),
+            "---\ntitle: \"Q&A\" # comment\ntags: [a, b]\n---\n# Body\n",
+        );
+        assert!(html.contains("<title>Q&amp;A</title>"));
+        assert!(html.contains("<h1>Body</h1>"));
+        assert!(!html.contains("tags"));
+
+        let html = render_document(Path::new("/tmp/notes.html"), "---\n\ntext\n");
+        assert!(html.contains("<title>notes</title>"));
+        assert!(html.contains("<hr />"));
+    }
+
+    #[test]
+    fn renders_math_as_mathml() {
+        let html = render_document(
+            Path::new("/tmp/notes.html"),
+            "Euler: $e^{i\\pi} + 1 = 0$ costs $5 and $6.\n\n$$\n\\frac{a}{b} < \\sqrt{x}\n$$\n",
+        );
+        assert!(html.contains(
+            "<math xmlns=\"http://www.w3.org/1998/Math/MathML\"><semantics><mrow>\
+             <msup><mi>e</mi><mrow><mi>i</mi><mi>π</mi></mrow></msup>"
+        ));
+        assert!(html.contains("costs $5 and $6."));
+        assert!(html.contains(
+            "<math xmlns=\"http://www.w3.org/1998/Math/MathML\" display=\"block\"><semantics>\
+             <mrow><mfrac><mi>a</mi><mi>b</mi></mfrac><mo>&lt;</mo><msqrt><mi>x</mi></msqrt></mrow>"
+        ));
+        assert!(html.contains(
+            "<annotation encoding=\"application/x-tex\">\\frac{a}{b} &lt; \\sqrt{x}</annotation>"
+        ));
+        assert!(!html.contains("<script"));
+    }
+
+    #[test]
+    fn numbers_and_back_links_footnotes() {
+        let html = render_document(
+            Path::new("/tmp/notes.html"),
+            "Later[^b] then first[^a].\n\n[^a]: Alpha\n[^b]: Beta\n[^unused]: Gamma\n",
+        );
+        assert!(html.contains(
+            "Later<sup class=\"footnote-ref\"><a href=\"#fn-b\" id=\"fnref-b\" \
+             data-footnote-ref>1</a></sup>"
+        ));
+        assert!(html.contains("<a href=\"#fn-a\" id=\"fnref-a\" data-footnote-ref>2</a>"));
+        let notes = &html[html.find("<section class=\"footnotes\"").unwrap()..];
+        assert!(notes.find("Beta").unwrap() < notes.find("Alpha").unwrap());
+        assert!(notes.contains("<a href=\"#fnref-a\" class=\"footnote-backref\""));
+        assert!(!notes.contains("Gamma"));
+    }
+
+    #[test]
+    fn renders_callouts_as_styled_boxes() {
+        let html = render_document(
+            Path::new("/tmp/notes.html"),
+            "> [!warning] Mind *this*\n> body\n>\n> > [!TIP]\n> > nested\n\n> [!NOTE]x\n",
+        );
+        assert!(html.contains(
+            "<div class=\"callout callout-warning\">\n\
+             <p class=\"callout-title\">Mind <em>this</em></p>\n<p>body</p>\n\
+             <div class=\"callout callout-tip\">\n<p class=\"callout-title\">Tip</p>\n\
+             <p>nested</p>\n</div>\n</div>\n<blockquote>\n<p>[!NOTE]x</p>\n</blockquote>\n"
+        ));
+        assert!(html.contains(".callout-warning { --callout-color: #9a6700; }"));
+    }
+
+    #[test]
+    fn converts_tex_structures() {
+        assert_eq!(
+            tex_to_mathml("\\sum_{i=1}^n x_i", true).split("<annotation").next().unwrap(),
+            "<math xmlns=\"http://www.w3.org/1998/Math/MathML\" display=\"block\"><semantics>\
+             <mrow><munderover><mo movablelimits=\"true\">∑</mo><mrow><mi>i</mi><mo>=</mo>\
+             <mn>1</mn></mrow><mi>n</mi></munderover><msub><mi>x</mi><mi>i</mi></msub></mrow>"
+        );
+        let matrix = tex_to_mathml("\\begin{pmatrix} 1 & 0 \\\\ 0 & 1 \\end{pmatrix}", false);
+        assert!(matrix.contains(
+            "<mtable><mtr><mtd><mn>1</mn></mtd><mtd><mn>0</mn></mtd></mtr>\
+             <mtr><mtd><mn>0</mn></mtd><mtd><mn>1</mn></mtd></mtr></mtable>"
+        ));
+        let fenced = tex_to_mathml("\\left( \\mathbb{R} \\right]", false);
+        assert!(fenced.contains(
+            "<mrow><mo fence=\"true\" stretchy=\"true\">(</mo>\
+             <mi mathvariant=\"double-struck\">R</mi><mo fence=\"true\" stretchy=\"true\">]</mo></mrow>"
+        ));
+        assert!(tex_to_mathml("\\unknown x", false).contains("<mtext>\\unknown</mtext>"));
+    }
+}
@@ -0,0 +1,708 @@
+//! TeX math to MathML, so exported documents show formulas without a script or a
+//! web font. The converter covers the LaTeX math notes usually contain: scripts,
+//! fractions and roots, Greek letters and operators, font commands, accents,
+//! `\left`/`\right` and matrix-like environments. Commands it does not know are
+//! kept as text, and the TeX source travels along as an annotation.
+
+const MATHML_NAMESPACE: &str = "http://www.w3.org/1998/Math/MathML";
+
+/// A `<math>` element for `tex`, laid out as a block when `display` is set.
+pub fn tex_to_mathml(tex: &str, display: bool) -> String {
+    let mut parser = Parser { chars: tex.chars().collect(), pos: 0, display };
+    let body = mrow(parser.parse_all());
+    let display_attr = if display { " display=\"block\"" } else { "" };
+    format!(
+        "<math xmlns=\"{MATHML_NAMESPACE}\"{display_attr}><semantics>{body}\
+         <annotation encoding=\"application/x-tex\">{}</annotation></semantics></math>",
+        escape(tex)
+    )
+}
+
+const GREEK: &[(&str, &str)] = &[
+    ("alpha", "α"),
+    ("beta", "β"),
+    ("gamma", "γ"),
+    ("delta", "δ"),
+    ("epsilon", "ϵ"),
+    ("varepsilon", "ε"),
+    ("zeta", "ζ"),
+    ("eta", "η"),
+    ("theta", "θ"),
+    ("vartheta", "ϑ"),
+    ("iota", "ι"),
+    ("kappa", "κ"),
+    ("lambda", "λ"),
+    ("mu", "μ"),
+    ("nu", "ν"),
+    ("xi", "ξ"),
+    ("omicron", "ο"),
+    ("pi", "π"),
+    ("varpi", "ϖ"),
+    ("rho", "ρ"),
+    ("varrho", "ϱ"),
+    ("sigma", "σ"),
+    ("varsigma", "ς"),
+    ("tau", "τ"),
+    ("upsilon", "υ"),
+    ("phi", "ϕ"),
+    ("varphi", "φ"),
+    ("chi", "χ"),
+    ("psi", "ψ"),
+    ("omega", "ω"),
+    ("infty", "∞"),
+    ("ell", "ℓ"),
+    ("hbar", "ℏ"),
+    ("nabla", "∇"),
+    ("partial", "∂"),
+    ("emptyset", "∅"),
+    ("varnothing", "∅"),
+    ("aleph", "ℵ"),
+    ("Re", "ℜ"),
+    ("Im", "ℑ"),
+    ("wp", "℘"),
+];
+
+/// Capital Greek letters, which are set upright.
+const UPRIGHT_GREEK: &[(&str, &str)] = &[
+    ("Gamma", "Γ"),
+    ("Delta", "Δ"),
+    ("Theta", "Θ"),
+    ("Lambda", "Λ"),
+    ("Xi", "Ξ"),
+    ("Pi", "Π"),
+    ("Sigma", "Σ"),
+    ("Upsilon", "Υ"),
+    ("Phi", "Φ"),
+    ("Psi", "Ψ"),
+    ("Omega", "Ω"),
+];
+
+const OPERATORS: &[(&str, &str)] = &[
+    ("pm", "±"),
+    ("mp", "∓"),
+    ("times", "×"),
+    ("div", "÷"),
+    ("cdot", "⋅"),
+    ("cdotp", "⋅"),
+    ("ast", "∗"),
+    ("star", "⋆"),
+    ("circ", "∘"),
+    ("bullet", "∙"),
+    ("cdots", "⋯"),
+    ("ldots", "…"),
+    ("dots", "…"),
+    ("vdots", "⋮"),
+    ("ddots", "⋱"),
+    ("le", "≤"),
+    ("leq", "≤"),
+    ("ge", "≥"),
+    ("geq", "≥"),
+    ("ne", "≠"),
+    ("neq", "≠"),
+    ("approx", "≈"),
+    ("equiv", "≡"),
+    ("sim", "∼"),
+    ("simeq", "≃"),
+    ("cong", "≅"),
+    ("propto", "∝"),
+    ("ll", "≪"),
+    ("gg", "≫"),
+    ("in", "∈"),
+    ("notin", "∉"),
+    ("ni", "∋"),
+    ("subset", "⊂"),
+    ("supset", "⊃"),
+    ("subseteq", "⊆"),
+    ("supseteq", "⊇"),
+    ("cup", "∪"),
+    ("cap", "∩"),
+    ("setminus", "∖"),
+    ("wedge", "∧"),
+    ("land", "∧"),
+    ("vee", "∨"),
+    ("lor", "∨"),
+    ("neg", "¬"),
+    ("lnot", "¬"),
+    ("forall", "∀"),
+    ("exists", "∃"),
+    ("nexists", "∄"),
+    ("to", "→"),
+    ("rightarrow", "→"),
+    ("leftarrow", "←"),
+    ("gets", "←"),
+    ("leftrightarrow", "↔"),
+    ("Rightarrow", "⇒"),
+    ("Leftarrow", "⇐"),
+    ("Leftrightarrow", "⇔"),
+    ("implies", "⟹"),
+    ("iff", "⟺"),
+    ("mapsto", "↦"),
+    ("uparrow", "↑"),
+    ("downarrow", "↓"),
+    ("oplus", "⊕"),
+    ("otimes", "⊗"),
+    ("perp", "⊥"),
+    ("parallel", "∥"),
+    ("mid", "∣"),
+    ("angle", "∠"),
+    ("triangle", "△"),
+    ("prime", "′"),
+    ("colon", ":"),
+    ("int", "∫"),
+    ("iint", "∬"),
+    ("iiint", "∭"),
+    ("oint", "∮"),
+];
+
+/// Delimiters, which `\left`, `\right` and the `\big` family take as well.
+const DELIMITERS: &[(&str, &str)] = &[
+    ("langle", "⟨"),
+    ("rangle", "⟩"),
+    ("lfloor", "⌊"),
+    ("rfloor", "⌋"),
+    ("lceil", "⌈"),
+    ("rceil", "⌉"),
+    ("vert", "|"),
+    ("lvert", "|"),
+    ("rvert", "|"),
+    ("Vert", "‖"),
+    ("lVert", "‖"),
+    ("rVert", "‖"),
+    ("lbrace", "{"),
+    ("rbrace", "}"),
+    ("{", "{"),
+    ("}", "}"),
+    ("|", "‖"),
+];
+
+/// Operators whose scripts go above and below in display math.
+const BIG_OPERATORS: &[(&str, &str)] = &[
+    ("sum", "∑"),
+    ("prod", "∏"),
+    ("coprod", "∐"),
+    ("bigcup", "⋃"),
+    ("bigcap", "⋂"),
+    ("bigoplus", "⨁"),
+    ("bigotimes", "⨂"),
+    ("bigvee", "⋁"),
+    ("bigwedge", "⋀"),
+];
+
+const FUNCTIONS: &[&str] = &[
+    "sin", "cos", "tan", "cot", "sec", "csc", "arcsin", "arccos", "arctan", "sinh", "cosh", "tanh",
+    "coth", "log", "ln", "lg", "exp", "arg", "deg", "dim", "hom", "ker",
+];
+
+/// Functions whose scripts go below in display math, like `\lim_{x \to 0}`.
+const LIMIT_FUNCTIONS: &[&str] =
+    &["lim", "liminf", "limsup", "max", "min", "sup", "inf", "det", "gcd", "Pr"];
+
+const FONTS: &[(&str, &str)] = &[
+    ("mathrm", "normal"),
+    ("mathbf", "bold"),
+    ("mathit", "italic"),
+    ("mathbb", "double-struck"),
+    ("mathcal", "script"),
+    ("mathscr", "script"),
+    ("mathfrak", "fraktur"),
+    ("mathsf", "sans-serif"),
+    ("mathtt", "monospace"),
+    ("boldsymbol", "bold-italic"),
+    ("bm", "bold-italic"),
+];
+
+/// Accents set over their argument, and whether they stretch with it.
+const ACCENTS: &[(&str, &str, bool)] = &[
+    ("hat", "^", false),
+    ("widehat", "^", true),
+    ("bar", "¯", false),
+    ("overline", "‾", true),
+    ("vec", "→", false),
+    ("overrightarrow", "→", true),
+    ("dot", "˙", false),
+    ("ddot", "¨", false),
+    ("tilde", "~", false),
+    ("widetilde", "~", true),
+    ("check", "ˇ", false),
+    ("breve", "˘", false),
+    ("acute", "´", false),
+    ("grave", "`", false),
+    ("overbrace", "⏞", true),
+];
+
+const UNDER_ACCENTS: &[(&str, &str)] = &[("underline", "_"), ("underbrace", "⏟")];
+
+const SPACES: &[(&str, &str)] = &[
+    (",", "0.1667em"),
+    (":", "0.2222em"),
+    (">", "0.2222em"),
+    (";", "0.2778em"),
+    ("!", "-0.1667em"),
+    (" ", "0.25em"),
+    ("quad", "1em"),
+    ("qquad", "2em"),
+];
+
+/// Characters a backslash makes literal.
+const ESCAPED: &[char] = &['%', '$', '#', '&', '_'];
+
+fn lookup<'a>(table: &[(&str, &'a str)], name: &str) -> Option<&'a str> {
+    table.iter().find(|(key, _)| *key == name).map(|(_, value)| *value)
+}
+
+struct Parser {
+    chars: Vec<char>,
+    pos: usize,
+    display: bool,
+}
+
+impl Parser {
+    /// Parses the whole input. Stray closing braces, alignment marks and line breaks
+    /// outside an environment are dropped.
+    fn parse_all(&mut self) -> Vec<String> {
+        let mut nodes = Vec::new();
+        loop {
+            nodes.extend(self.parse_row());
+            match self.peek() {
+                None => return nodes,
+                Some('\\') => {
+                    if self.peek_at(1) == Some('\\') {
+                        self.pos += 2;
+                    } else {
+                        self.pos += 1;
+                        self.read_command_name();
+                    }
+                }
+                Some(_) => self.pos += 1,
+            }
+        }
+    }
+
+    /// Parses nodes up to the end of a group or cell: a `}`, `&`, `\\`, `\right`,
+    /// `\middle` or `\end`, which is left unconsumed.
+    fn parse_row(&mut self) -> Vec<String> {
+        let mut nodes = Vec::new();
+        loop {
+            self.skip_whitespace();
+            match self.peek() {
+                None | Some('}' | '&') => return nodes,
+                Some('\\') if self.peek_at(1) == Some('\\') => return nodes,
+                Some('\\') if matches!(self.peek_command(), Some("right" | "middle" | "end")) => {
+                    return nodes;
+                }
+                Some(_) => nodes.extend(self.parse_scripted()),
+            }
+        }
+    }
+
+    /// An atom with the subscript, superscript and primes after it.
+    fn parse_scripted(&mut self) -> Option<String> {
+        let (base, limits) = self.parse_atom()?;
+        let limits = limits && self.display;
+        let mut sub = None;
+        let mut sup = None;
+        loop {
+            self.skip_whitespace();
+            match self.peek() {
+                Some('_') if sub.is_none() => {
+                    self.pos += 1;
+                    sub = Some(self.parse_argument());
+                }
+                Some('^') if sup.is_none() => {
+                    self.pos += 1;
+                    sup = Some(self.parse_argument());
+                }
+                Some('\'') if sup.is_none() => {
+                    let mut primes = String::new();
+                    while self.peek() == Some('\'') {
+                        self.pos += 1;
+                        primes.push('′');
+                    }
+                    sup = Some(format!("<mo>{primes}</mo>"));
+                }
+                _ => break,
+            }
+        }
+
+        Some(match (sub, sup) {
+            (None, None) => base,
+            (Some(sub), None) if limits => format!("<munder>{base}{sub}</munder>"),
+            (Some(sub), None) => format!("<msub>{base}{sub}</msub>"),
+            (None, Some(sup)) if limits => format!("<mover>{base}{sup}</mover>"),
+            (None, Some(sup)) => format!("<msup>{base}{sup}</msup>"),
+            (Some(sub), Some(sup)) if limits => {
+                format!("<munderover>{base}{sub}{sup}</munderover>")
+            }
+            (Some(sub), Some(sup)) => format!("<msubsup>{base}{sub}{sup}</msubsup>"),
+        })
+    }
+
+    /// One node, and whether scripts on it go above and below in display math.
+    fn parse_atom(&mut self) -> Option<(String, bool)> {
+        let ch = self.peek()?;
+        self.pos += 1;
+        let node = match ch {
+            '{' => self.parse_group_rest(),
+            '\\' => return self.parse_command(),
+            '0'..='9' => {
+                let mut number = ch.to_string();
+                while let Some(next) = self.peek() {
+                    let decimal =
+                        next == '.' && self.peek_at(1).is_some_and(|ch| ch.is_ascii_digit());
+                    if !(next.is_ascii_digit() || decimal) {
+                        break;
+                    }
+                    number.push(next);
+                    self.pos += 1;
+                }
+                format!("<mn>{number}</mn>")
+            }
+            '~' => "<mspace width=\"0.25em\"></mspace>".to_string(),
+            '-' => "<mo>−</mo>".to_string(),
+            '*' => "<mo>∗</mo>".to_string(),
+            '\'' => "<mo>′</mo>".to_string(),
+            ch if ch.is_alphabetic() => format!("<mi>{ch}</mi>"),
+            ch => format!("<mo>{}</mo>", escape(&ch.to_string())),
+        };
+        Some((node, false))
+    }
+
+    /// The argument of a command or script: a group, a command, or one character.
+    fn parse_argument(&mut self) -> String {
+        self.skip_whitespace();
+        match self.peek() {
+            Some('{') => {
+                self.pos += 1;
+                self.parse_group_rest()
+            }
+            Some(ch) if ch.is_ascii_digit() => {
+                self.pos += 1;
+                format!("<mn>{ch}</mn>")
+            }
+            Some('}' | '&') | None => "<mrow></mrow>".to_string(),
+            Some(_) => self.parse_atom().map(|(node, _)| node).unwrap_or_default(),
+        }
+    }
+
+    /// A group whose `{` is consumed.
+    fn parse_group_rest(&mut self) -> String {
+        let row = self.parse_row();
+        if self.peek() == Some('}') {
+            self.pos += 1;
+        }
+        mrow(row)
+    }
+
+    fn parse_command(&mut self) -> Option<(String, bool)> {
+        let name = self.read_command_name();
+        let name = name.as_str();
+
+        if let Some(symbol) = lookup(GREEK, name) {
+            return Some((format!("<mi>{symbol}</mi>"), false));
+        }
+        if let Some(symbol) = lookup(UPRIGHT_GREEK, name) {
+            return Some((format!("<mi mathvariant=\"normal\">{symbol}</mi>"), false));
+        }
+        if let Some(symbol) = lookup(OPERATORS, name) {
+            return Some((format!("<mo>{}</mo>", escape(symbol)), false));
+        }
+        if let Some(symbol) = lookup(BIG_OPERATORS, name) {
+            return Some((format!("<mo movablelimits=\"true\">{symbol}</mo>"), true));
+        }
+        if let Some(symbol) = lookup(DELIMITERS, name) {
+            return Some((format!("<mo>{}</mo>", escape(symbol)), false));
+        }
+        if FUNCTIONS.contains(&name) {
+            return Some((format!("<mi>{name}</mi><mo>&#x2061;</mo>"), false));
+        }
+        if LIMIT_FUNCTIONS.contains(&name) {
+            return Some((format!("<mi>{name}</mi>"), true));
+        }
+        if let Some(width) = lookup(SPACES, name) {
+            return Some((format!("<mspace width=\"{width}\"></mspace>"), false));
+        }
+        if let Some(variant) = lookup(FONTS, name) {
+            let argument = self.parse_argument();
+            return Some((with_variant(&argument, variant), false));
+        }
+        if let Some((_, accent, stretchy)) = ACCENTS.iter().find(|(key, ..)| *key == name) {
+            let argument = self.parse_argument();
+            return Some((
+                format!(
+                    "<mover accent=\"true\">{argument}<mo stretchy=\"{stretchy}\">{accent}</mo></mover>"
+                ),
+                false,
+            ));
+        }
+        if let Some(accent) = lookup(UNDER_ACCENTS, name) {
+            let argument = self.parse_argument();
+            return Some((
+                format!("<munder accentunder=\"true\">{argument}<mo>{accent}</mo></munder>"),
+                false,
+            ));
+        }
+        if name.len() == 1 && ESCAPED.contains(&name.chars().next()?) {
+            return Some((format!("<mo>{}</mo>", escape(name)), false));
+        }
+
+        let node = match name {
+            "frac" | "dfrac" | "tfrac" | "cfrac" => {
+                let numerator = self.parse_argument();
+                let denominator = self.parse_argument();
+                format!("<mfrac>{numerator}{denominator}</mfrac>")
+            }
+            "binom" | "dbinom" | "tbinom" => {
+                let top = self.parse_argument();
+                let bottom = self.parse_argument();
+                format!(
+                    "<mrow><mo>(</mo><mfrac linethickness=\"0\">{top}{bottom}</mfrac><mo>)</mo></mrow>"
+                )
+            }
+            "sqrt" => {
+                self.skip_whitespace();
+                let index = (self.peek() == Some('[')).then(|| {
+                    self.pos += 1;
+                    let raw = self.read_until(']');
+                    let mut parser =
+                        Parser { chars: raw.chars().collect(), pos: 0, display: false };
+                    mrow(parser.parse_all())
+                });
+                let radicand = self.parse_argument();
+                match index {
+                    Some(index) => format!("<mroot>{radicand}{index}</mroot>"),
+                    None => format!("<msqrt>{radicand}</msqrt>"),
+                }
+            }
+            "text" | "textrm" | "textup" | "mbox" | "textnormal" => {
+                format!("<mtext>{}</mtext>", escape(&self.read_raw_argument()))
+            }
+            "textbf" => {
+                format!("<mtext mathvariant=\"bold\">{}</mtext>", escape(&self.read_raw_argument()))
+            }
+            "textit" => format!(
+                "<mtext mathvariant=\"italic\">{}</mtext>",
+                escape(&self.read_raw_argument())
+            ),
+            "operatorname" => {
+                return Some((format!("<mi>{}</mi>", escape(&self.read_raw_argument())), false));
+            }
+            "left" => self.parse_fenced(),
+            "big" | "Big" | "bigg" | "Bigg" | "bigl" | "bigr" | "Bigl" | "Bigr" | "biggl"
+            | "biggr" | "Biggl" | "Biggr" => {
+                let delimiter = self.read_delimiter();
+                format!("<mo>{}</mo>", escape(&delimiter))
+            }
+            "begin" => self.parse_environment(),
+            "displaystyle" | "textstyle" | "limits" | "nolimits" => String::new(),
+            name => format!("<mtext>\\{}</mtext>", escape(name)),
+        };
+        Some((node, false))
+    }
+
+    /// `\left` … `\right`, with any `\middle` delimiters, after the `\left`.
+    fn parse_fenced(&mut self) -> String {
+        let mut nodes = vec![fence(&self.read_delimiter())];
+        loop {
+            nodes.extend(self.parse_row());
+            match self.peek_command() {
+                Some("middle") => {
+                    self.pos += 1;
+                    self.read_command_name();
+                    nodes.push(fence(&self.read_delimiter()));
+                }
+                Some("right") => {
+                    self.pos += 1;
+                    self.read_command_name();
+                    nodes.push(fence(&self.read_delimiter()));
+                    break;
+                }
+                _ => break,
+            }
+        }
+        format!("<mrow>{}</mrow>", nodes.concat())
+    }
+
+    /// `\begin{name}` … `\end{name}` as a table, after the `\begin`.
+    fn parse_environment(&mut self) -> String {
+        let name = self.read_raw_argument();
+        if name == "array" {
+            self.read_raw_argument();
+        }
+
+        let mut rows: Vec<Vec<String>> = vec![Vec::new()];
+        loop {
+            let cell = mrow(self.parse_row());
+            rows.last_mut().expect("current row").push(cell);
+            match self.peek() {
+                Some('&') => self.pos += 1,
+                Some('\\') if self.peek_at(1) == Some('\\') => {
+                    self.pos += 2;
+                    rows.push(Vec::new());
+                }
+                Some('\\') => {
+                    self.pos += 1;
+                    if self.read_command_name() == "end" {
+                        self.read_raw_argument();
+                        break;
+                    }
+                }
+                Some(_) => self.pos += 1,
+                None => break,
+            }
+        }
+        if rows.last().is_some_and(|row| row.iter().all(|cell| cell == "<mrow></mrow>")) {
+            rows.pop();
+        }
+
+        let body: String = rows
+            .iter()
+            .map(|row| {
+                let cells: String = row.iter().map(|cell| format!("<mtd>{cell}</mtd>")).collect();
+                format!("<mtr>{cells}</mtr>")
+            })
+            .collect();
+        let (open, close, attrs) = match name.trim_end_matches('*') {
+            "pmatrix" => ("(", ")", ""),
+            "bmatrix" => ("[", "]", ""),
+            "Bmatrix" => ("{", "}", ""),
+            "vmatrix" => ("|", "|", ""),
+            "Vmatrix" => ("‖", "‖", ""),
+            "cases" => ("{", "", " columnalign=\"left left\""),
+            "aligned" | "align" | "split" | "alignat" | "eqnarray" => {
+                ("", "", " columnalign=\"right left\" displaystyle=\"true\"")
+            }
+            "gathered" | "gather" => ("", "", " displaystyle=\"true\""),
+            _ => ("", "", ""),
+        };
+        let table = format!("<mtable{attrs}>{body}</mtable>");
+        if open.is_empty() && close.is_empty() {
+            return table;
+        }
+        format!("<mrow>{}{table}{}</mrow>", fence(open), fence(close))
+    }
+
+    /// The delimiter after `\left`, `\right` or `\big`; `.` is none.
+    fn read_delimiter(&mut self) -> String {
+        self.skip_whitespace();
+        match self.peek() {
+            Some('\\') => {
+                self.pos += 1;
+                let name = self.read_command_name();
+                lookup(DELIMITERS, &name).unwrap_or_default().to_string()
+            }
+            Some('.') => {
+                self.pos += 1;
+                String::new()
+            }
+            Some(ch) => {
+                self.pos += 1;
+                ch.to_string()
+            }
+            None => String::new(),
+        }
+    }
+
+    /// The name after a consumed backslash: a run of letters or one other character.
+    fn read_command_name(&mut self) -> String {
+        let start = self.pos;
+        while self.peek().is_some_and(|ch| ch.is_ascii_alphabetic()) {
+            self.pos += 1;
+        }
+        if self.pos == start && self.peek().is_some() {
+            self.pos += 1;
+        }
+        self.chars[start..self.pos].iter().collect()
+    }
+
+    /// The text of a braced argument, or of the next character without braces.
+    fn read_raw_argument(&mut self) -> String {
+        self.skip_whitespace();
+        match self.peek() {
+            Some('{') => {
+                self.pos += 1;
+                self.read_until('}')
+            }
+            Some(ch) => {
+                self.pos += 1;
+                ch.to_string()
+            }
+            None => String::new(),
+        }
+    }
+
+    /// The text up to the `close` that balances the current nesting, which is
+    /// consumed.
+    fn read_until(&mut self, close: char) -> String {
+        let open = if close == '}' { '{' } else { '[' };
+        let start = self.pos;
+        let mut depth = 0;
+        while let Some(ch) = self.peek() {
+            if ch == close && depth == 0 {
+                let text = self.chars[start..self.pos].iter().collect();
+                self.pos += 1;
+                return text;
+            }
+            if ch == open {
+                depth += 1;
+            } else if ch == close {
+                depth -= 1;
+            }
+            self.pos += 1;
+        }
+        self.chars[start..].iter().collect()
+    }
+
+    fn peek_command(&self) -> Option<&'static str> {
+        if self.peek() != Some('\\') {
+            return None;
+        }
+        ["right", "middle", "end"].into_iter().find(|name| {
+            let len = name.len();
+            let matches = self
+                .chars
+                .get(self.pos + 1..self.pos + 1 + len)
+                .is_some_and(|chars| chars.iter().copied().eq(name.chars()));
+            matches && !self.peek_at(1 + len).is_some_and(|ch| ch.is_ascii_alphabetic())
+        })
+    }
+
+    fn skip_whitespace(&mut self) {
+        while self.peek().is_some_and(char::is_whitespace) {
+            self.pos += 1;
+        }
+    }
+
+    fn peek(&self) -> Option<char> {
+        self.peek_at(0)
+    }
+
+    fn peek_at(&self, offset: usize) -> Option<char> {
+        self.chars.get(self.pos + offset).copied()
+    }
+}
+
+fn mrow(nodes: Vec<String>) -> String {
+    match <[String; 1]>::try_from(nodes) {
+        Ok([node]) => node,
+        Err(nodes) => format!("<mrow>{}</mrow>", nodes.concat()),
+    }
+}
+
+fn fence(delimiter: &str) -> String {
+    if delimiter.is_empty() {
+        return String::new();
+    }
+    format!("<mo fence=\"true\" stretchy=\"true\">{}</mo>", escape(delimiter))
+}
+
+/// `node` with its identifiers and numbers set in `variant`.
+fn with_variant(node: &str, variant: &str) -> String {
+    let attr = format!(" mathvariant=\"{variant}\"");
+    node.replace("<mi mathvariant=\"normal\">", "<mi>")
+        .replace("<mi>", &format!("<mi{attr}>"))
+        .replace("<mn>", &format!("<mn{attr}>"))
+}
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;").replace('"', "&quot;")
+}
@@ -1,15 +1,16 @@
 use std::collections::hash_map::DefaultHasher;
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::fs;
 use std::hash::{Hash, Hasher};
 use std::io::Write;
 use std::path::{Component, Path, PathBuf};
-use std::sync::Mutex;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
 use std::time::{SystemTime, UNIX_EPOCH};
 
 use fn_core::{
-    ListWorkspaceEntriesResponse, OpenFileResponse, SaveFileResponse, WatchStartedResponse,
-    WorkspaceEntry, WorkspaceEntryKind,
+    ListWorkspaceEntriesResponse, OpenFileResponse, ResolveWikiLinksResponse, SaveFileResponse,
+    WatchStartedResponse, WikiLinkResolution, WorkspaceEntry, WorkspaceEntryKind,
 };
 use notify::{RecommendedWatcher, RecursiveMode, Watcher};
 use once_cell::sync::Lazy;
@@ -18,6 +19,17 @@ use thiserror::Error;
 static WATCHERS: Lazy<Mutex<HashMap<String, RecommendedWatcher>>> =
     Lazy::new(|| Mutex::new(HashMap::new()));
 
+/// Note indexes by canonical workspace root, for wiki-link resolution.
+static NOTE_INDEXES: Lazy<Mutex<HashMap<PathBuf, NoteIndex>>> =
+    Lazy::new(|| Mutex::new(HashMap::new()));
+
+/// The Markdown files of a workspace, listed again once its watcher reports a change.
+struct NoteIndex {
+    notes: Vec<PathBuf>,
+    stale: Arc<AtomicBool>,
+    _watcher: RecommendedWatcher,
+}
+
 #[derive(Debug, Error)]
 pub enum FsError {
     #[error("path is empty")]
@@ -98,6 +110,7 @@ pub fn save_file(
 pub fn save_as_file(path: &str, content: &str) -> Result<SaveFileResponse, FsError> {
     let normalized = normalize_write_target(path)?;
     let bytes_written = atomic_write(&normalized, content.as_bytes())?;
+    invalidate_note_indexes(&normalized);
     let meta = fs::metadata(&normalized)?;
     let version = hash_version(content.as_bytes(), last_modified_epoch_ms(&meta));
 
@@ -196,6 +209,152 @@ pub fn list_workspace_entries(
     })
 }
 
+/// Resolves wiki-link targets to Markdown files under the workspace root. A target
+/// with a `/` is a path relative to the root, with `.md` added when it has no
+/// Markdown extension; any other target names a note by its file stem, or by its
+/// file name when it has an extension, ignoring case. Of several notes with that
+/// name the shallowest wins, then the first by path. Targets that match nothing, or
+/// only something outside the workspace, resolve to no path.
+pub fn resolve_wiki_links(
+    workspace_root: &str,
+    targets: &[String],
+) -> Result<ResolveWikiLinksResponse, FsError> {
+    let root = normalize_workspace_root(workspace_root)?;
+    let links = with_workspace_notes(&root, |notes| {
+        let mut links = Vec::with_capacity(targets.len());
+        for target in targets {
+            let resolved = resolve_wiki_link_target(&root, notes, target.trim());
+            links.push(WikiLinkResolution {
+                target: target.clone(),
+                relative_path: resolved
+                    .as_ref()
+                    .map(|path| relative_path_string(&root, path))
+                    .transpose()?,
+                absolute_path: resolved.map(|path| path.display().to_string()),
+            });
+        }
+        Ok(links)
+    })?;
+
+    Ok(ResolveWikiLinksResponse { root_path: root.display().to_string(), links })
+}
+
+/// Runs `f` on the workspace's Markdown files, listing them only on first use and
+/// after a change under the root. Without a watcher on the root nothing would tell
+/// the index it is stale, so the files are listed on every call instead.
+fn with_workspace_notes<T>(
+    root: &Path,
+    f: impl FnOnce(&[PathBuf]) -> Result<T, FsError>,
+) -> Result<T, FsError> {
+    let mut indexes = NOTE_INDEXES.lock().map_err(|_| FsError::Lock)?;
+    if let Some(index) = indexes.get_mut(root) {
+        // Cleared before listing, so changes made meanwhile mark it stale again.
+        if index.stale.swap(false, Ordering::AcqRel) {
+            index.notes = workspace_markdown_files(root)?;
+        }
+        return f(&index.notes);
+    }
+
+    let stale = Arc::new(AtomicBool::new(false));
+    let flag = Arc::clone(&stale);
+    let watcher = notify::recommended_watcher(move |_res| flag.store(true, Ordering::Release))
+        .and_then(|mut watcher| watcher.watch(root, RecursiveMode::Recursive).map(|()| watcher));
+    let notes = workspace_markdown_files(root)?;
+    let Ok(watcher) = watcher else {
+        return f(&notes);
+    };
+    let index = NoteIndex { notes, stale, _watcher: watcher };
+    f(&indexes.entry(root.to_path_buf()).or_insert(index).notes)
+}
+
+/// Marks the note index of every workspace containing `path` stale, so a note just
+/// written is found without waiting for the watcher.
+fn invalidate_note_indexes(path: &Path) {
+    let Ok(path) = path.canonicalize() else {
+        return;
+    };
+    if let Ok(indexes) = NOTE_INDEXES.lock() {
+        for (root, index) in indexes.iter() {
+            if path.starts_with(root) {
+                index.stale.store(true, Ordering::Release);
+            }
+        }
+    }
+}
+
+fn resolve_wiki_link_target(root: &Path, notes: &[PathBuf], target: &str) -> Option<PathBuf> {
+    if target.is_empty() {
+        return None;
+    }
+
+    if target.contains(['/', '\\']) {
+        let relative = if is_markdown_file(Path::new(target)) {
+            target.to_string()
+        } else {
+            format!("{target}.md")
+        };
+        let candidate = resolve_workspace_relative_path(root, Some(&relative)).ok()?;
+        return candidate.is_file().then_some(candidate);
+    }
+
+    let by_file_name = is_markdown_file(Path::new(target));
+    let target = target.to_lowercase();
+    notes
+        .iter()
+        .find(|note| {
+            let name = if by_file_name { note.file_name() } else { note.file_stem() };
+            name.is_some_and(|name| name.to_string_lossy().to_lowercase() == target)
+        })
+        .cloned()
+}
+
+/// Every Markdown file under the workspace root, shallowest first and then by path.
+/// Hidden entries are skipped, as is anything a symlink leads out of the workspace.
+/// Directories below the root that cannot be read are skipped too.
+fn workspace_markdown_files(root: &Path) -> Result<Vec<PathBuf>, FsError> {
+    let mut notes = Vec::new();
+    let mut visited = HashSet::from([root.to_path_buf()]);
+    let mut pending = vec![root.to_path_buf()];
+
+    while let Some(directory) = pending.pop() {
+        let items = match fs::read_dir(&directory) {
+            Ok(items) => items,
+            Err(err) if directory == root => return Err(FsError::Io(err)),
+            Err(_) => continue,
+        };
+        for item in items {
+            let Ok(item) = item else {
+                continue;
+            };
+            let path = item.path();
+            if path.file_name().is_some_and(|name| name.to_string_lossy().starts_with('.')) {
+                continue;
+            }
+
+            let canonical = match path.canonicalize() {
+                Ok(target) => target,
+                Err(_) => continue,
+            };
+            if !canonical.starts_with(root) {
+                continue;
+            }
+
+            if canonical.is_dir() {
+                if visited.insert(canonical) {
+                    pending.push(path);
+                }
+            } else if canonical.is_file() && is_markdown_file(&path) {
+                notes.push(path);
+            }
+        }
+    }
+
+    notes.sort_by_cached_key(|note| {
+        (note.components().count(), note.to_string_lossy().to_lowercase())
+    });
+    Ok(notes)
+}
+
 fn normalize_existing_file(path: &str) -> Result<PathBuf, FsError> {
     let target = normalize_path(path)?;
 
@@ -1,7 +1,11 @@
 use std::fs;
+use std::thread;
+use std::time::{Duration, Instant};
 
 use fn_core::WorkspaceEntryKind;
-use fn_fs::{list_workspace_entries, open_file, save_as_file, save_file, FsError};
+use fn_fs::{
+    list_workspace_entries, open_file, resolve_wiki_links, save_as_file, save_file, FsError,
+};
 use tempfile::tempdir;
 
 #[cfg(unix)]
@@ -130,3 +134,135 @@ fn list_workspace_entries_keeps_symlink_alias_for_current_relative_path() {
     assert_eq!(listed.entries.len(), 1);
     assert_eq!(listed.entries[0].relative_path, "link/note.md");
 }
+
+#[test]
+fn resolve_wiki_links_matches_notes_by_name_and_relative_path() {
+    let dir = tempdir().expect("tempdir should be created");
+    let root = dir.path().join("workspace");
+    let outside = dir.path().join("outside");
+    fs::create_dir_all(root.join("notes")).expect("notes should be created");
+    fs::create_dir_all(root.join(".hidden")).expect(".hidden should be created");
+    fs::create_dir_all(&outside).expect("outside should be created");
+    fs::write(root.join("Home.md"), "home").expect("Home.md should exist");
+    fs::write(root.join("notes/home.md"), "nested home").expect("notes/home.md should exist");
+    fs::write(root.join("notes/Guide.markdown"), "guide").expect("Guide.markdown should exist");
+    fs::write(root.join(".hidden/Secret.md"), "secret").expect("Secret.md should exist");
+    fs::write(outside.join("Outside.md"), "outside").expect("Outside.md should exist");
+
+    let targets = ["home", "Guide", "notes/home", "Guide.markdown", "../outside/Outside", "Secret"]
+        .map(String::from);
+    let resolved = resolve_wiki_links(root.to_str().expect("root path must be utf-8"), &targets)
+        .expect("resolution should succeed");
+
+    let paths = resolved
+        .links
+        .iter()
+        .map(|link| (link.target.as_str(), link.relative_path.as_deref()))
+        .collect::<Vec<_>>();
+    assert_eq!(
+        paths,
+        vec![
+            ("home", Some("Home.md")),
+            ("Guide", Some("notes/Guide.markdown")),
+            ("notes/home", Some("notes/home.md")),
+            ("Guide.markdown", Some("notes/Guide.markdown")),
+            ("../outside/Outside", None),
+            ("Secret", None),
+        ]
+    );
+    assert!(resolved.links[0]
+        .absolute_path
+        .as_deref()
+        .is_some_and(|path| path.ends_with("Home.md")));
+}
+
+#[test]
+fn resolve_wiki_links_ignores_case_beyond_ascii() {
+    let dir = tempdir().expect("tempdir should be created");
+    fs::write(dir.path().join("ärger.md"), "ärger").expect("ärger.md should exist");
+    fs::write(dir.path().join("Ölkanne.md"), "öl").expect("Ölkanne.md should exist");
+
+    let targets = ["Ärger", "ÖLKANNE.MD"].map(String::from);
+    let resolved =
+        resolve_wiki_links(dir.path().to_str().expect("root path must be utf-8"), &targets)
+            .expect("resolution should succeed");
+
+    let paths = resolved.links.iter().map(|link| link.relative_path.as_deref()).collect::<Vec<_>>();
+    assert_eq!(paths, vec![Some("ärger.md"), Some("Ölkanne.md")]);
+}
+
+#[cfg(unix)]
+#[test]
+fn resolve_wiki_links_skips_notes_behind_symlinks_out_of_the_workspace() {
+    let dir = tempdir().expect("tempdir should be created");
+    let root = dir.path().join("workspace");
+    let outside = dir.path().join("outside");
+    fs::create_dir_all(&root).expect("workspace should be created");
+    fs::create_dir_all(&outside).expect("outside should be created");
+    fs::write(outside.join("Outside.md"), "outside").expect("Outside.md should exist");
+    symlink(&outside, root.join("escape")).expect("symlink should be created");
+    symlink(&root, root.join("loop")).expect("symlink should be created");
+
+    let targets = ["Outside", "escape/Outside"].map(String::from);
+    let resolved = resolve_wiki_links(root.to_str().expect("root path must be utf-8"), &targets)
+        .expect("resolution should succeed");
+
+    assert!(resolved.links.iter().all(|link| link.relative_path.is_none()));
+}
+
+#[test]
+fn resolve_wiki_links_sees_notes_added_after_an_earlier_resolution() {
+    let dir = tempdir().expect("tempdir should be created");
+    let root = dir.path().join("workspace");
+    fs::create_dir_all(root.join("notes")).expect("notes should be created");
+    let root_str = root.to_str().expect("root path must be utf-8");
+    let resolves = |target: &str| {
+        resolve_wiki_links(root_str, &[target.to_string()])
+            .expect("resolution should succeed")
+            .links[0]
+            .relative_path
+            .clone()
+    };
+
+    assert_eq!(resolves("Saved"), None);
+    save_as_file(root.join("notes/Saved.md").to_str().expect("path must be utf-8"), "saved")
+        .expect("save as should work");
+    assert_eq!(resolves("Saved").as_deref(), Some("notes/Saved.md"));
+
+    // Files written by something else show up once the workspace watcher reports them.
+    assert_eq!(resolves("External"), None);
+    fs::write(root.join("External.md"), "external").expect("External.md should be written");
+    let deadline = Instant::now() + Duration::from_secs(5);
+    while resolves("External").is_none() && Instant::now() < deadline {
+        thread::sleep(Duration::from_millis(20));
+    }
+    assert_eq!(resolves("External").as_deref(), Some("External.md"));
+}
+
+#[cfg(unix)]
+#[test]
+fn resolve_wiki_links_skips_unreadable_directories() {
+    use std::os::unix::fs::PermissionsExt;
+
+    let dir = tempdir().expect("tempdir should be created");
+    let root = dir.path().join("workspace");
+    let locked = root.join("locked");
+    fs::create_dir_all(&locked).expect("locked should be created");
+    fs::write(root.join("Home.md"), "home").expect("Home.md should exist");
+    fs::write(locked.join("Locked.md"), "locked").expect("Locked.md should exist");
+    fs::set_permissions(&locked, fs::Permissions::from_mode(0o000))
+        .expect("permissions should be set");
+    // Privileged users read the directory anyway.
+    let unreadable = fs::read_dir(&locked).is_err();
+
+    let targets = ["Home", "Locked"].map(String::from);
+    let resolved = resolve_wiki_links(root.to_str().expect("root path must be utf-8"), &targets);
+    fs::set_permissions(&locked, fs::Permissions::from_mode(0o755))
+        .expect("permissions should be restored");
+
+    let resolved = resolved.expect("resolution should succeed");
+    assert_eq!(resolved.links[0].relative_path.as_deref(), Some("Home.md"));
+    if unreadable {
+        assert_eq!(resolved.links[1].relative_path, None);
+    }
+}